@@ -0,0 +1,110 @@
+// Trains a character-level LSTM language model on a local text file, then samples from it.
+//
+//   cargo run --release --example char_lm -- <text file> [epochs]
+//
+// The text is split into BATCH parallel streams that are read SEQUENCE characters at a
+// time. The LSTM is stateful, so each chunk starts from the state the previous chunk
+// ended in, and gradients are truncated at chunk boundaries. Characters are fed through
+// an embedding, which is updated sparsely: only the rows of characters seen in a chunk.
+use std::collections::BTreeSet;
+use ndarray::{Array2, ArrayD, IxDyn};
+use rand::distributions::{Distribution, WeightedIndex};
+use feed_forward::cross_entropy::softmax;
+use feed_forward::embedding::Embedding;
+use feed_forward::init::Initializer;
+use feed_forward::layer::{clip_grad_norm, Layer};
+use feed_forward::layers::Dense;
+use feed_forward::loss::{CrossEntropyLoss, Loss};
+use feed_forward::recurrent::Lstm;
+use feed_forward::seed::rng_from_seed;
+
+const BATCH: usize = 16;
+const SEQUENCE: usize = 32;
+const EMBEDDING: usize = 32;
+const HIDDEN: usize = 128;
+const LEARNING_RATE: f32 = 2.0;
+const MAX_GRAD_NORM: f32 = 5.0;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("usage: char_lm <text file> [epochs]")?;
+    let epochs: usize = args.next().map(|a| a.parse()).transpose()?.unwrap_or(10);
+
+    let text: Vec<char> = std::fs::read_to_string(&path)?.chars().collect();
+    let alphabet: Vec<char> = text.iter().copied().collect::<BTreeSet<_>>().into_iter().collect();
+    let vocab = alphabet.len();
+    let ids: Vec<usize> = text.iter().map(|c| alphabet.binary_search(c).unwrap()).collect();
+    let stream_len = (ids.len().saturating_sub(1)) / BATCH;
+    if stream_len < SEQUENCE {
+        return Err(format!("{} is too short; need at least {} characters", path, BATCH * SEQUENCE + 1).into());
+    }
+    println!("{} characters, {} distinct", ids.len(), vocab);
+
+    let mut rng = rng_from_seed(Some(0));
+    let mut embedding = Embedding::<f32>::new(vocab, EMBEDDING, Initializer::Normal { mean: 0.0, std: 0.1 }, &mut rng);
+    let mut lstm = Lstm::new(EMBEDDING, HIDDEN, &mut rng).with_stateful(true);
+    let mut output = Dense::new(HIDDEN, vocab, Initializer::XavierUniform, &mut rng);
+    let loss = CrossEntropyLoss::new();
+
+    for epoch in 1..=epochs {
+        lstm.reset_state();
+        let (mut total, mut chunks) = (0.0, 0);
+        for start in (0..stream_len - SEQUENCE + 1).step_by(SEQUENCE) {
+            let mut inputs = ArrayD::zeros(IxDyn(&[BATCH, SEQUENCE]));
+            let mut targets = Array2::zeros((BATCH * SEQUENCE, vocab));
+            for b in 0..BATCH {
+                for t in 0..SEQUENCE {
+                    let position = b * stream_len + start + t;
+                    inputs[[b, t]] = ids[position] as f32;
+                    targets[[b * SEQUENCE + t, ids[position + 1]]] = 1.0;
+                }
+            }
+
+            // the sparse step below already zeroed the embedding rows it used
+            lstm.zero_grad();
+            output.zero_grad();
+            let hidden = lstm.forward(&embedding.forward(&inputs, true), true);
+            let logits = output.forward(&hidden, true);
+            let flat = logits.clone().into_shape((BATCH * SEQUENCE, vocab))?;
+            let result = loss.compute(flat.view(), targets.view());
+            total += result.scalar();
+            chunks += 1;
+
+            let grad_logits = result.gradient.into_dyn().into_shape(logits.raw_dim())?;
+            let grad_hidden = output.backward(&grad_logits);
+            embedding.backward(&lstm.backward(&grad_hidden));
+
+            let mut parameters = lstm.parameters_mut();
+            parameters.extend(output.parameters_mut());
+            parameters.extend(embedding.parameters_mut());
+            clip_grad_norm(&mut parameters, MAX_GRAD_NORM);
+            for parameter in lstm.parameters_mut().into_iter().chain(output.parameters_mut()) {
+                parameter.value.scaled_add(-LEARNING_RATE, &parameter.gradient);
+            }
+            embedding.sparse_sgd_step(LEARNING_RATE);
+        }
+        let mean = total / chunks as f32;
+        println!("epoch {}: loss {:.4}, perplexity {:.2}", epoch, mean, mean.exp());
+        println!("  {}", sample(&mut embedding, &mut lstm, &mut output, &alphabet, ids[0], 200, 0.8, &mut rng));
+    }
+    Ok(())
+}
+
+/// Generates `length` characters one at a time, starting from `first`.
+#[allow(clippy::too_many_arguments)]
+fn sample<R: rand::Rng>(embedding: &mut Embedding<f32>, lstm: &mut Lstm<f32>, output: &mut Dense<f32>, alphabet: &[char],
+                        first: usize, length: usize, temperature: f32, rng: &mut R) -> String {
+    lstm.reset_state();
+    let mut current = first;
+    let mut text = String::new();
+    for _ in 0..length {
+        text.push(alphabet[current]);
+        let input = ArrayD::from_elem(IxDyn(&[1, 1]), current as f32);
+        let logits = output.forward(&lstm.forward(&embedding.forward(&input, false), false), false);
+        let logits = logits.into_shape(alphabet.len()).unwrap() / temperature;
+        let probabilities = softmax(logits.view());
+        current = WeightedIndex::new(probabilities.iter()).unwrap().sample(rng);
+    }
+    lstm.reset_state();
+    text
+}
@@ -0,0 +1,41 @@
+// Trains LeNet-5 or the small VGG-style CNN on MNIST, reports test accuracy and saves a
+// checkpoint.
+//
+//   cargo run --release --example cnn_mnist -- <lenet5|small-vgg> [epochs] [checkpoint path]
+use feed_forward::checkpoint::save_parameters;
+use feed_forward::loss::CrossEntropyLoss;
+use feed_forward::models::{evaluate, lenet5, mnist_images_to_batch, one_hot, small_vgg, train_epoch};
+use feed_forward::seed::rng_from_seed;
+use mnist_data::mnist_data::*;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let name = args.next().unwrap_or_else(|| "lenet5".to_string());
+    let epochs: usize = args.next().map(|a| a.parse()).transpose()?.unwrap_or(5);
+    let checkpoint = args.next().unwrap_or_else(|| format!("{}.ckpt", name.replace('-', "_")));
+
+    let mut rng = rng_from_seed(Some(0));
+    // the deeper VGG-style network wants a smaller step
+    let (mut model, learning_rate) = match name.as_str() {
+        "lenet5" => (lenet5::<f32, _>(10, &mut rng), 0.05),
+        "small-vgg" => (small_vgg::<f32, _>(10, &mut rng), 0.02),
+        _ => return Err(format!("unknown model {:?}, expected lenet5 or small-vgg", name).into()),
+    };
+    println!("{}", model.summary());
+
+    let mnist = get_large_mnist_data()?;
+    let train_images = mnist_images_to_batch::<f32>(&convert_mnist_images_to_ndarray2(mnist.trn_img));
+    let train_targets = one_hot(&mnist.trn_lbl, 10);
+    let test_images = mnist_images_to_batch::<f32>(&convert_mnist_images_to_ndarray2(mnist.tst_img));
+
+    let loss = CrossEntropyLoss::new();
+    for epoch in 1..=epochs {
+        let train_loss = train_epoch(&mut model, &train_images, &train_targets, &loss, 32, learning_rate, &mut rng);
+        let accuracy = evaluate(&mut model, &test_images, &mnist.tst_lbl, 256);
+        println!("epoch {}: train loss {:.4}, test accuracy {:.2}%", epoch, train_loss, accuracy * 100.0);
+    }
+
+    save_parameters(&model, &checkpoint)?;
+    println!("saved checkpoint to {}", checkpoint);
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use ndarray::{Array, ArrayView, Dimension, Zip};
+use crate::cross_entropy::{sigmoid, softplus};
+use crate::precision::{cast, Real};
+
+// SELU's constants, chosen so activations keep zero mean and unit variance
+const SELU_ALPHA: f64 = 1.673_263_242_354_377_3;
+const SELU_SCALE: f64 = 1.050_700_987_355_480_5;
+// sqrt(2 / pi), used by the tanh approximation of GELU
+const GELU_COEFFICIENT: f64 = 0.797_884_560_802_865_4;
+
+/// An element-wise nonlinearity, along with its derivative.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation<F: Real> {
+    /// 1 if x > 0, otherwise 0. This is what the perceptron uses; its derivative is 0
+    /// everywhere it exists, so it can't be trained with backprop.
+    Step,
+    Sigmoid,
+    Tanh,
+    Relu,
+    /// x if x > 0, otherwise slope * x.
+    LeakyRelu(F),
+    /// x if x > 0, otherwise alpha * (e^x - 1).
+    Elu(F),
+    /// Scaled ELU with the self-normalizing constants from Klambauer et al.
+    Selu,
+    /// x * Phi(x), using the usual tanh approximation of the normal CDF Phi.
+    Gelu,
+    /// log(1 + e^x), a smooth ReLU.
+    Softplus,
+    /// x * sigmoid(x), also known as SiLU.
+    Swish,
+}
+
+impl<F: Real> Activation<F> {
+    /// Applies the activation to a single value.
+    pub fn apply(&self, x: F) -> F {
+        match *self {
+            Activation::Step => if x > F::zero() { F::one() } else { F::zero() },
+            Activation::Sigmoid => sigmoid(x),
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.max(F::zero()),
+            Activation::LeakyRelu(slope) => if x > F::zero() { x } else { slope * x },
+            Activation::Elu(alpha) => if x > F::zero() { x } else { alpha * x.exp_m1() },
+            Activation::Selu => {
+                let scale = cast::<F>(SELU_SCALE);
+                if x > F::zero() { scale * x } else { scale * cast::<F>(SELU_ALPHA) * x.exp_m1() }
+            }
+            Activation::Gelu => {
+                let half = cast::<F>(0.5);
+                half * x * (F::one() + gelu_inner(x).tanh())
+            }
+            Activation::Softplus => softplus(x),
+            Activation::Swish => x * sigmoid(x),
+        }
+    }
+
+    /// The derivative of the activation at a single value.
+    /// At kinks (e.g. ReLU at 0) we use the left derivative.
+    pub fn derivative_at(&self, x: F) -> F {
+        match *self {
+            Activation::Step => F::zero(),
+            Activation::Sigmoid => {
+                let s = sigmoid(x);
+                s * (F::one() - s)
+            }
+            Activation::Tanh => {
+                let t = x.tanh();
+                F::one() - t * t
+            }
+            Activation::Relu => if x > F::zero() { F::one() } else { F::zero() },
+            Activation::LeakyRelu(slope) => if x > F::zero() { F::one() } else { slope },
+            Activation::Elu(alpha) => if x > F::zero() { F::one() } else { alpha * x.exp() },
+            Activation::Selu => {
+                let scale = cast::<F>(SELU_SCALE);
+                if x > F::zero() { scale } else { scale * cast::<F>(SELU_ALPHA) * x.exp() }
+            }
+            Activation::Gelu => {
+                let half = cast::<F>(0.5);
+                let t = gelu_inner(x).tanh();
+                let d_inner = cast::<F>(GELU_COEFFICIENT) * (F::one() + cast::<F>(3.0 * 0.044715) * x * x);
+                half * (F::one() + t) + half * x * (F::one() - t * t) * d_inner
+            }
+            Activation::Softplus => sigmoid(x),
+            Activation::Swish => {
+                let s = sigmoid(x);
+                s + x * s * (F::one() - s)
+            }
+        }
+    }
+
+    /// Applies the activation element-wise.
+    pub fn forward<D: Dimension>(&self, x: ArrayView<F, D>) -> Array<F, D> {
+        x.mapv(|v| self.apply(v))
+    }
+
+    /// The element-wise derivative of the activation, evaluated at `x`.
+    pub fn derivative<D: Dimension>(&self, x: ArrayView<F, D>) -> Array<F, D> {
+        x.mapv(|v| self.derivative_at(v))
+    }
+
+    /// Backpropagates through the activation: given the input `x` and dL/d(output),
+    /// returns dL/dx. Since the activation is element-wise, this is just the product.
+    pub fn backward<D: Dimension>(&self, x: ArrayView<F, D>, grad_output: ArrayView<F, D>) -> Array<F, D> {
+        Zip::from(&x).and(&grad_output).map_collect(|&x, &g| g * self.derivative_at(x))
+    }
+}
+
+fn gelu_inner<F: Real>(x: F) -> F {
+    cast::<F>(GELU_COEFFICIENT) * (x + cast::<F>(0.044715) * x * x * x)
+}
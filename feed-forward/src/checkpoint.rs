@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use ndarray::{ArrayD, IxDyn};
+use crate::layer::Layer;
+use crate::precision::{cast, Real};
+
+// A checkpoint is the model's parameters in `Layer::parameters` order:
+//
+//   magic "RMLCKPT1", u64 parameter count, then per parameter
+//   u64 name length, name bytes, u64 ndim, ndim x u64 dims, len x f64 values
+//
+// all little endian. Values are stored as f64 whatever F is, so an f32 model can be
+// reloaded as f64 and vice versa. Only parameters are saved; state that isn't a
+// parameter, like batch norm's running statistics, is not.
+
+const MAGIC: &[u8; 8] = b"RMLCKPT1";
+
+/// Writes every parameter of `model` to `path`.
+pub fn save_parameters<F: Real, L: Layer<F> + ?Sized>(model: &L, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_parameters(model, &mut out)?;
+    out.flush()
+}
+
+/// `save_parameters` to any writer, e.g. to embed the parameters in a larger file.
+pub fn write_parameters<F: Real, L: Layer<F> + ?Sized, W: Write>(model: &L, out: &mut W) -> io::Result<()> {
+    let parameters = model.parameters();
+    out.write_all(MAGIC)?;
+    write_u64(out, parameters.len() as u64)?;
+    for parameter in parameters {
+        write_u64(out, parameter.name.len() as u64)?;
+        out.write_all(parameter.name.as_bytes())?;
+        write_u64(out, parameter.value.ndim() as u64)?;
+        for &d in parameter.value.shape() {
+            write_u64(out, d as u64)?;
+        }
+        for &v in parameter.value.iter() {
+            out.write_all(&v.to_f64().unwrap().to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a checkpoint written by `save_parameters` into `model`, which must have the same
+/// architecture: the parameter count, names and shapes all have to match.
+pub fn load_parameters<F: Real, L: Layer<F> + ?Sized>(model: &mut L, path: impl AsRef<Path>) -> io::Result<()> {
+    read_parameters(model, &mut BufReader::new(File::open(path)?))
+}
+
+/// `load_parameters` from any reader, which is left just past the parameters.
+pub fn read_parameters<F: Real, L: Layer<F> + ?Sized, R: Read>(model: &mut L, input: &mut R) -> io::Result<()> {
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a checkpoint file".to_string()));
+    }
+
+    let mut parameters = model.parameters_mut();
+    let count = read_u64(input)? as usize;
+    if count != parameters.len() {
+        return Err(invalid(format!("checkpoint has {} parameters, model has {}", count, parameters.len())));
+    }
+    // read everything before touching the model, so a bad file leaves it unchanged
+    let mut values = Vec::with_capacity(count);
+    for parameter in parameters.iter() {
+        let name_len = read_u64(input)? as usize;
+        let mut name = vec![0u8; name_len];
+        input.read_exact(&mut name)?;
+        let ndim = read_u64(input)? as usize;
+        let shape = (0..ndim).map(|_| read_u64(input).map(|d| d as usize)).collect::<io::Result<Vec<_>>>()?;
+        if name != parameter.name.as_bytes() || shape != parameter.value.shape() {
+            return Err(invalid(format!("checkpoint parameter {} {:?} doesn't match model parameter {} {:?}",
+                                       String::from_utf8_lossy(&name), shape, parameter.name, parameter.value.shape())));
+        }
+        let len = shape.iter().product();
+        let mut data = Vec::with_capacity(len);
+        let mut buffer = [0u8; 8];
+        for _ in 0..len {
+            input.read_exact(&mut buffer)?;
+            data.push(cast::<F>(f64::from_le_bytes(buffer)));
+        }
+        values.push(ArrayD::from_shape_vec(IxDyn(&shape), data).unwrap());
+    }
+    for (parameter, value) in parameters.iter_mut().zip(values) {
+        parameter.value = value;
+    }
+    Ok(())
+}
+
+fn write_u64<W: Write>(out: &mut W, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buffer = [0u8; 8];
+    input.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
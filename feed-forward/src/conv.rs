@@ -0,0 +1,488 @@
+use ndarray::{s, Array2, Array4, ArrayD, ArrayView4, Axis, Ix4, IxDyn};
+use rand::Rng;
+use crate::init::{Fans, Initializer};
+use crate::layer::{Layer, Parameter, ParameterKind};
+use crate::precision::{cast, Real};
+
+// Convolution and pooling layers over (N, C, H, W) image batches.
+//
+// Conv2d lowers the convolution to one matrix multiply per group with im2col: every
+// receptive field of the input is copied out as a column, after which the convolution is
+// kernels (out, C * kh * kw) . columns (C * kh * kw, N * OH * OW). The backward pass is the
+// same two products transposed, plus col2im to scatter the column gradients back.
+
+/// The spatial arithmetic shared by convolution and pooling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    kernel: (usize, usize),
+    stride: (usize, usize),
+    padding: (usize, usize),
+    dilation: (usize, usize),
+}
+
+impl Window {
+    fn output_size(&self, height: usize, width: usize) -> (usize, usize) {
+        let extent = |size: usize, k: usize, s: usize, p: usize, d: usize| {
+            let span = d * (k - 1) + 1;
+            assert!(size + 2 * p >= span, "the kernel doesn't fit in the (padded) input");
+            (size + 2 * p - span) / s + 1
+        };
+        (
+            extent(height, self.kernel.0, self.stride.0, self.padding.0, self.dilation.0),
+            extent(width, self.kernel.1, self.stride.1, self.padding.1, self.dilation.1),
+        )
+    }
+
+    // position in the unpadded input that kernel tap (ki, kj) of output (oi, oj) reads,
+    // or None if it falls in the padding
+    fn source(&self, oi: usize, oj: usize, ki: usize, kj: usize, height: usize, width: usize) -> Option<(usize, usize)> {
+        let i = (oi * self.stride.0 + ki * self.dilation.0) as isize - self.padding.0 as isize;
+        let j = (oj * self.stride.1 + kj * self.dilation.1) as isize - self.padding.1 as isize;
+        if i < 0 || j < 0 || i >= height as isize || j >= width as isize {
+            None
+        } else {
+            Some((i as usize, j as usize))
+        }
+    }
+}
+
+/// Copies every receptive field over `channels` into a column:
+/// rows are indexed by (channel, ki, kj), columns by (n, oi, oj).
+fn im2col<F: Real>(x: ArrayView4<F>, channels: std::ops::Range<usize>, window: &Window) -> Array2<F> {
+    let (n, _, height, width) = x.dim();
+    let (oh, ow) = window.output_size(height, width);
+    let (kh, kw) = window.kernel;
+    let mut columns = Array2::zeros((channels.len() * kh * kw, n * oh * ow));
+    for (c_offset, c) in channels.enumerate() {
+        for ki in 0..kh {
+            for kj in 0..kw {
+                let row = (c_offset * kh + ki) * kw + kj;
+                let mut target = columns.row_mut(row);
+                for b in 0..n {
+                    for oi in 0..oh {
+                        for oj in 0..ow {
+                            if let Some((i, j)) = window.source(oi, oj, ki, kj, height, width) {
+                                target[(b * oh + oi) * ow + oj] = x[[b, c, i, j]];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    columns
+}
+
+/// The adjoint of `im2col`: adds each column entry back onto the input pixel it came from.
+fn col2im<F: Real>(columns: &Array2<F>, grad_input: &mut Array4<F>, channels: std::ops::Range<usize>, window: &Window) {
+    let (n, _, height, width) = grad_input.dim();
+    let (oh, ow) = window.output_size(height, width);
+    let (kh, kw) = window.kernel;
+    for (c_offset, c) in channels.enumerate() {
+        for ki in 0..kh {
+            for kj in 0..kw {
+                let source = columns.row((c_offset * kh + ki) * kw + kj);
+                for b in 0..n {
+                    for oi in 0..oh {
+                        for oj in 0..ow {
+                            if let Some((i, j)) = window.source(oi, oj, ki, kj, height, width) {
+                                grad_input[[b, c, i, j]] += source[(b * oh + oi) * ow + oj];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn to_array4<F: Real>(input: &ArrayD<F>, layer: &str) -> Array4<F> {
+    assert_eq!(input.ndim(), 4, "{} expects (N, C, H, W) inputs, got shape {:?}", layer, input.shape());
+    input.view().into_dimensionality::<Ix4>().unwrap().to_owned()
+}
+
+/// 2-D convolution with stride, zero padding, dilation and grouped channels.
+///
+/// The kernels have shape (out_channels, in_channels / groups, kh, kw); with `groups`
+/// set to g, input and output channels are split into g blocks and block i of the output
+/// only sees block i of the input.
+pub struct Conv2d<F: Real> {
+    weights: Parameter<F>,
+    bias: Parameter<F>,
+    in_channels: usize,
+    out_channels: usize,
+    groups: usize,
+    window: Window,
+    // the input shape and one im2col matrix per group from the last forward pass
+    cache: Option<(IxDyn, Vec<Array2<F>>)>,
+}
+
+impl<F: Real> Conv2d<F> {
+    pub fn new<R: Rng>(in_channels: usize, out_channels: usize, kernel_size: (usize, usize), initializer: Initializer<F>, rng: &mut R) -> Conv2d<F> {
+        Self::grouped(in_channels, out_channels, kernel_size, 1, initializer, rng)
+    }
+
+    /// A convolution whose channels are split into `groups` independent blocks.
+    /// Setting `groups` to `in_channels` gives a depthwise convolution.
+    pub fn grouped<R: Rng>(in_channels: usize, out_channels: usize, kernel_size: (usize, usize), groups: usize, initializer: Initializer<F>, rng: &mut R) -> Conv2d<F> {
+        assert!(groups > 0 && in_channels % groups == 0 && out_channels % groups == 0,
+                "in_channels and out_channels must both be divisible by groups");
+        assert!(kernel_size.0 > 0 && kernel_size.1 > 0, "kernel size must be positive");
+        let (kh, kw) = kernel_size;
+        let shape = (out_channels, in_channels / groups, kh, kw);
+        let fans = Fans::new(in_channels / groups * kh * kw, out_channels / groups * kh * kw);
+        let weights = initializer.initialize(shape, fans, rng);
+        Conv2d {
+            weights: Parameter::new("kernels", ParameterKind::Weight, weights.into_dyn()),
+            bias: Parameter::new("bias", ParameterKind::Bias, ArrayD::zeros(IxDyn(&[out_channels]))),
+            in_channels,
+            out_channels,
+            groups,
+            window: Window { kernel: kernel_size, stride: (1, 1), padding: (0, 0), dilation: (1, 1) },
+            cache: None,
+        }
+    }
+
+    pub fn with_stride(mut self, stride: (usize, usize)) -> Conv2d<F> {
+        assert!(stride.0 > 0 && stride.1 > 0, "stride must be positive");
+        self.window.stride = stride;
+        self
+    }
+
+    /// Zero padding added to each side of the input.
+    pub fn with_padding(mut self, padding: (usize, usize)) -> Conv2d<F> {
+        self.window.padding = padding;
+        self
+    }
+
+    /// Spacing between kernel taps; a dilation of 2 spreads a 3x3 kernel over 5x5 pixels.
+    pub fn with_dilation(mut self, dilation: (usize, usize)) -> Conv2d<F> {
+        assert!(dilation.0 > 0 && dilation.1 > 0, "dilation must be positive");
+        self.window.dilation = dilation;
+        self
+    }
+
+    /// The (H, W) this layer produces for an (H, W) input.
+    pub fn output_size(&self, height: usize, width: usize) -> (usize, usize) {
+        self.window.output_size(height, width)
+    }
+
+    // the kernels of one group flattened to (out_channels / groups, C_g * kh * kw)
+    fn group_kernels(&self, group: usize) -> Array2<F> {
+        let per_group = self.out_channels / self.groups;
+        let kernels = self.weights.value.view().into_dimensionality::<Ix4>().unwrap();
+        let block = kernels.slice(s![group * per_group..(group + 1) * per_group, .., .., ..]);
+        let cols = block.len() / per_group;
+        block.as_standard_layout().into_owned().into_shape((per_group, cols)).unwrap()
+    }
+}
+
+impl<F: Real> Layer<F> for Conv2d<F> {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let x = to_array4(input, "Conv2d");
+        let (n, c, height, width) = x.dim();
+        assert_eq!(c, self.in_channels, "expected {} input channels, got {}", self.in_channels, c);
+        let (oh, ow) = self.window.output_size(height, width);
+        let in_per_group = self.in_channels / self.groups;
+        let out_per_group = self.out_channels / self.groups;
+        let bias = self.bias.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+
+        let mut output = Array4::zeros((n, self.out_channels, oh, ow));
+        let mut columns = Vec::with_capacity(self.groups);
+        for g in 0..self.groups {
+            let cols = im2col(x.view(), g * in_per_group..(g + 1) * in_per_group, &self.window);
+            let product = self.group_kernels(g).dot(&cols); // (out_per_group, N * OH * OW)
+            let product = product.into_shape((out_per_group, n, oh, ow)).unwrap().permuted_axes([1, 0, 2, 3]);
+            output.slice_mut(s![.., g * out_per_group..(g + 1) * out_per_group, .., ..]).assign(&product);
+            columns.push(cols);
+        }
+        for (o, mut channel) in output.axis_iter_mut(Axis(1)).enumerate() {
+            channel += bias[o];
+        }
+
+        self.cache = Some((input.raw_dim(), columns));
+        output.into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let (input_shape, columns) = self.cache.take().expect("backward called before forward");
+        let g_out = grad_output.view().into_dimensionality::<Ix4>().unwrap();
+        let (n, _, oh, ow) = g_out.dim();
+        let in_per_group = self.in_channels / self.groups;
+        let out_per_group = self.out_channels / self.groups;
+
+        self.bias.gradient += &g_out.sum_axis(Axis(3)).sum_axis(Axis(2)).sum_axis(Axis(0)).into_dyn();
+
+        let mut grad_input = ArrayD::zeros(input_shape.clone()).into_dimensionality::<Ix4>().unwrap();
+        let mut grad_kernels = ArrayD::zeros(self.weights.value.raw_dim());
+        for (g, cols) in columns.iter().enumerate() {
+            // back to (out_per_group, N * OH * OW), the layout of the forward product
+            let block = g_out.slice(s![.., g * out_per_group..(g + 1) * out_per_group, .., ..]);
+            let block = block.permuted_axes([1, 0, 2, 3]).as_standard_layout().into_owned()
+                .into_shape((out_per_group, n * oh * ow)).unwrap();
+
+            let d_kernels = block.dot(&cols.t());
+            let kernel_shape = (out_per_group, in_per_group, self.window.kernel.0, self.window.kernel.1);
+            grad_kernels.slice_mut(s![g * out_per_group..(g + 1) * out_per_group, .., .., ..])
+                .assign(&d_kernels.into_shape(kernel_shape).unwrap());
+
+            let d_columns = self.group_kernels(g).t().dot(&block);
+            col2im(&d_columns, &mut grad_input, g * in_per_group..(g + 1) * in_per_group, &self.window);
+        }
+        self.weights.gradient += &grad_kernels;
+
+        self.cache = Some((input_shape, columns));
+        grad_input.into_dyn()
+    }
+
+    fn name(&self) -> String {
+        let w = &self.window;
+        format!("Conv2d({} -> {}, kernel {:?}, stride {:?}, padding {:?}, dilation {:?}, groups {})",
+                self.in_channels, self.out_channels, w.kernel, w.stride, w.padding, w.dilation, self.groups)
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.weights, &self.bias]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.weights, &mut self.bias]
+    }
+}
+
+/// Max pooling over (N, C, H, W) inputs. Gradients flow only to the largest input of
+/// each window (the first one, on ties).
+pub struct MaxPool2d {
+    window: Window,
+    // input shape and, for each output, the flat (h * W + w) index of its maximum
+    cache: Option<(IxDyn, Array4<usize>)>,
+}
+
+impl MaxPool2d {
+    /// A pool with the stride equal to the kernel, i.e. non-overlapping windows.
+    pub fn new(kernel_size: (usize, usize)) -> MaxPool2d {
+        assert!(kernel_size.0 > 0 && kernel_size.1 > 0, "kernel size must be positive");
+        MaxPool2d {
+            window: Window { kernel: kernel_size, stride: kernel_size, padding: (0, 0), dilation: (1, 1) },
+            cache: None,
+        }
+    }
+
+    pub fn with_stride(mut self, stride: (usize, usize)) -> MaxPool2d {
+        assert!(stride.0 > 0 && stride.1 > 0, "stride must be positive");
+        self.window.stride = stride;
+        self
+    }
+
+    /// Padding that is never selected as a maximum. At most half the kernel, so every
+    /// window still covers some of the input.
+    pub fn with_padding(mut self, padding: (usize, usize)) -> MaxPool2d {
+        assert!(padding.0 <= self.window.kernel.0 / 2 && padding.1 <= self.window.kernel.1 / 2,
+                "padding must be at most half the kernel size");
+        self.window.padding = padding;
+        self
+    }
+}
+
+impl<F: Real> Layer<F> for MaxPool2d {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let x = to_array4(input, "MaxPool2d");
+        let (n, c, height, width) = x.dim();
+        let (oh, ow) = self.window.output_size(height, width);
+        let mut output = Array4::zeros((n, c, oh, ow));
+        let mut argmax = Array4::zeros((n, c, oh, ow));
+        for ((b, ch, oi, oj), out) in output.indexed_iter_mut() {
+            // starts from the first pixel in the window, so a window of NaNs still
+            // sends its gradient somewhere inside it
+            let mut best: Option<(F, usize)> = None;
+            for ki in 0..self.window.kernel.0 {
+                for kj in 0..self.window.kernel.1 {
+                    if let Some((i, j)) = self.window.source(oi, oj, ki, kj, height, width) {
+                        let v = x[[b, ch, i, j]];
+                        if best.is_none_or(|(best, _)| v > best) {
+                            best = Some((v, i * width + j));
+                        }
+                    }
+                }
+            }
+            let (value, index) = best.expect("the padding leaves every window some of the input");
+            *out = value;
+            argmax[[b, ch, oi, oj]] = index;
+        }
+        self.cache = Some((input.raw_dim(), argmax));
+        output.into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let (input_shape, argmax) = self.cache.as_ref().expect("backward called before forward");
+        let width = input_shape[3];
+        let mut grad_input = ArrayD::zeros(input_shape.clone());
+        let g = grad_output.view().into_dimensionality::<Ix4>().unwrap();
+        for ((b, ch, oi, oj), &index) in argmax.indexed_iter() {
+            grad_input[[b, ch, index / width, index % width]] += g[[b, ch, oi, oj]];
+        }
+        grad_input
+    }
+
+    fn name(&self) -> String {
+        format!("MaxPool2d(kernel {:?}, stride {:?})", self.window.kernel, self.window.stride)
+    }
+}
+
+/// Average pooling over (N, C, H, W) inputs. Padded positions count as zeros, so every
+/// window is divided by the full kernel area.
+pub struct AvgPool2d {
+    window: Window,
+    input_shape: Option<IxDyn>,
+}
+
+impl AvgPool2d {
+    /// A pool with the stride equal to the kernel, i.e. non-overlapping windows.
+    pub fn new(kernel_size: (usize, usize)) -> AvgPool2d {
+        assert!(kernel_size.0 > 0 && kernel_size.1 > 0, "kernel size must be positive");
+        AvgPool2d {
+            window: Window { kernel: kernel_size, stride: kernel_size, padding: (0, 0), dilation: (1, 1) },
+            input_shape: None,
+        }
+    }
+
+    pub fn with_stride(mut self, stride: (usize, usize)) -> AvgPool2d {
+        assert!(stride.0 > 0 && stride.1 > 0, "stride must be positive");
+        self.window.stride = stride;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: (usize, usize)) -> AvgPool2d {
+        self.window.padding = padding;
+        self
+    }
+
+    fn area<F: Real>(&self) -> F {
+        cast((self.window.kernel.0 * self.window.kernel.1) as f64)
+    }
+}
+
+impl<F: Real> Layer<F> for AvgPool2d {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let x = to_array4(input, "AvgPool2d");
+        let (n, c, height, width) = x.dim();
+        let (oh, ow) = self.window.output_size(height, width);
+        let area: F = self.area();
+        let mut output = Array4::zeros((n, c, oh, ow));
+        for ((b, ch, oi, oj), out) in output.indexed_iter_mut() {
+            let mut total = F::zero();
+            for ki in 0..self.window.kernel.0 {
+                for kj in 0..self.window.kernel.1 {
+                    if let Some((i, j)) = self.window.source(oi, oj, ki, kj, height, width) {
+                        total += x[[b, ch, i, j]];
+                    }
+                }
+            }
+            *out = total / area;
+        }
+        self.input_shape = Some(input.raw_dim());
+        output.into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let input_shape = self.input_shape.clone().expect("backward called before forward");
+        let (height, width) = (input_shape[2], input_shape[3]);
+        let area: F = self.area();
+        let mut grad_input = ArrayD::zeros(input_shape);
+        let g = grad_output.view().into_dimensionality::<Ix4>().unwrap();
+        for ((b, ch, oi, oj), &grad) in g.indexed_iter() {
+            for ki in 0..self.window.kernel.0 {
+                for kj in 0..self.window.kernel.1 {
+                    if let Some((i, j)) = self.window.source(oi, oj, ki, kj, height, width) {
+                        grad_input[[b, ch, i, j]] += grad / area;
+                    }
+                }
+            }
+        }
+        grad_input
+    }
+
+    fn name(&self) -> String {
+        format!("AvgPool2d(kernel {:?}, stride {:?})", self.window.kernel, self.window.stride)
+    }
+}
+
+/// Averages each channel over its whole spatial extent: (N, C, H, W) to (N, C).
+#[derive(Default)]
+pub struct GlobalAvgPool2d {
+    input_shape: Option<IxDyn>,
+}
+
+impl GlobalAvgPool2d {
+    pub fn new() -> GlobalAvgPool2d {
+        GlobalAvgPool2d { input_shape: None }
+    }
+}
+
+impl<F: Real> Layer<F> for GlobalAvgPool2d {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let x = to_array4(input, "GlobalAvgPool2d");
+        self.input_shape = Some(input.raw_dim());
+        let area = cast::<F>((x.dim().2 * x.dim().3).max(1) as f64);
+        (x.sum_axis(Axis(3)).sum_axis(Axis(2)) / area).into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let input_shape = self.input_shape.clone().expect("backward called before forward");
+        let area = cast::<F>((input_shape[2] * input_shape[3]).max(1) as f64);
+        let g = grad_output.view().into_dimensionality::<ndarray::Ix2>().unwrap();
+        let spread = g.insert_axis(Axis(2)).insert_axis(Axis(3));
+        (spread.broadcast(input_shape.clone()).unwrap().to_owned() / area).into_dyn()
+    }
+
+    fn name(&self) -> String {
+        "GlobalAvgPool2d".to_string()
+    }
+}
+
+/// Takes the maximum of each channel over its whole spatial extent: (N, C, H, W) to (N, C).
+#[derive(Default)]
+pub struct GlobalMaxPool2d {
+    // input shape and the flat (h * W + w) index of each channel's maximum
+    cache: Option<(IxDyn, Array2<usize>)>,
+}
+
+impl GlobalMaxPool2d {
+    pub fn new() -> GlobalMaxPool2d {
+        GlobalMaxPool2d { cache: None }
+    }
+}
+
+impl<F: Real> Layer<F> for GlobalMaxPool2d {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let x = to_array4(input, "GlobalMaxPool2d");
+        let (n, c, _, width) = x.dim();
+        let mut output = Array2::zeros((n, c));
+        let mut argmax = Array2::zeros((n, c));
+        for b in 0..n {
+            for ch in 0..c {
+                let (index, value) = x.slice(s![b, ch, .., ..]).indexed_iter()
+                    .fold(((0, 0), F::neg_infinity()), |best, ((i, j), &v)| if v > best.1 { ((i, j), v) } else { best });
+                output[[b, ch]] = value;
+                argmax[[b, ch]] = index.0 * width + index.1;
+            }
+        }
+        self.cache = Some((input.raw_dim(), argmax));
+        output.into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let (input_shape, argmax) = self.cache.as_ref().expect("backward called before forward");
+        let width = input_shape[3];
+        let mut grad_input = ArrayD::zeros(input_shape.clone());
+        let g = grad_output.view().into_dimensionality::<ndarray::Ix2>().unwrap();
+        for ((b, ch), &index) in argmax.indexed_iter() {
+            grad_input[[b, ch, index / width, index % width]] += g[[b, ch]];
+        }
+        grad_input
+    }
+
+    fn name(&self) -> String {
+        "GlobalMaxPool2d".to_string()
+    }
+}
@@ -0,0 +1,170 @@
+use ndarray::{Array1, Array2, ArrayView, Axis, Ix1, Ix2, Zip};
+use crate::loss::{BceWithLogitsLoss, CrossEntropyLoss, Loss};
+use crate::precision::Real;
+
+// Given some logit output from a neural network, we can calculate
+// the cross entropy loss.
+//
+// Everything here works on raw logits and subtracts the largest logit before
+// exponentiating. softmax(x) == softmax(x - c) for any constant c, and shifting by the
+// max means the largest exponent is e^0 = 1, so nothing can overflow to inf/NaN.
+
+/// Numerically stable softmax of a single vector of logits.
+pub fn softmax<F: Real>(x: ArrayView<F, Ix1>) -> Array1<F> {
+    let max = max_of(x);
+    let mut exps = x.mapv(|v| (v - max).exp()); // e ^ (logit - max)
+    let sum: F = exps.iter().copied().sum(); // at least 1, since the max maps to e^0
+    exps /= sum;
+    exps
+}
+
+/// Numerically stable log(softmax(x)), computed as x - logsumexp(x).
+/// Prefer this over softmax(x).ln(), which gives -inf once a probability underflows.
+pub fn log_softmax<F: Real>(x: ArrayView<F, Ix1>) -> Array1<F> {
+    let lse = logsumexp(x);
+    x.mapv(|v| v - lse)
+}
+
+/// log(sum(exp(x))) without overflowing.
+pub fn logsumexp<F: Real>(x: ArrayView<F, Ix1>) -> F {
+    let max = max_of(x);
+    if max.is_infinite() {
+        return max;
+    }
+    let sum: F = x.iter().map(|&v| (v - max).exp()).sum();
+    max + sum.ln()
+}
+
+/// Row-wise softmax, where each row holds the logits of one sample.
+pub fn softmax_rows<F: Real>(x: ArrayView<F, Ix2>) -> Array2<F> {
+    let mut out = x.to_owned();
+    for mut row in out.outer_iter_mut() {
+        let softmaxed = softmax(row.view());
+        row.assign(&softmaxed);
+    }
+    out
+}
+
+/// Row-wise log-softmax, where each row holds the logits of one sample.
+pub fn log_softmax_rows<F: Real>(x: ArrayView<F, Ix2>) -> Array2<F> {
+    let mut out = x.to_owned();
+    for mut row in out.outer_iter_mut() {
+        let lse = logsumexp(row.view());
+        row.mapv_inplace(|v| v - lse);
+    }
+    out
+}
+
+/// Backpropagates through softmax: given s = softmax(x) and dL/ds, returns dL/dx.
+/// The Jacobian is diag(s) - s s^T, so this is s * (g - <g, s>).
+pub fn softmax_backward<F: Real>(softmax_output: ArrayView<F, Ix1>, grad_output: ArrayView<F, Ix1>) -> Array1<F> {
+    let dot = softmax_output.dot(&grad_output);
+    Zip::from(&softmax_output).and(&grad_output).map_collect(|&s, &g| s * (g - dot))
+}
+
+/// Backpropagates through log-softmax: given x and dL/d(log_softmax(x)), returns dL/dx.
+/// The Jacobian is I - 1 softmax(x)^T, so this is g - softmax(x) * sum(g).
+pub fn log_softmax_backward<F: Real>(x: ArrayView<F, Ix1>, grad_output: ArrayView<F, Ix1>) -> Array1<F> {
+    let total = grad_output.sum();
+    let probabilities = softmax(x);
+    Zip::from(&grad_output).and(&probabilities).map_collect(|&g, &p| g - p * total)
+}
+
+/// Knobs for `cross_entropy`. The defaults give plain, unweighted cross-entropy.
+#[derive(Debug, Clone)]
+pub struct CrossEntropyOptions<F: Real> {
+    /// Moves this much probability mass off the true class and spreads it evenly over
+    /// all K classes, i.e. the target becomes (1 - eps) * one_hot + eps / K.
+    pub label_smoothing: F,
+    /// Per-class weights (length K). The loss of each sample is scaled by the weight of
+    /// its true class, and the mean is taken over the summed weights.
+    pub class_weights: Option<Array1<F>>,
+}
+
+impl<F: Real> Default for CrossEntropyOptions<F> {
+    fn default() -> Self {
+        CrossEntropyOptions {
+            label_smoothing: F::zero(),
+            class_weights: None,
+        }
+    }
+}
+
+/// Mean cross-entropy between row-wise logits (N x K) and the true class of each row.
+pub fn cross_entropy<F: Real>(logits: ArrayView<F, Ix2>, targets: &[usize], options: &CrossEntropyOptions<F>) -> F {
+    cross_entropy_with_grad(logits, targets, options).0
+}
+
+/// Gradient of `cross_entropy` with respect to the logits.
+pub fn cross_entropy_grad<F: Real>(logits: ArrayView<F, Ix2>, targets: &[usize], options: &CrossEntropyOptions<F>) -> Array2<F> {
+    cross_entropy_with_grad(logits, targets, options).1
+}
+
+/// Computes the mean cross-entropy and its gradient with respect to the logits in one pass,
+/// as `CrossEntropyLoss` on one-hot targets.
+///
+/// With q the (smoothed) target distribution and p = softmax(logits), each sample
+/// contributes w_y * -sum_k q_k log p_k, and its gradient is w_y * (p - q).
+/// Both are divided by the summed weights (N when unweighted).
+pub fn cross_entropy_with_grad<F: Real>(logits: ArrayView<F, Ix2>, targets: &[usize], options: &CrossEntropyOptions<F>) -> (F, Array2<F>) {
+    let (n, k) = logits.dim();
+    assert_eq!(n, targets.len(), "expected one target per row of logits");
+    let mut one_hot = Array2::zeros((n, k));
+    for (i, &target) in targets.iter().enumerate() {
+        assert!(target < k, "target class {} is out of range for {} classes", target, k);
+        one_hot[[i, target]] = F::one();
+    }
+    let output = CrossEntropyLoss::with_options(options.clone()).compute(logits, one_hot.view());
+    (output.scalar(), output.gradient)
+}
+
+/// Mean binary cross-entropy on raw logits, with targets in [0, 1].
+///
+/// Uses the stable form max(x, 0) - x * y + log(1 + e^-|x|), so large logits of
+/// either sign never go through sigmoid. `pos_weight` scales the loss of positive
+/// targets, which helps with the heavy imbalance of one-vs-rest labels.
+pub fn binary_cross_entropy_with_logits<F: Real>(logits: ArrayView<F, Ix1>, targets: ArrayView<F, Ix1>, pos_weight: Option<F>) -> F {
+    binary_cross_entropy_with_logits_and_grad(logits, targets, pos_weight).0
+}
+
+/// Gradient of `binary_cross_entropy_with_logits` with respect to the logits.
+pub fn binary_cross_entropy_with_logits_grad<F: Real>(logits: ArrayView<F, Ix1>, targets: ArrayView<F, Ix1>, pos_weight: Option<F>) -> Array1<F> {
+    binary_cross_entropy_with_logits_and_grad(logits, targets, pos_weight).1
+}
+
+/// Computes the mean binary cross-entropy on logits and its gradient in one pass, as
+/// `BceWithLogitsLoss` on a batch of single-output samples.
+pub fn binary_cross_entropy_with_logits_and_grad<F: Real>(logits: ArrayView<F, Ix1>, targets: ArrayView<F, Ix1>, pos_weight: Option<F>) -> (F, Array1<F>) {
+    assert_eq!(logits.len(), targets.len(), "expected one target per logit");
+    let output = BceWithLogitsLoss::new(pos_weight).compute(logits.insert_axis(Axis(1)), targets.insert_axis(Axis(1)));
+    (output.scalar(), output.gradient.remove_axis(Axis(1)))
+}
+
+/// Class with the highest logit in each row.
+pub fn argmax_rows<F: Real>(x: ArrayView<F, Ix2>) -> Vec<usize> {
+    x.axis_iter(Axis(0))
+        .map(|row| {
+            row.iter().enumerate()
+                .fold((0, F::neg_infinity()), |(best, best_value), (i, &v)| if v > best_value { (i, v) } else { (best, best_value) })
+                .0
+        })
+        .collect()
+}
+
+fn max_of<F: Real>(x: ArrayView<F, Ix1>) -> F {
+    x.iter().fold(F::neg_infinity(), |acc, &v| acc.max(v))
+}
+
+// log(1 + e^x) without overflow for large x or precision loss for very negative x
+pub(crate) fn softplus<F: Real>(x: F) -> F {
+    x.max(F::zero()) + (-x.abs()).exp().ln_1p()
+}
+
+pub(crate) fn sigmoid<F: Real>(x: F) -> F {
+    if x >= F::zero() {
+        F::one() / (F::one() + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (F::one() + e)
+    }
+}
@@ -0,0 +1,207 @@
+use ndarray::{Array2, ArrayD, ArrayView1, ArrayView2, ArrayViewMut1, ArrayViewMut2, Axis, Ix2, IxDyn};
+use rand::Rng;
+use crate::init::{Fans, Initializer};
+use crate::layer::{Layer, Parameter, ParameterKind};
+use crate::precision::Real;
+
+/// A lookup table from token ids to dense vectors.
+///
+/// The input is an array of ids of any shape (stored as floats, like every layer input),
+/// and the output appends an axis of `dim` features: (N, T) ids become (N, T, dim).
+/// Only the rows that were looked up receive gradient, and `sparse_sgd_step` updates just
+/// those rows, so a step costs O(batch * dim) rather than O(vocabulary * dim).
+pub struct Embedding<F: Real> {
+    weights: Parameter<F>,
+    padding_idx: Option<usize>,
+    max_norm: Option<F>,
+    frozen: bool,
+    ids: Vec<usize>,
+    input_shape: Option<IxDyn>,
+    // rows with gradient since the last sparse step, sorted and deduplicated on use
+    touched: Vec<usize>,
+}
+
+impl<F: Real> Embedding<F> {
+    pub fn new<R: Rng>(vocabulary: usize, dim: usize, initializer: Initializer<F>, rng: &mut R) -> Embedding<F> {
+        let weights = initializer.initialize((vocabulary, dim), Fans::new(1, dim), rng);
+        Self::from_pretrained(weights)
+    }
+
+    /// An embedding whose vectors are the rows of `vectors`.
+    pub fn from_pretrained(vectors: Array2<F>) -> Embedding<F> {
+        Embedding {
+            weights: Parameter::new("embeddings", ParameterKind::Weight, vectors.into_dyn()),
+            padding_idx: None,
+            max_norm: None,
+            frozen: false,
+            ids: vec![],
+            input_shape: None,
+            touched: vec![],
+        }
+    }
+
+    /// Reserves `index` for padding: its vector is zero and never trained.
+    pub fn with_padding_idx(mut self, index: usize) -> Embedding<F> {
+        assert!(index < self.vocabulary(), "padding index {} is out of range", index);
+        self.weights.value.index_axis_mut(Axis(0), index).fill(F::zero());
+        self.padding_idx = Some(index);
+        self
+    }
+
+    /// Rescales every looked-up vector whose L2 norm exceeds `max_norm` back onto that
+    /// norm, in place, before it is used.
+    pub fn with_max_norm(mut self, max_norm: F) -> Embedding<F> {
+        self.max_norm = Some(max_norm);
+        self
+    }
+
+    /// A frozen embedding has no trainable parameters, e.g. for fixed pretrained vectors.
+    pub fn with_frozen(mut self, frozen: bool) -> Embedding<F> {
+        self.frozen = frozen;
+        self
+    }
+
+    /// Overwrites the vectors of the given ids, e.g. with ones read from a pretrained
+    /// file, leaving the rest as they were. Returns how many rows were loaded.
+    pub fn load_pretrained<'a, I>(&mut self, rows: I) -> usize
+    where
+        I: IntoIterator<Item = (usize, ArrayView1<'a, F>)>,
+    {
+        let mut loaded = 0;
+        for (id, vector) in rows {
+            assert_eq!(vector.len(), self.dim(), "pretrained vector for id {} has {} dimensions, expected {}", id, vector.len(), self.dim());
+            if Some(id) != self.padding_idx {
+                self.vector_mut(id).assign(&vector);
+                loaded += 1;
+            }
+        }
+        loaded
+    }
+
+    pub fn vocabulary(&self) -> usize {
+        self.weights.value.shape()[0]
+    }
+
+    pub fn dim(&self) -> usize {
+        self.weights.value.shape()[1]
+    }
+
+    pub fn padding_idx(&self) -> Option<usize> {
+        self.padding_idx
+    }
+
+    /// The whole table, one row per id.
+    pub fn vectors(&self) -> ArrayView2<'_, F> {
+        self.weights.value.view().into_dimensionality::<Ix2>().unwrap()
+    }
+
+    pub fn vectors_mut(&mut self) -> ArrayViewMut2<'_, F> {
+        self.weights.value.view_mut().into_dimensionality::<Ix2>().unwrap()
+    }
+
+    pub fn vector(&self, id: usize) -> ArrayView1<'_, F> {
+        self.vectors().index_axis_move(Axis(0), id)
+    }
+
+    pub fn vector_mut(&mut self, id: usize) -> ArrayViewMut1<'_, F> {
+        self.weights.value.view_mut().into_dimensionality::<Ix2>().unwrap().index_axis_move(Axis(0), id)
+    }
+
+    /// Moves one row by -learning_rate * gradient, skipping the padding row. This is the
+    /// update word2vec-style trainers use, one (word, gradient) pair at a time.
+    pub fn update_row(&mut self, id: usize, gradient: ArrayView1<F>, learning_rate: F) {
+        if Some(id) != self.padding_idx && !self.frozen {
+            self.vector_mut(id).scaled_add(-learning_rate, &gradient);
+        }
+    }
+
+    /// The ids whose rows have received gradient since the last `sparse_sgd_step`.
+    pub fn touched_rows(&mut self) -> &[usize] {
+        self.touched.sort_unstable();
+        self.touched.dedup();
+        &self.touched
+    }
+
+    /// Gradient descent on the touched rows only; their gradients are then zeroed so the
+    /// next batch can accumulate without a full `zero_grad`.
+    pub fn sparse_sgd_step(&mut self, learning_rate: F) {
+        self.touched_rows();
+        let touched = std::mem::take(&mut self.touched);
+        let mut values = self.weights.value.view_mut().into_dimensionality::<Ix2>().unwrap();
+        let mut gradients = self.weights.gradient.view_mut().into_dimensionality::<Ix2>().unwrap();
+        for id in touched {
+            let mut gradient = gradients.index_axis_mut(Axis(0), id);
+            values.index_axis_mut(Axis(0), id).scaled_add(-learning_rate, &gradient);
+            gradient.fill(F::zero());
+        }
+    }
+
+    fn renormalize(&mut self, id: usize, limit: F) {
+        let mut row = self.vector_mut(id);
+        let norm = row.iter().map(|&w| w * w).sum::<F>().sqrt();
+        if norm > limit {
+            let scale = limit / norm;
+            row.mapv_inplace(|w| w * scale);
+        }
+    }
+}
+
+impl<F: Real> Layer<F> for Embedding<F> {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let vocabulary = self.vocabulary();
+        self.ids = input.iter()
+            .map(|&v| {
+                let id = v.to_usize().filter(|_| v.fract() == F::zero()).expect("token ids must be non-negative integers");
+                assert!(id < vocabulary, "token id {} is out of range for a vocabulary of {}", id, vocabulary);
+                id
+            })
+            .collect();
+        if let Some(limit) = self.max_norm {
+            for k in 0..self.ids.len() {
+                self.renormalize(self.ids[k], limit);
+            }
+        }
+
+        let mut output = Array2::zeros((self.ids.len(), self.dim()));
+        for (mut row, &id) in output.outer_iter_mut().zip(&self.ids) {
+            row.assign(&self.vector(id));
+        }
+        let mut shape = input.shape().to_vec();
+        shape.push(self.dim());
+        self.input_shape = Some(input.raw_dim());
+        output.into_dyn().into_shape(IxDyn(&shape)).unwrap()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let input_shape = self.input_shape.clone().expect("backward called before forward");
+        if !self.frozen {
+            let grad = grad_output.as_standard_layout().into_owned().into_shape((self.ids.len(), self.dim())).unwrap();
+            let mut gradients = self.weights.gradient.view_mut().into_dimensionality::<Ix2>().unwrap();
+            for (row, &id) in grad.outer_iter().zip(&self.ids) {
+                if Some(id) != self.padding_idx {
+                    gradients.index_axis_mut(Axis(0), id).scaled_add(F::one(), &row);
+                    self.touched.push(id);
+                }
+            }
+        }
+        // ids aren't differentiable
+        ArrayD::zeros(input_shape)
+    }
+
+    fn name(&self) -> String {
+        format!("Embedding({} x {})", self.vocabulary(), self.dim())
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        if self.frozen { vec![] } else { vec![&self.weights] }
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        if self.frozen { vec![] } else { vec![&mut self.weights] }
+    }
+
+    fn zero_grad(&mut self) {
+        self.weights.zero_grad();
+        self.touched.clear();
+    }
+}
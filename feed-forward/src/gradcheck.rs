@@ -0,0 +1,175 @@
+use ndarray::{Array, ArrayD, Dimension, IntoDimension};
+use crate::layer::Layer;
+use crate::precision::{cast, Real};
+
+// Backprop bugs rarely crash, they just train badly. The cheapest way to catch them is to
+// nudge every input by +-epsilon, measure how the output moves (central differences), and
+// compare that against the analytic gradient.
+
+/// Tolerances for a gradient check.
+#[derive(Debug, Clone, Copy)]
+pub struct GradCheckConfig<F: Real> {
+    /// Step used for the central differences.
+    pub epsilon: F,
+    /// An entry passes if its relative error is at most this...
+    pub relative_tolerance: F,
+    /// ...or if its absolute error is at most this, which covers gradients near 0
+    /// where the relative error is mostly noise.
+    pub absolute_tolerance: F,
+}
+
+impl<F: Real> Default for GradCheckConfig<F> {
+    /// Defaults suited to f64. f32 needs a larger epsilon and looser tolerances,
+    /// e.g. 1e-3 and 1e-2.
+    fn default() -> Self {
+        GradCheckConfig {
+            epsilon: cast(1e-6),
+            relative_tolerance: cast(1e-5),
+            absolute_tolerance: cast(1e-8),
+        }
+    }
+}
+
+/// How the analytic and numerical gradient compare for a single parameter.
+#[derive(Debug, Clone)]
+pub struct ParameterError<F: Real> {
+    /// Index of the parameter within its array.
+    pub index: Vec<usize>,
+    pub analytic: F,
+    pub numeric: F,
+    pub absolute_error: F,
+    /// |analytic - numeric| / max(|analytic|, |numeric|)
+    pub relative_error: F,
+    pub passed: bool,
+}
+
+/// The result of comparing a whole gradient.
+#[derive(Debug, Clone)]
+pub struct GradCheckReport<F: Real> {
+    pub errors: Vec<ParameterError<F>>,
+}
+
+impl<F: Real> GradCheckReport<F> {
+    pub fn passed(&self) -> bool {
+        self.errors.iter().all(|e| e.passed)
+    }
+
+    pub fn failures(&self) -> Vec<&ParameterError<F>> {
+        self.errors.iter().filter(|e| !e.passed).collect()
+    }
+
+    pub fn max_relative_error(&self) -> F {
+        self.errors.iter().fold(F::zero(), |acc, e| acc.max(e.relative_error))
+    }
+
+    /// The entry with the largest relative error, if there are any entries at all.
+    pub fn worst(&self) -> Option<&ParameterError<F>> {
+        self.errors.iter().fold(None, |worst: Option<&ParameterError<F>>, e| match worst {
+            Some(w) if w.relative_error >= e.relative_error => Some(w),
+            _ => Some(e),
+        })
+    }
+
+    /// Panics with a description of every failing entry. Meant for use in tests.
+    pub fn assert_passed(&self) {
+        let failures = self.failures();
+        if failures.is_empty() {
+            return;
+        }
+        let details = failures.iter()
+            .map(|e| format!("  {:?}: analytic {} vs numeric {} (relative error {})", e.index, e.analytic, e.numeric, e.relative_error))
+            .collect::<Vec<_>>()
+            .join("\n");
+        panic!("gradient check failed for {} of {} entries:\n{}", failures.len(), self.errors.len(), details);
+    }
+}
+
+/// Estimates the gradient of a scalar function with central differences,
+/// (f(x + eps) - f(x - eps)) / 2 eps, one entry at a time.
+pub fn numerical_gradient<F: Real, D: Dimension>(mut f: impl FnMut(&Array<F, D>) -> F, x: &Array<F, D>, epsilon: F) -> Array<F, D> {
+    // both arrays are in standard layout, so their flat slices line up index for index
+    let mut nudged = x.as_standard_layout().into_owned();
+    let mut gradient = Array::zeros(x.raw_dim());
+    let two_eps = epsilon + epsilon;
+    for i in 0..gradient.len() {
+        let original = nudged.as_slice().unwrap()[i];
+        nudged.as_slice_mut().unwrap()[i] = original + epsilon;
+        let plus = f(&nudged);
+        nudged.as_slice_mut().unwrap()[i] = original - epsilon;
+        let minus = f(&nudged);
+        nudged.as_slice_mut().unwrap()[i] = original;
+        gradient.as_slice_mut().unwrap()[i] = (plus - minus) / two_eps;
+    }
+    gradient
+}
+
+/// Compares an analytic gradient of `f` at `x` against central differences.
+pub fn check_gradient<F: Real, D: Dimension>(f: impl FnMut(&Array<F, D>) -> F, x: &Array<F, D>, analytic: &Array<F, D>, config: &GradCheckConfig<F>) -> GradCheckReport<F> {
+    assert_eq!(x.shape(), analytic.shape(), "the analytic gradient must have the same shape as the input");
+    let numeric = numerical_gradient(f, x, config.epsilon);
+    compare(analytic, &numeric, config)
+}
+
+/// Compares two gradients of the same shape entry by entry.
+pub fn compare<F: Real, D: Dimension>(analytic: &Array<F, D>, numeric: &Array<F, D>, config: &GradCheckConfig<F>) -> GradCheckReport<F> {
+    let errors = analytic.indexed_iter().zip(numeric.iter())
+        .map(|((index, &a), &n)| {
+            let absolute_error = (a - n).abs();
+            let scale = a.abs().max(n.abs());
+            let relative_error = if scale > F::zero() { absolute_error / scale } else { F::zero() };
+            ParameterError {
+                index: index.into_dimension().slice().to_vec(),
+                analytic: a,
+                numeric: n,
+                absolute_error,
+                relative_error,
+                passed: relative_error <= config.relative_tolerance || absolute_error <= config.absolute_tolerance,
+            }
+        })
+        .collect();
+    GradCheckReport { errors }
+}
+
+/// Checks a layer's `backward` against central differences, for both the gradient with
+/// respect to its input and the gradients of each of its parameters.
+///
+/// The layer's output is reduced to a scalar with a fixed, uneven set of weights, so that
+/// every output element gets a different upstream gradient. Entries in the report are
+/// indexed with a leading 0 for the input and p + 1 for the layer's p-th parameter.
+/// The layer runs in training mode, so layers with randomness (dropout) can't be checked.
+pub fn check_layer<F: Real, L: Layer<F> + ?Sized>(layer: &mut L, input: &ArrayD<F>, config: &GradCheckConfig<F>) -> GradCheckReport<F> {
+    let output = layer.forward(input, true);
+    let upstream = ArrayD::from_shape_fn(output.raw_dim(), |index| {
+        let flat: usize = index.slice().iter().enumerate().map(|(axis, &i)| (axis + 1) * 7 * i + i).sum();
+        cast::<F>(((flat % 11) as f64 - 5.0) / 5.0 + 0.05)
+    });
+    let objective = |out: &ArrayD<F>| (out * &upstream).sum();
+
+    layer.zero_grad();
+    let analytic_input = layer.backward(&upstream);
+    let analytic_parameters = layer.gradients().into_iter().cloned().collect::<Vec<_>>();
+
+    let mut errors = vec![];
+    let numeric_input = numerical_gradient(|x| objective(&layer.forward(x, true)), input, config.epsilon);
+    errors.extend(tagged(compare(&analytic_input, &numeric_input, config), 0));
+
+    for (p, analytic) in analytic_parameters.iter().enumerate() {
+        let original = layer.parameters()[p].value.clone();
+        let numeric = numerical_gradient(|value| {
+            layer.parameters_mut()[p].value.assign(value);
+            objective(&layer.forward(input, true))
+        }, &original, config.epsilon);
+        layer.parameters_mut()[p].value.assign(&original);
+        errors.extend(tagged(compare(analytic, &numeric, config), p + 1));
+    }
+    GradCheckReport { errors }
+}
+
+fn tagged<F: Real>(report: GradCheckReport<F>, tag: usize) -> Vec<ParameterError<F>> {
+    report.errors.into_iter()
+        .map(|mut e| {
+            e.index.insert(0, tag);
+            e
+        })
+        .collect()
+}
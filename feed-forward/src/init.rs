@@ -0,0 +1,121 @@
+use ndarray::{Array, Array2, Dimension, IntoDimension};
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Uniform};
+use crate::precision::{cast, Real};
+
+/// The number of inputs and outputs feeding a weight, which the variance-scaling
+/// initializers use to keep activations from exploding or vanishing layer to layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fans {
+    pub fan_in: usize,
+    pub fan_out: usize,
+}
+
+impl Fans {
+    pub fn new(fan_in: usize, fan_out: usize) -> Fans {
+        Fans { fan_in, fan_out }
+    }
+}
+
+/// How to fill a freshly created weight array.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Initializer<F: Real> {
+    Zeros,
+    Constant(F),
+    Uniform { low: F, high: F },
+    Normal { mean: F, std: F },
+    /// A normal distribution with any draw more than two standard deviations from
+    /// the mean thrown away and redrawn.
+    TruncatedNormal { mean: F, std: F },
+    /// Glorot & Bengio: U(-a, a) with a = sqrt(6 / (fan_in + fan_out)). Suits tanh/sigmoid.
+    XavierUniform,
+    /// Glorot & Bengio: N(0, 2 / (fan_in + fan_out)).
+    XavierNormal,
+    /// He et al.: U(-a, a) with a = sqrt(6 / fan_in). Suits ReLU.
+    HeUniform,
+    /// He et al.: N(0, 2 / fan_in).
+    HeNormal,
+    /// A (semi-)orthogonal matrix scaled by `gain`. Arrays with more than two axes are
+    /// treated as a matrix of shape[0] rows by everything else.
+    Orthogonal { gain: F },
+}
+
+impl<F: Real> Initializer<F> {
+    /// Creates an array of the given shape filled according to `self`.
+    pub fn initialize<Sh, R>(&self, shape: Sh, fans: Fans, rng: &mut R) -> Array<F, Sh::Dim>
+    where
+        Sh: IntoDimension,
+        R: Rng,
+    {
+        let dim = shape.into_dimension();
+        let fan_in = fans.fan_in.max(1) as f64;
+        let fan_out = fans.fan_out.max(1) as f64;
+        match *self {
+            Initializer::Zeros => Array::zeros(dim),
+            Initializer::Constant(value) => Array::from_elem(dim, value),
+            Initializer::Uniform { low, high } => sample_uniform(dim, low.to_f64().unwrap(), high.to_f64().unwrap(), rng),
+            Initializer::Normal { mean, std } => sample_normal(dim, mean.to_f64().unwrap(), std.to_f64().unwrap(), rng),
+            Initializer::TruncatedNormal { mean, std } => {
+                let (mean, std) = (mean.to_f64().unwrap(), std.to_f64().unwrap());
+                let normal = Normal::new(mean, std).expect("standard deviation must be finite and non-negative");
+                Array::from_shape_simple_fn(dim, || loop {
+                    let v = normal.sample(rng);
+                    if (v - mean).abs() <= 2.0 * std {
+                        return cast(v);
+                    }
+                })
+            }
+            Initializer::XavierUniform => {
+                let limit = (6.0 / (fan_in + fan_out)).sqrt();
+                sample_uniform(dim, -limit, limit, rng)
+            }
+            Initializer::XavierNormal => sample_normal(dim, 0.0, (2.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initializer::HeUniform => {
+                let limit = (6.0 / fan_in).sqrt();
+                sample_uniform(dim, -limit, limit, rng)
+            }
+            Initializer::HeNormal => sample_normal(dim, 0.0, (2.0 / fan_in).sqrt(), rng),
+            Initializer::Orthogonal { gain } => {
+                let rows = dim.slice().first().copied().unwrap_or(1);
+                let cols = dim.size() / rows.max(1);
+                let matrix = orthogonal(rows, cols, rng) * gain;
+                matrix.into_shape(dim).unwrap()
+            }
+        }
+    }
+}
+
+fn sample_uniform<D: Dimension, F: Real, R: Rng>(dim: D, low: f64, high: f64, rng: &mut R) -> Array<F, D> {
+    if low >= high {
+        return Array::from_elem(dim, cast(low));
+    }
+    let uniform = Uniform::new(low, high);
+    Array::from_shape_simple_fn(dim, || cast(uniform.sample(rng)))
+}
+
+fn sample_normal<D: Dimension, F: Real, R: Rng>(dim: D, mean: f64, std: f64, rng: &mut R) -> Array<F, D> {
+    let normal = Normal::new(mean, std).expect("standard deviation must be finite and non-negative");
+    Array::from_shape_simple_fn(dim, || cast(normal.sample(rng)))
+}
+
+// Orthonormalizes the shorter side of a Gaussian matrix with modified Gram-Schmidt,
+// so either the rows or the columns end up orthonormal
+fn orthogonal<F: Real, R: Rng>(rows: usize, cols: usize, rng: &mut R) -> Array2<F> {
+    let transpose = rows < cols;
+    let (n, k) = if transpose { (cols, rows) } else { (rows, cols) };
+    let mut q: Array2<f64> = sample_normal(ndarray::Ix2(n, k), 0.0, 1.0, rng);
+
+    for j in 0..k {
+        for i in 0..j {
+            let projection = q.column(i).dot(&q.column(j));
+            let basis = q.column(i).to_owned();
+            q.column_mut(j).scaled_add(-projection, &basis);
+        }
+        let norm = q.column(j).dot(&q.column(j)).sqrt();
+        // a Gaussian column is linearly dependent on the others with probability 0
+        q.column_mut(j).mapv_inplace(|v| v / norm);
+    }
+
+    let q = if transpose { q.reversed_axes().as_standard_layout().into_owned() } else { q };
+    q.mapv(cast)
+}
@@ -0,0 +1,268 @@
+use ndarray::{Array2, ArrayD};
+use crate::loss::Loss;
+use crate::precision::Real;
+use crate::regularization::Regularization;
+
+/// What role a parameter plays, which decides e.g. whether weight decay applies to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// Connection weights and convolution kernels.
+    Weight,
+    Bias,
+    /// The learned scale and shift of normalization layers.
+    Normalization,
+}
+
+/// A trainable array together with the gradient accumulated for it by `Layer::backward`.
+#[derive(Debug, Clone)]
+pub struct Parameter<F: Real> {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+    pub value: ArrayD<F>,
+    pub gradient: ArrayD<F>,
+}
+
+impl<F: Real> Parameter<F> {
+    pub fn new(name: &'static str, kind: ParameterKind, value: ArrayD<F>) -> Parameter<F> {
+        let gradient = ArrayD::zeros(value.raw_dim());
+        Parameter { name, kind, value, gradient }
+    }
+
+    pub fn zero_grad(&mut self) {
+        self.gradient.fill(F::zero());
+    }
+}
+
+/// A building block of a network.
+///
+/// Inputs and outputs are dynamically shaped arrays whose first axis is the batch, so
+/// dense (N, features), image (N, C, H, W) and sequence (N, T, features) layers can all
+/// be chained. Layers cache whatever `backward` needs during `forward`, so `backward`
+/// must follow the `forward` call it refers to.
+pub trait Layer<F: Real> {
+    /// Computes the layer's output. `training` switches on training-only behaviour such
+    /// as dropout masks and batch statistics.
+    fn forward(&mut self, input: &ArrayD<F>, training: bool) -> ArrayD<F>;
+
+    /// Given dL/d(output) for the last forward pass, adds dL/d(parameter) to each
+    /// parameter's gradient and returns dL/d(input).
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F>;
+
+    /// A short, human readable name for summaries.
+    fn name(&self) -> String;
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![]
+    }
+
+    /// The gradients accumulated so far, in the same order as `parameters`.
+    fn gradients(&self) -> Vec<&ArrayD<F>> {
+        self.parameters().into_iter().map(|p| &p.gradient).collect()
+    }
+
+    fn zero_grad(&mut self) {
+        for parameter in self.parameters_mut() {
+            parameter.zero_grad();
+        }
+    }
+}
+
+/// A stack of layers applied one after another.
+///
+/// ```ignore
+/// let mut rng = rng_from_seed(Some(0));
+/// let mut model = Sequential::new()
+///     .add(Dense::new(784, 128, Initializer::HeNormal, &mut rng))
+///     .add(ActivationLayer::new(Activation::Relu))
+///     .add(Dense::new(128, 10, Initializer::XavierUniform, &mut rng));
+/// ```
+#[derive(Default)]
+pub struct Sequential<F: Real> {
+    layers: Vec<Box<dyn Layer<F>>>,
+    regularization: Regularization<F>,
+    gradient_clip: Option<F>,
+}
+
+/// The loss on one training batch, split into the part that comes from the data and the
+/// part that comes from the regularization penalty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchLoss<F: Real> {
+    pub data_loss: F,
+    pub penalty: F,
+}
+
+impl<F: Real> BatchLoss<F> {
+    /// The quantity the optimizer actually minimizes.
+    pub fn total(&self) -> F {
+        self.data_loss + self.penalty
+    }
+}
+
+impl<F: Real> Sequential<F> {
+    pub fn new() -> Sequential<F> {
+        Sequential { layers: vec![], regularization: Regularization::none(), gradient_clip: None }
+    }
+
+    /// Sets the penalties and constraints `train_batch` applies.
+    pub fn with_regularization(mut self, regularization: Regularization<F>) -> Sequential<F> {
+        self.regularization = regularization;
+        self
+    }
+
+    pub fn regularization(&self) -> &Regularization<F> {
+        &self.regularization
+    }
+
+    /// Makes `train_batch` rescale the gradients to a global norm of at most `max_norm`
+    /// before each update, which keeps recurrent networks from taking exploding steps.
+    pub fn with_gradient_clipping(mut self, max_norm: F) -> Sequential<F> {
+        self.gradient_clip = Some(max_norm);
+        self
+    }
+
+    /// Appends a layer, builder style.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<L: Layer<F> + 'static>(mut self, layer: L) -> Sequential<F> {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn push(&mut self, layer: Box<dyn Layer<F>>) {
+        self.layers.push(layer);
+    }
+
+    pub fn layers(&self) -> &[Box<dyn Layer<F>>] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Box<dyn Layer<F>>] {
+        &mut self.layers
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Runs the network in inference mode.
+    pub fn predict(&mut self, input: &ArrayD<F>) -> ArrayD<F> {
+        self.forward(input, false)
+    }
+
+    /// Total number of trainable scalars.
+    pub fn num_parameters(&self) -> usize {
+        self.parameters().iter().map(|p| p.value.len()).sum()
+    }
+
+    /// One line per layer with its name and parameter count.
+    pub fn summary(&self) -> String {
+        let mut lines = self.layers.iter().enumerate()
+            .map(|(i, layer)| {
+                let count: usize = layer.parameters().iter().map(|p| p.value.len()).sum();
+                format!("{:>3}: {:<40} {:>10} parameters", i, layer.name(), count)
+            })
+            .collect::<Vec<_>>();
+        lines.push(format!("total: {} parameters", self.num_parameters()));
+        lines.join("\n")
+    }
+
+    /// Plain gradient descent: every parameter moves by -learning_rate * gradient.
+    pub fn sgd_step(&mut self, learning_rate: F) {
+        for parameter in self.parameters_mut() {
+            parameter.value.scaled_add(-learning_rate, &parameter.gradient);
+        }
+    }
+
+    /// Runs one step of training on a batch: forward, loss, backward and an SGD update,
+    /// with the model's regularization folded into the targets, gradients and weights
+    /// and the gradients clipped if `with_gradient_clipping` was set.
+    /// The network's output is flattened to (N, outputs) before it reaches the loss.
+    pub fn train_batch(&mut self, input: &ArrayD<F>, targets: &Array2<F>, loss: &dyn Loss<F>, learning_rate: F) -> BatchLoss<F> {
+        self.zero_grad();
+        let regularization = self.regularization;
+        let targets = regularization.smooth_targets(targets);
+
+        let output = self.forward(input, true);
+        let rows = output.shape()[0];
+        let flat = to_matrix(&output, rows, output.len() / rows.max(1));
+        let result = loss.compute(flat.view(), targets.view());
+        let data_loss = result.scalar();
+        let penalty = regularization.penalty(&self.parameters());
+
+        let grad_output = result.gradient.into_dyn().into_shape(output.raw_dim()).unwrap();
+        self.backward(&grad_output);
+        regularization.add_gradients(&mut self.parameters_mut());
+        if let Some(max_norm) = self.gradient_clip {
+            clip_grad_norm(&mut self.parameters_mut(), max_norm);
+        }
+        self.sgd_step(learning_rate);
+        regularization.apply_max_norm(&mut self.parameters_mut());
+        BatchLoss { data_loss, penalty }
+    }
+}
+
+impl<F: Real> Layer<F> for Sequential<F> {
+    fn forward(&mut self, input: &ArrayD<F>, training: bool) -> ArrayD<F> {
+        let mut activations = input.clone();
+        for layer in self.layers.iter_mut() {
+            activations = layer.forward(&activations, training);
+        }
+        activations
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let mut grad = grad_output.clone();
+        for layer in self.layers.iter_mut().rev() {
+            grad = layer.backward(&grad);
+        }
+        grad
+    }
+
+    fn name(&self) -> String {
+        format!("Sequential({} layers)", self.layers.len())
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        self.layers.iter().flat_map(|layer| layer.parameters()).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        self.layers.iter_mut().flat_map(|layer| layer.parameters_mut()).collect()
+    }
+}
+
+/// Scales every gradient by the same factor so that, taken together as one vector, their
+/// L2 norm is at most `max_norm`. Returns the norm before clipping.
+pub fn clip_grad_norm<F: Real>(parameters: &mut [&mut Parameter<F>], max_norm: F) -> F {
+    let norm = parameters.iter()
+        .map(|p| p.gradient.iter().map(|&g| g * g).sum::<F>())
+        .sum::<F>()
+        .sqrt();
+    if norm > max_norm {
+        let scale = max_norm / norm;
+        for parameter in parameters.iter_mut() {
+            parameter.gradient.mapv_inplace(|g| g * scale);
+        }
+    }
+    norm
+}
+
+/// Clamps every gradient entry to [-limit, limit].
+pub fn clip_grad_value<F: Real>(parameters: &mut [&mut Parameter<F>], limit: F) {
+    for parameter in parameters.iter_mut() {
+        parameter.gradient.mapv_inplace(|g| g.max(-limit).min(limit));
+    }
+}
+
+// reshapes into a (rows, cols) matrix, going through standard layout since
+// layers don't promise their outputs are contiguous
+pub(crate) fn to_matrix<F: Real>(x: &ArrayD<F>, rows: usize, cols: usize) -> Array2<F> {
+    x.as_standard_layout().into_owned().into_shape((rows, cols)).unwrap()
+}
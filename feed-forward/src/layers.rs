@@ -0,0 +1,359 @@
+use ndarray::{Array1, Array2, ArrayD, Axis, IxDyn};
+use rand::rngs::StdRng;
+use rand::Rng;
+use crate::activation::Activation;
+use crate::init::{Fans, Initializer};
+use crate::layer::{to_matrix, Layer, Parameter, ParameterKind};
+use crate::precision::{cast, Real};
+use crate::seed::rng_from_seed;
+
+/// A fully connected layer, output = input . W + b.
+///
+/// The features are the last axis of the input, so (N, in) maps to (N, out) and a
+/// sequence batch (N, T, in) maps to (N, T, out) with the same weights at every step.
+pub struct Dense<F: Real> {
+    weights: Parameter<F>,
+    bias: Parameter<F>,
+    input: Option<Array2<F>>,
+    input_shape: Option<IxDyn>,
+}
+
+impl<F: Real> Dense<F> {
+    pub fn new<R: Rng>(inputs: usize, outputs: usize, initializer: Initializer<F>, rng: &mut R) -> Dense<F> {
+        let weights = initializer.initialize((inputs, outputs), Fans::new(inputs, outputs), rng);
+        Dense {
+            weights: Parameter::new("weights", ParameterKind::Weight, weights.into_dyn()),
+            bias: Parameter::new("bias", ParameterKind::Bias, ArrayD::zeros(IxDyn(&[outputs]))),
+            input: None,
+            input_shape: None,
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.weights.value.shape()[0]
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.weights.value.shape()[1]
+    }
+
+    fn weight_matrix(&self) -> ndarray::ArrayView2<'_, F> {
+        self.weights.value.view().into_dimensionality().unwrap()
+    }
+}
+
+impl<F: Real> Layer<F> for Dense<F> {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let features = *input.shape().last().expect("dense layers need at least one axis");
+        assert_eq!(features, self.inputs(), "expected {} input features, got {}", self.inputs(), features);
+        let x = to_matrix(input, input.len() / features.max(1), features);
+
+        let bias = self.bias.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let output = x.dot(&self.weight_matrix()) + bias;
+
+        let mut output_shape = input.shape().to_vec();
+        *output_shape.last_mut().unwrap() = self.outputs();
+        self.input = Some(x);
+        self.input_shape = Some(input.raw_dim());
+        output.into_dyn().into_shape(IxDyn(&output_shape)).unwrap()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let x = self.input.as_ref().expect("backward called before forward");
+        let g = to_matrix(grad_output, x.nrows(), self.outputs());
+
+        self.weights.gradient += &x.t().dot(&g).into_dyn();
+        self.bias.gradient += &g.sum_axis(Axis(0)).into_dyn();
+
+        let grad_input = g.dot(&self.weight_matrix().t());
+        grad_input.into_dyn().into_shape(self.input_shape.clone().unwrap()).unwrap()
+    }
+
+    fn name(&self) -> String {
+        format!("Dense({} -> {})", self.inputs(), self.outputs())
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.weights, &self.bias]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.weights, &mut self.bias]
+    }
+}
+
+/// Applies an `Activation` element-wise, so nonlinearities can sit between layers.
+pub struct ActivationLayer<F: Real> {
+    activation: Activation<F>,
+    input: Option<ArrayD<F>>,
+}
+
+impl<F: Real> ActivationLayer<F> {
+    pub fn new(activation: Activation<F>) -> ActivationLayer<F> {
+        ActivationLayer { activation, input: None }
+    }
+}
+
+impl<F: Real> Layer<F> for ActivationLayer<F> {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        self.input = Some(input.clone());
+        self.activation.forward(input.view())
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let input = self.input.as_ref().expect("backward called before forward");
+        self.activation.backward(input.view(), grad_output.view())
+    }
+
+    fn name(&self) -> String {
+        format!("{:?}", self.activation)
+    }
+}
+
+/// Inverted dropout: during training every element is zeroed with probability `rate`
+/// and the survivors are scaled by 1 / (1 - rate), so the expected activation is the
+/// same as at inference time, when the layer does nothing.
+pub struct Dropout<F: Real> {
+    rate: F,
+    rng: StdRng,
+    mask: Option<ArrayD<F>>,
+}
+
+impl<F: Real> Dropout<F> {
+    /// `seed` makes the masks reproducible; see `seed::rng_from_seed`.
+    pub fn new(rate: F, seed: Option<u64>) -> Dropout<F> {
+        assert!(rate >= F::zero() && rate < F::one(), "dropout rate must be in [0, 1)");
+        Dropout { rate, rng: rng_from_seed(seed), mask: None }
+    }
+}
+
+impl<F: Real> Layer<F> for Dropout<F> {
+    fn forward(&mut self, input: &ArrayD<F>, training: bool) -> ArrayD<F> {
+        if !training || self.rate == F::zero() {
+            self.mask = None;
+            return input.clone();
+        }
+        let keep = F::one() - self.rate;
+        let scale = F::one() / keep;
+        let keep_probability = keep.to_f64().unwrap();
+        let rng = &mut self.rng;
+        let mask = input.mapv(|_| if rng.gen::<f64>() < keep_probability { scale } else { F::zero() });
+        let output = input * &mask;
+        self.mask = Some(mask);
+        output
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        match &self.mask {
+            Some(mask) => grad_output * mask,
+            None => grad_output.clone(),
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("Dropout({})", self.rate)
+    }
+}
+
+/// Batch normalization over the features of (N, features) inputs.
+///
+/// In training, each feature is normalized with the batch mean and variance, and
+/// exponential moving averages of both are kept for use at inference time.
+pub struct BatchNorm1d<F: Real> {
+    gamma: Parameter<F>,
+    beta: Parameter<F>,
+    running_mean: Array1<F>,
+    running_var: Array1<F>,
+    momentum: F,
+    epsilon: F,
+    // normalized input and 1 / sqrt(var + eps) from the last training forward pass
+    cache: Option<(Array2<F>, Array1<F>)>,
+}
+
+impl<F: Real> BatchNorm1d<F> {
+    pub fn new(features: usize) -> BatchNorm1d<F> {
+        BatchNorm1d {
+            gamma: Parameter::new("gamma", ParameterKind::Normalization, ArrayD::ones(IxDyn(&[features]))),
+            beta: Parameter::new("beta", ParameterKind::Normalization, ArrayD::zeros(IxDyn(&[features]))),
+            running_mean: Array1::zeros(features),
+            running_var: Array1::ones(features),
+            momentum: cast(0.1),
+            epsilon: cast(1e-5),
+            cache: None,
+        }
+    }
+
+    /// How fast the running statistics follow the batch statistics (default 0.1).
+    pub fn with_momentum(mut self, momentum: F) -> BatchNorm1d<F> {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn running_mean(&self) -> &Array1<F> {
+        &self.running_mean
+    }
+
+    pub fn running_var(&self) -> &Array1<F> {
+        &self.running_var
+    }
+
+    fn features(&self) -> usize {
+        self.gamma.value.len()
+    }
+}
+
+impl<F: Real> Layer<F> for BatchNorm1d<F> {
+    fn forward(&mut self, input: &ArrayD<F>, training: bool) -> ArrayD<F> {
+        assert_eq!(input.ndim(), 2, "BatchNorm1d expects (N, features) inputs");
+        let x = to_matrix(input, input.shape()[0], self.features());
+        let gamma = self.gamma.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let beta = self.beta.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+
+        let (mean, var) = if training {
+            let mean = x.mean_axis(Axis(0)).unwrap();
+            let var = x.var_axis(Axis(0), F::zero());
+            let n = x.nrows();
+            // the running variance uses the unbiased estimate, like PyTorch does
+            let unbiased = if n > 1 { &var * cast::<F>(n as f64 / (n - 1) as f64) } else { var.clone() };
+            let m = self.momentum;
+            self.running_mean = &self.running_mean * (F::one() - m) + &mean * m;
+            self.running_var = &self.running_var * (F::one() - m) + &unbiased * m;
+            (mean, var)
+        } else {
+            (self.running_mean.clone(), self.running_var.clone())
+        };
+
+        let inv_std = var.mapv(|v| F::one() / (v + self.epsilon).sqrt());
+        let normalized = (&x - &mean) * &inv_std;
+        let output = &normalized * &gamma + beta;
+        self.cache = if training { Some((normalized, inv_std)) } else { None };
+        output.into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let (normalized, inv_std) = self.cache.as_ref().expect("backward needs a training forward pass");
+        let g = to_matrix(grad_output, normalized.nrows(), self.features());
+        let gamma = self.gamma.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+
+        self.gamma.gradient += &(&g * normalized).sum_axis(Axis(0)).into_dyn();
+        self.beta.gradient += &g.sum_axis(Axis(0)).into_dyn();
+
+        // dx = gamma * inv_std * (g - mean(g) - x_hat * mean(g * x_hat))
+        let g_mean = g.mean_axis(Axis(0)).unwrap();
+        let g_xhat_mean = (&g * normalized).mean_axis(Axis(0)).unwrap();
+        let grad_input = (&g - &g_mean - &(normalized * &g_xhat_mean)) * &(&gamma * inv_std);
+        grad_input.into_dyn()
+    }
+
+    fn name(&self) -> String {
+        format!("BatchNorm1d({})", self.features())
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+}
+
+/// Layer normalization: each sample is normalized over its last axis,
+/// then scaled and shifted by learned per-feature parameters.
+pub struct LayerNorm<F: Real> {
+    gamma: Parameter<F>,
+    beta: Parameter<F>,
+    epsilon: F,
+    cache: Option<(Array2<F>, Array1<F>, IxDyn)>,
+}
+
+impl<F: Real> LayerNorm<F> {
+    pub fn new(features: usize) -> LayerNorm<F> {
+        LayerNorm {
+            gamma: Parameter::new("gamma", ParameterKind::Normalization, ArrayD::ones(IxDyn(&[features]))),
+            beta: Parameter::new("beta", ParameterKind::Normalization, ArrayD::zeros(IxDyn(&[features]))),
+            epsilon: cast(1e-5),
+            cache: None,
+        }
+    }
+
+    fn features(&self) -> usize {
+        self.gamma.value.len()
+    }
+}
+
+impl<F: Real> Layer<F> for LayerNorm<F> {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let features = self.features();
+        assert_eq!(input.shape().last().copied(), Some(features), "expected {} features on the last axis", features);
+        let x = to_matrix(input, input.len() / features, features);
+        let gamma = self.gamma.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let beta = self.beta.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+
+        let mean = x.mean_axis(Axis(1)).unwrap().insert_axis(Axis(1));
+        let var = x.var_axis(Axis(1), F::zero());
+        let inv_std = var.mapv(|v| F::one() / (v + self.epsilon).sqrt());
+        let normalized = (&x - &mean) * inv_std.view().insert_axis(Axis(1));
+        let output = &normalized * &gamma + beta;
+
+        self.cache = Some((normalized, inv_std, input.raw_dim()));
+        output.into_dyn().into_shape(input.raw_dim()).unwrap()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let (normalized, inv_std, input_shape) = self.cache.as_ref().expect("backward called before forward");
+        let g = to_matrix(grad_output, normalized.nrows(), self.features());
+        let gamma = self.gamma.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+
+        self.gamma.gradient += &(&g * normalized).sum_axis(Axis(0)).into_dyn();
+        self.beta.gradient += &g.sum_axis(Axis(0)).into_dyn();
+
+        // the same formula as batch norm, with the means taken across features instead
+        let g_hat = &g * &gamma;
+        let g_mean = g_hat.mean_axis(Axis(1)).unwrap().insert_axis(Axis(1));
+        let g_xhat_mean = (&g_hat * normalized).mean_axis(Axis(1)).unwrap().insert_axis(Axis(1));
+        let grad_input = (&g_hat - &g_mean - &(normalized * &g_xhat_mean)) * inv_std.view().insert_axis(Axis(1));
+        grad_input.into_dyn().into_shape(input_shape.clone()).unwrap()
+    }
+
+    fn name(&self) -> String {
+        format!("LayerNorm({})", self.features())
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+}
+
+/// Flattens everything but the batch axis, e.g. (N, 28, 28) images into (N, 784) rows.
+#[derive(Default)]
+pub struct Flatten {
+    input_shape: Option<IxDyn>,
+}
+
+impl Flatten {
+    pub fn new() -> Flatten {
+        Flatten { input_shape: None }
+    }
+}
+
+impl<F: Real> Layer<F> for Flatten {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let rows = input.shape()[0];
+        self.input_shape = Some(input.raw_dim());
+        to_matrix(input, rows, input.len() / rows.max(1)).into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let shape = self.input_shape.clone().expect("backward called before forward");
+        grad_output.as_standard_layout().into_owned().into_shape(shape).unwrap()
+    }
+
+    fn name(&self) -> String {
+        "Flatten".to_string()
+    }
+}
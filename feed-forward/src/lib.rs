@@ -1,40 +1,120 @@
+pub mod activation;
+pub mod checkpoint;
+pub mod conv;
+pub mod cross_entropy;
+pub mod embedding;
+pub mod gradcheck;
+pub mod init;
+pub mod layer;
+pub mod layers;
+pub mod loss;
+pub mod models;
+pub mod precision;
+pub mod recurrent;
+pub mod regularization;
+pub mod seed;
+pub mod tensorboard;
+
 pub mod perceptron {
     use ndarray::{ArrayView, Ix1};
+    use rand::rngs::StdRng;
+    use crate::activation::Activation;
+    use crate::init::{Fans, Initializer};
+    use crate::precision::{Accumulation, Real};
+    use crate::regularization::{clip_unit_norms, Regularization};
+    use crate::seed::{rng_from_seed, shuffled_indices};
 
     /// Stores state of the binary classifier; perceptrons are also known as linear units.
-    pub struct Perceptron {
-       weights: ndarray::Array1<f64>,
-       bias: f64,
+    /// The precision defaults to f64, but f32 works just as well for training.
+    pub struct Perceptron<F: Real = f64> {
+       weights: ndarray::Array1<F>,
+       bias: F,
+       accumulation: Accumulation,
+       // when set, the training samples are visited in a new random order every iteration
+       shuffle_rng: Option<StdRng>,
+       regularization: Regularization<F>,
     }
 
-    pub fn correct_labels(labels: &Vec<u8>, class: u8) -> Vec<u8> {
+    pub fn correct_labels(labels: &[u8], class: u8) -> Vec<u8> {
         labels.iter().map(|&x| if x == class { 1 } else { 0 }).collect::<Vec<u8>>()
     }
 
-    impl Perceptron {
-        pub fn new(num_features: usize) -> Perceptron {
+    impl<F: Real> Perceptron<F> {
+        pub fn new(num_features: usize) -> Perceptron<F> {
+            Self::from_weights(ndarray::Array1::zeros(num_features), F::zero())
+        }
+
+        /// A perceptron with the given weights and bias, e.g. ones saved from a trained model.
+        pub fn from_weights(weights: ndarray::Array1<F>, bias: F) -> Perceptron<F> {
             Perceptron {
-                weights: ndarray::Array1::zeros(num_features),
-                bias: 0.0,
+                weights,
+                bias,
+                accumulation: Accumulation::Native,
+                shuffle_rng: None,
+                regularization: Regularization::none(),
             }
         }
 
+        /// Applies weight decay and/or a max-norm constraint after every mistake-driven
+        /// update, so the weights can't keep growing on samples it never gets right.
+        /// The perceptron rule has no loss to smooth, so label smoothing is rejected.
+        pub fn with_regularization(mut self, regularization: Regularization<F>) -> Perceptron<F> {
+            assert!(regularization.label_smoothing == F::zero(), "label smoothing has no effect on a perceptron");
+            self.regularization = regularization;
+            self
+        }
+
+        /// The current L1/L2 penalty of the weights.
+        pub fn penalty(&self) -> F {
+            self.regularization.penalty_of(self.weights.view())
+        }
+
+        /// Replaces the all-zero starting weights with ones drawn from `initializer`.
+        /// `seed` pins the draw down for reproducible runs (see `seed::rng_from_seed`).
+        pub fn with_initializer(mut self, initializer: &Initializer<F>, seed: Option<u64>) -> Perceptron<F> {
+            let mut rng = rng_from_seed(seed);
+            let fans = Fans::new(self.weights.len(), 1);
+            self.weights = initializer.initialize(self.weights.len(), fans, &mut rng);
+            self
+        }
+
+        /// Shuffles the order of the training samples on every iteration.
+        /// The perceptron rule depends on that order, so the seed makes training reproducible.
+        pub fn with_shuffling(mut self, seed: Option<u64>) -> Perceptron<F> {
+            self.shuffle_rng = Some(rng_from_seed(seed));
+            self
+        }
+
+        /// Sets the precision the linear unit output is accumulated in,
+        /// e.g. an f32 perceptron can still sum its inputs in f64.
+        pub fn with_accumulation(mut self, accumulation: Accumulation) -> Perceptron<F> {
+            self.accumulation = accumulation;
+            self
+        }
+
+        pub fn weights(&self) -> &ndarray::Array1<F> {
+            &self.weights
+        }
+
+        pub fn bias(&self) -> F {
+            self.bias
+        }
+
         /// Given some perceptrons' weights and biases,
         /// gives a binary output (0 or 1) depending on the calculated output
         /// We use ArrayView to avoid copying the data and get some compile-time guarantees
         /// about input dimensionality.
         /// Returns the prediction as well as the linear unit output (confidence?)
-        pub fn predict(&self, input: ArrayView<f64, Ix1>) -> (f64, f64) {
-            let mut linear_unit_output = self.weights.dot(&input);
+        pub fn predict(&self, input: ArrayView<F, Ix1>) -> (F, F) {
+            let mut linear_unit_output = self.accumulation.dot(self.weights.view(), input);
             linear_unit_output += self.bias;
-            return (if linear_unit_output > 0f64 { 1f64 } else { 0f64 }, linear_unit_output);
+            (Activation::Step.apply(linear_unit_output), linear_unit_output)
         }
 
         /// Given a set of data, normalizes the data to be between 0.0 and 1.0
-        pub fn normalize(data: &ndarray::Array2<u8>) -> ndarray::Array2<f64>{
+        pub fn normalize(data: &ndarray::Array2<u8>) -> ndarray::Array2<F> {
             let mut highest = u8::MIN;
             let mut lowest = u8::MAX;
-            let mut return_data = data.clone();
             for i in data.iter() {
                 if *i > highest {
                     highest = *i;
@@ -43,11 +123,13 @@ pub mod perceptron {
                     lowest = *i;
                 }
             }
-            for i in return_data.iter_mut() {
-                *i = (*i - lowest) / (highest - lowest);
+            // a constant input has no range to scale by, so everything maps to 0.0
+            if highest <= lowest {
+                return ndarray::Array2::zeros(data.raw_dim());
             }
-            // convert to f64
-            return_data.mapv(|x| x as f64)
+            // the division happens in F, so we keep the fractional part of each pixel
+            let range = F::from_u8(highest - lowest).unwrap();
+            data.mapv(|x| F::from_u8(x - lowest).unwrap() / range)
         }
 
         /// Performs the "Perceptron Algorithm" given some set of data "training_data"
@@ -61,30 +143,42 @@ pub mod perceptron {
         ///         ii.) otherwise, we update weights by multiplying the feature by a - predict(x).
         ///
         /// The samples are organized as rows, with the last column being the ground truth.
-        pub fn train(&mut self, training_data: &ndarray::Array2<u8>, training_labels: &Vec<u8>, n_iterations: usize) {
+        #[allow(clippy::doc_overindented_list_items)]
+        pub fn train(&mut self, training_data: &ndarray::Array2<u8>, training_labels: &[u8], n_iterations: usize) {
             // we find the highest and lowest values, and normalize:
             let normalized_data = Self::normalize(training_data);
 
             // we iterate over the training data N times, or until the weights don't change
             for i in 0..n_iterations {
                 let prev_weights = self.weights.clone();
-                for (idx, row) in normalized_data.outer_iter().enumerate() {
-                    // cloning a slice turns the view into an owned array
-                    // println!("Row {}: {:?}", idx, row.clone());
-                    let x = row.clone(); // the sample
-                    let a = training_labels[idx] as f64; // the ground truth
+                let order = match self.shuffle_rng.as_mut() {
+                    Some(rng) => shuffled_indices(normalized_data.nrows(), rng),
+                    None => (0..normalized_data.nrows()).collect(),
+                };
+                for idx in order {
+                    let x = normalized_data.row(idx); // the sample
+                    // println!("Row {}: {:?}", idx, x);
+                    let a = F::from_u8(training_labels[idx]).unwrap(); // the ground truth
                     let (prediction, _) = self.predict(x);
-                    if a - prediction == 0f64 { // if the prediction is correct, we continue
+                    if a - prediction == F::zero() { // if the prediction is correct, we continue
                         // println!("Prediction {} was correct, continuing", prediction);
                         continue;
                     }
                     // prediction was incorrect, update weights
-                    if prediction == 0f64 && a == 1f64 { // false negative
+                    if prediction == F::zero() && a == F::one() { // false negative
                         self.weights += &x;
-                        self.bias += 1f64;
-                    } else if prediction == 1f64 && a == 0f64 { // false positive
+                        self.bias += F::one();
+                    } else if prediction == F::one() && a == F::zero() { // false positive
                         self.weights -= &x;
-                        self.bias -= 1f64;
+                        self.bias -= F::one();
+                    }
+                    if self.regularization.l1 != F::zero() || self.regularization.l2 != F::zero() {
+                        // the perceptron's learning rate is 1, so the decay is applied as is
+                        let decay = self.regularization.gradient_of(self.weights.view());
+                        self.weights -= &decay;
+                    }
+                    if let Some(limit) = self.regularization.max_norm {
+                        clip_unit_norms(&mut self.weights, limit);
                     }
                 }
                 // if the weights haven't changed, we break out of the loop
@@ -97,12 +191,12 @@ pub mod perceptron {
 
         /// Given some set of validation images, and their labels,
         /// and print out the predictions of the model, as well as the accuracy.
-        pub fn validate(&self, validation_images: &ndarray::Array2<u8>, validation_labels: &Vec<u8>) {
+        pub fn validate(&self, validation_images: &ndarray::Array2<u8>, validation_labels: &[u8]) {
             let normalized_validation_images = Self::normalize(validation_images);
             let mut correct_predictions = 0;
             for (idx, row) in normalized_validation_images.outer_iter().enumerate() {
                 let (prediction, _) = self.predict(row);
-                if prediction == validation_labels[idx] as f64 {
+                if prediction == F::from_u8(validation_labels[idx]).unwrap() {
                     correct_predictions += 1;
                 }
             }
@@ -111,13 +205,13 @@ pub mod perceptron {
     }
 
     // multi-class perceptrons
-    pub struct MultiClassPerceptron {
-        perceptrons: Vec<Perceptron>,
+    pub struct MultiClassPerceptron<F: Real = f64> {
+        perceptrons: Vec<Perceptron<F>>,
         classes: Vec<i32>
     }
 
-    impl MultiClassPerceptron {
-        pub fn new(classes: Vec<i32>, num_features: usize) -> MultiClassPerceptron {
+    impl<F: Real> MultiClassPerceptron<F> {
+        pub fn new(classes: Vec<i32>, num_features: usize) -> MultiClassPerceptron<F> {
             let mut perceptrons = vec![];
             for _ in 0..classes.len() {
                 perceptrons.push(Perceptron::new(num_features));
@@ -128,9 +222,29 @@ pub mod perceptron {
             }
         }
 
-        pub fn train(&mut self, training_data: &ndarray::Array2<u8>, training_labels: &Vec<u8>, n_iterations: usize) {
+        /// One perceptron per class, in the order of `classes`.
+        pub fn from_perceptrons(classes: Vec<i32>, perceptrons: Vec<Perceptron<F>>) -> MultiClassPerceptron<F> {
+            assert_eq!(classes.len(), perceptrons.len(), "one perceptron per class");
+            MultiClassPerceptron { perceptrons, classes }
+        }
+
+        pub fn classes(&self) -> &[i32] {
+            &self.classes
+        }
+
+        pub fn perceptrons(&self) -> &[Perceptron<F>] {
+            &self.perceptrons
+        }
+
+        /// Sets the accumulation precision of every underlying perceptron.
+        pub fn with_accumulation(mut self, accumulation: Accumulation) -> MultiClassPerceptron<F> {
+            self.perceptrons = self.perceptrons.into_iter().map(|p| p.with_accumulation(accumulation)).collect();
+            self
+        }
+
+        pub fn train(&mut self, training_data: &ndarray::Array2<u8>, training_labels: &[u8], n_iterations: usize) {
             for i in 0..self.classes.len() {
-                let mut perceptron = &mut self.perceptrons[i];
+                let perceptron = &mut self.perceptrons[i];
                 // create the corrected labels
                 let corrected_labels = correct_labels(training_labels, self.classes[i] as u8);
                 // train the perceptron
@@ -138,27 +252,21 @@ pub mod perceptron {
             }
         }
 
-        pub fn predict(&self, input: ArrayView<f64, Ix1>) -> usize {
-            let mut predictions: Vec<(f64, f64)> = vec![];
+        pub fn predict(&self, input: ArrayView<F, Ix1>) -> usize {
+            let mut predictions: Vec<(F, F)> = vec![];
             for perceptron in &self.perceptrons {
                 predictions.push(perceptron.predict(input));
             }
             // println!("Predictions: {:?}", predictions);
 
-            let ones = predictions.iter().filter(|(prediction, confidence)| *prediction == 1f64).collect::<Vec<_>>();
-            return if ones.len() == 0 {
-                // return the class with the lowest confidence
-                let (idx, _) = predictions.iter().enumerate().min_by(|(_, (_, confidence1)), (_, (_, confidence2))| confidence1.partial_cmp(confidence2).unwrap()).unwrap();
-                self.classes[idx] as usize
-            } else {
-                // return the index of the 1 prediction with the highest confidence
-                let (idx, _) = ones.iter().enumerate().max_by(|(_, (_, confidence1)), (_, (_, confidence2))| confidence1.partial_cmp(confidence2).unwrap()).unwrap();
-                self.classes[idx] as usize
-            }
+            // the class whose perceptron is most confident: one that fires if any does,
+            // otherwise the one closest to firing
+            let (idx, _) = predictions.iter().enumerate().max_by(|(_, (_, confidence1)), (_, (_, confidence2))| confidence1.partial_cmp(confidence2).unwrap()).unwrap();
+            self.classes[idx] as usize
         }
 
-        pub fn validate(&self, validation_images: &ndarray::Array2<u8>, validation_labels: &Vec<u8>) {
-            let normalized_validation_images = Perceptron::normalize(validation_images);
+        pub fn validate(&self, validation_images: &ndarray::Array2<u8>, validation_labels: &[u8]) {
+            let normalized_validation_images = Perceptron::<F>::normalize(validation_images);
             let mut correct_predictions = 0;
 
             // iterate over each validation sample
@@ -173,7 +281,7 @@ pub mod perceptron {
             println!("Accuracy: {}", correct_predictions as f64 / validation_labels.len() as f64);
         }
 
-        pub fn validate_nth_perceptron(&self, class_idx: usize, validation_images: &ndarray::Array2<u8>, validation_labels: &Vec<u8>) {
+        pub fn validate_nth_perceptron(&self, class_idx: usize, validation_images: &ndarray::Array2<u8>, validation_labels: &[u8]) {
             let perceptron = &self.perceptrons[class_idx];
             // correct the validation label
             let corrected_labels = correct_labels(validation_labels, self.classes[class_idx] as u8);
@@ -181,20 +289,3 @@ pub mod perceptron {
         }
     }
 }
-
-pub mod cross_entropy {
-    use ndarray::{ArrayView, Ix1};
-
-    // Given some logit output from a neural network, we can calculate
-    // the cross entropy loss.
-    pub fn softmax(x: ArrayView<f64, Ix1>) -> ndarray::Array1<f64> {
-        let mut exps = x.mapv(|x| x.exp()); // e ^ logit
-        let sum: f64 = exps.iter().sum(); // sum of all normalized logits
-        if sum == 0f64 {
-            println!("Sum of exps is 0, returning exps");
-            return exps;
-        }
-        exps /= sum;
-        exps
-    }
-}
\ No newline at end of file
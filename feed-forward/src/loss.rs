@@ -0,0 +1,594 @@
+use ndarray::{s, Array1, Array2, ArrayView, Axis, Ix2};
+use crate::cross_entropy::{log_softmax_rows, sigmoid, softplus, CrossEntropyOptions};
+use crate::precision::{cast, Real};
+
+// All losses take a batch of predictions with one sample per row, a batch of targets,
+// and hand back both the loss and its gradient with respect to the predictions, which
+// is exactly what backprop needs from them. The integer-label helpers in `cross_entropy`
+// are wrappers around `CrossEntropyLoss` and `BceWithLogitsLoss`. The perceptrons keep
+// their own mistake-driven update, which is a step down the gradient of `Hinge::new(0.0)`,
+// and don't take a `Loss`.
+
+/// How the per-sample losses of a batch are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduction {
+    /// Average over the samples in the batch.
+    #[default]
+    Mean,
+    /// Sum over the samples in the batch.
+    Sum,
+    /// Keep one loss value per sample.
+    None,
+}
+
+/// The result of evaluating a loss on a batch.
+#[derive(Debug, Clone)]
+pub struct LossOutput<F: Real> {
+    /// A single value for `Reduction::Mean`/`Reduction::Sum`, or one value per sample.
+    pub value: Array1<F>,
+    /// Gradient with respect to the predictions, with the same shape as the predictions.
+    /// For `Reduction::None` this is the gradient of the summed per-sample losses.
+    pub gradient: Array2<F>,
+}
+
+impl<F: Real> LossOutput<F> {
+    /// The loss as one number, summing the per-sample values if they weren't reduced.
+    pub fn scalar(&self) -> F {
+        self.value.sum()
+    }
+}
+
+/// A differentiable loss function.
+///
+/// Implementors only provide the unreduced per-sample losses and the gradient of each
+/// sample's loss; `compute` applies the configured `Reduction` on top of that.
+pub trait Loss<F: Real> {
+    /// Per-sample losses, along with the gradient of each sample's loss with respect
+    /// to the predictions.
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>);
+
+    fn reduction(&self) -> Reduction;
+
+    /// How much each sample's loss counts. With weights, `Reduction::Mean` divides by
+    /// their sum instead of the batch size.
+    fn sample_weights(&self, _targets: ArrayView<F, Ix2>) -> Option<Array1<F>> {
+        None
+    }
+
+    /// Evaluates the loss on a batch and reduces it.
+    fn compute(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> LossOutput<F> {
+        let (mut values, mut gradient) = self.per_sample(predictions, targets);
+        let mut count = cast::<F>(values.len().max(1) as f64);
+        if let Some(weights) = self.sample_weights(targets) {
+            values *= &weights;
+            gradient *= &weights.view().insert_axis(Axis(1));
+            count = weights.sum();
+        }
+        match self.reduction() {
+            Reduction::Mean => {
+                // every sample weighted 0: nothing to learn from
+                if count == F::zero() {
+                    return LossOutput { value: Array1::zeros(1), gradient: Array2::zeros(gradient.raw_dim()) };
+                }
+                gradient /= count;
+                LossOutput { value: Array1::from_elem(1, values.sum() / count), gradient }
+            }
+            Reduction::Sum => LossOutput { value: Array1::from_elem(1, values.sum()), gradient },
+            Reduction::None => LossOutput { value: values, gradient },
+        }
+    }
+}
+
+fn check_shapes<F: Real>(predictions: &ArrayView<F, Ix2>, targets: &ArrayView<F, Ix2>) {
+    assert_eq!(predictions.dim(), targets.dim(), "predictions and targets must have the same shape");
+}
+
+fn row_count<F: Real>(predictions: &ArrayView<F, Ix2>) -> F {
+    cast(predictions.ncols().max(1) as f64)
+}
+
+// implements an element-wise loss whose per-sample value is the mean over the row
+fn elementwise<F: Real>(predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>, f: impl Fn(F, F) -> (F, F)) -> (Array1<F>, Array2<F>) {
+    check_shapes(&predictions, &targets);
+    let width = row_count(&predictions);
+    let mut values = Array1::zeros(predictions.nrows());
+    let mut gradient = Array2::zeros(predictions.raw_dim());
+    for (i, (p_row, t_row)) in predictions.outer_iter().zip(targets.outer_iter()).enumerate() {
+        let mut total = F::zero();
+        for (j, (&p, &t)) in p_row.iter().zip(t_row.iter()).enumerate() {
+            let (value, grad) = f(p, t);
+            total += value;
+            gradient[[i, j]] = grad / width;
+        }
+        values[i] = total / width;
+    }
+    (values, gradient)
+}
+
+macro_rules! with_reduction {
+    ($name:ident) => {
+        impl<F: Real> $name<F> {
+            pub fn with_reduction(mut self, reduction: Reduction) -> Self {
+                self.reduction = reduction;
+                self
+            }
+        }
+    };
+}
+
+/// Mean squared error, (p - t)^2 averaged over the outputs of each sample.
+#[derive(Debug, Clone, Default)]
+pub struct MeanSquaredError<F: Real> {
+    reduction: Reduction,
+    _precision: std::marker::PhantomData<F>,
+}
+
+impl<F: Real> MeanSquaredError<F> {
+    pub fn new() -> Self {
+        MeanSquaredError { reduction: Reduction::Mean, _precision: std::marker::PhantomData }
+    }
+}
+with_reduction!(MeanSquaredError);
+
+impl<F: Real> Loss<F> for MeanSquaredError<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let two = cast::<F>(2.0);
+        elementwise(predictions, targets, |p, t| ((p - t) * (p - t), two * (p - t)))
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Mean absolute error, |p - t| averaged over the outputs of each sample.
+/// The gradient at p == t is taken to be 0.
+#[derive(Debug, Clone, Default)]
+pub struct MeanAbsoluteError<F: Real> {
+    reduction: Reduction,
+    _precision: std::marker::PhantomData<F>,
+}
+
+impl<F: Real> MeanAbsoluteError<F> {
+    pub fn new() -> Self {
+        MeanAbsoluteError { reduction: Reduction::Mean, _precision: std::marker::PhantomData }
+    }
+}
+with_reduction!(MeanAbsoluteError);
+
+impl<F: Real> Loss<F> for MeanAbsoluteError<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        elementwise(predictions, targets, |p, t| {
+            let r = p - t;
+            let sign = if r > F::zero() { F::one() } else if r < F::zero() { -F::one() } else { F::zero() };
+            (r.abs(), sign)
+        })
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Huber loss: quadratic for residuals within `delta`, linear beyond it,
+/// so a few wildly wrong outputs can't dominate the gradient.
+#[derive(Debug, Clone)]
+pub struct Huber<F: Real> {
+    delta: F,
+    reduction: Reduction,
+}
+
+impl<F: Real> Huber<F> {
+    pub fn new(delta: F) -> Self {
+        Huber { delta, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(Huber);
+
+impl<F: Real> Loss<F> for Huber<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let half = cast::<F>(0.5);
+        let delta = self.delta;
+        elementwise(predictions, targets, |p, t| {
+            let r = p - t;
+            if r.abs() <= delta {
+                (half * r * r, r)
+            } else {
+                (delta * (r.abs() - half * delta), delta * r.signum())
+            }
+        })
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Hinge loss max(0, margin - t * p), with targets of -1 or +1.
+///
+/// This is the margin view of the perceptron: with a margin of 0 it's exactly the
+/// perceptron criterion, which only penalises misclassified samples, while the usual
+/// margin of 1 also pushes correctly classified samples away from the boundary.
+#[derive(Debug, Clone)]
+pub struct Hinge<F: Real> {
+    margin: F,
+    reduction: Reduction,
+}
+
+impl<F: Real> Hinge<F> {
+    pub fn new(margin: F) -> Self {
+        Hinge { margin, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(Hinge);
+
+impl<F: Real> Loss<F> for Hinge<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let margin = self.margin;
+        elementwise(predictions, targets, |p, t| {
+            let slack = margin - t * p;
+            if slack > F::zero() { (slack, -t) } else { (F::zero(), F::zero()) }
+        })
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Squared hinge loss max(0, margin - t * p)^2, with targets of -1 or +1.
+/// Smooth at the margin, unlike `Hinge`.
+#[derive(Debug, Clone)]
+pub struct SquaredHinge<F: Real> {
+    margin: F,
+    reduction: Reduction,
+}
+
+impl<F: Real> SquaredHinge<F> {
+    pub fn new(margin: F) -> Self {
+        SquaredHinge { margin, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(SquaredHinge);
+
+impl<F: Real> Loss<F> for SquaredHinge<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let margin = self.margin;
+        let two = cast::<F>(2.0);
+        elementwise(predictions, targets, |p, t| {
+            let slack = margin - t * p;
+            if slack > F::zero() { (slack * slack, -two * slack * t) } else { (F::zero(), F::zero()) }
+        })
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Sigmoid focal loss on raw logits, with (possibly soft) targets in [0, 1].
+///
+/// Scales binary cross-entropy by (1 - p_t)^gamma, so easy, confidently correct samples
+/// contribute almost nothing and training focuses on the hard ones. `alpha`, if set,
+/// weights positives by alpha and negatives by 1 - alpha.
+#[derive(Debug, Clone)]
+pub struct FocalLoss<F: Real> {
+    gamma: F,
+    alpha: Option<F>,
+    reduction: Reduction,
+}
+
+impl<F: Real> FocalLoss<F> {
+    pub fn new(gamma: F, alpha: Option<F>) -> Self {
+        FocalLoss { gamma, alpha, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(FocalLoss);
+
+impl<F: Real> Loss<F> for FocalLoss<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let gamma = self.gamma;
+        let (alpha_pos, alpha_neg) = match self.alpha {
+            Some(alpha) => (alpha, F::one() - alpha),
+            None => (F::one(), F::one()),
+        };
+        elementwise(predictions, targets, |x, y| {
+            let p = sigmoid(x);
+            let q = F::one() - p;
+            // -log(p) and -log(1 - p)
+            let neg_log_p = softplus(-x);
+            let neg_log_q = softplus(x);
+
+            let positive = alpha_pos * q.powf(gamma) * neg_log_p;
+            let negative = alpha_neg * p.powf(gamma) * neg_log_q;
+            let value = y * positive + (F::one() - y) * negative;
+
+            // d/dx of each term, using dp/dx = p * q
+            let d_positive = alpha_pos * q.powf(gamma) * (-gamma * p * neg_log_p - q);
+            let d_negative = alpha_neg * p.powf(gamma) * (gamma * q * neg_log_q + p);
+            (value, y * d_positive + (F::one() - y) * d_negative)
+        })
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Softmax cross-entropy on raw logits, with one-hot (or probability) target rows.
+/// `cross_entropy` takes integer labels instead.
+#[derive(Debug, Clone)]
+pub struct CrossEntropyLoss<F: Real> {
+    label_smoothing: F,
+    class_weights: Option<Array1<F>>,
+    reduction: Reduction,
+}
+
+impl<F: Real> CrossEntropyLoss<F> {
+    pub fn new() -> Self {
+        CrossEntropyLoss { label_smoothing: F::zero(), class_weights: None, reduction: Reduction::Mean }
+    }
+
+    /// Label smoothing and class weights as `cross_entropy` takes them.
+    pub fn with_options(options: CrossEntropyOptions<F>) -> Self {
+        CrossEntropyLoss { label_smoothing: options.label_smoothing, class_weights: options.class_weights, reduction: Reduction::Mean }
+    }
+
+    /// Mixes each target row with the uniform distribution: (1 - eps) * t + eps / K.
+    pub fn with_label_smoothing(mut self, label_smoothing: F) -> Self {
+        self.label_smoothing = label_smoothing;
+        self
+    }
+
+    /// Scales each sample's loss by the weight of its class (the target-weighted sum of
+    /// the weights for soft targets); the mean is then taken over the summed weights.
+    pub fn with_class_weights(mut self, class_weights: Array1<F>) -> Self {
+        self.class_weights = Some(class_weights);
+        self
+    }
+}
+with_reduction!(CrossEntropyLoss);
+
+impl<F: Real> Default for CrossEntropyLoss<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: Real> Loss<F> for CrossEntropyLoss<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        check_shapes(&predictions, &targets);
+        let eps = self.label_smoothing;
+        let uniform = eps / row_count(&predictions);
+        let smoothed = targets.mapv(|t| (F::one() - eps) * t + uniform);
+
+        let log_probabilities = log_softmax_rows(predictions);
+        let mut values = Array1::zeros(predictions.nrows());
+        let mut gradient = log_probabilities.mapv(|v| v.exp());
+        for (i, (lp, q)) in log_probabilities.outer_iter().zip(smoothed.outer_iter()).enumerate() {
+            values[i] = -lp.dot(&q);
+            // d/dx of -sum(q * log_softmax(x)) is softmax(x) * sum(q) - q
+            let mass = q.sum();
+            let mut row = gradient.row_mut(i);
+            row *= mass;
+            row -= &q;
+        }
+        (values, gradient)
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    fn sample_weights(&self, targets: ArrayView<F, Ix2>) -> Option<Array1<F>> {
+        let weights = self.class_weights.as_ref()?;
+        assert_eq!(weights.len(), targets.ncols(), "expected one class weight per column of logits");
+        Some(targets.dot(weights))
+    }
+}
+
+/// Binary cross-entropy on raw logits, with targets in [0, 1], averaged over the outputs
+/// of each sample.
+///
+/// Uses the stable form max(x, 0) - x * y + log(1 + e^-|x|), so large logits of either
+/// sign never go through sigmoid. `pos_weight` scales the loss of positive targets, which
+/// helps with the heavy imbalance of one-vs-rest labels.
+#[derive(Debug, Clone)]
+pub struct BceWithLogitsLoss<F: Real> {
+    pos_weight: Option<F>,
+    reduction: Reduction,
+}
+
+impl<F: Real> BceWithLogitsLoss<F> {
+    pub fn new(pos_weight: Option<F>) -> Self {
+        BceWithLogitsLoss { pos_weight, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(BceWithLogitsLoss);
+
+impl<F: Real> Loss<F> for BceWithLogitsLoss<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let pos_weight = self.pos_weight.unwrap_or_else(F::one);
+        elementwise(predictions, targets, |x, y| {
+            // -log(sigmoid(x)) and -log(1 - sigmoid(x)), both computed stably
+            let value = pos_weight * y * softplus(-x) + (F::one() - y) * softplus(x);
+            let s = sigmoid(x);
+            (value, pos_weight * y * (s - F::one()) + (F::one() - y) * s)
+        })
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Negative log-likelihood, for predictions that are already log-probabilities
+/// (e.g. the output of `log_softmax_rows`) and one-hot target rows.
+#[derive(Debug, Clone, Default)]
+pub struct NegativeLogLikelihood<F: Real> {
+    reduction: Reduction,
+    _precision: std::marker::PhantomData<F>,
+}
+
+impl<F: Real> NegativeLogLikelihood<F> {
+    pub fn new() -> Self {
+        NegativeLogLikelihood { reduction: Reduction::Mean, _precision: std::marker::PhantomData }
+    }
+}
+with_reduction!(NegativeLogLikelihood);
+
+impl<F: Real> Loss<F> for NegativeLogLikelihood<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        check_shapes(&predictions, &targets);
+        let values = predictions.outer_iter().zip(targets.outer_iter())
+            .map(|(lp, t)| -lp.dot(&t))
+            .collect();
+        (values, targets.mapv(|t| -t))
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// KL(target || prediction), for predictions that are log-probabilities and target
+/// rows that are probability distributions. Terms with a zero target contribute 0.
+#[derive(Debug, Clone, Default)]
+pub struct KlDivergence<F: Real> {
+    reduction: Reduction,
+    _precision: std::marker::PhantomData<F>,
+}
+
+impl<F: Real> KlDivergence<F> {
+    pub fn new() -> Self {
+        KlDivergence { reduction: Reduction::Mean, _precision: std::marker::PhantomData }
+    }
+}
+with_reduction!(KlDivergence);
+
+impl<F: Real> Loss<F> for KlDivergence<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        check_shapes(&predictions, &targets);
+        let values = predictions.outer_iter().zip(targets.outer_iter())
+            .map(|(lp, t)| {
+                lp.iter().zip(t.iter())
+                    .filter(|(_, &t)| t > F::zero())
+                    .map(|(&lp, &t)| t * (t.ln() - lp))
+                    .sum()
+            })
+            .collect();
+        (values, targets.mapv(|t| -t))
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Cosine embedding loss over pairs of embeddings.
+///
+/// Predictions hold the pairs back to back: rows 2i and 2i + 1 are the two embeddings of
+/// pair i, and targets has one row per pair holding +1 (similar) or -1 (dissimilar).
+/// Similar pairs cost 1 - cos, dissimilar pairs cost max(0, cos - margin).
+#[derive(Debug, Clone)]
+pub struct CosineEmbedding<F: Real> {
+    margin: F,
+    reduction: Reduction,
+}
+
+impl<F: Real> CosineEmbedding<F> {
+    pub fn new(margin: F) -> Self {
+        CosineEmbedding { margin, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(CosineEmbedding);
+
+impl<F: Real> Loss<F> for CosineEmbedding<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let pairs = predictions.nrows() / 2;
+        assert_eq!(predictions.nrows(), 2 * pairs, "predictions must hold an even number of rows");
+        assert_eq!(targets.nrows(), pairs, "expected one target per pair of predictions");
+
+        let tiny = cast::<F>(1e-12);
+        let mut values = Array1::zeros(pairs);
+        let mut gradient = Array2::zeros(predictions.raw_dim());
+        for i in 0..pairs {
+            let a = predictions.row(2 * i);
+            let b = predictions.row(2 * i + 1);
+            let norm_a = a.dot(&a).sqrt().max(tiny);
+            let norm_b = b.dot(&b).sqrt().max(tiny);
+            let cos = a.dot(&b) / (norm_a * norm_b);
+
+            // dcos/da = b / (|a||b|) - cos * a / |a|^2, and symmetrically for b
+            let (value, scale) = if targets[[i, 0]] > F::zero() {
+                (F::one() - cos, -F::one())
+            } else if cos > self.margin {
+                (cos - self.margin, F::one())
+            } else {
+                (F::zero(), F::zero())
+            };
+            values[i] = value;
+            if scale != F::zero() {
+                let d_a = (&b / (norm_a * norm_b) - &a * (cos / (norm_a * norm_a))) * scale;
+                let d_b = (&a / (norm_a * norm_b) - &b * (cos / (norm_b * norm_b))) * scale;
+                gradient.row_mut(2 * i).assign(&d_a);
+                gradient.row_mut(2 * i + 1).assign(&d_b);
+            }
+        }
+        (values, gradient)
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
+
+/// Triplet margin loss max(0, |a - p| - |a - n| + margin) with Euclidean distances.
+///
+/// Predictions hold the triplets back to back: rows 3i, 3i + 1 and 3i + 2 are the anchor,
+/// positive and negative of triplet i. Targets aren't used and may be empty.
+#[derive(Debug, Clone)]
+pub struct Triplet<F: Real> {
+    margin: F,
+    reduction: Reduction,
+}
+
+impl<F: Real> Triplet<F> {
+    pub fn new(margin: F) -> Self {
+        Triplet { margin, reduction: Reduction::Mean }
+    }
+}
+with_reduction!(Triplet);
+
+impl<F: Real> Loss<F> for Triplet<F> {
+    fn per_sample(&self, predictions: ArrayView<F, Ix2>, _targets: ArrayView<F, Ix2>) -> (Array1<F>, Array2<F>) {
+        let triplets = predictions.nrows() / 3;
+        assert_eq!(predictions.nrows(), 3 * triplets, "predictions must hold a multiple of 3 rows");
+
+        let tiny = cast::<F>(1e-12);
+        let mut values = Array1::zeros(triplets);
+        let mut gradient = Array2::zeros(predictions.raw_dim());
+        for i in 0..triplets {
+            let rows = predictions.slice(s![3 * i..3 * i + 3, ..]);
+            let to_positive = &rows.row(0) - &rows.row(1);
+            let to_negative = &rows.row(0) - &rows.row(2);
+            let d_positive = to_positive.dot(&to_positive).sqrt().max(tiny);
+            let d_negative = to_negative.dot(&to_negative).sqrt().max(tiny);
+
+            let value = d_positive - d_negative + self.margin;
+            if value > F::zero() {
+                values[i] = value;
+                let unit_positive = to_positive / d_positive;
+                let unit_negative = to_negative / d_negative;
+                gradient.row_mut(3 * i).assign(&(&unit_positive - &unit_negative));
+                gradient.row_mut(3 * i + 1).assign(&(-unit_positive));
+                gradient.row_mut(3 * i + 2).assign(&unit_negative);
+            }
+        }
+        (values, gradient)
+    }
+
+    fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+}
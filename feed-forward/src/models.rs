@@ -0,0 +1,102 @@
+use ndarray::{Array2, ArrayD, Axis, IxDyn, Slice};
+use rand::Rng;
+use crate::activation::Activation;
+use crate::conv::{AvgPool2d, Conv2d, MaxPool2d};
+use crate::cross_entropy::argmax_rows;
+use crate::init::Initializer;
+use crate::layer::{to_matrix, Layer, Sequential};
+use crate::layers::{ActivationLayer, Dense, Dropout, Flatten};
+use crate::loss::Loss;
+use crate::precision::{cast, Real};
+use crate::seed::shuffled_indices;
+
+/// LeNet-5 (LeCun et al., 1998) for (N, 1, 28, 28) images.
+///
+/// The original takes 32x32 inputs; padding the first convolution by 2 gives 28x28 MNIST
+/// digits the same geometry. Outputs are logits, one per class.
+pub fn lenet5<F: Real, R: Rng>(classes: usize, rng: &mut R) -> Sequential<F> {
+    Sequential::new()
+        .add(Conv2d::new(1, 6, (5, 5), Initializer::XavierUniform, rng).with_padding((2, 2)))
+        .add(ActivationLayer::new(Activation::Tanh))
+        .add(AvgPool2d::new((2, 2)))
+        .add(Conv2d::new(6, 16, (5, 5), Initializer::XavierUniform, rng))
+        .add(ActivationLayer::new(Activation::Tanh))
+        .add(AvgPool2d::new((2, 2)))
+        .add(Flatten::new())
+        .add(Dense::new(16 * 5 * 5, 120, Initializer::XavierUniform, rng))
+        .add(ActivationLayer::new(Activation::Tanh))
+        .add(Dense::new(120, 84, Initializer::XavierUniform, rng))
+        .add(ActivationLayer::new(Activation::Tanh))
+        .add(Dense::new(84, classes, Initializer::XavierUniform, rng))
+}
+
+/// A small VGG-style network for (N, 1, 28, 28) images: two blocks of two 3x3 ReLU
+/// convolutions and a 2x2 max pool, then a dropout-regularized classifier.
+pub fn small_vgg<F: Real, R: Rng>(classes: usize, rng: &mut R) -> Sequential<F> {
+    let dropout_seed = rng.gen();
+    Sequential::new()
+        .add(Conv2d::new(1, 16, (3, 3), Initializer::HeNormal, rng).with_padding((1, 1)))
+        .add(ActivationLayer::new(Activation::Relu))
+        .add(Conv2d::new(16, 16, (3, 3), Initializer::HeNormal, rng).with_padding((1, 1)))
+        .add(ActivationLayer::new(Activation::Relu))
+        .add(MaxPool2d::new((2, 2)))
+        .add(Conv2d::new(16, 32, (3, 3), Initializer::HeNormal, rng).with_padding((1, 1)))
+        .add(ActivationLayer::new(Activation::Relu))
+        .add(Conv2d::new(32, 32, (3, 3), Initializer::HeNormal, rng).with_padding((1, 1)))
+        .add(ActivationLayer::new(Activation::Relu))
+        .add(MaxPool2d::new((2, 2)))
+        .add(Flatten::new())
+        .add(Dense::new(32 * 7 * 7, 128, Initializer::HeNormal, rng))
+        .add(ActivationLayer::new(Activation::Relu))
+        .add(Dropout::new(cast(0.5), Some(dropout_seed)))
+        .add(Dense::new(128, classes, Initializer::XavierUniform, rng))
+}
+
+/// Turns the (N, 784) pixel rows from `convert_mnist_images_to_ndarray2` into an
+/// (N, 1, 28, 28) batch scaled to [0, 1].
+pub fn mnist_images_to_batch<F: Real>(images: &Array2<u8>) -> ArrayD<F> {
+    let scale = cast::<F>(1.0 / 255.0);
+    images.mapv(|p| cast::<F>(p as f64) * scale)
+        .into_shape(IxDyn(&[images.nrows(), 1, 28, 28]))
+        .expect("MNIST images have 784 pixels")
+}
+
+/// One row per label with a 1 in the label's column.
+pub fn one_hot<F: Real>(labels: &[u8], classes: usize) -> Array2<F> {
+    let mut targets = Array2::zeros((labels.len(), classes));
+    for (row, &label) in labels.iter().enumerate() {
+        targets[[row, label as usize]] = F::one();
+    }
+    targets
+}
+
+/// Runs one shuffled pass of mini-batch SGD over `inputs` (batch first) and returns the
+/// mean training loss per batch.
+pub fn train_epoch<F: Real, R: Rng>(model: &mut Sequential<F>, inputs: &ArrayD<F>, targets: &Array2<F>, loss: &dyn Loss<F>,
+                                    batch_size: usize, learning_rate: F, rng: &mut R) -> F {
+    let order = shuffled_indices(inputs.shape()[0], rng);
+    let mut total = F::zero();
+    let mut batches = 0;
+    for chunk in order.chunks(batch_size.max(1)) {
+        let batch = inputs.select(Axis(0), chunk);
+        let batch_targets = targets.select(Axis(0), chunk);
+        total += model.train_batch(&batch, &batch_targets, loss, learning_rate).total();
+        batches += 1;
+    }
+    total / cast(batches.max(1) as f64)
+}
+
+/// The fraction of `inputs` whose highest scoring output is the label.
+pub fn evaluate<F: Real>(model: &mut Sequential<F>, inputs: &ArrayD<F>, labels: &[u8], batch_size: usize) -> f64 {
+    let n = inputs.shape()[0];
+    let mut correct = 0;
+    for start in (0..n).step_by(batch_size.max(1)) {
+        let end = (start + batch_size.max(1)).min(n);
+        let output = model.forward(&inputs.slice_axis(Axis(0), Slice::from(start..end)).to_owned(), false);
+        let scores = to_matrix(&output, end - start, output.len() / (end - start));
+        correct += argmax_rows(scores.view()).iter().zip(&labels[start..end])
+            .filter(|(&predicted, &label)| predicted == label as usize)
+            .count();
+    }
+    correct as f64 / n.max(1) as f64
+}
@@ -0,0 +1,55 @@
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+
+use ndarray::{ArrayView, Ix1, ScalarOperand};
+use num_traits::{Float, FromPrimitive, NumAssign};
+
+/// The scalar type every model and loss function in this crate is generic over.
+/// It is just `num_traits::Float` plus the bits ndarray needs for in-place arithmetic,
+/// so in practice this means f32 or f64.
+pub trait Real: Float + FromPrimitive + NumAssign + ScalarOperand + Sum + Debug + Display + Send + Sync + 'static {}
+
+impl<T> Real for T where T: Float + FromPrimitive + NumAssign + ScalarOperand + Sum + Debug + Display + Send + Sync + 'static {}
+
+/// Converts an f64 constant into the model's precision.
+/// Every f32/f64 can hold any literal we use, so this never fails in practice.
+pub fn cast<F: Real>(value: f64) -> F {
+    F::from_f64(value).unwrap()
+}
+
+/// Controls the precision that dot products and sums are accumulated in.
+///
+/// Training in f32 halves the memory used by weights, but summing 784 pixel
+/// contributions in f32 loses a few bits. `Accumulation::F64` keeps the weights in f32
+/// while doing the running sum in f64, and only rounds the final result back down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Accumulation {
+    /// Accumulate in the same precision as the model.
+    #[default]
+    Native,
+    /// Accumulate in f64 regardless of the model's precision.
+    F64,
+}
+
+impl Accumulation {
+    /// Dot product of two vectors, accumulated according to `self`.
+    pub fn dot<F: Real>(&self, a: ArrayView<F, Ix1>, b: ArrayView<F, Ix1>) -> F {
+        match self {
+            Accumulation::Native => a.dot(&b),
+            Accumulation::F64 => {
+                let sum: f64 = a.iter().zip(b.iter())
+                    .map(|(x, y)| x.to_f64().unwrap() * y.to_f64().unwrap())
+                    .sum();
+                cast(sum)
+            }
+        }
+    }
+
+    /// Sum of a sequence of values, accumulated according to `self`.
+    pub fn sum<F: Real, I: IntoIterator<Item = F>>(&self, values: I) -> F {
+        match self {
+            Accumulation::Native => values.into_iter().sum(),
+            Accumulation::F64 => cast(values.into_iter().map(|x| x.to_f64().unwrap()).sum()),
+        }
+    }
+}
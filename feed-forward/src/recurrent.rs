@@ -0,0 +1,619 @@
+use ndarray::{concatenate, s, Array2, Array3, ArrayD, ArrayView2, Axis, Ix2, Ix3, IxDyn};
+use rand::Rng;
+use crate::activation::Activation;
+use crate::cross_entropy::sigmoid;
+use crate::init::{Fans, Initializer};
+use crate::layer::{Layer, Parameter, ParameterKind};
+use crate::precision::Real;
+
+// Recurrent layers over (N, T, features) batches, producing (N, T, hidden).
+//
+// A `Cell` computes one time step; `Recurrent` unrolls it over the sequence and runs
+// backpropagation through time. Per-step caches are whatever the cell needs for its
+// backward pass, kept as a list of (N, *) matrices so the unrolling code stays generic.
+
+/// One step of a recurrent network.
+pub trait Cell<F: Real> {
+    fn input_size(&self) -> usize;
+
+    fn hidden_size(&self) -> usize;
+
+    /// How many (N, hidden) arrays make up the state. The first is the output, h.
+    fn state_size(&self) -> usize {
+        1
+    }
+
+    /// Returns the next state and a cache for `step_backward`.
+    fn step(&self, x: &Array2<F>, state: &[Array2<F>]) -> (Vec<Array2<F>>, Vec<Array2<F>>);
+
+    /// Given dL/d(next state), adds the parameter gradients and returns
+    /// dL/d(x) and dL/d(previous state).
+    fn step_backward(&mut self, cache: &[Array2<F>], grad_state: &[Array2<F>]) -> (Array2<F>, Vec<Array2<F>>);
+
+    fn name(&self) -> &'static str;
+
+    fn parameters(&self) -> Vec<&Parameter<F>>;
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>>;
+}
+
+fn matrix<F: Real>(parameter: &Parameter<F>) -> ArrayView2<'_, F> {
+    parameter.value.view().into_dimensionality::<Ix2>().unwrap()
+}
+
+fn accumulate<F: Real>(parameter: &mut Parameter<F>, gradient: Array2<F>) {
+    parameter.gradient += &gradient.into_dyn();
+}
+
+fn accumulate_bias<F: Real>(parameter: &mut Parameter<F>, gradient: &Array2<F>) {
+    parameter.gradient += &gradient.sum_axis(Axis(0)).into_dyn();
+}
+
+// input weights (inputs, gates * hidden) are Xavier initialized, recurrent weights
+// (hidden, gates * hidden) orthogonal so repeated multiplication neither explodes nor vanishes
+fn recurrent_parameters<F: Real, R: Rng>(inputs: usize, hidden: usize, gates: usize, rng: &mut R) -> (Parameter<F>, Parameter<F>) {
+    let input_weights = Initializer::XavierUniform.initialize((inputs, gates * hidden), Fans::new(inputs, hidden), rng);
+    let recurrent_weights = Initializer::Orthogonal { gain: F::one() }.initialize((hidden, gates * hidden), Fans::new(hidden, hidden), rng);
+    (
+        Parameter::new("input_weights", ParameterKind::Weight, input_weights.into_dyn()),
+        Parameter::new("recurrent_weights", ParameterKind::Weight, recurrent_weights.into_dyn()),
+    )
+}
+
+/// The Elman cell, h' = activation(x Wx + h Wh + b).
+pub struct RnnCell<F: Real> {
+    input_weights: Parameter<F>,
+    recurrent_weights: Parameter<F>,
+    bias: Parameter<F>,
+    activation: Activation<F>,
+}
+
+impl<F: Real> RnnCell<F> {
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> RnnCell<F> {
+        let (input_weights, recurrent_weights) = recurrent_parameters(inputs, hidden, 1, rng);
+        RnnCell {
+            input_weights,
+            recurrent_weights,
+            bias: Parameter::new("bias", ParameterKind::Bias, ArrayD::zeros(IxDyn(&[hidden]))),
+            activation: Activation::Tanh,
+        }
+    }
+}
+
+impl<F: Real> Cell<F> for RnnCell<F> {
+    fn input_size(&self) -> usize {
+        self.input_weights.value.shape()[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.recurrent_weights.value.shape()[0]
+    }
+
+    fn step(&self, x: &Array2<F>, state: &[Array2<F>]) -> (Vec<Array2<F>>, Vec<Array2<F>>) {
+        let bias = self.bias.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let pre = x.dot(&matrix(&self.input_weights)) + state[0].dot(&matrix(&self.recurrent_weights)) + bias;
+        let h = self.activation.forward(pre.view());
+        (vec![h], vec![x.clone(), state[0].clone(), pre])
+    }
+
+    fn step_backward(&mut self, cache: &[Array2<F>], grad_state: &[Array2<F>]) -> (Array2<F>, Vec<Array2<F>>) {
+        let (x, h_prev, pre) = (&cache[0], &cache[1], &cache[2]);
+        let d_pre = self.activation.backward(pre.view(), grad_state[0].view());
+        let dx = d_pre.dot(&matrix(&self.input_weights).t());
+        let dh = d_pre.dot(&matrix(&self.recurrent_weights).t());
+        accumulate(&mut self.input_weights, x.t().dot(&d_pre));
+        accumulate(&mut self.recurrent_weights, h_prev.t().dot(&d_pre));
+        accumulate_bias(&mut self.bias, &d_pre);
+        (dx, vec![dh])
+    }
+
+    fn name(&self) -> &'static str {
+        "Rnn"
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.input_weights, &self.recurrent_weights, &self.bias]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.input_weights, &mut self.recurrent_weights, &mut self.bias]
+    }
+}
+
+/// Long short-term memory (Hochreiter & Schmidhuber), with the gates in the order
+/// input, forget, candidate, output. The state is (h, c).
+pub struct LstmCell<F: Real> {
+    input_weights: Parameter<F>,
+    recurrent_weights: Parameter<F>,
+    bias: Parameter<F>,
+}
+
+impl<F: Real> LstmCell<F> {
+    /// The forget gate's bias starts at 1 so early training remembers by default.
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> LstmCell<F> {
+        let (input_weights, recurrent_weights) = recurrent_parameters(inputs, hidden, 4, rng);
+        let mut bias = ArrayD::zeros(IxDyn(&[4 * hidden]));
+        bias.slice_mut(s![hidden..2 * hidden]).fill(F::one());
+        LstmCell {
+            input_weights,
+            recurrent_weights,
+            bias: Parameter::new("bias", ParameterKind::Bias, bias),
+        }
+    }
+}
+
+impl<F: Real> Cell<F> for LstmCell<F> {
+    fn input_size(&self) -> usize {
+        self.input_weights.value.shape()[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.recurrent_weights.value.shape()[0]
+    }
+
+    fn state_size(&self) -> usize {
+        2
+    }
+
+    fn step(&self, x: &Array2<F>, state: &[Array2<F>]) -> (Vec<Array2<F>>, Vec<Array2<F>>) {
+        let hidden = self.hidden_size();
+        let (h_prev, c_prev) = (&state[0], &state[1]);
+        let bias = self.bias.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let z = x.dot(&matrix(&self.input_weights)) + h_prev.dot(&matrix(&self.recurrent_weights)) + bias;
+        let gate = |k: usize| z.slice(s![.., k * hidden..(k + 1) * hidden]).to_owned();
+        let i = gate(0).mapv(sigmoid);
+        let f = gate(1).mapv(sigmoid);
+        let g = gate(2).mapv(|v| v.tanh());
+        let o = gate(3).mapv(sigmoid);
+        let c = &f * c_prev + &i * &g;
+        let tanh_c = c.mapv(|v| v.tanh());
+        let h = &o * &tanh_c;
+        (vec![h, c], vec![x.clone(), h_prev.clone(), c_prev.clone(), i, f, g, o, tanh_c])
+    }
+
+    fn step_backward(&mut self, cache: &[Array2<F>], grad_state: &[Array2<F>]) -> (Array2<F>, Vec<Array2<F>>) {
+        let (x, h_prev, c_prev) = (&cache[0], &cache[1], &cache[2]);
+        let (i, f, g, o, tanh_c) = (&cache[3], &cache[4], &cache[5], &cache[6], &cache[7]);
+        let (dh, dc_next) = (&grad_state[0], &grad_state[1]);
+        let one = F::one();
+
+        let dc = dc_next + &(dh * o * tanh_c.mapv(|t| one - t * t));
+        let d_i = &dc * g * i.mapv(|v| v * (one - v));
+        let d_f = &dc * c_prev * f.mapv(|v| v * (one - v));
+        let d_g = &dc * i * g.mapv(|v| one - v * v);
+        let d_o = dh * tanh_c * o.mapv(|v| v * (one - v));
+        let dz = concatenate![Axis(1), d_i, d_f, d_g, d_o];
+
+        let dx = dz.dot(&matrix(&self.input_weights).t());
+        let dh_prev = dz.dot(&matrix(&self.recurrent_weights).t());
+        accumulate(&mut self.input_weights, x.t().dot(&dz));
+        accumulate(&mut self.recurrent_weights, h_prev.t().dot(&dz));
+        accumulate_bias(&mut self.bias, &dz);
+        (dx, vec![dh_prev, dc * f])
+    }
+
+    fn name(&self) -> &'static str {
+        "Lstm"
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.input_weights, &self.recurrent_weights, &self.bias]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.input_weights, &mut self.recurrent_weights, &mut self.bias]
+    }
+}
+
+/// Gated recurrent unit (Cho et al.), with the gates in the order reset, update,
+/// candidate:
+///
+///    r = σ(x Wr + br + h Ur + cr), z = σ(x Wz + bz + h Uz + cz)
+///    n = tanh(x Wn + bn + r * (h Un + cn)), h' = (1 - z) * n + z * h
+pub struct GruCell<F: Real> {
+    input_weights: Parameter<F>,
+    recurrent_weights: Parameter<F>,
+    input_bias: Parameter<F>,
+    recurrent_bias: Parameter<F>,
+}
+
+impl<F: Real> GruCell<F> {
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> GruCell<F> {
+        let (input_weights, recurrent_weights) = recurrent_parameters(inputs, hidden, 3, rng);
+        GruCell {
+            input_weights,
+            recurrent_weights,
+            input_bias: Parameter::new("input_bias", ParameterKind::Bias, ArrayD::zeros(IxDyn(&[3 * hidden]))),
+            recurrent_bias: Parameter::new("recurrent_bias", ParameterKind::Bias, ArrayD::zeros(IxDyn(&[3 * hidden]))),
+        }
+    }
+}
+
+impl<F: Real> Cell<F> for GruCell<F> {
+    fn input_size(&self) -> usize {
+        self.input_weights.value.shape()[0]
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.recurrent_weights.value.shape()[0]
+    }
+
+    fn step(&self, x: &Array2<F>, state: &[Array2<F>]) -> (Vec<Array2<F>>, Vec<Array2<F>>) {
+        let hidden = self.hidden_size();
+        let h_prev = &state[0];
+        let input_bias = self.input_bias.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let recurrent_bias = self.recurrent_bias.value.view().into_dimensionality::<ndarray::Ix1>().unwrap();
+        let gx = x.dot(&matrix(&self.input_weights)) + input_bias;
+        let gh = h_prev.dot(&matrix(&self.recurrent_weights)) + recurrent_bias;
+        let part = |a: &Array2<F>, k: usize| a.slice(s![.., k * hidden..(k + 1) * hidden]).to_owned();
+
+        let r = (part(&gx, 0) + part(&gh, 0)).mapv(sigmoid);
+        let z = (part(&gx, 1) + part(&gh, 1)).mapv(sigmoid);
+        let gh_n = part(&gh, 2);
+        let n = (part(&gx, 2) + &r * &gh_n).mapv(|v| v.tanh());
+        let h = z.mapv(|v| F::one() - v) * &n + &z * h_prev;
+        (vec![h], vec![x.clone(), h_prev.clone(), r, z, n, gh_n])
+    }
+
+    fn step_backward(&mut self, cache: &[Array2<F>], grad_state: &[Array2<F>]) -> (Array2<F>, Vec<Array2<F>>) {
+        let (x, h_prev, r, z, n, gh_n) = (&cache[0], &cache[1], &cache[2], &cache[3], &cache[4], &cache[5]);
+        let dh = &grad_state[0];
+        let one = F::one();
+
+        let d_pre_n = dh * &z.mapv(|v| one - v) * n.mapv(|v| one - v * v);
+        let d_pre_z = dh * &(h_prev - n) * z.mapv(|v| v * (one - v));
+        let d_pre_r = &d_pre_n * gh_n * r.mapv(|v| v * (one - v));
+        let dgx = concatenate![Axis(1), d_pre_r, d_pre_z, d_pre_n];
+        let dgh = concatenate![Axis(1), d_pre_r, d_pre_z, &d_pre_n * r];
+
+        let dx = dgx.dot(&matrix(&self.input_weights).t());
+        let dh_prev = dh * z + dgh.dot(&matrix(&self.recurrent_weights).t());
+        accumulate(&mut self.input_weights, x.t().dot(&dgx));
+        accumulate(&mut self.recurrent_weights, h_prev.t().dot(&dgh));
+        accumulate_bias(&mut self.input_bias, &dgx);
+        accumulate_bias(&mut self.recurrent_bias, &dgh);
+        (dx, vec![dh_prev])
+    }
+
+    fn name(&self) -> &'static str {
+        "Gru"
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        vec![&self.input_weights, &self.recurrent_weights, &self.input_bias, &self.recurrent_bias]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        vec![&mut self.input_weights, &mut self.recurrent_weights, &mut self.input_bias, &mut self.recurrent_bias]
+    }
+}
+
+/// A layer that can be told which time steps of its (N, T, features) input are real.
+pub trait SequenceLayer<F: Real>: Layer<F> {
+    /// Sets an (N, T) mask of ones for real steps and zeros for padding, used by every
+    /// following forward pass until it is replaced or cleared with `None`.
+    fn set_mask(&mut self, mask: Option<Array2<F>>);
+}
+
+// (time step, mask column, cell cache) for each step of the last forward pass
+type Step<F> = (usize, Option<Array2<F>>, Vec<Array2<F>>);
+
+/// Unrolls a `Cell` over (N, T, inputs) sequences, giving (N, T, hidden) outputs.
+///
+/// Padded steps (mask 0) leave the state untouched and output zeros, so sequences of
+/// different lengths can share a batch.
+pub struct Recurrent<F: Real, C: Cell<F>> {
+    cell: C,
+    mask: Option<Array2<F>>,
+    truncation: Option<usize>,
+    stateful: bool,
+    reversed: bool,
+    carried_state: Option<Vec<Array2<F>>>,
+    steps: Vec<Step<F>>,
+    input_shape: Option<IxDyn>,
+}
+
+/// An Elman network, `Recurrent` over an `RnnCell`.
+pub type Rnn<F> = Recurrent<F, RnnCell<F>>;
+pub type Lstm<F> = Recurrent<F, LstmCell<F>>;
+pub type Gru<F> = Recurrent<F, GruCell<F>>;
+
+impl<F: Real> Rnn<F> {
+    /// A tanh RNN.
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> Rnn<F> {
+        Recurrent::from_cell(RnnCell::new(inputs, hidden, rng))
+    }
+
+    pub fn with_activation(mut self, activation: Activation<F>) -> Rnn<F> {
+        self.cell.activation = activation;
+        self
+    }
+}
+
+impl<F: Real> Lstm<F> {
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> Lstm<F> {
+        Recurrent::from_cell(LstmCell::new(inputs, hidden, rng))
+    }
+}
+
+impl<F: Real> Gru<F> {
+    pub fn new<R: Rng>(inputs: usize, hidden: usize, rng: &mut R) -> Gru<F> {
+        Recurrent::from_cell(GruCell::new(inputs, hidden, rng))
+    }
+}
+
+impl<F: Real, C: Cell<F>> Recurrent<F, C> {
+    pub fn from_cell(cell: C) -> Recurrent<F, C> {
+        Recurrent {
+            cell,
+            mask: None,
+            truncation: None,
+            stateful: false,
+            reversed: false,
+            carried_state: None,
+            steps: vec![],
+            input_shape: None,
+        }
+    }
+
+    /// Truncated backpropagation through time: the gradient flowing back through the
+    /// state is cut every `steps` time steps.
+    pub fn with_truncation(mut self, steps: usize) -> Recurrent<F, C> {
+        assert!(steps > 0, "truncation must be at least one step");
+        self.truncation = Some(steps);
+        self
+    }
+
+    /// Carries the final state of each forward pass over as the initial state of the next
+    /// one (without backpropagating into it), so a long sequence can be fed in chunks.
+    /// The carried state is dropped when the batch size changes or on `reset_state`.
+    pub fn with_stateful(mut self, stateful: bool) -> Recurrent<F, C> {
+        self.stateful = stateful;
+        self
+    }
+
+    /// Runs over the sequence from the last step to the first.
+    pub fn with_reversed(mut self, reversed: bool) -> Recurrent<F, C> {
+        self.reversed = reversed;
+        self
+    }
+
+    pub fn reset_state(&mut self) {
+        self.carried_state = None;
+    }
+
+    pub fn cell(&self) -> &C {
+        &self.cell
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.cell.hidden_size()
+    }
+}
+
+impl<F: Real, C: Cell<F>> Layer<F> for Recurrent<F, C> {
+    fn forward(&mut self, input: &ArrayD<F>, _training: bool) -> ArrayD<F> {
+        let x = input.view().into_dimensionality::<Ix3>().expect("recurrent layers expect (N, T, features) inputs");
+        let (n, t_len, features) = x.dim();
+        assert_eq!(features, self.cell.input_size(), "expected {} input features, got {}", self.cell.input_size(), features);
+        if let Some(mask) = &self.mask {
+            assert_eq!(mask.dim(), (n, t_len), "the mask must be (N, T)");
+        }
+        let hidden = self.cell.hidden_size();
+
+        let mut state = match self.carried_state.take() {
+            Some(state) if self.stateful && state[0].nrows() == n => state,
+            _ => vec![Array2::zeros((n, hidden)); self.cell.state_size()],
+        };
+        let mut output = Array3::zeros((n, t_len, hidden));
+        let order: Vec<usize> = if self.reversed { (0..t_len).rev().collect() } else { (0..t_len).collect() };
+        self.steps.clear();
+        for t in order {
+            let x_t = x.slice(s![.., t, ..]).to_owned();
+            let (mut next, cache) = self.cell.step(&x_t, &state);
+            let mask = self.mask.as_ref().map(|m| m.column(t).to_owned().insert_axis(Axis(1)));
+            if let Some(m) = &mask {
+                let keep = m.mapv(|v| F::one() - v);
+                for (new, old) in next.iter_mut().zip(&state) {
+                    *new = &*new * m + old * &keep;
+                }
+                output.slice_mut(s![.., t, ..]).assign(&(&next[0] * m));
+            } else {
+                output.slice_mut(s![.., t, ..]).assign(&next[0]);
+            }
+            self.steps.push((t, mask, cache));
+            state = next;
+        }
+
+        if self.stateful {
+            self.carried_state = Some(state);
+        }
+        self.input_shape = Some(input.raw_dim());
+        output.into_dyn()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let grad_output = grad_output.view().into_dimensionality::<Ix3>().unwrap();
+        let input_shape = self.input_shape.clone().expect("backward called before forward");
+        let n = input_shape[0];
+        let hidden = self.cell.hidden_size();
+        let mut grad_input = Array3::zeros((input_shape[0], input_shape[1], input_shape[2]));
+        let mut grad_state = vec![Array2::zeros((n, hidden)); self.cell.state_size()];
+
+        let steps = std::mem::take(&mut self.steps);
+        for (k, (t, mask, cache)) in steps.iter().enumerate().rev() {
+            if let Some(truncation) = self.truncation {
+                if (k + 1) % truncation == 0 {
+                    grad_state.iter_mut().for_each(|g| g.fill(F::zero()));
+                }
+            }
+            let grad_t = grad_output.slice(s![.., *t, ..]);
+            let (grad_cell, grad_skip) = match mask {
+                Some(m) => {
+                    grad_state[0] += &(&grad_t * m);
+                    let keep = m.mapv(|v| F::one() - v);
+                    let skip = grad_state.iter().map(|g| g * &keep).collect::<Vec<_>>();
+                    (grad_state.iter().map(|g| g * m).collect::<Vec<_>>(), Some(skip))
+                }
+                None => {
+                    grad_state[0] += &grad_t;
+                    (grad_state, None)
+                }
+            };
+            let (dx, mut previous) = self.cell.step_backward(cache, &grad_cell);
+            if let Some(skip) = grad_skip {
+                for (p, s) in previous.iter_mut().zip(skip) {
+                    *p += &s;
+                }
+            }
+            grad_input.slice_mut(s![.., *t, ..]).assign(&dx);
+            grad_state = previous;
+        }
+        self.steps = steps;
+        grad_input.into_dyn()
+    }
+
+    fn name(&self) -> String {
+        let direction = if self.reversed { ", reversed" } else { "" };
+        format!("{}({} -> {}{})", self.cell.name(), self.cell.input_size(), self.cell.hidden_size(), direction)
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        self.cell.parameters()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        self.cell.parameters_mut()
+    }
+}
+
+impl<F: Real, C: Cell<F>> SequenceLayer<F> for Recurrent<F, C> {
+    fn set_mask(&mut self, mask: Option<Array2<F>>) {
+        self.mask = mask;
+    }
+}
+
+/// Runs one recurrent layer forwards and another backwards over the sequence and
+/// concatenates their outputs, (N, T, forward hidden + backward hidden).
+pub struct Bidirectional<F: Real, C: Cell<F>> {
+    forward: Recurrent<F, C>,
+    backward: Recurrent<F, C>,
+}
+
+impl<F: Real, C: Cell<F>> Bidirectional<F, C> {
+    /// `backward` is switched to run in reverse.
+    pub fn new(forward: Recurrent<F, C>, backward: Recurrent<F, C>) -> Bidirectional<F, C> {
+        assert_eq!(forward.cell.input_size(), backward.cell.input_size(), "both directions must take the same inputs");
+        Bidirectional { forward: forward.with_reversed(false), backward: backward.with_reversed(true) }
+    }
+}
+
+impl<F: Real, C: Cell<F>> Layer<F> for Bidirectional<F, C> {
+    fn forward(&mut self, input: &ArrayD<F>, training: bool) -> ArrayD<F> {
+        let forward = self.forward.forward(input, training);
+        let backward = self.backward.forward(input, training);
+        concatenate(Axis(2), &[forward.view(), backward.view()]).unwrap()
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let split = self.forward.hidden_size();
+        let grad_forward = grad_output.slice_axis(Axis(2), (..split).into()).to_owned();
+        let grad_backward = grad_output.slice_axis(Axis(2), (split..).into()).to_owned();
+        self.forward.backward(&grad_forward) + self.backward.backward(&grad_backward)
+    }
+
+    fn name(&self) -> String {
+        format!("Bidirectional({}, {})", self.forward.name(), self.backward.name())
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        let mut parameters = self.forward.parameters();
+        parameters.extend(self.backward.parameters());
+        parameters
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        let mut parameters = self.forward.parameters_mut();
+        parameters.extend(self.backward.parameters_mut());
+        parameters
+    }
+}
+
+impl<F: Real, C: Cell<F>> SequenceLayer<F> for Bidirectional<F, C> {
+    fn set_mask(&mut self, mask: Option<Array2<F>>) {
+        self.forward.set_mask(mask.clone());
+        self.backward.set_mask(mask);
+    }
+}
+
+/// Sequence layers applied one on top of another, sharing one mask.
+///
+/// ```ignore
+/// let mut encoder = Stacked::new()
+///     .add(Bidirectional::new(Lstm::new(16, 32, &mut rng), Lstm::new(16, 32, &mut rng)))
+///     .add(Lstm::new(64, 32, &mut rng));
+/// encoder.set_mask(Some(mask));
+/// ```
+#[derive(Default)]
+pub struct Stacked<F: Real> {
+    layers: Vec<Box<dyn SequenceLayer<F>>>,
+}
+
+impl<F: Real> Stacked<F> {
+    pub fn new() -> Stacked<F> {
+        Stacked { layers: vec![] }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<L: SequenceLayer<F> + 'static>(mut self, layer: L) -> Stacked<F> {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl<F: Real> Layer<F> for Stacked<F> {
+    fn forward(&mut self, input: &ArrayD<F>, training: bool) -> ArrayD<F> {
+        let mut activations = input.clone();
+        for layer in self.layers.iter_mut() {
+            activations = layer.forward(&activations, training);
+        }
+        activations
+    }
+
+    fn backward(&mut self, grad_output: &ArrayD<F>) -> ArrayD<F> {
+        let mut grad = grad_output.clone();
+        for layer in self.layers.iter_mut().rev() {
+            grad = layer.backward(&grad);
+        }
+        grad
+    }
+
+    fn name(&self) -> String {
+        let names = self.layers.iter().map(|layer| layer.name()).collect::<Vec<_>>();
+        format!("Stacked({})", names.join(", "))
+    }
+
+    fn parameters(&self) -> Vec<&Parameter<F>> {
+        self.layers.iter().flat_map(|layer| layer.parameters()).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Parameter<F>> {
+        self.layers.iter_mut().flat_map(|layer| layer.parameters_mut()).collect()
+    }
+}
+
+impl<F: Real> SequenceLayer<F> for Stacked<F> {
+    fn set_mask(&mut self, mask: Option<Array2<F>>) {
+        for layer in self.layers.iter_mut() {
+            layer.set_mask(mask.clone());
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use ndarray::{Array, Array2, ArrayView, Axis, Dimension};
+use crate::layer::{Parameter, ParameterKind};
+use crate::precision::{cast, Real};
+
+/// Penalties and constraints that keep the weights from fitting the training set's noise.
+///
+/// L1 and L2 only apply to weights (`ParameterKind::Weight`); decaying biases and
+/// normalization parameters doesn't reduce overfitting and can hurt. Dropout is a layer
+/// of its own, see `layers::Dropout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regularization<F: Real> {
+    /// Adds l1 * sum(|w|) to the loss, which pushes unhelpful weights to exactly 0.
+    pub l1: F,
+    /// Adds l2 / 2 * sum(w^2) to the loss (weight decay).
+    pub l2: F,
+    /// After each update, rescales any unit whose incoming weight vector is longer than
+    /// this back onto the ball of that radius.
+    pub max_norm: Option<F>,
+    /// Mixes targets with the uniform distribution before they reach the loss:
+    /// (1 - eps) * t + eps / K. Losses with their own smoothing (`CrossEntropyLoss`)
+    /// shouldn't be given both.
+    pub label_smoothing: F,
+}
+
+impl<F: Real> Default for Regularization<F> {
+    fn default() -> Self {
+        Regularization {
+            l1: F::zero(),
+            l2: F::zero(),
+            max_norm: None,
+            label_smoothing: F::zero(),
+        }
+    }
+}
+
+impl<F: Real> Regularization<F> {
+    pub fn none() -> Regularization<F> {
+        Self::default()
+    }
+
+    pub fn with_l1(mut self, l1: F) -> Regularization<F> {
+        self.l1 = l1;
+        self
+    }
+
+    pub fn with_l2(mut self, l2: F) -> Regularization<F> {
+        self.l2 = l2;
+        self
+    }
+
+    pub fn with_max_norm(mut self, max_norm: F) -> Regularization<F> {
+        self.max_norm = Some(max_norm);
+        self
+    }
+
+    pub fn with_label_smoothing(mut self, label_smoothing: F) -> Regularization<F> {
+        self.label_smoothing = label_smoothing;
+        self
+    }
+
+    /// The L1 + L2 penalty of a single weight array.
+    pub fn penalty_of<D: Dimension>(&self, weights: ArrayView<F, D>) -> F {
+        if self.l1 == F::zero() && self.l2 == F::zero() {
+            return F::zero();
+        }
+        let half = cast::<F>(0.5);
+        weights.iter().fold(F::zero(), |acc, &w| acc + self.l1 * w.abs() + half * self.l2 * w * w)
+    }
+
+    /// The gradient of `penalty_of` with respect to the weights.
+    pub fn gradient_of<D: Dimension>(&self, weights: ArrayView<F, D>) -> Array<F, D> {
+        weights.mapv(|w| self.l1 * sign(w) + self.l2 * w)
+    }
+
+    /// The total penalty over every weight parameter.
+    pub fn penalty(&self, parameters: &[&Parameter<F>]) -> F {
+        parameters.iter()
+            .filter(|p| p.kind == ParameterKind::Weight)
+            .map(|p| self.penalty_of(p.value.view()))
+            .sum()
+    }
+
+    /// Adds the penalty's gradient to the gradient of every weight parameter.
+    pub fn add_gradients(&self, parameters: &mut [&mut Parameter<F>]) {
+        if self.l1 == F::zero() && self.l2 == F::zero() {
+            return;
+        }
+        for parameter in parameters.iter_mut().filter(|p| p.kind == ParameterKind::Weight) {
+            let gradient = self.gradient_of(parameter.value.view());
+            parameter.gradient += &gradient;
+        }
+    }
+
+    /// Enforces the max-norm constraint on every weight parameter.
+    pub fn apply_max_norm(&self, parameters: &mut [&mut Parameter<F>]) {
+        if let Some(limit) = self.max_norm {
+            for parameter in parameters.iter_mut().filter(|p| p.kind == ParameterKind::Weight) {
+                clip_unit_norms(&mut parameter.value, limit);
+            }
+        }
+    }
+
+    /// Applies label smoothing to a batch of one-hot (or probability) target rows.
+    pub fn smooth_targets(&self, targets: &Array2<F>) -> Array2<F> {
+        smooth_labels(targets, self.label_smoothing)
+    }
+}
+
+/// (1 - eps) * t + eps / K for each row of K targets.
+pub fn smooth_labels<F: Real>(targets: &Array2<F>, epsilon: F) -> Array2<F> {
+    if epsilon == F::zero() {
+        return targets.clone();
+    }
+    let uniform = epsilon / cast::<F>(targets.ncols().max(1) as f64);
+    targets.mapv(|t| (F::one() - epsilon) * t + uniform)
+}
+
+/// Rescales each unit's incoming weights to a Euclidean norm of at most `limit`.
+///
+/// A unit's incoming weights are a column of a (inputs, outputs) dense matrix, and a
+/// slice along the first axis of (out_channels, ...) convolution kernels. A 1-D array
+/// (e.g. a perceptron's weights) is a single unit.
+pub fn clip_unit_norms<F: Real, D: Dimension>(weights: &mut Array<F, D>, limit: F) {
+    let axis = match weights.ndim() {
+        0 => return,
+        1 => {
+            clip_lane(weights.view_mut().into_dyn(), limit);
+            return;
+        }
+        2 => Axis(1),
+        _ => Axis(0),
+    };
+    for unit in weights.view_mut().into_dyn().axis_iter_mut(axis) {
+        clip_lane(unit, limit);
+    }
+}
+
+fn clip_lane<F: Real>(mut lane: ndarray::ArrayViewMutD<F>, limit: F) {
+    let norm = lane.iter().map(|&w| w * w).sum::<F>().sqrt();
+    if norm > limit {
+        let scale = limit / norm;
+        lane.mapv_inplace(|w| w * scale);
+    }
+}
+
+fn sign<F: Real>(w: F) -> F {
+    if w > F::zero() {
+        F::one()
+    } else if w < F::zero() {
+        -F::one()
+    } else {
+        F::zero()
+    }
+}
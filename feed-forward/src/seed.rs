@@ -0,0 +1,32 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// Every random choice in the crate (weight initialization, shuffling, dropout masks)
+// draws from an StdRng handed out by `rng_from_seed`, so fixing a seed fixes the run.
+//
+// Seeds are always passed in explicitly, never read from process-wide state, so a run
+// doesn't depend on the order RNGs are created in or on which thread creates them.
+// `derive_seed` splits one seed into several when something needs more than one stream.
+// Without a seed we use OS entropy.
+
+/// Creates the random number generator for a model or layer.
+/// `Some(seed)` gives the same stream on every run; `None` seeds from OS entropy.
+pub fn rng_from_seed(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Derives an independent child seed, e.g. one per dropout layer of a seeded model.
+pub fn derive_seed<R: Rng>(rng: &mut R) -> u64 {
+    rng.gen()
+}
+
+/// The indices 0..n in a random order, for shuffling training samples each epoch.
+pub fn shuffled_indices<R: Rng>(n: usize, rng: &mut R) -> Vec<usize> {
+    let mut indices = (0..n).collect::<Vec<usize>>();
+    indices.shuffle(rng);
+    indices
+}
@@ -0,0 +1,386 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ndarray::ArrayView2;
+
+// TensorBoard reads "event files": TFRecord files whose records are serialized `Event`
+// protocol buffers. Both layers are simple enough to write by hand.
+//
+// A TFRecord is
+//
+//   u64 length, u32 masked crc32c(length), data, u32 masked crc32c(data)
+//
+// all little endian. The protobuf messages we need (field numbers from TensorFlow's
+// event.proto and summary.proto) are
+//
+//   Event          1 wall_time: double, 2 step: int64, 3 file_version: string,
+//                  5 summary: Summary
+//   Summary        1 value: repeated Value
+//   Value          1 tag: string, 2 simple_value: float, 4 image: Image,
+//                  5 histo: HistogramProto
+//   Image          1 height: int32, 2 width: int32, 3 colorspace: int32,
+//                  4 encoded_image_string: bytes (a PNG)
+//   HistogramProto 1 min, 2 max, 3 num, 4 sum, 5 sum_squares: double,
+//                  6 bucket_limit, 7 bucket: packed repeated double
+//
+// The embedding projector doesn't use event files; it reads projector_config.pbtxt and
+// the TSV files it points to, see `write_projector`.
+
+/// Writes scalars, histograms and images to an event file in a log directory, for
+/// `tensorboard --logdir`.
+pub struct SummaryWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl SummaryWriter {
+    /// Starts a new event file in `logdir`, creating the directory if needed.
+    pub fn create(logdir: impl AsRef<Path>) -> io::Result<SummaryWriter> {
+        let logdir = logdir.as_ref();
+        fs::create_dir_all(logdir)?;
+        let host = fs::read_to_string("/etc/hostname").ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| "localhost".to_string());
+        let name = format!("events.out.tfevents.{:010}.{}.{}", wall_time() as u64, host, std::process::id());
+        let path = logdir.join(name);
+        let mut writer = SummaryWriter { path: path.clone(), out: BufWriter::new(File::create(path)?) };
+        let mut event = event_header(0);
+        string_field(&mut event, 3, "brain.Event:2");
+        writer.write_event(&event)?;
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn add_scalar(&mut self, tag: &str, value: f64, step: u64) -> io::Result<()> {
+        let mut summary_value = vec![];
+        string_field(&mut summary_value, 1, tag);
+        key(&mut summary_value, 2, FIXED32);
+        summary_value.extend_from_slice(&(value as f32).to_le_bytes());
+        self.write_summary(&summary_value, step)
+    }
+
+    /// The distribution of `values` in `bins` equal-width buckets.
+    pub fn add_histogram(&mut self, tag: &str, values: &[f64], bins: usize, step: u64) -> io::Result<()> {
+        let histogram = Histogram::new(values, bins);
+        let mut summary_value = vec![];
+        string_field(&mut summary_value, 1, tag);
+        message_field(&mut summary_value, 5, &histogram.encode());
+        self.write_summary(&summary_value, step)
+    }
+
+    /// An 8-bit image given row by row, with 1 (grey), 3 (RGB) or 4 (RGBA) `channels`
+    /// interleaved.
+    pub fn add_image(&mut self, tag: &str, height: usize, width: usize, channels: usize, pixels: &[u8], step: u64) -> io::Result<()> {
+        let png = encode_png(height, width, channels, pixels)?;
+        let mut image = vec![];
+        varint_field(&mut image, 1, height as u64);
+        varint_field(&mut image, 2, width as u64);
+        varint_field(&mut image, 3, channels as u64);
+        bytes_field(&mut image, 4, &png);
+        let mut summary_value = vec![];
+        string_field(&mut summary_value, 1, tag);
+        message_field(&mut summary_value, 4, &image);
+        self.write_summary(&summary_value, step)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn write_summary(&mut self, summary_value: &[u8], step: u64) -> io::Result<()> {
+        let mut summary = vec![];
+        message_field(&mut summary, 1, summary_value);
+        let mut event = event_header(step);
+        message_field(&mut event, 5, &summary);
+        self.write_event(&event)
+    }
+
+    fn write_event(&mut self, event: &[u8]) -> io::Result<()> {
+        write_record(&mut self.out, event)
+    }
+}
+
+impl Drop for SummaryWriter {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+fn wall_time() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64())
+}
+
+fn event_header(step: u64) -> Vec<u8> {
+    let mut event = vec![];
+    key(&mut event, 1, FIXED64);
+    event.extend_from_slice(&wall_time().to_le_bytes());
+    varint_field(&mut event, 2, step);
+    event
+}
+
+/// Bucket counts as TensorBoard's `HistogramProto` has them: `limits[i]` is the upper
+/// edge of bucket i.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    pub num: f64,
+    pub sum: f64,
+    pub sum_squares: f64,
+    pub limits: Vec<f64>,
+    pub counts: Vec<f64>,
+}
+
+impl Histogram {
+    /// Non-finite values are left out.
+    pub fn new(values: &[f64], bins: usize) -> Histogram {
+        let values = values.iter().copied().filter(|v| v.is_finite()).collect::<Vec<_>>();
+        let bins = bins.max(1);
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if values.is_empty() {
+            return Histogram { min: 0.0, max: 0.0, num: 0.0, sum: 0.0, sum_squares: 0.0, limits: vec![], counts: vec![] };
+        }
+        // a constant gets one bucket of its own, which TensorBoard draws fine
+        let width = if max > min { (max - min) / bins as f64 } else { 1.0 };
+        let bins = if max > min { bins } else { 1 };
+        let mut counts = vec![0.0; bins];
+        for &v in &values {
+            counts[(((v - min) / width) as usize).min(bins - 1)] += 1.0;
+        }
+        let mut limits = (1..=bins).map(|i| min + width * i as f64).collect::<Vec<_>>();
+        limits[bins - 1] = max;
+        Histogram {
+            min,
+            max,
+            num: values.len() as f64,
+            sum: values.iter().sum(),
+            sum_squares: values.iter().map(|v| v * v).sum(),
+            limits,
+            counts,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        for (field, value) in [(1, self.min), (2, self.max), (3, self.num), (4, self.sum), (5, self.sum_squares)] {
+            key(&mut out, field, FIXED64);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        for (field, values) in [(6, &self.limits), (7, &self.counts)] {
+            bytes_field(&mut out, field, &values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>());
+        }
+        out
+    }
+}
+
+/// Adds `name` to the embedding projector of `logdir`: the vectors go to
+/// `<name>/tensors.tsv`, one label per row to `<name>/metadata.tsv`, and every
+/// embedding written so far is listed in `projector_config.pbtxt`.
+pub fn write_projector(logdir: impl AsRef<Path>, name: &str, labels: &[String], vectors: ArrayView2<f32>) -> io::Result<()> {
+    if labels.len() != vectors.nrows() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} labels for {} vectors", labels.len(), vectors.nrows())));
+    }
+    let logdir = logdir.as_ref();
+    let directory = logdir.join(name);
+    fs::create_dir_all(&directory)?;
+    let mut tensors = BufWriter::new(File::create(directory.join("tensors.tsv"))?);
+    for row in vectors.outer_iter() {
+        writeln!(tensors, "{}", row.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\t"))?;
+    }
+    tensors.flush()?;
+    let mut metadata = BufWriter::new(File::create(directory.join("metadata.tsv"))?);
+    for label in labels {
+        // a label with a tab or newline would shift every row after it
+        writeln!(metadata, "{}", label.replace(['\t', '\n', '\r'], " "))?;
+    }
+    metadata.flush()?;
+
+    let mut names = fs::read_dir(logdir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("tensors.tsv").is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    names.sort();
+    let mut config = String::new();
+    for name in names {
+        config.push_str(&format!(
+            "embeddings {{\n  tensor_name: \"{0}\"\n  tensor_path: \"{0}/tensors.tsv\"\n  metadata_path: \"{0}/metadata.tsv\"\n}}\n",
+            name.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+    fs::write(logdir.join("projector_config.pbtxt"), config)
+}
+
+/// Writes `data` as one TFRecord.
+pub fn write_record<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    let length = (data.len() as u64).to_le_bytes();
+    out.write_all(&length)?;
+    out.write_all(&masked_crc32c(&length).to_le_bytes())?;
+    out.write_all(data)?;
+    out.write_all(&masked_crc32c(data).to_le_bytes())
+}
+
+/// Reads every record of a TFRecord file, checking both checksums of each.
+pub fn read_records(path: impl AsRef<Path>) -> io::Result<Vec<Vec<u8>>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut records = vec![];
+    let mut length = [0u8; 8];
+    loop {
+        match input.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(records),
+            Err(e) => return Err(e),
+        }
+        if read_u32(&mut input)? != masked_crc32c(&length) {
+            return Err(invalid("corrupt record length"));
+        }
+        let mut data = vec![0u8; u64::from_le_bytes(length) as usize];
+        input.read_exact(&mut data)?;
+        if read_u32(&mut input)? != masked_crc32c(&data) {
+            return Err(invalid("corrupt record"));
+        }
+        records.push(data);
+    }
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// protobuf wire types
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+const FIXED32: u8 = 5;
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn key(out: &mut Vec<u8>, field: u32, wire_type: u8) {
+    varint(out, ((field as u64) << 3) | wire_type as u64);
+}
+
+fn varint_field(out: &mut Vec<u8>, field: u32, value: u64) {
+    key(out, field, VARINT);
+    varint(out, value);
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    key(out, field, LENGTH_DELIMITED);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn string_field(out: &mut Vec<u8>, field: u32, text: &str) {
+    bytes_field(out, field, text.as_bytes());
+}
+
+fn message_field(out: &mut Vec<u8>, field: u32, message: &[u8]) {
+    bytes_field(out, field, message);
+}
+
+/// CRC-32C (Castagnoli), the checksum of TFRecords.
+pub fn crc32c(data: &[u8]) -> u32 {
+    crc(data, 0x82f6_3b78)
+}
+
+/// TFRecords store their CRCs rotated and offset, so that a CRC of data that itself
+/// contains CRCs is still a good checksum.
+pub fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    (crc.rotate_right(15)).wrapping_add(0xa282_ead8)
+}
+
+/// The CRC-32 of zlib and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    crc(data, 0xedb8_8320)
+}
+
+// bitwise, reflected; event files are small enough that a table buys nothing
+fn crc(data: &[u8], polynomial: u32) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ polynomial } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// A PNG with the pixel data in stored (uncompressed) deflate blocks, which every
+/// decoder reads and which is plenty for thumbnails like MNIST digits.
+pub fn encode_png(height: usize, width: usize, channels: usize, pixels: &[u8]) -> io::Result<Vec<u8>> {
+    let color_type = match channels {
+        1 => 0,
+        3 => 2,
+        4 => 6,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("images have 1, 3 or 4 channels, not {}", channels))),
+    };
+    if pixels.len() != height * width * channels || height == 0 || width == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} bytes for a {}x{}x{} image", pixels.len(), height, width, channels)));
+    }
+
+    // every scanline starts with its filter type, 0 for none
+    let mut raw = Vec::with_capacity(height * (width * channels + 1));
+    for row in pixels.chunks(width * channels) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(65_535).collect::<Vec<_>>();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push((i + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", &header), (b"IDAT", &zlib), (b"IEND", &vec![])] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    Ok(png)
+}
@@ -1,3 +1,15 @@
+// fixtures shared by the test modules below
+mod common {
+    use feed_forward::init::{Fans, Initializer};
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::{ArrayD, IxDyn};
+
+    /// Standard normal values of the given shape, the same for the same seed.
+    pub fn random_input(shape: &[usize], seed: u64) -> ArrayD<f64> {
+        Initializer::Normal { mean: 0.0, std: 1.0 }.initialize(IxDyn(shape), Fans::new(1, 1), &mut rng_from_seed(Some(seed)))
+    }
+}
+
 mod ff_tests {
 
     // #[test]
@@ -20,10 +32,1038 @@ mod softmax_tests {
         let data = ndarray::array![1.0, 2.0, 3.0];
         let view = data.view();
         let softmaxed_data = feed_forward::cross_entropy::softmax(view);
-        assert_eq!(softmaxed_data, ndarray::array![0.09003057317038046, 0.24472847105479767, 0.6652409557748219]);
+        // the max logit is subtracted before exponentiating, which moves the last digit of two entries
+        assert_eq!(softmaxed_data, ndarray::array![0.09003057317038046, 0.24472847105479764, 0.6652409557748218]);
 
         // check if the sum of the softmaxed data is approximately 1 (to account for fp errors)
         let sum: f64 = softmaxed_data.iter().sum();
         assert!((sum - 1.0).abs() < 1e-10);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_softmax_large_logits() {
+        let data = ndarray::array![1000.0f64, 1001.0, 1002.0];
+        let softmaxed_data = feed_forward::cross_entropy::softmax(data.view());
+        assert!(softmaxed_data.iter().all(|p| p.is_finite()));
+        let shifted = feed_forward::cross_entropy::softmax(ndarray::array![1.0f64, 2.0, 3.0].view());
+        for (a, b) in softmaxed_data.iter().zip(shifted.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_log_softmax_rows() {
+        let data = ndarray::array![[1.0f64, 2.0, 3.0], [-1000.0, 0.0, 1000.0]];
+        let log_probs = feed_forward::cross_entropy::log_softmax_rows(data.view());
+        let probs = feed_forward::cross_entropy::softmax_rows(data.view());
+        assert!(log_probs.iter().all(|p| p.is_finite()));
+        for (lp, p) in log_probs.row(0).iter().zip(probs.row(0).iter()) {
+            assert!((lp.exp() - p).abs() < 1e-12);
+        }
+        assert!((log_probs[[1, 0]] + 2000.0).abs() < 1e-9);
+    }
+}
+
+mod cross_entropy_tests {
+    use feed_forward::cross_entropy::*;
+    use ndarray::{array, Array2};
+
+    // central differences of a scalar function of the logits
+    fn numerical_grad(logits: &Array2<f64>, f: impl Fn(&Array2<f64>) -> f64) -> Array2<f64> {
+        let eps = 1e-6;
+        let mut grad = Array2::zeros(logits.raw_dim());
+        for idx in ndarray::indices(logits.dim()) {
+            let mut plus = logits.clone();
+            plus[idx] += eps;
+            let mut minus = logits.clone();
+            minus[idx] -= eps;
+            grad[idx] = (f(&plus) - f(&minus)) / (2.0 * eps);
+        }
+        grad
+    }
+
+    #[test]
+    fn test_cross_entropy_matches_manual() {
+        let logits = array![[2.0f64, 1.0, 0.1], [0.5, 2.5, 0.3]];
+        let targets = [0, 1];
+        let loss = cross_entropy(logits.view(), &targets, &CrossEntropyOptions::default());
+        let log_probs = log_softmax_rows(logits.view());
+        let expected = -(log_probs[[0, 0]] + log_probs[[1, 1]]) / 2.0;
+        assert!((loss - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_cross_entropy_grad_with_smoothing_and_weights() {
+        let logits = array![[2.0, 1.0, 0.1], [0.5, 2.5, 0.3], [-1.0, 0.0, 4.0]];
+        let targets = [0, 1, 1];
+        let options = CrossEntropyOptions {
+            label_smoothing: 0.1,
+            class_weights: Some(array![1.0, 2.0, 0.5]),
+        };
+        let analytic = cross_entropy_grad(logits.view(), &targets, &options);
+        let numeric = numerical_grad(&logits, |l| cross_entropy(l.view(), &targets, &options));
+        for (a, n) in analytic.iter().zip(numeric.iter()) {
+            assert!((a - n).abs() < 1e-6, "analytic {} vs numeric {}", a, n);
+        }
+    }
+
+    #[test]
+    fn test_binary_cross_entropy_with_logits() {
+        let logits = array![0.0f64, 800.0, -800.0, 1.5];
+        let targets = array![1.0, 1.0, 0.0, 0.0];
+        let (loss, grad) = binary_cross_entropy_with_logits_and_grad(logits.view(), targets.view(), Some(2.0));
+        assert!(loss.is_finite());
+        // only the first and last samples are wrong enough to contribute
+        let expected = (2.0 * 2f64.ln() + (1.0 + 1.5f64.exp()).ln()) / 4.0;
+        assert!((loss - expected).abs() < 1e-12);
+
+        let as_matrix = logits.clone().insert_axis(ndarray::Axis(0));
+        let numeric = numerical_grad(&as_matrix, |l| {
+            binary_cross_entropy_with_logits(l.row(0), targets.view(), Some(2.0))
+        });
+        for (a, n) in grad.iter().zip(numeric.iter()) {
+            assert!((a - n).abs() < 1e-6, "analytic {} vs numeric {}", a, n);
+        }
+    }
+
+    #[test]
+    fn test_softmax_and_log_softmax_backward() {
+        let x = array![0.3, -1.2, 2.0];
+        let g = array![1.0, -0.5, 0.25];
+        let as_matrix = x.clone().insert_axis(ndarray::Axis(0));
+
+        let analytic = softmax_backward(softmax(x.view()).view(), g.view());
+        let numeric = numerical_grad(&as_matrix, |l| softmax(l.row(0)).dot(&g));
+        for (a, n) in analytic.iter().zip(numeric.iter()) {
+            assert!((a - n).abs() < 1e-6);
+        }
+
+        let analytic = log_softmax_backward(x.view(), g.view());
+        let numeric = numerical_grad(&as_matrix, |l| log_softmax(l.row(0)).dot(&g));
+        for (a, n) in analytic.iter().zip(numeric.iter()) {
+            assert!((a - n).abs() < 1e-6);
+        }
+    }
+}
+mod precision_tests {
+    use feed_forward::perceptron::Perceptron;
+    use feed_forward::precision::Accumulation;
+
+    #[test]
+    fn test_normalize_keeps_fractions() {
+        let data = ndarray::array![[0u8, 85], [170, 255]];
+        let normalized: ndarray::Array2<f32> = Perceptron::<f32>::normalize(&data);
+        assert_eq!(normalized, ndarray::array![[0f32, 1f32 / 3f32], [2f32 / 3f32, 1f32]]);
+    }
+
+    #[test]
+    fn test_f32_perceptron_with_f64_accumulation() {
+        // x > y is linearly separable, so both precisions should learn it
+        let data = ndarray::array![[255u8, 0], [200, 10], [0, 255], [10, 200]];
+        let labels = vec![1u8, 1, 0, 0];
+        let mut model = Perceptron::<f32>::new(2).with_accumulation(Accumulation::F64);
+        model.train(&data, &labels, 10);
+
+        let normalized = Perceptron::<f32>::normalize(&data);
+        for (row, &label) in normalized.outer_iter().zip(labels.iter()) {
+            assert_eq!(model.predict(row).0, label as f32);
+        }
+    }
+
+    #[test]
+    fn test_multi_class_predicts_most_confident_class() {
+        use feed_forward::perceptron::MultiClassPerceptron;
+        // classes 7, 3 and 5 respond to the first, second and third input
+        let perceptrons = (0..3).map(|i| {
+            let mut weights = ndarray::Array1::<f64>::zeros(3);
+            weights[i] = 1.0;
+            Perceptron::from_weights(weights, -0.5)
+        }).collect();
+        let model = MultiClassPerceptron::from_perceptrons(vec![7, 3, 5], perceptrons);
+        assert_eq!(model.predict(ndarray::array![0.0, 0.0, 1.0].view()), 5);
+        assert_eq!(model.predict(ndarray::array![0.0, 0.9, 0.7].view()), 3);
+        // none fires: the one closest to firing wins
+        assert_eq!(model.predict(ndarray::array![0.1, 0.0, 0.4].view()), 5);
+    }
+
+    #[test]
+    fn test_accumulation_matches_native_dot() {
+        let a = ndarray::array![0.1f32, 0.2, 0.3];
+        let b = ndarray::array![1.0f32, 2.0, 3.0];
+        let native = Accumulation::Native.dot(a.view(), b.view());
+        let wide = Accumulation::F64.dot(a.view(), b.view());
+        assert!((native - wide).abs() < 1e-6);
+    }
+}
+
+mod loss_tests {
+    use feed_forward::gradcheck::{check_gradient, GradCheckConfig};
+    use feed_forward::loss::*;
+    use ndarray::{array, Array2};
+
+    // compares a loss' analytic gradient with central differences of its reduced value
+    fn check_loss_gradient(loss: &dyn Loss<f64>, predictions: &Array2<f64>, targets: &Array2<f64>) {
+        let analytic = loss.compute(predictions.view(), targets.view()).gradient;
+        let config = GradCheckConfig { relative_tolerance: 1e-5, absolute_tolerance: 1e-7, ..Default::default() };
+        check_gradient(|p| loss.compute(p.view(), targets.view()).scalar(), predictions, &analytic, &config).assert_passed();
+    }
+
+    #[test]
+    fn test_reductions() {
+        let predictions = array![[1.0, 2.0], [3.0, 5.0]];
+        let targets = array![[1.0, 1.0], [1.0, 1.0]];
+        let mean = MeanSquaredError::new().compute(predictions.view(), targets.view());
+        let sum = MeanSquaredError::new().with_reduction(Reduction::Sum).compute(predictions.view(), targets.view());
+        let none = MeanSquaredError::new().with_reduction(Reduction::None).compute(predictions.view(), targets.view());
+        assert_eq!(none.value, array![0.5, 10.0]);
+        assert_eq!(sum.value, array![10.5]);
+        assert_eq!(mean.value, array![5.25]);
+        assert_eq!(mean.gradient, &sum.gradient / 2.0);
+    }
+
+    #[test]
+    fn test_regression_loss_gradients() {
+        let predictions = array![[0.3, -1.2, 2.5], [0.9, 0.1, -0.4]];
+        let targets = array![[0.0, -1.0, 1.0], [1.0, 0.5, 0.2]];
+        check_loss_gradient(&MeanSquaredError::new(), &predictions, &targets);
+        check_loss_gradient(&MeanAbsoluteError::new(), &predictions, &targets);
+        check_loss_gradient(&Huber::new(0.5), &predictions, &targets);
+    }
+
+    #[test]
+    fn test_margin_loss_gradients() {
+        let predictions = array![[0.3, -1.2], [2.5, 0.4]];
+        let targets = array![[1.0, 1.0], [-1.0, 1.0]];
+        check_loss_gradient(&Hinge::new(1.0), &predictions, &targets);
+        check_loss_gradient(&SquaredHinge::new(1.0), &predictions, &targets);
+
+        // with no margin the hinge loss only counts misclassified samples, like the perceptron rule
+        let perceptron = Hinge::new(0.0).with_reduction(Reduction::None).compute(predictions.view(), targets.view());
+        assert_eq!(perceptron.value, array![0.6, 1.25]);
+    }
+
+    #[test]
+    fn test_probabilistic_loss_gradients() {
+        let logits = array![[2.0, -1.0, 0.5], [0.1, 0.2, 3.0]];
+        let one_hot = array![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        check_loss_gradient(&CrossEntropyLoss::new().with_label_smoothing(0.1), &logits, &one_hot);
+        check_loss_gradient(&FocalLoss::new(2.0, Some(0.25)), &logits, &one_hot);
+
+        let log_probs = feed_forward::cross_entropy::log_softmax_rows(logits.view());
+        let distribution = array![[0.7, 0.2, 0.1], [0.0, 0.5, 0.5]];
+        check_loss_gradient(&NegativeLogLikelihood::new(), &log_probs, &one_hot);
+        check_loss_gradient(&KlDivergence::new(), &log_probs, &distribution);
+
+        // KL of a distribution with itself is 0
+        let kl = KlDivergence::new().compute(distribution.mapv(|p: f64| p.max(1e-300).ln()).view(), distribution.view());
+        assert!(kl.scalar().abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_weighted_cross_entropy_matches_integer_labels() {
+        use feed_forward::cross_entropy::{cross_entropy_with_grad, CrossEntropyOptions};
+        let logits = array![[2.0, -1.0, 0.5], [0.1, 0.2, 3.0], [1.0, 1.0, -2.0]];
+        let one_hot = array![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        let options = CrossEntropyOptions { label_smoothing: 0.1, class_weights: Some(array![1.0, 2.0, 0.5]) };
+        let loss = CrossEntropyLoss::with_options(options.clone());
+        check_loss_gradient(&loss, &logits, &one_hot);
+        check_loss_gradient(&BceWithLogitsLoss::new(Some(3.0)), &logits, &one_hot);
+
+        let output = loss.compute(logits.view(), one_hot.view());
+        let (value, gradient) = cross_entropy_with_grad(logits.view(), &[0, 1, 1], &options);
+        assert_eq!(output.scalar(), value);
+        assert_eq!(output.gradient, gradient);
+        // the mean is over the summed weights, 1 + 2 + 2
+        let summed = loss.with_reduction(Reduction::Sum).compute(logits.view(), one_hot.view());
+        assert!((summed.scalar() / 5.0 - value).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_focal_loss_without_focusing_is_bce() {
+        let logits = array![[2.0f64, -1.0], [0.5, 3.0]];
+        let targets = array![[1.0, 0.0], [0.0, 1.0]];
+        let focal = FocalLoss::new(0.0, None).compute(logits.view(), targets.view()).scalar();
+        let bce = feed_forward::cross_entropy::binary_cross_entropy_with_logits(
+            logits.view().into_shape(4).unwrap(), targets.view().into_shape(4).unwrap(), None);
+        assert!((focal - bce).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_embedding_loss_gradients() {
+        let pairs = array![[1.0, 0.5, -0.2], [0.8, 0.7, 0.1], [0.3, -0.9, 0.4], [0.2, -0.6, 0.5]];
+        let labels = array![[1.0], [-1.0]];
+        check_loss_gradient(&CosineEmbedding::new(0.1), &pairs, &labels);
+
+        let triplets = array![[0.0, 0.0], [1.0, 0.5], [0.4, 0.3], [1.0, 1.0], [1.1, 0.9], [-1.0, 2.0]];
+        let no_targets = Array2::zeros((0, 2));
+        check_loss_gradient(&Triplet::new(1.0), &triplets, &no_targets);
+    }
+}
+
+mod activation_tests {
+    use feed_forward::activation::Activation;
+    use ndarray::array;
+
+    #[test]
+    fn test_activation_derivatives() {
+        let activations = [
+            Activation::Sigmoid,
+            Activation::Tanh,
+            Activation::Relu,
+            Activation::LeakyRelu(0.01),
+            Activation::Elu(1.0),
+            Activation::Selu,
+            Activation::Gelu,
+            Activation::Softplus,
+            Activation::Swish,
+        ];
+        // stay away from 0, where ReLU-style activations have a kink
+        let x = array![-3.0f64, -1.2, -0.3, 0.2, 0.9, 2.5];
+        let eps = 1e-6;
+        for activation in activations.iter() {
+            let analytic = activation.derivative(x.view());
+            let numeric = x.mapv(|v| (activation.apply(v + eps) - activation.apply(v - eps)) / (2.0 * eps));
+            for (a, n) in analytic.iter().zip(numeric.iter()) {
+                assert!((a - n).abs() < 1e-6, "{:?}: analytic {} vs numeric {}", activation, a, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_activation_values() {
+        let x = array![[-1.0, 0.0], [0.5, 2.0]];
+        assert_eq!(Activation::Step.forward(x.view()), array![[0.0, 0.0], [1.0, 1.0]]);
+        assert_eq!(Activation::Relu.forward(x.view()), array![[0.0, 0.0], [0.5, 2.0]]);
+        assert_eq!(Activation::LeakyRelu(0.1).forward(x.view()), array![[-0.1, 0.0], [0.5, 2.0]]);
+        assert!((Activation::Sigmoid.apply(0.0f64) - 0.5).abs() < 1e-15);
+        assert!((Activation::Softplus.apply(1000.0f64) - 1000.0).abs() < 1e-9);
+        // GELU is close to ReLU away from 0
+        assert!((Activation::Gelu.apply(5.0f64) - 5.0).abs() < 1e-5);
+        assert!(Activation::Gelu.apply(-5.0f64).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_activation_backward() {
+        let x = array![-1.0, 0.5, 2.0];
+        let grad_output = array![1.0, 2.0, 3.0];
+        let grad_input = Activation::Relu.backward(x.view(), grad_output.view());
+        assert_eq!(grad_input, array![0.0, 2.0, 3.0]);
+    }
+}
+
+mod gradcheck_tests {
+    use feed_forward::gradcheck::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_numerical_gradient() {
+        // f(x) = sum(x^3) has gradient 3x^2
+        let x = array![[1.0, -2.0], [0.5, 3.0]];
+        let numeric = numerical_gradient(|x| x.mapv(|v: f64| v.powi(3)).sum(), &x, 1e-5);
+        let analytic = x.mapv(|v| 3.0 * v * v);
+        let report = compare(&analytic, &numeric, &GradCheckConfig::default());
+        report.assert_passed();
+        assert!(report.max_relative_error() < 1e-8);
+    }
+
+    #[test]
+    fn test_check_gradient_reports_wrong_entries() {
+        let x = array![1.0, 2.0, 3.0];
+        // the analytic gradient of sum(x^2) is 2x; the middle entry is deliberately wrong
+        let wrong = array![2.0, 5.0, 6.0];
+        let report = check_gradient(|x| x.mapv(|v: f64| v * v).sum(), &x, &wrong, &GradCheckConfig::default());
+        assert!(!report.passed());
+        let failures = report.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].index, vec![1]);
+        assert_eq!(report.worst().unwrap().index, vec![1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "gradient check failed")]
+    fn test_assert_passed_panics() {
+        let x = array![1.0];
+        check_gradient(|x| x[0] * x[0], &x, &array![0.0], &GradCheckConfig::default()).assert_passed();
+    }
+}
+
+mod init_tests {
+    use feed_forward::init::{Fans, Initializer};
+    use feed_forward::perceptron::Perceptron;
+    use feed_forward::seed::*;
+    use ndarray::Array2;
+
+    #[test]
+    fn test_seeded_initialization_is_reproducible() {
+        let init = Initializer::<f64>::HeNormal;
+        let a: Array2<f64> = init.initialize((784, 32), Fans::new(784, 32), &mut rng_from_seed(Some(7)));
+        let b: Array2<f64> = init.initialize((784, 32), Fans::new(784, 32), &mut rng_from_seed(Some(7)));
+        let c: Array2<f64> = init.initialize((784, 32), Fans::new(784, 32), &mut rng_from_seed(Some(8)));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_initializer_ranges() {
+        let mut rng = rng_from_seed(Some(0));
+        let xavier: Array2<f64> = Initializer::XavierUniform.initialize((100, 50), Fans::new(100, 50), &mut rng);
+        let limit = (6.0f64 / 150.0).sqrt();
+        assert!(xavier.iter().all(|w| w.abs() <= limit));
+
+        let truncated: Array2<f64> = Initializer::TruncatedNormal { mean: 1.0, std: 0.5 }.initialize((100, 50), Fans::new(100, 50), &mut rng);
+        assert!(truncated.iter().all(|w| (w - 1.0).abs() <= 1.0));
+
+        let normal: Array2<f32> = Initializer::Normal { mean: 0.0, std: 1.0 }.initialize((200, 200), Fans::new(200, 200), &mut rng);
+        let mean = normal.mean().unwrap();
+        assert!(mean.abs() < 0.05);
+    }
+
+    #[test]
+    fn test_orthogonal_initializer() {
+        let mut rng = rng_from_seed(Some(3));
+        for &(rows, cols) in [(6usize, 4usize), (4, 6)].iter() {
+            let w: Array2<f64> = Initializer::Orthogonal { gain: 1.0 }.initialize((rows, cols), Fans::new(cols, rows), &mut rng);
+            // whichever side is shorter should be orthonormal
+            let gram = if rows >= cols { w.t().dot(&w) } else { w.dot(&w.t()) };
+            let identity = Array2::<f64>::eye(rows.min(cols));
+            assert!(gram.iter().zip(identity.iter()).all(|(a, b)| (a - b).abs() < 1e-10));
+        }
+    }
+
+    #[test]
+    fn test_seeded_perceptron_is_reproducible() {
+        let data = ndarray::array![[255u8, 0, 30], [200, 10, 0], [0, 255, 90], [10, 200, 45], [120, 130, 0]];
+        let labels = vec![1u8, 1, 0, 0, 1];
+        let train = || {
+            let mut model = Perceptron::<f32>::new(3)
+                .with_initializer(&Initializer::Uniform { low: -0.1, high: 0.1 }, Some(5))
+                .with_shuffling(Some(6));
+            model.train(&data, &labels, 5);
+            model.weights().clone()
+        };
+        assert_eq!(train(), train());
+    }
+}
+
+mod layer_tests {
+    use feed_forward::activation::Activation;
+    use feed_forward::gradcheck::{check_layer, GradCheckConfig};
+    use feed_forward::init::Initializer;
+    use feed_forward::layer::{Layer, Sequential};
+    use feed_forward::layers::*;
+    use feed_forward::loss::CrossEntropyLoss;
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::{array, ArrayD, IxDyn};
+    use crate::common::random_input;
+
+    #[test]
+    fn test_layer_gradients() {
+        let mut rng = rng_from_seed(Some(11));
+        let config = GradCheckConfig::default();
+        check_layer(&mut Dense::new(4, 3, Initializer::XavierNormal, &mut rng), &random_input(&[5, 4], 1), &config).assert_passed();
+        // dense layers treat the last axis as features, e.g. for sequences
+        check_layer(&mut Dense::new(4, 3, Initializer::XavierNormal, &mut rng), &random_input(&[2, 3, 4], 2), &config).assert_passed();
+        check_layer(&mut ActivationLayer::new(Activation::Tanh), &random_input(&[3, 4], 3), &config).assert_passed();
+        check_layer(&mut BatchNorm1d::new(4), &random_input(&[6, 4], 4), &config).assert_passed();
+        check_layer(&mut LayerNorm::new(4), &random_input(&[2, 3, 4], 5), &config).assert_passed();
+        check_layer(&mut Flatten::new(), &random_input(&[2, 3, 2], 6), &config).assert_passed();
+    }
+
+    #[test]
+    fn test_sequential_gradients() {
+        let mut rng = rng_from_seed(Some(12));
+        let mut model = Sequential::new()
+            .add(Flatten::new())
+            .add(Dense::new(6, 5, Initializer::HeNormal, &mut rng))
+            .add(ActivationLayer::new(Activation::Swish))
+            .add(LayerNorm::new(5))
+            .add(Dense::new(5, 2, Initializer::XavierUniform, &mut rng));
+        assert_eq!(model.num_parameters(), 6 * 5 + 5 + 2 * 5 + 5 * 2 + 2);
+        check_layer(&mut model, &random_input(&[3, 2, 3], 7), &GradCheckConfig::default()).assert_passed();
+    }
+
+    #[test]
+    fn test_dropout_inverted_scaling() {
+        let mut dropout = Dropout::new(0.25, Some(3));
+        let input = ArrayD::<f64>::ones(IxDyn(&[100, 100]));
+        let output = dropout.forward(&input, true);
+        // survivors are scaled up so the expected value is unchanged
+        assert!(output.iter().all(|&v| v == 0.0 || (v - 1.0 / 0.75).abs() < 1e-12));
+        assert!((output.mean().unwrap() - 1.0).abs() < 0.05);
+        // gradients only flow through the kept units
+        let grad = dropout.backward(&input);
+        assert_eq!(grad, output);
+        // inference leaves the input alone
+        assert_eq!(dropout.forward(&input, false), input);
+    }
+
+    #[test]
+    fn test_batch_norm_running_statistics() {
+        let mut norm = BatchNorm1d::new(2).with_momentum(1.0);
+        let input = array![[1.0f64, 10.0], [3.0, 30.0]].into_dyn();
+        let output = norm.forward(&input, true);
+        assert!(output.sum().abs() < 1e-9);
+        assert_eq!(norm.running_mean(), &array![2.0, 20.0]);
+        assert_eq!(norm.running_var(), &array![2.0, 200.0]);
+        // inference uses the running statistics rather than the batch's own
+        let single = array![[2.0, 20.0]].into_dyn();
+        assert!(norm.forward(&single, false).iter().all(|v: &f64| v.abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_sequential_learns_xor() {
+        let mut rng = rng_from_seed(Some(21));
+        let mut model = Sequential::new()
+            .add(Dense::new(2, 8, Initializer::XavierUniform, &mut rng))
+            .add(ActivationLayer::new(Activation::Tanh))
+            .add(Dense::new(8, 2, Initializer::XavierUniform, &mut rng));
+        let inputs = array![[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]].into_dyn();
+        let targets = array![[1.0, 0.0], [0.0, 1.0], [0.0, 1.0], [1.0, 0.0]];
+        let loss = CrossEntropyLoss::new();
+
+        let first = model.train_batch(&inputs, &targets, &loss, 0.5).total();
+        let mut last = first;
+        for _ in 0..500 {
+            last = model.train_batch(&inputs, &targets, &loss, 0.5).total();
+        }
+        assert!(last < first * 0.1, "loss went from {} to {}", first, last);
+
+        let predictions = model.predict(&inputs);
+        let classes = feed_forward::cross_entropy::argmax_rows(predictions.into_dimensionality().unwrap().view());
+        assert_eq!(classes, vec![0, 1, 1, 0]);
+    }
+}
+
+mod regularization_tests {
+    use feed_forward::activation::Activation;
+    use feed_forward::gradcheck::{check_gradient, GradCheckConfig};
+    use feed_forward::init::Initializer;
+    use feed_forward::layer::{Layer, ParameterKind, Sequential};
+    use feed_forward::layers::{ActivationLayer, Dense};
+    use feed_forward::loss::MeanSquaredError;
+    use feed_forward::perceptron::Perceptron;
+    use feed_forward::regularization::*;
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::array;
+
+    #[test]
+    fn test_penalty_gradient() {
+        let reg = Regularization::none().with_l1(0.01).with_l2(0.1);
+        let w = array![[0.5f64, -1.5], [2.0, 0.25]];
+        let analytic = reg.gradient_of(w.view());
+        check_gradient(|w| reg.penalty_of(w.view()), &w, &analytic, &GradCheckConfig::default()).assert_passed();
+        assert!((reg.penalty_of(w.view()) - (0.01 * 4.25 + 0.05 * 6.5625)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_max_norm_clips_columns() {
+        let mut w = array![[3.0f64, 0.1], [4.0, 0.1]];
+        clip_unit_norms(&mut w, 1.0);
+        assert!((w[[0, 0]] - 0.6).abs() < 1e-12 && (w[[1, 0]] - 0.8).abs() < 1e-12);
+        // columns already inside the ball are left alone
+        assert_eq!(w.column(1), array![0.1, 0.1]);
+    }
+
+    #[test]
+    fn test_label_smoothing() {
+        let smoothed = smooth_labels(&array![[0.0, 1.0, 0.0, 0.0]], 0.2);
+        assert!(smoothed.iter().zip([0.05, 0.85, 0.05, 0.05].iter()).all(|(a, b): (&f64, &f64)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_regularized_training_reports_penalty() {
+        let mut rng = rng_from_seed(Some(4));
+        let regularization = Regularization::none().with_l2(0.01).with_max_norm(1.0);
+        let mut model = Sequential::new()
+            .add(Dense::new(3, 4, Initializer::Normal { mean: 0.0, std: 2.0 }, &mut rng))
+            .add(ActivationLayer::new(Activation::Relu))
+            .add(Dense::new(4, 1, Initializer::Normal { mean: 0.0, std: 2.0 }, &mut rng))
+            .with_regularization(regularization);
+        let inputs = array![[1.0f64, 0.0, 0.5], [0.0, 1.0, -0.5]].into_dyn();
+        let targets = array![[1.0], [-1.0]];
+
+        let loss = model.train_batch(&inputs, &targets, &MeanSquaredError::new(), 0.1);
+        assert!(loss.penalty > 0.0);
+        assert_eq!(loss.total(), loss.data_loss + loss.penalty);
+
+        // after the update every unit's incoming weights respect the max-norm constraint
+        for parameter in model.parameters().iter().filter(|p| p.kind == ParameterKind::Weight) {
+            for column in parameter.value.view().into_dimensionality::<ndarray::Ix2>().unwrap().columns() {
+                assert!(column.dot(&column).sqrt() <= 1.0 + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_perceptron_max_norm() {
+        // contradictory labels make the unregularized perceptron's weights keep moving
+        let data = ndarray::array![[255u8, 0], [255, 0], [0, 255], [0, 255]];
+        let labels = vec![1u8, 0, 1, 0];
+        let mut model = Perceptron::<f64>::new(2).with_regularization(Regularization::none().with_l2(0.1).with_max_norm(0.5));
+        model.train(&data, &labels, 50);
+        assert!(model.weights().dot(model.weights()).sqrt() <= 0.5 + 1e-12);
+        assert!(model.penalty() > 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "label smoothing")]
+    fn test_perceptron_rejects_label_smoothing() {
+        let _ = Perceptron::<f64>::new(2).with_regularization(Regularization::none().with_label_smoothing(0.1));
+    }
+}
+
+mod conv_tests {
+    use feed_forward::conv::*;
+    use feed_forward::gradcheck::{check_layer, GradCheckConfig};
+    use feed_forward::init::Initializer;
+    use feed_forward::layer::Layer;
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::{Array4, ArrayD, IxDyn};
+    use crate::common::random_input;
+
+    // the textbook six nested loops, for comparison against im2col
+    fn direct_convolution(x: &ArrayD<f64>, kernels: &ArrayD<f64>, bias: &ArrayD<f64>, stride: usize, padding: usize, dilation: usize, groups: usize) -> Array4<f64> {
+        let (n, c, h, w) = (x.shape()[0], x.shape()[1], x.shape()[2], x.shape()[3]);
+        let (out, c_g, kh, kw) = (kernels.shape()[0], kernels.shape()[1], kernels.shape()[2], kernels.shape()[3]);
+        let oh = (h + 2 * padding - dilation * (kh - 1) - 1) / stride + 1;
+        let ow = (w + 2 * padding - dilation * (kw - 1) - 1) / stride + 1;
+        let out_per_group = out / groups;
+        assert_eq!(c / groups, c_g);
+        let mut y = Array4::zeros((n, out, oh, ow));
+        for b in 0..n {
+            for o in 0..out {
+                let g = o / out_per_group;
+                for oi in 0..oh {
+                    for oj in 0..ow {
+                        let mut total = bias[[o]];
+                        for ci in 0..c_g {
+                            for ki in 0..kh {
+                                for kj in 0..kw {
+                                    let i = (oi * stride + ki * dilation) as isize - padding as isize;
+                                    let j = (oj * stride + kj * dilation) as isize - padding as isize;
+                                    if i >= 0 && j >= 0 && (i as usize) < h && (j as usize) < w {
+                                        total += kernels[[o, ci, ki, kj]] * x[[b, g * c_g + ci, i as usize, j as usize]];
+                                    }
+                                }
+                            }
+                        }
+                        y[[b, o, oi, oj]] = total;
+                    }
+                }
+            }
+        }
+        y
+    }
+
+    #[test]
+    fn test_conv_matches_direct_convolution() {
+        let mut rng = rng_from_seed(Some(31));
+        let mut conv = Conv2d::grouped(4, 6, (3, 2), 2, Initializer::HeNormal, &mut rng)
+            .with_stride((2, 2)).with_padding((1, 1)).with_dilation((2, 2));
+        conv.parameters_mut()[1].value = random_input(&[6], 2);
+        let input = random_input(&[2, 4, 7, 6], 1);
+        let output = conv.forward(&input, false);
+        let expected = direct_convolution(&input, &conv.parameters()[0].value, &conv.parameters()[1].value, 2, 1, 2, 2);
+        assert_eq!(output.shape(), expected.shape());
+        assert_eq!(conv.output_size(7, 6), (3, 3));
+        assert!(output.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_conv_gradients() {
+        let mut rng = rng_from_seed(Some(32));
+        let config = GradCheckConfig::default();
+        check_layer(&mut Conv2d::new(2, 3, (3, 3), Initializer::XavierNormal, &mut rng), &random_input(&[2, 2, 5, 5], 1), &config).assert_passed();
+        let mut strided = Conv2d::grouped(4, 2, (2, 3), 2, Initializer::XavierNormal, &mut rng)
+            .with_stride((2, 1)).with_padding((1, 2)).with_dilation((1, 2));
+        check_layer(&mut strided, &random_input(&[2, 4, 6, 5], 2), &config).assert_passed();
+    }
+
+    #[test]
+    fn test_pooling_gradients() {
+        let config = GradCheckConfig::default();
+        // random inputs keep every window's maximum unique, so max pooling is differentiable there
+        check_layer(&mut MaxPool2d::new((2, 2)), &random_input(&[2, 3, 4, 4], 3), &config).assert_passed();
+        check_layer(&mut MaxPool2d::new((3, 3)).with_stride((2, 2)).with_padding((1, 1)), &random_input(&[1, 2, 5, 5], 4), &config).assert_passed();
+        check_layer(&mut AvgPool2d::new((2, 2)), &random_input(&[2, 3, 4, 4], 5), &config).assert_passed();
+        check_layer(&mut AvgPool2d::new((3, 3)).with_stride((1, 2)).with_padding((1, 1)), &random_input(&[1, 2, 5, 4], 6), &config).assert_passed();
+        check_layer(&mut GlobalAvgPool2d::new(), &random_input(&[2, 3, 3, 4], 7), &config).assert_passed();
+        check_layer(&mut GlobalMaxPool2d::new(), &random_input(&[2, 3, 3, 4], 8), &config).assert_passed();
+    }
+
+    #[test]
+    fn test_pooling_values() {
+        let input = ArrayD::from_shape_vec(IxDyn(&[1, 1, 2, 4]), vec![1.0f64, 5.0, 2.0, 0.0, 3.0, 4.0, 8.0, 6.0]).unwrap();
+        let max = Layer::<f64>::forward(&mut MaxPool2d::new((2, 2)), &input, false);
+        assert_eq!(max.into_raw_vec(), vec![5.0, 8.0]);
+        let avg = Layer::<f64>::forward(&mut AvgPool2d::new((2, 2)), &input, false);
+        assert_eq!(avg.into_raw_vec(), vec![3.25, 4.0]);
+        let global = Layer::<f64>::forward(&mut GlobalMaxPool2d::new(), &input, false);
+        assert_eq!(global.shape(), &[1, 1]);
+        assert_eq!(global[[0, 0]], 8.0);
+    }
+
+    #[test]
+    fn test_max_pool_gradient_stays_in_window() {
+        let input = ArrayD::from_shape_vec(IxDyn(&[1, 1, 2, 4]), vec![1.0f64, 5.0, f64::NAN, f64::NAN, 3.0, 4.0, f64::NAN, f64::NAN]).unwrap();
+        let mut pool = MaxPool2d::new((2, 2));
+        let output = Layer::<f64>::forward(&mut pool, &input, true);
+        assert!(output[[0, 0, 0, 1]].is_nan());
+        let grad = Layer::<f64>::backward(&mut pool, &ArrayD::ones(IxDyn(&[1, 1, 1, 2])));
+        assert_eq!(grad.into_raw_vec(), vec![0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "padding must be at most half the kernel size")]
+    fn test_max_pool_rejects_padding_beyond_half_the_kernel() {
+        let _ = MaxPool2d::new((2, 2)).with_padding((2, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "stride must be positive")]
+    fn test_pooling_rejects_zero_stride() {
+        let _ = AvgPool2d::new((2, 2)).with_stride((0, 1));
+    }
+}
+
+mod model_tests {
+    use feed_forward::checkpoint::{load_parameters, save_parameters};
+    use feed_forward::layer::Layer;
+    use feed_forward::loss::CrossEntropyLoss;
+    use feed_forward::models::*;
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::{Array2, ArrayD, IxDyn};
+
+    #[test]
+    fn test_reference_models_shapes() {
+        let mut rng = rng_from_seed(Some(41));
+        let images = mnist_images_to_batch::<f64>(&Array2::from_elem((2, 784), 255u8));
+        assert_eq!(images.shape(), &[2, 1, 28, 28]);
+        assert_eq!(images[[1, 0, 27, 27]], 1.0);
+
+        let mut lenet = lenet5::<f64, _>(10, &mut rng);
+        assert_eq!(lenet.predict(&images).shape(), &[2, 10]);
+        // the classic parameter count for a 10-class LeNet-5
+        assert_eq!(lenet.num_parameters(), 61_706);
+        let mut vgg = small_vgg::<f64, _>(10, &mut rng);
+        assert_eq!(vgg.predict(&images).shape(), &[2, 10]);
+    }
+
+    #[test]
+    fn test_lenet_fits_a_tiny_batch() {
+        let mut rng = rng_from_seed(Some(42));
+        let mut model = lenet5::<f64, _>(2, &mut rng);
+        // class 0 is dark on the left half, class 1 on the right
+        let mut images = ArrayD::zeros(IxDyn(&[8, 1, 28, 28]));
+        let labels = [0u8, 1, 0, 1, 0, 1, 0, 1];
+        for (i, &label) in labels.iter().enumerate() {
+            for row in 0..28 {
+                for col in 0..14 {
+                    images[[i, 0, row, col + 14 * label as usize]] = 1.0;
+                }
+            }
+        }
+        let targets = one_hot(&labels, 2);
+        let loss = CrossEntropyLoss::new();
+        let first = train_epoch(&mut model, &images, &targets, &loss, 4, 0.1, &mut rng);
+        let mut last = first;
+        for _ in 0..10 {
+            last = train_epoch(&mut model, &images, &targets, &loss, 4, 0.1, &mut rng);
+        }
+        assert!(last < first);
+        assert_eq!(evaluate(&mut model, &images, &labels, 3), 1.0);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!("feed_forward_checkpoint_{}.ckpt", std::process::id()));
+        let mut rng = rng_from_seed(Some(43));
+        let model = lenet5::<f64, _>(10, &mut rng);
+        save_parameters(&model, &path).unwrap();
+
+        // same architecture, different weights, in single precision
+        let mut restored = lenet5::<f32, _>(10, &mut rng_from_seed(Some(44)));
+        load_parameters(&mut restored, &path).unwrap();
+        for (a, b) in model.parameters().iter().zip(restored.parameters()) {
+            assert!(a.value.iter().zip(b.value.iter()).all(|(&x, &y)| (x as f32) == y));
+        }
+
+        // a different architecture is rejected and left untouched
+        let mut other = small_vgg::<f64, _>(10, &mut rng);
+        let before = other.parameters()[0].value.clone();
+        assert!(load_parameters(&mut other, &path).is_err());
+        assert_eq!(other.parameters()[0].value, before);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+mod recurrent_tests {
+    use feed_forward::gradcheck::{check_layer, GradCheckConfig};
+    use feed_forward::layer::{clip_grad_norm, Layer, Parameter, ParameterKind};
+    use feed_forward::recurrent::*;
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::{array, s, ArrayD, IxDyn};
+    use crate::common::random_input;
+
+    #[test]
+    fn test_cell_gradients() {
+        let mut rng = rng_from_seed(Some(51));
+        let config = GradCheckConfig::default();
+        let input = random_input(&[2, 4, 3], 1);
+        // the second sequence is two steps long
+        let mask = array![[1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 0.0, 0.0]];
+
+        check_layer(&mut Rnn::new(3, 5, &mut rng), &input, &config).assert_passed();
+        check_layer(&mut Lstm::new(3, 5, &mut rng), &input, &config).assert_passed();
+        check_layer(&mut Gru::new(3, 5, &mut rng), &input, &config).assert_passed();
+
+        let mut lstm = Lstm::new(3, 4, &mut rng);
+        lstm.set_mask(Some(mask.clone()));
+        check_layer(&mut lstm, &input, &config).assert_passed();
+        let mut gru = Gru::new(3, 4, &mut rng).with_reversed(true);
+        gru.set_mask(Some(mask));
+        check_layer(&mut gru, &input, &config).assert_passed();
+    }
+
+    #[test]
+    fn test_bidirectional_and_stacked_gradients() {
+        let mut rng = rng_from_seed(Some(52));
+        let config = GradCheckConfig::default();
+        let mut stacked = Stacked::new()
+            .add(Bidirectional::new(Gru::new(3, 4, &mut rng), Gru::new(3, 4, &mut rng)))
+            .add(Rnn::new(8, 3, &mut rng));
+        assert_eq!(stacked.forward(&random_input(&[2, 5, 3], 2), false).shape(), &[2, 5, 3]);
+        stacked.set_mask(Some(array![[1.0, 1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0, 0.0]]));
+        check_layer(&mut stacked, &random_input(&[2, 5, 3], 3), &config).assert_passed();
+    }
+
+    #[test]
+    fn test_masked_steps_match_shorter_sequence() {
+        let mut rng = rng_from_seed(Some(53));
+        let input = random_input(&[2, 5, 3], 4);
+        let mut bidirectional = Bidirectional::new(Lstm::new(3, 4, &mut rng), Lstm::new(3, 4, &mut rng));
+        bidirectional.set_mask(Some(array![[1.0, 1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0, 0.0]]));
+        let padded = bidirectional.forward(&input, false);
+
+        bidirectional.set_mask(None);
+        let short = bidirectional.forward(&input.slice(s![1..2, ..3, ..]).to_owned().into_dyn(), false);
+        let real = padded.slice(s![1..2, ..3, ..]);
+        assert!(real.iter().zip(short.iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(padded.slice(s![1, 3.., ..]).iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_truncated_backpropagation() {
+        let input = random_input(&[1, 4, 2], 5);
+        let mut upstream = ArrayD::zeros(IxDyn(&[1, 4, 3]));
+        upstream[[0, 3, 0]] = 1.0;
+
+        let mut full = Rnn::new(2, 3, &mut rng_from_seed(Some(54)));
+        full.forward(&input, true);
+        let full_grad = full.backward(&upstream);
+        assert!(full_grad.slice(s![0, 0, ..]).iter().any(|&g| g != 0.0));
+
+        // with windows of two steps the loss at t = 3 can't reach t = 0 or 1
+        let mut truncated = Rnn::new(2, 3, &mut rng_from_seed(Some(54))).with_truncation(2);
+        truncated.forward(&input, true);
+        let truncated_grad = truncated.backward(&upstream);
+        assert!(truncated_grad.slice(s![0, ..2, ..]).iter().all(|&g| g == 0.0));
+        assert_eq!(truncated_grad.slice(s![0, 2.., ..]), full_grad.slice(s![0, 2.., ..]));
+    }
+
+    #[test]
+    fn test_stateful_layers_carry_state() {
+        let input = random_input(&[2, 6, 3], 6);
+        let mut whole = Gru::new(3, 4, &mut rng_from_seed(Some(55)));
+        let expected = whole.forward(&input, false);
+
+        let mut chunked = Gru::new(3, 4, &mut rng_from_seed(Some(55))).with_stateful(true);
+        let first = chunked.forward(&input.slice(s![.., ..3, ..]).to_owned().into_dyn(), false);
+        let second = chunked.forward(&input.slice(s![.., 3.., ..]).to_owned().into_dyn(), false);
+        assert_eq!(first.view(), expected.slice(s![.., ..3, ..]).into_dyn());
+        assert!(second.iter().zip(expected.slice(s![.., 3.., ..]).iter()).all(|(a, b)| (a - b).abs() < 1e-12));
+
+        chunked.reset_state();
+        let restarted = chunked.forward(&input.slice(s![.., 3.., ..]).to_owned().into_dyn(), false);
+        assert_ne!(restarted, second);
+    }
+
+    #[test]
+    fn test_clip_grad_norm() {
+        let mut a = Parameter::new("a", ParameterKind::Weight, ArrayD::<f64>::zeros(IxDyn(&[2])));
+        let mut b = Parameter::new("b", ParameterKind::Bias, ArrayD::<f64>::zeros(IxDyn(&[1])));
+        a.gradient = array![3.0, 0.0].into_dyn();
+        b.gradient = array![4.0].into_dyn();
+        assert_eq!(clip_grad_norm(&mut [&mut a, &mut b], 1.0), 5.0);
+        assert!((a.gradient[[0]] - 0.6).abs() < 1e-12 && a.gradient[[1]] == 0.0);
+        assert!((b.gradient[[0]] - 0.8).abs() < 1e-12);
+        // already small enough: left alone
+        let clipped = b.gradient.clone();
+        assert!((clip_grad_norm(&mut [&mut a, &mut b], 2.0) - 1.0).abs() < 1e-12);
+        assert_eq!(b.gradient, clipped);
+    }
+}
+
+mod embedding_tests {
+    use feed_forward::embedding::Embedding;
+    use feed_forward::init::Initializer;
+    use feed_forward::layer::Layer;
+    use feed_forward::seed::rng_from_seed;
+    use ndarray::{array, Array2, ArrayD, IxDyn};
+
+    fn table() -> Embedding<f64> {
+        Embedding::from_pretrained(array![[0.0, 0.0], [1.0, 2.0], [3.0, 4.0], [5.0, 6.0]])
+    }
+
+    #[test]
+    fn test_lookup_and_sparse_gradient() {
+        let mut embedding = table();
+        let ids = array![[1.0, 3.0, 1.0]].into_dyn();
+        let output = embedding.forward(&ids, true);
+        assert_eq!(output.shape(), &[1, 3, 2]);
+        assert_eq!(output, array![[[1.0, 2.0], [5.0, 6.0], [1.0, 2.0]]].into_dyn());
+
+        let grad = array![[[1.0, 1.0], [2.0, 2.0], [0.5, 0.5]]].into_dyn();
+        assert_eq!(embedding.backward(&grad), ArrayD::zeros(IxDyn(&[1, 3])));
+        // repeated ids accumulate
+        assert_eq!(embedding.parameters()[0].gradient, array![[0.0, 0.0], [1.5, 1.5], [0.0, 0.0], [2.0, 2.0]].into_dyn());
+        assert_eq!(embedding.touched_rows(), &[1, 3]);
+
+        embedding.sparse_sgd_step(1.0);
+        assert_eq!(embedding.vectors(), array![[0.0, 0.0], [-0.5, 0.5], [3.0, 4.0], [3.0, 4.0]]);
+        assert!(embedding.parameters()[0].gradient.iter().all(|&g| g == 0.0));
+        assert!(embedding.touched_rows().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "token ids must be non-negative integers")]
+    fn test_fractional_ids_are_rejected() {
+        table().forward(&array![2.7].into_dyn(), false);
+    }
+
+    #[test]
+    fn test_padding_and_max_norm() {
+        let mut embedding = table().with_padding_idx(2).with_max_norm(5.0);
+        assert_eq!(embedding.vector(2), array![0.0, 0.0]);
+        let output = embedding.forward(&array![2.0, 3.0, 1.0].into_dyn(), true);
+        // [5, 6] has norm sqrt(61) > 5 and is renormalized in the table itself
+        let norm = |v: ndarray::ArrayView1<f64>| v.dot(&v).sqrt();
+        assert!((norm(embedding.vector(3)) - 5.0).abs() < 1e-12);
+        assert_eq!(output.index_axis(ndarray::Axis(0), 1), embedding.vector(3).into_dyn());
+        assert_eq!(embedding.vector(1), array![1.0, 2.0]);
+
+        embedding.backward(&ArrayD::ones(IxDyn(&[3, 2])));
+        embedding.sparse_sgd_step(1.0);
+        assert_eq!(embedding.vector(2), array![0.0, 0.0]);
+        embedding.update_row(2, array![1.0, 1.0].view(), 1.0);
+        assert_eq!(embedding.vector(2), array![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_load_pretrained_and_freeze() {
+        let mut rng = rng_from_seed(Some(61));
+        let mut embedding = Embedding::<f64>::new(5, 3, Initializer::Normal { mean: 0.0, std: 1.0 }, &mut rng).with_padding_idx(0);
+        let pretrained = Array2::from_shape_fn((2, 3), |(i, j)| (i * 3 + j) as f64);
+        let loaded = embedding.load_pretrained(vec![(4, pretrained.row(0)), (0, pretrained.row(1))]);
+        assert_eq!(loaded, 1);
+        assert_eq!(embedding.vector(4), array![0.0, 1.0, 2.0]);
+        assert_eq!(embedding.vector(0), array![0.0, 0.0, 0.0]);
+
+        let mut frozen = Embedding::from_pretrained(pretrained).with_frozen(true);
+        assert!(frozen.parameters().is_empty());
+        frozen.forward(&array![1.0].into_dyn(), true);
+        frozen.backward(&array![[1.0, 1.0, 1.0]].into_dyn());
+        frozen.sparse_sgd_step(1.0);
+        assert_eq!(frozen.vector(1), array![3.0, 4.0, 5.0]);
+    }
+}
+mod tensorboard_tests {
+    use std::fs;
+    use feed_forward::tensorboard::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+
+    #[test]
+    fn test_record_framing() {
+        let mut out = vec![];
+        write_record(&mut out, b"abc").unwrap();
+        assert_eq!(out.len(), 8 + 4 + 3 + 4);
+        assert_eq!(&out[..8], &3u64.to_le_bytes());
+        assert_eq!(&out[8..12], &masked_crc32c(&3u64.to_le_bytes()).to_le_bytes());
+        assert_eq!(&out[12..15], b"abc");
+        let crc = crc32c(b"abc");
+        assert_eq!(&out[15..], &(crc.rotate_right(15).wrapping_add(0xa282_ead8)).to_le_bytes());
+    }
+
+    #[test]
+    fn test_writer_writes_readable_records() {
+        let logdir = std::env::temp_dir().join(format!("ff_tensorboard_{}", std::process::id()));
+        let path = {
+            let mut writer = SummaryWriter::create(&logdir).unwrap();
+            writer.add_scalar("loss", 0.5, 1).unwrap();
+            writer.add_histogram("weights", &[0.0, 1.0, 2.0], 4, 1).unwrap();
+            writer.add_image("digit", 2, 3, 1, &[0, 50, 100, 150, 200, 250], 1).unwrap();
+            writer.path().to_path_buf()
+        };
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("events.out.tfevents."));
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), 4);
+        // the first event only says which format follows
+        assert!(records[0].windows(13).any(|w| w == b"brain.Event:2"));
+        // tag "loss" followed by simple_value 0.5 as a float
+        let scalar = [&[0x0a, 4][..], b"loss", &[0x15], &0.5f32.to_le_bytes()].concat();
+        assert!(records[1].windows(scalar.len()).any(|w| w == scalar.as_slice()));
+        assert!(records[3].windows(4).any(|w| w == b"IHDR"));
+
+        let mut corrupt = fs::read(&path).unwrap();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        fs::write(&path, corrupt).unwrap();
+        assert!(read_records(&path).is_err());
+        fs::remove_dir_all(&logdir).unwrap();
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let histogram = Histogram::new(&[0.0, 0.5, 1.0, 1.0, f64::NAN], 2);
+        assert_eq!(histogram.num, 4.0);
+        assert_eq!(histogram.limits, vec![0.5, 1.0]);
+        assert_eq!(histogram.counts, vec![1.0, 3.0]);
+        assert_eq!(histogram.sum_squares, 2.25);
+        let constant = Histogram::new(&[3.0, 3.0], 10);
+        assert_eq!(constant.counts, vec![2.0]);
+        assert_eq!(Histogram::new(&[], 10).num, 0.0);
+    }
+
+    #[test]
+    fn test_png_layout() {
+        let png = encode_png(2, 3, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR: width 3, height 2, 8 bit greyscale
+        assert_eq!(&png[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&png[16..29], &[0, 0, 0, 3, 0, 0, 0, 2, 8, 0, 0, 0, 0]);
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+        assert!(encode_png(2, 3, 2, &[0; 12]).is_err());
+        assert!(encode_png(2, 3, 1, &[0; 5]).is_err());
+    }
+
+    #[test]
+    fn test_projector_lists_every_embedding() {
+        let logdir = std::env::temp_dir().join(format!("ff_projector_{}", std::process::id()));
+        let labels = vec!["a".to_string(), "b\tc".to_string()];
+        write_projector(&logdir, "first", &labels, array![[1.0f32, 2.0], [3.0, 4.5]].view()).unwrap();
+        write_projector(&logdir, "second", &labels[..1], array![[0.0f32, 1.0]].view()).unwrap();
+        assert_eq!(fs::read_to_string(logdir.join("first/tensors.tsv")).unwrap(), "1\t2\n3\t4.5\n");
+        assert_eq!(fs::read_to_string(logdir.join("first/metadata.tsv")).unwrap(), "a\nb c\n");
+        let config = fs::read_to_string(logdir.join("projector_config.pbtxt")).unwrap();
+        assert!(config.contains("tensor_path: \"first/tensors.tsv\""));
+        assert!(config.contains("metadata_path: \"second/metadata.tsv\""));
+        assert!(write_projector(&logdir, "bad", &labels, array![[0.0f32]].view()).is_err());
+        fs::remove_dir_all(&logdir).unwrap();
+    }
+}
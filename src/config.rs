@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use feed_forward::seed::rng_from_seed;
+use crate::data::{Dataset, Preprocessing, Subset};
+use crate::experiment::{Decay, TrainSettings};
+use crate::model::ModelSpec;
+
+/// An experiment as written in a TOML, YAML or JSON file.
+///
+/// Every section is optional and defaults to what the `train` command does without flags.
+/// A `sweep` section turns one experiment into many runs, see `Sweep`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    pub name: String,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub data: DataConfig,
+    #[serde(default)]
+    pub preprocessing: Preprocessing,
+    #[serde(default)]
+    pub model: ModelSpec,
+    #[serde(default)]
+    pub optimizer: OptimizerConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sweep: Option<Sweep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataConfig {
+    #[serde(with = "text")]
+    pub dataset: Dataset,
+    /// A subset name or "train,validation,test" sizes, as for `--subset`.
+    #[serde(with = "text")]
+    pub subset: Subset,
+}
+
+/// Mini-batch SGD, the one optimizer the networks have.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptimizerConfig {
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    pub weight_decay: f64,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        let settings = TrainSettings::default();
+        OptimizerConfig { learning_rate: settings.learning_rate, batch_size: settings.batch_size, weight_decay: settings.weight_decay }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub epochs: usize,
+    pub decay: Decay,
+    /// Saves the model to the run directory every this many epochs, on top of the best
+    /// and the final one.
+    pub checkpoint_every: Option<usize>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        let settings = TrainSettings::default();
+        ScheduleConfig { epochs: settings.epochs, decay: settings.decay, checkpoint_every: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SweepMethod {
+    /// Every combination of the parameter lists.
+    #[default]
+    Grid,
+    /// `samples` draws from the lists and ranges.
+    Random,
+}
+
+/// Turns an experiment into several runs by overriding fields of the rest of the file,
+/// named by their dotted path such as "optimizer.learning_rate" or "model.hidden".
+///
+/// Each of the `variants` (just the experiment itself when there are none) is combined
+/// with every grid point or random draw of `parameters`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sweep {
+    #[serde(default)]
+    pub method: SweepMethod,
+    /// How many draws a random sweep makes.
+    #[serde(default = "one")]
+    pub samples: usize,
+    /// Seeds the random draws; the experiment's seed when not given.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// How many runs train at once.
+    #[serde(default = "one")]
+    pub parallel: usize,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, Values>,
+    #[serde(default)]
+    pub variants: Vec<BTreeMap<String, Value>>,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// The values a swept parameter takes: a list, or for random sweeps a range, drawn
+/// log-uniformly with `log` and rounded with `integer`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Values {
+    List(Vec<Value>),
+    Range {
+        min: f64,
+        max: f64,
+        #[serde(default)]
+        log: bool,
+        #[serde(default)]
+        integer: bool,
+    },
+}
+
+/// One configuration of a sweep, ready to train.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub name: String,
+    /// The overrides that made this run, by dotted path, apart from its name.
+    pub parameters: BTreeMap<String, Value>,
+    /// The experiment with the overrides applied and no sweep left.
+    pub config: ExperimentConfig,
+}
+
+impl ExperimentConfig {
+    /// An experiment with every section at its default.
+    pub fn new(name: impl Into<String>) -> ExperimentConfig {
+        ExperimentConfig {
+            name: name.into(),
+            seed: None,
+            data: DataConfig::default(),
+            preprocessing: Preprocessing::default(),
+            model: ModelSpec::default(),
+            optimizer: OptimizerConfig::default(),
+            schedule: ScheduleConfig::default(),
+            sweep: None,
+        }
+    }
+
+    /// Reads a .toml, .yaml/.yml or .json experiment.
+    pub fn load(path: impl AsRef<Path>) -> Result<ExperimentConfig, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let config = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&text)?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&text)?,
+            Some("json") => serde_json::from_str(&text)?,
+            _ => return Err(format!("{}: expected a .toml, .yaml, .yml or .json experiment", path.display()).into()),
+        };
+        Ok(config)
+    }
+
+    pub fn settings(&self) -> TrainSettings {
+        TrainSettings {
+            epochs: self.schedule.epochs,
+            learning_rate: self.optimizer.learning_rate,
+            decay: self.schedule.decay,
+            weight_decay: self.optimizer.weight_decay,
+            batch_size: self.optimizer.batch_size,
+            seed: self.seed,
+        }
+    }
+
+    /// The runs the sweep describes, or the experiment alone. Runs whose names would
+    /// clash get their number appended.
+    pub fn runs(&self) -> Result<Vec<Run>, String> {
+        let mut base = self.clone();
+        let sweep = base.sweep.take().unwrap_or(Sweep {
+            method: SweepMethod::Grid,
+            samples: 1,
+            seed: None,
+            parallel: 1,
+            parameters: BTreeMap::new(),
+            variants: vec![],
+        });
+        let base = serde_json::to_value(&base).map_err(|e| e.to_string())?;
+
+        let points = match sweep.method {
+            SweepMethod::Grid => grid(&sweep.parameters)?,
+            SweepMethod::Random => {
+                let mut rng = rng_from_seed(sweep.seed.or(self.seed));
+                (0..sweep.samples).map(|_| draw(&sweep.parameters, &mut rng)).collect()
+            }
+        };
+        let variants = if sweep.variants.is_empty() { vec![BTreeMap::new()] } else { sweep.variants };
+
+        let mut runs = vec![];
+        for variant in &variants {
+            for point in &points {
+                let mut parameters = variant.clone();
+                parameters.extend(point.iter().map(|(k, v)| (k.clone(), v.clone())));
+                let mut value = base.clone();
+                for (path, parameter) in &parameters {
+                    set(&mut value, path, parameter.clone())?;
+                }
+                let config: ExperimentConfig = serde_json::from_value(value).map_err(|e| format!("{}: {}", describe(&parameters), e))?;
+                config.model.validate().map_err(|e| format!("{}: {}", describe(&parameters), e))?;
+                // the name is the run's, not a parameter
+                parameters.remove("name");
+                runs.push(Run { name: config.name.clone(), parameters, config });
+            }
+        }
+
+        let mut counts = HashMap::new();
+        for run in &runs {
+            *counts.entry(run.name.clone()).or_insert(0) += 1;
+        }
+        for (i, run) in runs.iter_mut().enumerate() {
+            if counts[&run.name] > 1 {
+                run.name = format!("{}-{:03}", run.name, i + 1);
+                run.config.name = run.name.clone();
+            }
+        }
+        Ok(runs)
+    }
+
+    /// How many runs the sweep trains at once.
+    pub fn parallel(&self) -> usize {
+        self.sweep.as_ref().map_or(1, |sweep| sweep.parallel.max(1))
+    }
+}
+
+impl Run {
+    /// The experiment as a single run, no overrides.
+    pub fn new(config: ExperimentConfig) -> Run {
+        Run { name: config.name.clone(), parameters: BTreeMap::new(), config }
+    }
+}
+
+type Point = BTreeMap<String, Value>;
+
+fn grid(parameters: &BTreeMap<String, Values>) -> Result<Vec<Point>, String> {
+    let mut points = vec![Point::new()];
+    for (path, values) in parameters {
+        let Values::List(values) = values else {
+            return Err(format!("sweep parameter {}: grid sweeps need a list of values, ranges are for random sweeps", path));
+        };
+        points = points.into_iter()
+            .flat_map(|point| values.iter().map(move |value| {
+                let mut point = point.clone();
+                point.insert(path.clone(), value.clone());
+                point
+            }))
+            .collect();
+    }
+    Ok(points)
+}
+
+fn draw<R: Rng>(parameters: &BTreeMap<String, Values>, rng: &mut R) -> Point {
+    parameters.iter().map(|(path, values)| {
+        let value = match values {
+            Values::List(values) if values.is_empty() => Value::Null,
+            Values::List(values) => values[rng.gen_range(0..values.len())].clone(),
+            Values::Range { min, max, log, integer } => {
+                let x = if *log {
+                    (min.ln() + rng.gen::<f64>() * (max.ln() - min.ln())).exp()
+                } else {
+                    min + rng.gen::<f64>() * (max - min)
+                };
+                if *integer {
+                    Value::from(x.round() as i64)
+                } else {
+                    Number::from_f64(x).map_or(Value::Null, Value::Number)
+                }
+            }
+        };
+        (path.clone(), value)
+    }).collect()
+}
+
+/// Sets the field at a dotted path, creating tables along the way.
+fn set(value: &mut Value, path: &str, new: Value) -> Result<(), String> {
+    let mut current = value;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        let Value::Object(table) = current else {
+            return Err(format!("sweep parameter {}: {} is not a table", path, key));
+        };
+        if keys.peek().is_none() {
+            table.insert(key.to_string(), new);
+            return Ok(());
+        }
+        current = table.entry(key).or_insert(Value::Null);
+    }
+    Err("empty sweep parameter".to_string())
+}
+
+fn describe(parameters: &BTreeMap<String, Value>) -> String {
+    if parameters.is_empty() {
+        return "experiment".to_string();
+    }
+    parameters.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(" ")
+}
+
+/// (De)serializes a field through its `Display` and `FromStr` impls, so experiment files
+/// spell values the way the command line does.
+pub(crate) mod text {
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
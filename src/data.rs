@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use ndarray::{Array2, Axis};
+use serde::{Deserialize, Serialize};
+use mnist_data::mnist_data::{convert_mnist_images_to_ndarray2, get_some_mnist_data};
+
+/// Where the images come from. Only MNIST for now; it is downloaded into `data/` on first use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Dataset {
+    #[default]
+    Mnist,
+}
+
+impl FromStr for Dataset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mnist" => Ok(Dataset::Mnist),
+            _ => Err(format!("unknown dataset {:?}, expected mnist", s)),
+        }
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dataset::Mnist => "mnist",
+        })
+    }
+}
+
+/// How many images of each split to load.
+///
+/// The named sizes are the ones `mnist_data` has helpers for; "full" uses all 60 000
+/// training images, the last 10 000 of them for validation. Any other size is written
+/// "train,validation,test".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subset {
+    pub train: usize,
+    pub validation: usize,
+    pub test: usize,
+}
+
+const NAMED_SUBSETS: [(&str, Subset); 4] = [
+    ("mini", Subset { train: 50, validation: 10, test: 10 }),
+    ("medium", Subset { train: 500, validation: 100, test: 100 }),
+    ("large", Subset { train: 5000, validation: 1000, test: 1000 }),
+    ("full", Subset { train: 50_000, validation: 10_000, test: 10_000 }),
+];
+
+impl Default for Subset {
+    fn default() -> Self {
+        NAMED_SUBSETS[1].1
+    }
+}
+
+impl FromStr for Subset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, subset)) = NAMED_SUBSETS.iter().find(|(name, _)| *name == s) {
+            return Ok(*subset);
+        }
+        let sizes = s.split(',').map(|n| n.trim().parse::<usize>()).collect::<Result<Vec<_>, _>>();
+        match sizes.as_deref() {
+            Ok(&[train, validation, test]) => Ok(Subset { train, validation, test }),
+            _ => Err(format!("unknown subset {:?}, expected mini, medium, large, full or train,validation,test sizes", s)),
+        }
+    }
+}
+
+impl fmt::Display for Subset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match NAMED_SUBSETS.iter().find(|(_, subset)| subset == self) {
+            Some((name, _)) => f.write_str(name),
+            None => write!(f, "{},{},{}", self.train, self.validation, self.test),
+        }
+    }
+}
+
+/// Which part of the data to evaluate or predict on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Split {
+    Train,
+    Validation,
+    #[default]
+    Test,
+}
+
+impl FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "train" => Ok(Split::Train),
+            "validation" | "val" => Ok(Split::Validation),
+            "test" => Ok(Split::Test),
+            _ => Err(format!("unknown split {:?}, expected train, validation or test", s)),
+        }
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Split::Train => "train",
+            Split::Validation => "validation",
+            Split::Test => "test",
+        })
+    }
+}
+
+/// Changes applied to every split after loading.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preprocessing {
+    /// Turns pixels at or above this value white (255) and the rest black.
+    pub binarize: Option<u8>,
+    /// Keeps only the images of these digits.
+    pub digits: Option<Vec<u8>>,
+}
+
+impl Preprocessing {
+    pub fn is_empty(&self) -> bool {
+        self == &Preprocessing::default()
+    }
+}
+
+/// Images as (N, 784) pixel rows with their digit labels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Samples {
+    pub images: Array2<u8>,
+    pub labels: Vec<u8>,
+}
+
+impl Samples {
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn preprocess(&self, preprocessing: &Preprocessing) -> Samples {
+        let mut samples = match &preprocessing.digits {
+            Some(digits) => {
+                let keep = (0..self.len()).filter(|&i| digits.contains(&self.labels[i])).collect::<Vec<_>>();
+                Samples { images: self.images.select(Axis(0), &keep), labels: keep.iter().map(|&i| self.labels[i]).collect() }
+            }
+            None => self.clone(),
+        };
+        if let Some(threshold) = preprocessing.binarize {
+            samples.images.mapv_inplace(|pixel| if pixel >= threshold { 255 } else { 0 });
+        }
+        samples
+    }
+}
+
+/// The three splits of a dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    pub train: Samples,
+    pub validation: Samples,
+    pub test: Samples,
+}
+
+impl Data {
+    pub fn load(dataset: Dataset, subset: Subset) -> Result<Data, Box<dyn Error>> {
+        match dataset {
+            Dataset::Mnist => {
+                if subset.train + subset.validation > 60_000 || subset.test > 10_000 {
+                    return Err(format!("MNIST has 60000 training and 10000 test images, {} asks for more", subset).into());
+                }
+                let mnist = get_some_mnist_data(subset.train as u32, subset.validation as u32, subset.test as u32)?;
+                let samples = |images, labels| Samples { images: convert_mnist_images_to_ndarray2(images), labels };
+                Ok(Data {
+                    train: samples(mnist.trn_img, mnist.trn_lbl),
+                    validation: samples(mnist.val_img, mnist.val_lbl),
+                    test: samples(mnist.tst_img, mnist.tst_lbl),
+                })
+            }
+        }
+    }
+
+    pub fn preprocess(&self, preprocessing: &Preprocessing) -> Data {
+        Data {
+            train: self.train.preprocess(preprocessing),
+            validation: self.validation.preprocess(preprocessing),
+            test: self.test.preprocess(preprocessing),
+        }
+    }
+
+    pub fn split(&self, split: Split) -> &Samples {
+        match split {
+            Split::Train => &self.train,
+            Split::Validation => &self.validation,
+            Split::Test => &self.test,
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use feed_forward::loss::CrossEntropyLoss;
+use feed_forward::models::{mnist_images_to_batch, one_hot, train_epoch};
+use feed_forward::seed::rng_from_seed;
+use crate::data::{Data, Samples};
+use crate::model::Model;
+
+/// How long and how fast to train.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainSettings {
+    pub epochs: usize,
+    /// The SGD step size of the networks. The perceptron rule has no step size: scaling
+    /// every update of zero-initialized weights by the same factor changes no prediction.
+    pub learning_rate: f64,
+    /// How the learning rate changes from epoch to epoch.
+    pub decay: Decay,
+    /// L2 penalty on the networks' weights.
+    pub weight_decay: f64,
+    pub batch_size: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for TrainSettings {
+    fn default() -> Self {
+        TrainSettings { epochs: 10, learning_rate: 0.05, decay: Decay::Constant, weight_decay: 0.0, batch_size: 32, seed: None }
+    }
+}
+
+/// A learning rate schedule, written `{ kind = "step", every = 5, factor = 0.5 }` and so
+/// on in experiment files.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Decay {
+    #[default]
+    Constant,
+    /// Multiplies the rate by `factor` every `every` epochs.
+    Step { every: usize, factor: f64 },
+    /// Multiplies the rate by `gamma` after every epoch.
+    Exponential { gamma: f64 },
+    /// Half a cosine from the initial rate down to `min_learning_rate` over the run.
+    Cosine {
+        #[serde(default)]
+        min_learning_rate: f64,
+    },
+}
+
+impl Decay {
+    /// The rate for `epoch` (counting from 1) of `epochs`.
+    pub fn learning_rate(&self, initial: f64, epoch: usize, epochs: usize) -> f64 {
+        let done = epoch.saturating_sub(1);
+        match *self {
+            Decay::Constant => initial,
+            Decay::Step { every, factor } => initial * factor.powi((done / every.max(1)) as i32),
+            Decay::Exponential { gamma } => initial * gamma.powi(done as i32),
+            Decay::Cosine { min_learning_rate } => {
+                let progress = done as f64 / epochs.max(1) as f64;
+                min_learning_rate + (initial - min_learning_rate) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+        }
+    }
+}
+
+/// What happened in one epoch of `train`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochReport {
+    /// Counting from 1.
+    pub epoch: usize,
+    /// The rate the networks trained with this epoch.
+    pub learning_rate: f64,
+    /// The mean cross-entropy per batch, for the networks.
+    pub train_loss: Option<f64>,
+    /// `None` when the validation split is empty.
+    pub validation_accuracy: Option<f64>,
+    pub seconds: f64,
+}
+
+impl fmt::Display for EpochReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "epoch {}", self.epoch)?;
+        if let Some(loss) = self.train_loss {
+            write!(f, ": train loss {:.4}", loss)?;
+        }
+        if let Some(accuracy) = self.validation_accuracy {
+            write!(f, "{} validation accuracy {:.2}%", if self.train_loss.is_some() { "," } else { ":" }, 100.0 * accuracy)?;
+        }
+        write!(f, " ({:.1}s)", self.seconds)
+    }
+}
+
+/// Trains `model` on `data.train` for `settings.epochs` epochs, scoring the validation
+/// split after each one and handing the report to `on_epoch`. Weight decay only applies
+/// to the networks.
+pub fn train(model: &mut Model, data: &Data, settings: &TrainSettings, mut on_epoch: impl FnMut(&mut Model, &EpochReport)) {
+    let mut rng = rng_from_seed(settings.seed);
+    if let Model::Network { network, .. } = model {
+        if settings.weight_decay > 0.0 {
+            let regularization = network.regularization().with_l2(settings.weight_decay as f32);
+            *network = std::mem::take(network).with_regularization(regularization);
+        }
+    }
+    let targets = model.targets(&data.train.labels);
+    // the networks train on scaled (N, 1, 28, 28) batches, converted once
+    let batches = match model {
+        Model::Network { .. } => Some((mnist_images_to_batch::<f32>(&data.train.images), one_hot::<f32>(&targets, model.classes()))),
+        _ => None,
+    };
+    let loss = CrossEntropyLoss::new();
+    for epoch in 1..=settings.epochs {
+        let start = Instant::now();
+        let learning_rate = settings.decay.learning_rate(settings.learning_rate, epoch, settings.epochs);
+        let train_loss = match model {
+            Model::Perceptron { perceptron, .. } => {
+                perceptron.train(&data.train.images, &targets, 1);
+                None
+            }
+            Model::OneVsRest(model) => {
+                model.train(&data.train.images, &targets, 1);
+                None
+            }
+            Model::Network { network, .. } => {
+                let (inputs, encoded) = batches.as_ref().unwrap();
+                Some(train_epoch(network, inputs, encoded, &loss, settings.batch_size, learning_rate as f32, &mut rng) as f64)
+            }
+        };
+        let validation_accuracy = (!data.validation.is_empty()).then(|| evaluate(model, &data.validation).accuracy());
+        let report = EpochReport { epoch, learning_rate, train_loss, validation_accuracy, seconds: start.elapsed().as_secs_f64() };
+        on_epoch(model, &report);
+    }
+}
+
+/// Accuracy overall and per class, from a confusion matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// `confusion[truth][prediction]` counts.
+    pub confusion: Vec<Vec<usize>>,
+}
+
+impl Evaluation {
+    pub fn new(classes: usize, targets: &[u8], predictions: &[u8]) -> Evaluation {
+        let mut confusion = vec![vec![0; classes]; classes];
+        for (&truth, &prediction) in targets.iter().zip(predictions) {
+            confusion[truth as usize][prediction as usize] += 1;
+        }
+        Evaluation { confusion }
+    }
+
+    pub fn total(&self) -> usize {
+        self.confusion.iter().flatten().sum()
+    }
+
+    pub fn correct(&self) -> usize {
+        (0..self.confusion.len()).map(|c| self.confusion[c][c]).sum()
+    }
+
+    /// The share of correct predictions, 0 with no samples.
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.correct(), self.total())
+    }
+
+    /// The share of samples of `class` predicted correctly (its recall).
+    pub fn class_accuracy(&self, class: usize) -> f64 {
+        ratio(self.confusion[class][class], self.confusion[class].iter().sum())
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (class, row) in self.confusion.iter().enumerate() {
+            let total = row.iter().sum::<usize>();
+            if total > 0 {
+                writeln!(f, "class {}: {:.2}% ({}/{})", class, 100.0 * self.class_accuracy(class), row[class], total)?;
+            }
+        }
+        write!(f, "accuracy: {:.2}% ({}/{})", 100.0 * self.accuracy(), self.correct(), self.total())
+    }
+}
+
+pub fn evaluate(model: &mut Model, samples: &Samples) -> Evaluation {
+    let predictions = model.predict(&samples.images);
+    Evaluation::new(model.classes(), &model.targets(&samples.labels), &predictions)
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
@@ -0,0 +1,8 @@
+//! The plumbing behind the `RustML` experiment binary: loading datasets, building,
+//! training, saving and evaluating models, and running and tracking experiments.
+pub mod config;
+pub mod data;
+pub mod experiment;
+pub mod model;
+pub mod sweep;
+pub mod tracking;
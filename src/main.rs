@@ -1,51 +1,221 @@
-use ndarray::{Array2, Axis};
-use feed_forward::perceptron::{MultiClassPerceptron, Perceptron};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use rustml::config::{DataConfig, ExperimentConfig, OptimizerConfig, Run, ScheduleConfig};
+use rustml::data::{Data, Dataset, Split, Subset};
+use rustml::experiment::{evaluate, Decay};
+use rustml::model::{Model, ModelKind, ModelSpec};
+use rustml::sweep::{self, run_all, write_results};
+use rustml::tracking::{Comparison, RunRecord};
 
-use mnist_data;
-use mnist_data::mnist_data::*;
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-fn main() {
-    let mnist = get_medium_mnist_data();
-    match &mnist {
-        Ok(_) => {
-            // println!("Mini Mnist Data: {:?}", mnist);
-        },
-        Err(e) => {
-            println!("Error: {:?}", e);
-        }
-    }
-
-    let mnist = mnist.unwrap();
-    let images: Array2<u8> = convert_mnist_images_to_ndarray2(mnist.trn_img);
-    let labels = mnist.trn_lbl;
-
-    // print number of rows in images and number of labels
-    println!("Number of rows in images: {}", images.shape()[0]);
-    println!("Number of labels: {}", labels.len());
-
-    let mut model = Perceptron::new(784);
-
-    // set non-zero labels to 0 and zero labels to 1
-    let corrected_labels = labels.iter().map(|&x| if x == 0 { 1 } else { 0 }).collect::<Vec<u8>>();
-
-    // we train the model with the contents of the training set
-    model.train(&images, &corrected_labels, 10);
+/// Trains, evaluates and applies MNIST classifiers.
+#[derive(Parser, Debug)]
+#[command(name = "RustML")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-    // predict some images
-    let test_images: Array2<u8> = convert_mnist_images_to_ndarray2(mnist.tst_img);
-    let test_labels = mnist.tst_lbl;
-    let corrected_test_labels = test_labels.iter().map(|&x| if x == 0 { 1 } else { 0 }).collect::<Vec<u8>>();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Trains a model, reporting validation accuracy per epoch and test accuracy at the end.
+    /// The run is recorded in a directory under --runs.
+    Train(TrainArgs),
+    /// Prints the accuracy, overall and per class, of a saved model.
+    Eval {
+        #[command(flatten)]
+        data: DataArgs,
+        /// A model written by train --output.
+        #[arg(long)]
+        model_path: PathBuf,
+        /// train, validation or test.
+        #[arg(long, default_value_t = Split::Test)]
+        split: Split,
+    },
+    /// Writes a saved model's prediction for every image of a split as CSV.
+    Predict {
+        #[command(flatten)]
+        data: DataArgs,
+        #[arg(long)]
+        model_path: PathBuf,
+        #[arg(long, default_value_t = Split::Test)]
+        split: Split,
+        /// Defaults to stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Runs an experiment file, sweep included, and writes results.csv and results.json.
+    Run {
+        /// A .toml, .yaml or .json experiment, e.g. experiments/perceptron-vs-mlp.toml.
+        config: PathBuf,
+        /// How many runs train at once; overrides the sweep's parallel setting.
+        #[arg(long)]
+        jobs: Option<usize>,
+        /// Where the results go; defaults to results/<experiment name>.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Lists the runs without training them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Doesn't record the runs in <output>/runs.
+        #[arg(long)]
+        no_track: bool,
+    },
+    /// Tabulates recorded runs: the settings that differ between them and their results.
+    Compare {
+        /// Run directories, or directories to search for them.
+        #[arg(default_value = "runs")]
+        paths: Vec<PathBuf>,
+        /// A column to order the runs by, e.g. test or best_validation.
+        #[arg(long)]
+        sort: Option<String>,
+        /// Prints CSV instead of an aligned table.
+        #[arg(long)]
+        csv: bool,
+    },
+}
 
-    model.validate(&test_images, &corrected_test_labels);
+#[derive(Args, Debug)]
+struct DataArgs {
+    #[arg(long, default_value_t = Dataset::Mnist)]
+    dataset: Dataset,
+    /// mini, medium, large, full, or train,validation,test sizes such as 2000,500,500.
+    #[arg(long, default_value_t = Subset::default())]
+    subset: Subset,
+}
 
-    // -----------------------
+#[derive(Args, Debug)]
+struct TrainArgs {
+    #[command(flatten)]
+    data: DataArgs,
+    /// perceptron, one-vs-rest, mlp, lenet5 or small-vgg.
+    #[arg(long, default_value_t = ModelKind::OneVsRest)]
+    model: ModelKind,
+    /// The digit a single perceptron learns to tell from the rest.
+    #[arg(long, default_value_t = 0)]
+    digit: u8,
+    /// Hidden layer sizes of the MLP, comma separated.
+    #[arg(long, value_delimiter = ',', default_value = "128")]
+    hidden: Vec<usize>,
+    #[arg(long, default_value_t = 10)]
+    epochs: usize,
+    /// SGD step size of the networks; perceptrons don't use one.
+    #[arg(long, default_value_t = 0.05)]
+    learning_rate: f64,
+    /// L2 penalty on the network weights.
+    #[arg(long, default_value_t = 0.0)]
+    weight_decay: f64,
+    #[arg(long, default_value_t = 32)]
+    batch_size: usize,
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Where to save the trained model, besides the run directory.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// The run's name; defaults to the model kind.
+    #[arg(long)]
+    name: Option<String>,
+    /// Where run directories go.
+    #[arg(long, default_value = "runs")]
+    runs: PathBuf,
+    /// Doesn't record the run.
+    #[arg(long)]
+    no_track: bool,
+    /// Saves a checkpoint every this many epochs; the best and final models are always saved.
+    #[arg(long)]
+    checkpoint_every: Option<usize>,
+}
 
-    let mut multi_model = MultiClassPerceptron::new(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9], 784);
-    multi_model.train(&images, &labels, 10);
-
-    for i in 0..10 {
-        multi_model.validate_nth_perceptron(i, &test_images, &test_labels);
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Train(args) => {
+            let experiment = ExperimentConfig {
+                seed: args.seed,
+                data: DataConfig { dataset: args.data.dataset, subset: args.data.subset },
+                model: ModelSpec { kind: args.model, digit: args.digit, hidden: args.hidden },
+                optimizer: OptimizerConfig { learning_rate: args.learning_rate, batch_size: args.batch_size, weight_decay: args.weight_decay },
+                schedule: ScheduleConfig { epochs: args.epochs, decay: Decay::Constant, checkpoint_every: args.checkpoint_every },
+                ..ExperimentConfig::new(args.name.unwrap_or_else(|| args.model.to_string()))
+            };
+            experiment.model.validate()?;
+            let data = load(&args.data)?;
+            let runs_root = (!args.no_track).then_some(args.runs.as_path());
+            let mut trained = sweep::run(&Run::new(experiment), &data, runs_root, |report| println!("{}", report))?;
+            println!("{}", evaluate(&mut trained.model, &data.test));
+            if let Some(directory) = &trained.directory {
+                println!("run recorded in {}", directory.display());
+            }
+            if let Some(path) = &args.output {
+                trained.model.save(path)?;
+                println!("saved {} to {}", args.model, path.display());
+            }
+        }
+        Command::Eval { data, model_path, split } => {
+            let mut model = Model::load(&model_path)?;
+            let data = load(&data)?;
+            println!("{}", evaluate(&mut model, data.split(split)));
+        }
+        Command::Predict { data, model_path, split, output } => {
+            let mut model = Model::load(&model_path)?;
+            let data = load(&data)?;
+            let samples = data.split(split);
+            let predictions = model.predict(&samples.images);
+            let mut out: Box<dyn Write> = match &output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout().lock()),
+            };
+            writeln!(out, "index,target,prediction")?;
+            for (index, (target, prediction)) in model.targets(&samples.labels).iter().zip(&predictions).enumerate() {
+                writeln!(out, "{},{},{}", index, target, prediction)?;
+            }
+            out.flush()?;
+        }
+        Command::Run { config, jobs, output, dry_run, no_track } => {
+            let experiment = ExperimentConfig::load(&config)?;
+            let runs = experiment.runs()?;
+            if dry_run {
+                for run in &runs {
+                    println!("{}: {}", run.name, serde_json::to_string(&run.parameters)?);
+                }
+                return Ok(());
+            }
+            let parallel = jobs.unwrap_or_else(|| experiment.parallel());
+            eprintln!("{}: {} runs, {} at a time", experiment.name, runs.len(), parallel);
+            let directory = output.unwrap_or_else(|| PathBuf::from("results").join(&experiment.name));
+            let runs_root = directory.join("runs");
+            let results = run_all(&runs, parallel, (!no_track).then_some(runs_root.as_path()), |run, report| println!("{}: {}", run.name, report))?;
+            write_results(&directory, &results)?;
+            println!();
+            for result in &results {
+                let validation = result.best_validation_accuracy.map_or("-".to_string(), |a| format!("{:.2}%", 100.0 * a));
+                println!("{:<32} {:<12} validation {:>7} test {:>6.2}% ({:.1}s)", result.run, result.model, validation, 100.0 * result.test_accuracy, result.seconds);
+            }
+            println!("results written to {}", directory.display());
+        }
+        Command::Compare { paths, sort, csv } => {
+            let records = RunRecord::find(&paths)?;
+            if records.is_empty() {
+                return Err(format!("no runs found in {}", paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")).into());
+            }
+            let mut comparison = Comparison::new(&records);
+            if let Some(column) = &sort {
+                comparison.sort_by(column)?;
+            }
+            if csv {
+                comparison.write_csv(&mut io::stdout().lock())?;
+            } else {
+                print!("{}", comparison);
+            }
+        }
     }
+    Ok(())
+}
 
-   multi_model.validate(&test_images, &test_labels);
-}
\ No newline at end of file
+fn load(args: &DataArgs) -> Result<Data> {
+    let data = Data::load(args.dataset, args.subset)?;
+    eprintln!("{} {}: {} train, {} validation, {} test images", args.dataset, args.subset, data.train.len(), data.validation.len(), data.test.len());
+    Ok(data)
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use ndarray::{s, Array1, Array2, Ix2};
+use serde::{Deserialize, Serialize};
+use feed_forward::activation::Activation;
+use feed_forward::checkpoint::{read_parameters, write_parameters};
+use feed_forward::cross_entropy::argmax_rows;
+use feed_forward::init::Initializer;
+use feed_forward::layer::Sequential;
+use feed_forward::layers::{ActivationLayer, Dense, Flatten};
+use feed_forward::models::{lenet5, mnist_images_to_batch, small_vgg};
+use feed_forward::perceptron::{correct_labels, MultiClassPerceptron, Perceptron};
+use feed_forward::seed::rng_from_seed;
+
+const MAGIC: &[u8; 8] = b"RMLMODL1";
+const PIXELS: usize = 784;
+const DIGITS: usize = 10;
+/// The most weights and biases a saved MLP may declare, 1 GiB of f32.
+const MAX_PARAMETERS: u64 = 1 << 28;
+
+/// The kinds of model an experiment can train.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModelKind {
+    /// One perceptron telling a digit from all the others.
+    Perceptron,
+    /// Ten perceptrons, one per digit.
+    #[default]
+    OneVsRest,
+    /// Dense ReLU layers of the sizes in `ModelSpec::hidden`, trained with cross-entropy.
+    Mlp,
+    Lenet5,
+    SmallVgg,
+}
+
+impl FromStr for ModelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perceptron" => Ok(ModelKind::Perceptron),
+            "one-vs-rest" => Ok(ModelKind::OneVsRest),
+            "mlp" => Ok(ModelKind::Mlp),
+            "lenet5" => Ok(ModelKind::Lenet5),
+            "small-vgg" => Ok(ModelKind::SmallVgg),
+            _ => Err(format!("unknown model {:?}, expected perceptron, one-vs-rest, mlp, lenet5 or small-vgg", s)),
+        }
+    }
+}
+
+impl fmt::Display for ModelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ModelKind::Perceptron => "perceptron",
+            ModelKind::OneVsRest => "one-vs-rest",
+            ModelKind::Mlp => "mlp",
+            ModelKind::Lenet5 => "lenet5",
+            ModelKind::SmallVgg => "small-vgg",
+        })
+    }
+}
+
+/// What to build: the kind of model and the settings that shape it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelSpec {
+    #[serde(with = "crate::config::text")]
+    pub kind: ModelKind,
+    /// The digit a single perceptron detects.
+    pub digit: u8,
+    /// The hidden layer sizes of an MLP.
+    pub hidden: Vec<usize>,
+}
+
+impl Default for ModelSpec {
+    fn default() -> Self {
+        ModelSpec { kind: ModelKind::default(), digit: 0, hidden: vec![128] }
+    }
+}
+
+impl ModelSpec {
+    /// Checks what the types don't: the digit has to be one.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.digit as usize >= DIGITS {
+            return Err(format!("--digit must be 0 to 9, not {}", self.digit));
+        }
+        Ok(())
+    }
+}
+
+/// A model built from a `ModelSpec`, trained or not.
+///
+/// Every model takes (N, 784) pixel rows and predicts a class per row: a digit, or for the
+/// single perceptron 1 when the image shows its digit and 0 otherwise.
+pub enum Model {
+    Perceptron { digit: u8, perceptron: Box<Perceptron> },
+    OneVsRest(MultiClassPerceptron),
+    Network { spec: ModelSpec, network: Sequential<f32> },
+}
+
+impl Model {
+    /// A fresh model. `seed` fixes the initial weights of the networks; perceptrons start
+    /// from zero.
+    pub fn new(spec: &ModelSpec, seed: Option<u64>) -> Model {
+        let mut rng = rng_from_seed(seed);
+        let network = match spec.kind {
+            ModelKind::Perceptron => return Model::Perceptron { digit: spec.digit, perceptron: Box::new(Perceptron::new(PIXELS)) },
+            ModelKind::OneVsRest => return Model::OneVsRest(MultiClassPerceptron::new((0..DIGITS as i32).collect(), PIXELS)),
+            ModelKind::Mlp => {
+                let mut network = Sequential::new().add(Flatten::new());
+                let mut inputs = PIXELS;
+                for &size in &spec.hidden {
+                    network = network
+                        .add(Dense::new(inputs, size, Initializer::HeNormal, &mut rng))
+                        .add(ActivationLayer::new(Activation::Relu));
+                    inputs = size;
+                }
+                network.add(Dense::new(inputs, DIGITS, Initializer::XavierUniform, &mut rng))
+            }
+            ModelKind::Lenet5 => lenet5(DIGITS, &mut rng),
+            ModelKind::SmallVgg => small_vgg(DIGITS, &mut rng),
+        };
+        Model::Network { spec: spec.clone(), network }
+    }
+
+    pub fn spec(&self) -> ModelSpec {
+        match self {
+            Model::Perceptron { digit, .. } => ModelSpec { kind: ModelKind::Perceptron, digit: *digit, hidden: vec![] },
+            Model::OneVsRest(_) => ModelSpec { kind: ModelKind::OneVsRest, digit: 0, hidden: vec![] },
+            Model::Network { spec, .. } => spec.clone(),
+        }
+    }
+
+    /// The number of classes the model predicts.
+    pub fn classes(&self) -> usize {
+        match self {
+            Model::Perceptron { .. } => 2,
+            _ => DIGITS,
+        }
+    }
+
+    /// Maps digit labels to the classes the model predicts.
+    pub fn targets(&self, labels: &[u8]) -> Vec<u8> {
+        match self {
+            Model::Perceptron { digit, .. } => correct_labels(labels, *digit),
+            _ => labels.to_vec(),
+        }
+    }
+
+    /// The predicted class of every row of `images`.
+    pub fn predict(&mut self, images: &Array2<u8>) -> Vec<u8> {
+        match self {
+            Model::Perceptron { perceptron, .. } => {
+                let normalized = Perceptron::<f64>::normalize(images);
+                normalized.outer_iter().map(|row| perceptron.predict(row).0 as u8).collect()
+            }
+            Model::OneVsRest(model) => {
+                let normalized = Perceptron::<f64>::normalize(images);
+                normalized.outer_iter().map(|row| model.predict(row) as u8).collect()
+            }
+            Model::Network { network, .. } => {
+                let mut predictions = Vec::with_capacity(images.nrows());
+                for start in (0..images.nrows()).step_by(256) {
+                    let end = (start + 256).min(images.nrows());
+                    let batch = mnist_images_to_batch::<f32>(&images.slice(s![start..end, ..]).to_owned());
+                    let scores = network.predict(&batch).into_dimensionality::<Ix2>().expect("the networks output one row of scores per image");
+                    predictions.extend(argmax_rows(scores.view()).into_iter().map(|class| class as u8));
+                }
+                predictions
+            }
+        }
+    }
+
+    /// Every parameter array by name, e.g. "digit-3/weights" or "layer-1/weight", for
+    /// histograms.
+    pub fn parameters(&self) -> Vec<(String, Vec<f64>)> {
+        let perceptron = |prefix: &str, perceptron: &Perceptron| vec![
+            (format!("{}weights", prefix), perceptron.weights().to_vec()),
+            (format!("{}bias", prefix), vec![perceptron.bias()]),
+        ];
+        match self {
+            Model::Perceptron { perceptron: p, .. } => perceptron("", p),
+            Model::OneVsRest(model) => model.classes().iter().zip(model.perceptrons())
+                .flat_map(|(class, p)| perceptron(&format!("digit-{}/", class), p))
+                .collect(),
+            Model::Network { network, .. } => network.layers().iter().enumerate()
+                .flat_map(|(i, layer)| layer.parameters().into_iter()
+                    .map(move |parameter| (format!("layer-{}/{}", i, parameter.name), parameter.value.iter().map(|&v| v as f64).collect())))
+                .collect(),
+        }
+    }
+
+    /// Writes the spec and the weights, so `load` can rebuild the model.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        let spec = self.spec();
+        write_u64(out, spec.kind as u64)?;
+        write_u64(out, spec.digit as u64)?;
+        write_u64(out, spec.hidden.len() as u64)?;
+        for &size in &spec.hidden {
+            write_u64(out, size as u64)?;
+        }
+        match self {
+            Model::Perceptron { perceptron, .. } => write_perceptron(out, perceptron),
+            Model::OneVsRest(model) => {
+                for perceptron in model.perceptrons() {
+                    write_perceptron(out, perceptron)?;
+                }
+                Ok(())
+            }
+            Model::Network { network, .. } => write_parameters(network, out),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Model> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Model> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a RustML model file"));
+        }
+        let kind = match read_u64(input)? {
+            0 => ModelKind::Perceptron,
+            1 => ModelKind::OneVsRest,
+            2 => ModelKind::Mlp,
+            3 => ModelKind::Lenet5,
+            4 => ModelKind::SmallVgg,
+            _ => return Err(invalid("unknown model kind")),
+        };
+        let digit = read_u64(input)?;
+        let layers = read_u64(input)?;
+        if digit >= DIGITS as u64 || layers > 64 {
+            return Err(invalid("bad model header"));
+        }
+        let hidden = (0..layers).map(|_| read_u64(input)).collect::<io::Result<Vec<_>>>()?;
+        // checked before Model::new allocates, so a corrupt header can't exhaust memory
+        if kind == ModelKind::Mlp && mlp_parameters(&hidden).is_none_or(|n| n > MAX_PARAMETERS) {
+            return Err(invalid("bad model header"));
+        }
+        let hidden = hidden.into_iter().map(|n| n as usize).collect();
+        let spec = ModelSpec { kind, digit: digit as u8, hidden };
+        let mut model = Model::new(&spec, Some(0));
+        match &mut model {
+            Model::Perceptron { perceptron, .. } => **perceptron = read_perceptron(input)?,
+            Model::OneVsRest(model) => {
+                let perceptrons = (0..DIGITS).map(|_| read_perceptron(input)).collect::<io::Result<Vec<_>>>()?;
+                *model = MultiClassPerceptron::from_perceptrons((0..DIGITS as i32).collect(), perceptrons);
+            }
+            Model::Network { network, .. } => read_parameters(network, input)?,
+        }
+        Ok(model)
+    }
+}
+
+// weights and biases of an MLP with these hidden sizes, None on overflow
+fn mlp_parameters(hidden: &[u64]) -> Option<u64> {
+    let mut total = 0u64;
+    let mut inputs = PIXELS as u64;
+    for &size in hidden.iter().chain([DIGITS as u64].iter()) {
+        total = total.checked_add(inputs.checked_add(1)?.checked_mul(size)?)?;
+        inputs = size;
+    }
+    Some(total)
+}
+
+fn write_perceptron<W: Write>(out: &mut W, perceptron: &Perceptron) -> io::Result<()> {
+    for &w in perceptron.weights() {
+        out.write_all(&w.to_le_bytes())?;
+    }
+    out.write_all(&perceptron.bias().to_le_bytes())
+}
+
+fn read_perceptron<R: Read>(input: &mut R) -> io::Result<Perceptron> {
+    let mut bytes = vec![0u8; (PIXELS + 1) * 8];
+    input.read_exact(&mut bytes)?;
+    let mut values = bytes.chunks_exact(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect::<Vec<_>>();
+    let bias = values.pop().unwrap();
+    Ok(Perceptron::from_weights(Array1::from(values), bias))
+}
+
+fn write_u64<W: Write>(out: &mut W, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
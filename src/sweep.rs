@@ -0,0 +1,158 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::config::Run;
+use crate::data::{Data, Dataset, Subset};
+use crate::experiment::{evaluate, train, EpochReport};
+use crate::model::Model;
+use crate::tracking::{RunDirectory, MAX_SEED};
+use feed_forward::seed::{derive_seed, rng_from_seed};
+
+/// A row of the results table: what a run was and how it did.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunResult {
+    pub run: String,
+    /// The sweep overrides behind the run.
+    pub parameters: BTreeMap<String, Value>,
+    pub model: String,
+    pub epochs: usize,
+    /// The last epoch's, for the networks.
+    pub train_loss: Option<f64>,
+    pub best_validation_accuracy: Option<f64>,
+    pub best_epoch: Option<usize>,
+    pub test_accuracy: f64,
+    pub seconds: f64,
+}
+
+/// A finished run.
+pub struct Trained {
+    pub model: Model,
+    pub result: RunResult,
+    /// The run directory, when the run was tracked.
+    pub directory: Option<PathBuf>,
+}
+
+/// Trains and tests one run on already loaded (and not yet preprocessed) data. With
+/// `runs_root` the run is tracked in a `RunDirectory` under it; a run without a seed then
+/// gets one, so the directory says how to reproduce it.
+pub fn run(run: &Run, data: &Data, runs_root: Option<&Path>, mut on_epoch: impl FnMut(&EpochReport)) -> io::Result<Trained> {
+    let start = Instant::now();
+    let mut run = run.clone();
+    if runs_root.is_some() {
+        run.config.seed.get_or_insert_with(|| derive_seed(&mut rng_from_seed(None)) % (MAX_SEED + 1));
+    }
+    let mut directory = runs_root.map(|root| RunDirectory::create(root, &run)).transpose()?;
+    let config = &run.config;
+    let data = data.preprocess(&config.preprocessing);
+    let settings = config.settings();
+    let mut model = Model::new(&config.model, settings.seed);
+    let mut train_loss = None;
+    let mut best: Option<(f64, usize)> = None;
+    let mut error = None;
+    train(&mut model, &data, &settings, |model, report| {
+        train_loss = report.train_loss;
+        if let Some(accuracy) = report.validation_accuracy {
+            if best.is_none_or(|(best, _)| accuracy > best) {
+                best = Some((accuracy, report.epoch));
+            }
+        }
+        if let Some(directory) = directory.as_mut() {
+            if let Err(e) = directory.record(model, report) {
+                error.get_or_insert(e);
+            }
+        }
+        on_epoch(report);
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    let result = RunResult {
+        run: run.name.clone(),
+        parameters: run.parameters.clone(),
+        model: config.model.kind.to_string(),
+        epochs: settings.epochs,
+        train_loss,
+        best_validation_accuracy: best.map(|(accuracy, _)| accuracy),
+        best_epoch: best.map(|(_, epoch)| epoch),
+        test_accuracy: evaluate(&mut model, &data.test).accuracy(),
+        seconds: start.elapsed().as_secs_f64(),
+    };
+    let path = directory.as_ref().map(|directory| directory.path().to_path_buf());
+    if let Some(mut directory) = directory {
+        directory.record_misclassified(&mut model, &data.test, settings.epochs as u64)?;
+        directory.finish(&model, &result)?;
+    }
+    Ok(Trained { model, result, directory: path })
+}
+
+/// Runs everything, `parallel` runs at a time, loading each dataset subset once and
+/// tracking the runs under `runs_root` if given. Results come back in the order of `runs`.
+pub fn run_all(runs: &[Run], parallel: usize, runs_root: Option<&Path>, on_epoch: impl Fn(&Run, &EpochReport) + Sync)
+               -> Result<Vec<RunResult>, Box<dyn Error>> {
+    let mut datasets: HashMap<(Dataset, Subset), Data> = HashMap::new();
+    for run in runs {
+        let key = (run.config.data.dataset, run.config.data.subset);
+        if let Entry::Vacant(entry) = datasets.entry(key) {
+            entry.insert(Data::load(key.0, key.1)?);
+        }
+    }
+    let one = |run: &Run| {
+        let data = &datasets[&(run.config.data.dataset, run.config.data.subset)];
+        self::run(run, data, runs_root, |report| on_epoch(run, report)).map(|trained| trained.result)
+    };
+    if parallel <= 1 {
+        return Ok(runs.iter().map(one).collect::<io::Result<_>>()?);
+    }
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(parallel).build()?;
+    Ok(pool.install(|| runs.par_iter().map(one).collect::<io::Result<_>>())?)
+}
+
+/// Writes `results.csv` and `results.json` into `directory`, one row per run.
+pub fn write_results(directory: impl AsRef<Path>, results: &[RunResult]) -> Result<(), Box<dyn Error>> {
+    let directory = directory.as_ref();
+    fs::create_dir_all(directory)?;
+    fs::write(directory.join("results.json"), serde_json::to_string_pretty(results)? + "\n")?;
+
+    let parameters = results.iter().flat_map(|r| r.parameters.keys()).collect::<BTreeSet<_>>();
+    let mut out = BufWriter::new(File::create(directory.join("results.csv"))?);
+    let mut header = vec!["run", "model"];
+    header.extend(parameters.iter().map(|p| p.as_str()));
+    header.extend(["epochs", "train_loss", "best_validation_accuracy", "best_epoch", "test_accuracy", "seconds"]);
+    writeln!(out, "{}", header.iter().map(|h| csv_field(h)).collect::<Vec<_>>().join(","))?;
+    for result in results {
+        let mut row = vec![result.run.clone(), result.model.clone()];
+        row.extend(parameters.iter().map(|p| match result.parameters.get(*p) {
+            Some(Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }));
+        row.push(result.epochs.to_string());
+        row.push(optional(result.train_loss));
+        row.push(optional(result.best_validation_accuracy));
+        row.push(result.best_epoch.map_or(String::new(), |e| e.to_string()));
+        row.push(result.test_accuracy.to_string());
+        row.push(format!("{:.3}", result.seconds));
+        writeln!(out, "{}", row.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
+
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
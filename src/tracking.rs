@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use feed_forward::tensorboard::SummaryWriter;
+use crate::config::{ExperimentConfig, Run};
+use crate::data::Samples;
+use crate::experiment::EpochReport;
+use crate::model::Model;
+use crate::sweep::{csv_field, RunResult};
+
+// A run directory holds everything needed to tell what a run was and how it went:
+//
+//   config.toml       the experiment with every default and sweep override filled in
+//   run.json          RunInfo: seed, git commit, start time, command line
+//   metrics.jsonl     one EpochReport per line, written as training goes
+//   metrics.csv       the same as a table
+//   checkpoints/      epoch-NNNN.model every `schedule.checkpoint_every` epochs,
+//                     best.model (best validation accuracy) and final.model
+//   summary.json      the RunResult, once the run finished
+//   events.out.tfevents.*
+//                     the metrics, weight histograms and misclassified test images
+//                     for TensorBoard, so `tensorboard --logdir runs` shows every run
+//
+// Nothing is sent anywhere; `compare` reads the directories back.
+
+const METRICS_HEADER: &str = "epoch,learning_rate,train_loss,validation_accuracy,seconds";
+/// The largest seed config.toml can hold, since TOML integers are signed 64-bit.
+pub const MAX_SEED: u64 = i64::MAX as u64;
+const HISTOGRAM_BINS: usize = 30;
+/// How many misclassified test images go to TensorBoard.
+const MISCLASSIFIED_IMAGES: usize = 16;
+
+/// Where and how a run was started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunInfo {
+    pub name: String,
+    pub seed: Option<u64>,
+    /// `None` outside a git checkout.
+    pub git_commit: Option<String>,
+    /// Whether tracked files had uncommitted changes.
+    pub git_dirty: Option<bool>,
+    /// Seconds since the Unix epoch.
+    pub started: u64,
+    pub command: Vec<String>,
+    /// The sweep overrides behind the run.
+    pub parameters: BTreeMap<String, Value>,
+}
+
+/// A run directory being written. Metrics are flushed after every epoch, so a run that
+/// dies half way still shows how far it got.
+pub struct RunDirectory {
+    path: PathBuf,
+    jsonl: BufWriter<File>,
+    csv: BufWriter<File>,
+    events: SummaryWriter,
+    checkpoint_every: Option<usize>,
+    best_accuracy: Option<f64>,
+}
+
+impl RunDirectory {
+    /// Creates `root/<run name>`, or `<run name>-2` and so on when that exists, and writes
+    /// the config and run info. The run's config should have its seed set, at most
+    /// `MAX_SEED`.
+    pub fn create(root: impl AsRef<Path>, run: &Run) -> io::Result<RunDirectory> {
+        let root = root.as_ref();
+        if let Some(seed) = run.config.seed.filter(|&seed| seed > MAX_SEED) {
+            let message = format!("seed {} is too large to record in config.toml; tracked runs take seeds up to {}", seed, MAX_SEED);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let config = toml::to_string(&run.config).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::create_dir_all(root)?;
+        let mut path = root.join(&run.name);
+        let mut attempt = 1;
+        // create_dir rather than an exists check, so parallel runs can't pick the same one
+        while let Err(e) = fs::create_dir(&path) {
+            if e.kind() != io::ErrorKind::AlreadyExists {
+                return Err(e);
+            }
+            attempt += 1;
+            path = root.join(format!("{}-{}", run.name, attempt));
+        }
+        fs::create_dir(path.join("checkpoints"))?;
+
+        fs::write(path.join("config.toml"), config)?;
+        let info = RunInfo {
+            name: run.name.clone(),
+            seed: run.config.seed,
+            git_commit: git(&["rev-parse", "HEAD"]),
+            git_dirty: git(&["status", "--porcelain", "--untracked-files=no"]).map(|status| !status.is_empty()),
+            started: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            command: std::env::args().collect(),
+            parameters: run.parameters.clone(),
+        };
+        fs::write(path.join("run.json"), serde_json::to_string_pretty(&info)? + "\n")?;
+
+        let jsonl = BufWriter::new(File::create(path.join("metrics.jsonl"))?);
+        let mut csv = BufWriter::new(File::create(path.join("metrics.csv"))?);
+        writeln!(csv, "{}", METRICS_HEADER)?;
+        let events = SummaryWriter::create(&path)?;
+        Ok(RunDirectory { path, jsonl, csv, events, checkpoint_every: run.config.schedule.checkpoint_every, best_accuracy: None })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Logs an epoch and saves whichever checkpoints it calls for.
+    pub fn record(&mut self, model: &Model, report: &EpochReport) -> io::Result<()> {
+        serde_json::to_writer(&mut self.jsonl, report)?;
+        writeln!(self.jsonl)?;
+        self.jsonl.flush()?;
+        writeln!(self.csv, "{},{},{},{},{:.3}", report.epoch, report.learning_rate, optional(report.train_loss), optional(report.validation_accuracy), report.seconds)?;
+        self.csv.flush()?;
+
+        let step = report.epoch as u64;
+        if let Some(loss) = report.train_loss {
+            self.events.add_scalar("loss/train", loss, step)?;
+            self.events.add_scalar("learning_rate", report.learning_rate, step)?;
+        }
+        if let Some(accuracy) = report.validation_accuracy {
+            self.events.add_scalar("accuracy/validation", accuracy, step)?;
+        }
+        for (name, values) in model.parameters() {
+            self.events.add_histogram(&name, &values, HISTOGRAM_BINS, step)?;
+        }
+        self.events.flush()?;
+
+        if self.checkpoint_every.is_some_and(|every| every > 0 && report.epoch % every == 0) {
+            model.save(self.checkpoint(&format!("epoch-{:04}", report.epoch)))?;
+        }
+        if let Some(accuracy) = report.validation_accuracy {
+            if self.best_accuracy.is_none_or(|best| accuracy > best) {
+                self.best_accuracy = Some(accuracy);
+                model.save(self.checkpoint("best"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the first few images of `samples` that `model` gets wrong to TensorBoard,
+    /// tagged with the true and the predicted class.
+    pub fn record_misclassified(&mut self, model: &mut Model, samples: &Samples, step: u64) -> io::Result<()> {
+        let predictions = model.predict(&samples.images);
+        let targets = model.targets(&samples.labels);
+        let wrong = (0..samples.len()).filter(|&i| predictions[i] != targets[i]).take(MISCLASSIFIED_IMAGES);
+        for (n, i) in wrong.enumerate() {
+            let tag = format!("misclassified/{:02} ({} as {})", n, targets[i], predictions[i]);
+            self.events.add_image(&tag, 28, 28, 1, &samples.images.row(i).to_vec(), step)?;
+        }
+        self.events.flush()
+    }
+
+    /// Saves the final model and the summary.
+    pub fn finish(mut self, model: &Model, result: &RunResult) -> io::Result<()> {
+        self.events.add_scalar("accuracy/test", result.test_accuracy, result.epochs as u64)?;
+        self.events.flush()?;
+        model.save(self.checkpoint("final"))?;
+        fs::write(self.path.join("summary.json"), serde_json::to_string_pretty(result)? + "\n")
+    }
+
+    fn checkpoint(&self, name: &str) -> PathBuf {
+        self.path.join("checkpoints").join(format!("{}.model", name))
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
+
+/// A run directory read back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    pub path: PathBuf,
+    pub info: RunInfo,
+    pub config: ExperimentConfig,
+    pub metrics: Vec<EpochReport>,
+    /// `None` while the run is going, or if it never finished.
+    pub summary: Option<RunResult>,
+}
+
+impl RunRecord {
+    pub fn is_run(path: impl AsRef<Path>) -> bool {
+        path.as_ref().join("run.json").is_file()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<RunRecord, Box<dyn Error>> {
+        let path = path.as_ref();
+        let context = |e: Box<dyn Error>| -> Box<dyn Error> { format!("{}: {}", path.display(), e).into() };
+        let info = serde_json::from_str(&fs::read_to_string(path.join("run.json"))?).map_err(|e| context(e.into()))?;
+        let config = toml::from_str(&fs::read_to_string(path.join("config.toml"))?).map_err(|e| context(e.into()))?;
+        let mut metrics = vec![];
+        if let Ok(file) = File::open(path.join("metrics.jsonl")) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                // the last line of a killed run can be cut short
+                match serde_json::from_str(&line) {
+                    Ok(report) => metrics.push(report),
+                    Err(_) => break,
+                }
+            }
+        }
+        let summary = match fs::read_to_string(path.join("summary.json")) {
+            Ok(text) => Some(serde_json::from_str(&text).map_err(|e| context(e.into()))?),
+            Err(_) => None,
+        };
+        Ok(RunRecord { path: path.to_path_buf(), info, config, metrics, summary })
+    }
+
+    /// The runs at `paths`, each either a run directory or a directory to search for them.
+    pub fn find(paths: &[PathBuf]) -> Result<Vec<RunRecord>, Box<dyn Error>> {
+        let mut records = vec![];
+        for path in paths {
+            find_into(path, &mut records)?;
+        }
+        Ok(records)
+    }
+
+    /// The best validation accuracy and its epoch, from the summary or else the metrics.
+    pub fn best_validation(&self) -> Option<(f64, usize)> {
+        if let Some(summary) = &self.summary {
+            return summary.best_validation_accuracy.zip(summary.best_epoch);
+        }
+        let mut best: Option<(f64, usize)> = None;
+        for report in &self.metrics {
+            if let Some(accuracy) = report.validation_accuracy {
+                if best.is_none_or(|(best, _)| accuracy > best) {
+                    best = Some((accuracy, report.epoch));
+                }
+            }
+        }
+        best
+    }
+}
+
+fn find_into(path: &Path, records: &mut Vec<RunRecord>) -> Result<(), Box<dyn Error>> {
+    if RunRecord::is_run(path) {
+        records.push(RunRecord::load(path)?);
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() && entry.file_name().is_some_and(|name| name != "checkpoints") {
+            find_into(&entry, records)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs side by side: the config fields that differ between them, then how they did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Comparison {
+    pub fn new(records: &[RunRecord]) -> Comparison {
+        let configs = records.iter().map(|record| {
+            let mut fields = BTreeMap::new();
+            flatten("", &serde_json::to_value(&record.config).unwrap_or(Value::Null), &mut fields);
+            fields.remove("name");
+            fields
+        }).collect::<Vec<_>>();
+        let mut keys = configs.iter().flat_map(|fields| fields.keys().cloned()).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        keys.retain(|key| configs.iter().any(|fields| fields.get(key) != configs[0].get(key)));
+
+        let mut header = vec!["run".to_string()];
+        header.extend(keys.iter().cloned());
+        header.extend(["epochs", "best_validation", "best_epoch", "test", "seconds", "commit"].map(String::from));
+        let rows = records.iter().zip(&configs).map(|(record, fields)| {
+            let mut row = vec![record.path.display().to_string()];
+            row.extend(keys.iter().map(|key| fields.get(key).cloned().unwrap_or_else(|| "-".to_string())));
+            row.push(format!("{}/{}", record.metrics.len(), record.config.schedule.epochs));
+            let best = record.best_validation();
+            row.push(best.map_or("-".to_string(), |(accuracy, _)| percent(accuracy)));
+            row.push(best.map_or("-".to_string(), |(_, epoch)| epoch.to_string()));
+            row.push(record.summary.as_ref().map_or("-".to_string(), |summary| percent(summary.test_accuracy)));
+            let seconds = match &record.summary {
+                Some(summary) => summary.seconds,
+                None => record.metrics.iter().map(|report| report.seconds).sum(),
+            };
+            row.push(format!("{:.1}", seconds));
+            let mut commit = record.info.git_commit.as_deref().map_or("-".to_string(), |commit| commit.chars().take(8).collect());
+            if record.info.git_dirty == Some(true) {
+                commit.push('+');
+            }
+            row.push(commit);
+            row
+        }).collect();
+        Comparison { header, rows }
+    }
+
+    /// Orders the rows by a column: numbers (percentages included) highest first, text
+    /// alphabetically, with missing values last.
+    pub fn sort_by(&mut self, column: &str) -> Result<(), String> {
+        let index = self.header.iter().position(|h| h == column)
+            .ok_or_else(|| format!("no column {:?}, expected one of {}", column, self.header.join(", ")))?;
+        let number = |field: &str| field.trim_end_matches('%').parse::<f64>().ok();
+        self.rows.sort_by(|a, b| {
+            let (a, b) = (&a[index], &b[index]);
+            match (number(a), number(b)) {
+                (Some(x), Some(y)) => y.total_cmp(&x),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => (a == "-").cmp(&(b == "-")).then_with(|| a.cmp(b)),
+            }
+        });
+        Ok(())
+    }
+
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            writeln!(out, "{}", row.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// An aligned text table.
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths = self.header.iter().map(|h| h.chars().count()).collect::<Vec<_>>();
+        for row in &self.rows {
+            for (width, field) in widths.iter_mut().zip(row) {
+                *width = (*width).max(field.chars().count());
+            }
+        }
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            let line = row.iter().zip(&widths).map(|(field, &width)| format!("{:<width$}", field, width = width)).collect::<Vec<_>>().join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, fields: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(table) => {
+            for (key, value) in table {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&path, value, fields);
+            }
+        }
+        Value::Null => {}
+        Value::String(s) => {
+            fields.insert(prefix.to_string(), s.clone());
+        }
+        _ => {
+            fields.insert(prefix.to_string(), value.to_string());
+        }
+    }
+}
+
+fn percent(accuracy: f64) -> String {
+    format!("{:.2}%", 100.0 * accuracy)
+}
@@ -0,0 +1,341 @@
+mod data_tests {
+    use rustml::data::{Split, Subset};
+
+    #[test]
+    fn test_subset_parses_names_and_sizes() {
+        assert_eq!("mini".parse::<Subset>(), Ok(Subset { train: 50, validation: 10, test: 10 }));
+        assert_eq!("2000, 500,0".parse::<Subset>(), Ok(Subset { train: 2000, validation: 500, test: 0 }));
+        assert!("2000,500".parse::<Subset>().is_err());
+        assert!("huge".parse::<Subset>().is_err());
+    }
+
+    #[test]
+    fn test_subset_display_round_trips() {
+        for subset in [Subset::default(), Subset { train: 7, validation: 8, test: 9 }] {
+            assert_eq!(subset.to_string().parse::<Subset>(), Ok(subset));
+        }
+        assert_eq!(Subset::default().to_string(), "medium");
+    }
+
+    #[test]
+    fn test_split_names() {
+        assert_eq!("val".parse::<Split>(), Ok(Split::Validation));
+        assert_eq!(Split::default().to_string(), "test");
+    }
+}
+
+mod model_tests {
+    use ndarray::Array2;
+    use rustml::model::{Model, ModelKind, ModelSpec};
+
+    fn images() -> Array2<u8> {
+        Array2::from_shape_fn((5, 784), |(i, j)| ((i * 31 + j * 7) % 256) as u8)
+    }
+
+    #[test]
+    fn test_model_kind_names_round_trip() {
+        for kind in [ModelKind::Perceptron, ModelKind::OneVsRest, ModelKind::Mlp, ModelKind::Lenet5, ModelKind::SmallVgg] {
+            assert_eq!(kind.to_string().parse::<ModelKind>(), Ok(kind));
+        }
+        assert!("svm".parse::<ModelKind>().is_err());
+    }
+
+    #[test]
+    fn test_mlp_save_load_keeps_predictions() {
+        let spec = ModelSpec { kind: ModelKind::Mlp, digit: 0, hidden: vec![16, 8] };
+        let mut model = Model::new(&spec, Some(3));
+        let mut bytes = vec![];
+        model.write_to(&mut bytes).unwrap();
+        let mut loaded = Model::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.spec(), spec);
+        assert_eq!(loaded.predict(&images()), model.predict(&images()));
+    }
+
+    #[test]
+    fn test_perceptron_save_load_keeps_digit() {
+        let spec = ModelSpec { kind: ModelKind::Perceptron, digit: 4, hidden: vec![] };
+        let mut bytes = vec![];
+        Model::new(&spec, None).write_to(&mut bytes).unwrap();
+        let mut loaded = Model::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.spec(), spec);
+        assert_eq!(loaded.targets(&[4, 1, 4]), vec![1, 0, 1]);
+        assert_eq!(loaded.predict(&images()).len(), 5);
+    }
+
+    #[test]
+    fn test_read_rejects_other_files() {
+        assert!(Model::read_from(&mut &b"RMLCKPT1........"[..]).is_err());
+    }
+
+    #[test]
+    fn test_read_rejects_huge_hidden_layers() {
+        let model = Model::new(&ModelSpec { kind: ModelKind::Mlp, digit: 0, hidden: vec![4] }, Some(1));
+        let mut bytes = vec![];
+        model.write_to(&mut bytes).unwrap();
+        // the first hidden size follows the magic, kind, digit and layer count
+        bytes[32..40].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let error = Model::read_from(&mut bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+mod experiment_tests {
+    use rustml::experiment::Evaluation;
+
+    #[test]
+    fn test_evaluation_counts() {
+        let evaluation = Evaluation::new(3, &[0, 0, 1, 2, 2], &[0, 1, 1, 2, 0]);
+        assert_eq!(evaluation.total(), 5);
+        assert_eq!(evaluation.correct(), 3);
+        assert!((evaluation.accuracy() - 0.6).abs() < 1e-12);
+        assert!((evaluation.class_accuracy(0) - 0.5).abs() < 1e-12);
+        assert_eq!(evaluation.confusion[2][0], 1);
+    }
+
+    #[test]
+    fn test_empty_evaluation_has_zero_accuracy() {
+        assert_eq!(Evaluation::new(10, &[], &[]).accuracy(), 0.0);
+    }
+}
+
+mod config_tests {
+    use rustml::config::ExperimentConfig;
+    use rustml::data::{Data, Preprocessing, Samples, Subset};
+    use rustml::experiment::Decay;
+    use rustml::model::ModelKind;
+    use ndarray::array;
+
+    fn parse(text: &str) -> ExperimentConfig {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_defaults_match_train_command() {
+        let config = parse("name = \"plain\"");
+        assert_eq!(config.settings(), rustml::experiment::TrainSettings::default());
+        assert_eq!(config.model.kind, ModelKind::OneVsRest);
+        assert_eq!(config.data.subset, Subset::default());
+        let runs = config.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].name, "plain");
+        assert!(runs[0].parameters.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        assert!(toml::from_str::<ExperimentConfig>("name = \"x\"\n[model]\nhiden = [10]").is_err());
+        assert!(toml::from_str::<ExperimentConfig>("name = \"x\"\n[data]\nsubset = \"huge\"").is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_digit_is_rejected() {
+        let error = parse("name = \"x\"\n[model]\nkind = \"perceptron\"\ndigit = 12").runs().unwrap_err();
+        assert_eq!(error, "experiment: --digit must be 0 to 9, not 12");
+        let swept = parse("name = \"x\"\n[sweep]\nparameters = { \"model.digit\" = [3, 10] }");
+        assert!(swept.runs().unwrap_err().contains("model.digit=10"));
+    }
+
+    #[test]
+    fn test_grid_sweep_crosses_variants_and_parameters() {
+        let config = parse(r#"
+            name = "grid"
+            [model]
+            kind = "mlp"
+            [sweep]
+            [sweep.parameters]
+            "optimizer.learning_rate" = [0.1, 0.01]
+            "model.hidden" = [[32], [64, 32]]
+            [[sweep.variants]]
+            "schedule.epochs" = 2
+            [[sweep.variants]]
+            "schedule.epochs" = 4
+        "#);
+        let runs = config.runs().unwrap();
+        assert_eq!(runs.len(), 8);
+        assert_eq!(runs[0].name, "grid-001");
+        assert_eq!(runs[7].name, "grid-008");
+        assert_eq!(runs[0].config.model.hidden, vec![32]);
+        assert_eq!(runs[1].config.optimizer.learning_rate, 0.01);
+        assert_eq!(runs[2].config.model.hidden, vec![64, 32]);
+        assert_eq!(runs[4].config.schedule.epochs, 4);
+        assert!(runs.iter().all(|run| run.config.sweep.is_none() && run.parameters.len() == 3));
+    }
+
+    #[test]
+    fn test_bad_overrides_name_the_parameter() {
+        let config = parse("name = \"bad\"\n[sweep.parameters]\n\"model.kind\" = [\"svm\"]");
+        assert!(config.runs().unwrap_err().contains("model.kind=\"svm\""));
+        let config = parse("name = \"bad\"\n[sweep.parameters]\n\"seed\" = { min = 1, max = 2 }");
+        assert!(config.runs().is_err());
+    }
+
+    #[test]
+    fn test_random_sweep_is_reproducible_and_in_range() {
+        let text = r#"
+            name: random
+            sweep:
+              method: random
+              samples: 20
+              seed: 3
+              parameters:
+                optimizer.learning_rate: { min: 0.001, max: 0.1, log: true }
+                optimizer.batch_size: { min: 8, max: 64, integer: true }
+                model.kind: [mlp, lenet5]
+        "#;
+        let config: ExperimentConfig = serde_yaml::from_str(text).unwrap();
+        let runs = config.runs().unwrap();
+        assert_eq!(runs, config.runs().unwrap());
+        assert_eq!(runs.len(), 20);
+        for run in &runs {
+            let optimizer = run.config.optimizer;
+            assert!((0.001..=0.1).contains(&optimizer.learning_rate));
+            assert!((8..=64).contains(&optimizer.batch_size));
+            assert!(matches!(run.config.model.kind, ModelKind::Mlp | ModelKind::Lenet5));
+        }
+    }
+
+    #[test]
+    fn test_checked_in_experiments_load() {
+        let comparison = ExperimentConfig::load("experiments/perceptron-vs-mlp.toml").unwrap();
+        let names = comparison.runs().unwrap().into_iter().map(|run| run.name).collect::<Vec<_>>();
+        assert_eq!(names, ["one-vs-rest", "mlp-64", "mlp-128", "mlp-256-128"]);
+        assert_eq!(comparison.parallel(), 2);
+        let search = ExperimentConfig::load("experiments/mlp-random-search.yaml").unwrap();
+        assert_eq!(search.runs().unwrap().len(), 12);
+        assert_eq!(search.schedule.decay, Decay::Cosine { min_learning_rate: 0.001 });
+    }
+
+    #[test]
+    fn test_decay_schedules() {
+        let step = Decay::Step { every: 2, factor: 0.5 };
+        let rates = (1..=5).map(|epoch| step.learning_rate(1.0, epoch, 5)).collect::<Vec<_>>();
+        assert_eq!(rates, [1.0, 1.0, 0.5, 0.5, 0.25]);
+        assert_eq!(Decay::Exponential { gamma: 0.5 }.learning_rate(2.0, 3, 10), 0.5);
+        let cosine = Decay::Cosine { min_learning_rate: 0.1 };
+        assert_eq!(cosine.learning_rate(1.0, 1, 4), 1.0);
+        assert!((cosine.learning_rate(1.0, 3, 4) - 0.55).abs() < 1e-12);
+        assert_eq!(Decay::Constant.learning_rate(0.3, 7, 10), 0.3);
+    }
+
+    #[test]
+    fn test_preprocessing_filters_and_binarizes() {
+        let samples = Samples { images: array![[0u8, 200], [100, 128], [255, 5]], labels: vec![3, 1, 3] };
+        let data = Data { train: samples.clone(), validation: samples.clone(), test: samples };
+        let preprocessing = Preprocessing { binarize: Some(128), digits: Some(vec![3]) };
+        let processed = data.preprocess(&preprocessing);
+        assert_eq!(processed.test.labels, vec![3, 3]);
+        assert_eq!(processed.train.images, array![[0u8, 255], [255, 0]]);
+        assert_eq!(data.preprocess(&Preprocessing::default()), data);
+    }
+}
+
+mod tracking_tests {
+    use std::fs;
+    use feed_forward::tensorboard::read_records;
+    use ndarray::Array2;
+    use rustml::config::{ExperimentConfig, Run};
+    use rustml::data::{Data, Samples};
+    use rustml::model::{Model, ModelKind};
+    use rustml::sweep;
+    use rustml::tracking::{Comparison, RunRecord};
+
+    fn data() -> Data {
+        // two easy classes: dark images are 0s, bright ones 1s
+        let labels = (0..40).map(|i| (i % 2) as u8).collect::<Vec<_>>();
+        let images = Array2::from_shape_fn((40, 784), |(i, j)| if i % 2 == 1 && j % 3 == 0 { 200 } else { (j % 7) as u8 });
+        let samples = Samples { images, labels };
+        Data { train: samples.clone(), validation: samples.clone(), test: samples }
+    }
+
+    fn experiment(name: &str, kind: ModelKind) -> ExperimentConfig {
+        let mut config = ExperimentConfig::new(name);
+        config.model.kind = kind;
+        config.model.hidden = vec![8];
+        config.schedule.epochs = 4;
+        config.schedule.checkpoint_every = Some(2);
+        config
+    }
+
+    #[test]
+    fn test_tracked_run_writes_directory() {
+        let root = std::env::temp_dir().join(format!("rustml_runs_{}", std::process::id()));
+        let data = data();
+        let mut epochs = 0;
+        let mut trained = sweep::run(&Run::new(experiment("mlp", ModelKind::Mlp)), &data, Some(&root), |_| epochs += 1).unwrap();
+        assert_eq!(epochs, 4);
+        let directory = trained.directory.unwrap();
+        assert_eq!(directory, root.join("mlp"));
+        for file in ["config.toml", "run.json", "metrics.jsonl", "metrics.csv", "summary.json"] {
+            assert!(directory.join(file).is_file(), "{}", file);
+        }
+        for checkpoint in ["epoch-0002", "epoch-0004", "best", "final"] {
+            assert!(directory.join("checkpoints").join(format!("{}.model", checkpoint)).is_file(), "{}", checkpoint);
+        }
+        assert_eq!(fs::read_to_string(directory.join("metrics.csv")).unwrap().lines().count(), 5);
+        let events = fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.file_name().unwrap().to_str().unwrap().starts_with("events.out.tfevents."))
+            .expect("an event file for TensorBoard");
+        // the header, then per epoch 3 scalars and 4 histograms, then the test accuracy
+        assert!(read_records(&events).unwrap().len() > 1 + 4 * 7);
+
+        let record = RunRecord::load(&directory).unwrap();
+        assert_eq!(record.metrics.len(), 4);
+        assert!(record.config.seed.is_some(), "tracked runs get a seed");
+        assert_eq!(record.info.seed, record.config.seed);
+        assert_eq!(record.summary.as_ref(), Some(&trained.result));
+        let mut reloaded = Model::load(directory.join("checkpoints/final.model")).unwrap();
+        assert_eq!(reloaded.predict(&data.test.images), trained.model.predict(&data.test.images));
+
+        // the same seed again reproduces the run, in a directory of its own
+        let again = sweep::run(&Run::new(record.config.clone()), &data, Some(&root), |_| {}).unwrap();
+        assert_eq!(again.directory.unwrap(), root.join("mlp-2"));
+        assert_eq!(again.result.train_loss, trained.result.train_loss);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_untracked_run_keeps_seed_unset() {
+        let trained = sweep::run(&Run::new(experiment("plain", ModelKind::OneVsRest)), &data(), None, |_| {}).unwrap();
+        assert!(trained.directory.is_none());
+        assert_eq!(trained.result.epochs, 4);
+    }
+
+    #[test]
+    fn test_tracked_run_rejects_seed_toml_cannot_hold() {
+        let root = std::env::temp_dir().join(format!("rustml_seed_{}", std::process::id()));
+        let mut config = experiment("huge-seed", ModelKind::Perceptron);
+        config.seed = Some(u64::MAX);
+        let error = sweep::run(&Run::new(config), &data(), Some(&root), |_| {}).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("too large"));
+        assert!(!root.join("huge-seed").exists());
+    }
+
+    #[test]
+    fn test_compare_shows_differences() {
+        let root = std::env::temp_dir().join(format!("rustml_compare_{}", std::process::id()));
+        let data = data();
+        sweep::run(&Run::new(experiment("a", ModelKind::Mlp)), &data, Some(&root.join("nested")), |_| {}).unwrap();
+        let mut other = experiment("b", ModelKind::OneVsRest);
+        other.seed = Some(1);
+        sweep::run(&Run::new(other), &data, Some(&root), |_| {}).unwrap();
+        // an unfinished run: no summary yet
+        sweep::run(&Run::new(experiment("c", ModelKind::Mlp)), &data, Some(&root), |_| {}).unwrap();
+        fs::remove_file(root.join("c/summary.json")).unwrap();
+
+        let records = RunRecord::find(std::slice::from_ref(&root)).unwrap();
+        assert_eq!(records.len(), 3);
+        let mut comparison = Comparison::new(&records);
+        assert!(comparison.header.contains(&"model.kind".to_string()));
+        assert!(!comparison.header.contains(&"schedule.epochs".to_string()));
+        comparison.sort_by("test").unwrap();
+        assert_eq!(comparison.rows[2][comparison.header.iter().position(|h| h == "test").unwrap()], "-");
+        assert!(comparison.sort_by("accuracy").is_err());
+        let mut csv = vec![];
+        comparison.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 4);
+        assert_eq!(comparison.to_string().lines().count(), 4);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
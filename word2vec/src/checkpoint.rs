@@ -0,0 +1,176 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use ndarray::Array2;
+use feed_forward::embedding::Embedding;
+use crate::fasttext::{FastText, Subwords};
+use crate::keyed_vectors::KeyedVectors;
+use crate::model::Word2Vec;
+use crate::train::{Architecture, Objective, TrainConfig};
+use crate::vocab::Vocabulary;
+
+const MAGIC: &[u8; 8] = b"RMLW2VC1";
+
+/// The state of a word2vec or fastText run after some epochs, enough to resume it with
+/// `TrainOptions::resume_from`.
+///
+/// Each epoch draws its random numbers from streams derived from the seed and the epoch
+/// number, so with a seed and the same number of threads a resumed run ends with the same
+/// vectors as one that was never interrupted.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub config: TrainConfig,
+    /// The n-gram settings of a fastText run, `None` for word2vec.
+    pub subwords: Option<Subwords>,
+    /// Epochs completed.
+    pub epoch: usize,
+    pub vocab: Vocabulary,
+    /// The input rows: the word vectors, then for fastText the n-gram buckets.
+    pub input: Array2<f32>,
+    /// The output rows the objective scores against.
+    pub output: Array2<f32>,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to a temporary file next to `path` and renames it over
+    /// `path`, so an interrupted save leaves the previous checkpoint intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let mut out = BufWriter::new(File::create(&partial)?);
+        self.write_to(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&partial, path)
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        let config = &self.config;
+        let subwords = self.subwords.unwrap_or(Subwords { min_n: 0, max_n: 0, buckets: 0 });
+        let header = [
+            self.epoch as u64,
+            (config.architecture == Architecture::Cbow) as u64,
+            (config.objective == Objective::HierarchicalSoftmax) as u64,
+            config.dim as u64,
+            config.window as u64,
+            config.negative as u64,
+            config.sample.to_bits(),
+            config.epochs as u64,
+            config.learning_rate.to_bits() as u64,
+            config.threads as u64,
+            config.table_size as u64,
+            config.seed.is_some() as u64,
+            config.seed.unwrap_or(0),
+            self.subwords.is_some() as u64,
+            subwords.min_n as u64,
+            subwords.max_n as u64,
+            subwords.buckets as u64,
+            self.input.nrows() as u64,
+            self.output.nrows() as u64,
+        ];
+        for value in header {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        let mut vocab = vec![];
+        self.vocab.write_to(&mut vocab)?;
+        out.write_all(&(vocab.len() as u64).to_le_bytes())?;
+        out.write_all(&vocab)?;
+        for embedding in [&self.input, &self.output] {
+            for v in embedding {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the file at `path` starts like a checkpoint, to tell it from a vector file.
+    pub fn is_checkpoint(path: impl AsRef<Path>) -> io::Result<bool> {
+        let mut magic = vec![];
+        File::open(path)?.take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+        Ok(magic == MAGIC)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Checkpoint> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a checkpoint written by `save`, checking that the matrices fit the vocabulary.
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<Checkpoint> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a word2vec checkpoint"));
+        }
+        let mut header = [0u64; 19];
+        for value in header.iter_mut() {
+            *value = read_u64(&mut input)?;
+        }
+        let [epoch, cbow, hierarchical, dim, window, negative, sample, epochs, learning_rate, threads, table_size, has_seed, seed, has_subwords, min_n, max_n, buckets, input_rows, output_rows] = header;
+        let config = TrainConfig {
+            architecture: if cbow == 1 { Architecture::Cbow } else { Architecture::SkipGram },
+            objective: if hierarchical == 1 { Objective::HierarchicalSoftmax } else { Objective::NegativeSampling },
+            dim: dim as usize,
+            window: window as usize,
+            negative: negative as usize,
+            sample: f64::from_bits(sample),
+            epochs: epochs as usize,
+            learning_rate: f32::from_bits(learning_rate as u32),
+            threads: (threads as usize).max(1),
+            table_size: table_size as usize,
+            seed: (has_seed == 1).then_some(seed),
+        };
+        let subwords = (has_subwords == 1).then_some(Subwords { min_n: min_n as usize, max_n: max_n as usize, buckets: buckets as usize });
+
+        let length = read_u64(&mut input)? as usize;
+        let mut vocab = vec![];
+        input.by_ref().take(length as u64).read_to_end(&mut vocab)?;
+        if vocab.len() != length {
+            return Err(invalid("truncated vocabulary"));
+        }
+        let vocab = Vocabulary::read_from(vocab.as_slice())?;
+        let expected_inputs = vocab.len() + subwords.map_or(0, |s| s.buckets);
+        if input_rows as usize != expected_inputs || epoch > epochs {
+            return Err(invalid("inconsistent checkpoint header"));
+        }
+        let inputs = read_matrix(&mut input, input_rows as usize, dim as usize)?;
+        let outputs = read_matrix(&mut input, output_rows as usize, dim as usize)?;
+        Ok(Checkpoint { config, subwords, epoch: epoch as usize, vocab, input: inputs, output: outputs })
+    }
+
+    pub fn into_word2vec(self) -> Word2Vec {
+        Word2Vec::new(self.vocab, Embedding::from_pretrained(self.input), Embedding::from_pretrained(self.output))
+    }
+
+    /// The fastText model, or `None` for a word2vec checkpoint.
+    pub fn into_fasttext(self) -> Option<FastText> {
+        let subwords = self.subwords?;
+        Some(FastText::new(self.vocab, subwords, Embedding::from_pretrained(self.input), Embedding::from_pretrained(self.output)))
+    }
+
+    /// The vectors of the vocabulary words, averaged with their n-grams for fastText.
+    pub fn into_keyed_vectors(self) -> KeyedVectors {
+        match self.subwords {
+            Some(_) => self.into_fasttext().expect("subwords are set").keyed_vectors(),
+            None => self.into_word2vec().keyed_vectors(),
+        }
+    }
+}
+
+fn read_matrix<R: Read>(input: &mut R, rows: usize, cols: usize) -> io::Result<Array2<f32>> {
+    let size = rows.checked_mul(cols).and_then(|n| n.checked_mul(4)).ok_or_else(|| invalid("checkpoint too large"))?;
+    let mut bytes = vec![0u8; size];
+    input.read_exact(&mut bytes)?;
+    let values = bytes.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+    Ok(Array2::from_shape_vec((rows, cols), values).expect("the size was checked"))
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Lines};
+use std::path::Path;
+use crate::phrases::Phrases;
+use crate::tokenize::Tokenizer;
+use crate::vocab::Vocabulary;
+
+// Sentences longer than this are split, as in the reference implementation, so a corpus
+// without line breaks still gives each thread manageable units of work.
+pub const MAX_SENTENCE_LENGTH: usize = 1000;
+
+/// Reads a plain-text corpus with one sentence per line and whitespace between words.
+pub fn read_sentences(path: impl AsRef<Path>) -> io::Result<Vec<Vec<String>>> {
+    stream_sentences(path, Tokenizer::default())?.collect()
+}
+
+/// Tokenized sentences read lazily from a corpus file, one line at a time.
+pub fn stream_sentences(path: impl AsRef<Path>, tokenizer: Tokenizer) -> io::Result<SentenceStream<BufReader<File>>> {
+    Ok(SentenceStream::new(BufReader::new(File::open(path)?), tokenizer))
+}
+
+/// An iterator over the sentences of a corpus, one per non-empty line, split into pieces of
+/// at most `MAX_SENTENCE_LENGTH` tokens.
+pub struct SentenceStream<R> {
+    lines: Lines<R>,
+    tokenizer: Tokenizer,
+    phrases: Option<Phrases>,
+    pending: VecDeque<Vec<String>>,
+}
+
+impl<R: BufRead> SentenceStream<R> {
+    pub fn new(reader: R, tokenizer: Tokenizer) -> SentenceStream<R> {
+        SentenceStream { lines: reader.lines(), tokenizer, phrases: None, pending: VecDeque::new() }
+    }
+
+    /// Joins detected phrases in each sentence.
+    pub fn with_phrases(mut self, phrases: Phrases) -> Self {
+        self.phrases = Some(phrases);
+        self
+    }
+}
+
+impl<R: BufRead> Iterator for SentenceStream<R> {
+    type Item = io::Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut tokens = self.tokenizer.tokenize(&line);
+            if let Some(phrases) = &self.phrases {
+                tokens = phrases.apply(tokens);
+            }
+            self.pending.extend(tokens.chunks(MAX_SENTENCE_LENGTH).map(<[String]>::to_vec));
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Maps each sentence to word ids, dropping out-of-vocabulary words and sentences that
+/// end up empty.
+pub fn encode_sentences(sentences: &[Vec<String>], vocab: &Vocabulary) -> Vec<Vec<usize>> {
+    sentences.iter()
+        .map(|sentence| vocab.encode(sentence.iter().map(String::as_str)))
+        .filter(|ids| !ids.is_empty())
+        .collect()
+}
+
+/// Like `encode_sentences` for a stream, so only the ids are ever held in memory.
+pub fn encode_stream<I>(sentences: I, vocab: &Vocabulary) -> io::Result<Vec<Vec<usize>>>
+where
+    I: IntoIterator<Item = io::Result<Vec<String>>>,
+{
+    let mut encoded = vec![];
+    for sentence in sentences {
+        let ids = vocab.encode(sentence?.iter().map(String::as_str));
+        if !ids.is_empty() {
+            encoded.push(ids);
+        }
+    }
+    Ok(encoded)
+}
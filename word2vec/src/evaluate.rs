@@ -0,0 +1,230 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use crate::keyed_vectors::KeyedVectors;
+
+/// One "a is to b as c is to d" question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analogy {
+    pub section: String,
+    pub a: String,
+    pub b: String,
+    pub c: String,
+    pub d: String,
+}
+
+/// Reads questions in the Google analogy format (questions-words.txt): ": section" lines
+/// start a section, every other line holds four words. With `lowercase` the words are
+/// lowercased, to match vectors trained on lowercased text.
+pub fn read_analogies(path: impl AsRef<Path>, lowercase: bool) -> io::Result<Vec<Analogy>> {
+    let mut questions = vec![];
+    let mut section = String::new();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix(':') {
+            section = name.trim().to_string();
+            continue;
+        }
+        let words = line.split_whitespace()
+            .map(|w| if lowercase { w.to_lowercase() } else { w.to_string() })
+            .collect::<Vec<_>>();
+        match <[String; 4]>::try_from(words) {
+            Ok([a, b, c, d]) => questions.push(Analogy { section: section.clone(), a, b, c, d }),
+            Err(_) => return Err(invalid(number, "expected four words")),
+        }
+    }
+    Ok(questions)
+}
+
+/// How analogy questions are answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnalogyMethod {
+    /// `KeyedVectors::analogy`.
+    #[default]
+    CosAdd,
+    /// `KeyedVectors::analogy_cos_mul`.
+    CosMul,
+}
+
+/// Analogy accuracy overall and per section.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AnalogyReport {
+    /// (section, correct, answered) in file order.
+    pub sections: Vec<(String, usize, usize)>,
+    pub correct: usize,
+    pub answered: usize,
+    /// Questions left out because a word isn't in the vocabulary.
+    pub skipped: usize,
+}
+
+impl AnalogyReport {
+    /// The share of answered questions that were right, 0 if none were answered.
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.correct, self.answered)
+    }
+}
+
+impl fmt::Display for AnalogyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (section, correct, answered) in &self.sections {
+            writeln!(f, "{}: {:.2}% ({}/{})", section, 100.0 * ratio(*correct, *answered), correct, answered)?;
+        }
+        write!(f, "total: {:.2}% ({}/{}), {} skipped", 100.0 * self.accuracy(), self.correct, self.answered, self.skipped)
+    }
+}
+
+/// Answers each question with b - a + c and counts it right when the best candidate,
+/// excluding a, b and c, is d. As is customary, only the `restrict` most frequent words
+/// are candidates (the reference tool uses 30 000) and questions with unknown words are
+/// skipped rather than counted wrong.
+pub fn evaluate_analogies(vectors: &KeyedVectors, questions: &[Analogy], method: AnalogyMethod, restrict: Option<usize>) -> AnalogyReport {
+    let mut report = AnalogyReport::default();
+    let known = |word: &str| vectors.id(word).is_some_and(|id| restrict.is_none_or(|r| id < r));
+    for question in questions {
+        if report.sections.last().is_none_or(|(name, _, _)| *name != question.section) {
+            report.sections.push((question.section.clone(), 0, 0));
+        }
+        if ![&question.a, &question.b, &question.c, &question.d].iter().all(|w| known(w)) {
+            report.skipped += 1;
+            continue;
+        }
+        let (positive, negative) = ([question.b.as_str(), question.c.as_str()], [question.a.as_str()]);
+        let answer = match method {
+            AnalogyMethod::CosAdd => vectors.analogy(&positive, &negative, 1, restrict),
+            AnalogyMethod::CosMul => vectors.analogy_cos_mul(&positive, &negative, 1, restrict),
+        };
+        let correct = answer.expect("all words are known").first().is_some_and(|(word, _)| *word == question.d);
+        let section = report.sections.last_mut().unwrap();
+        section.2 += 1;
+        report.answered += 1;
+        if correct {
+            section.1 += 1;
+            report.correct += 1;
+        }
+    }
+    report.sections.retain(|(_, _, answered)| *answered > 0);
+    report
+}
+
+/// Reads word pairs with human similarity scores, one "word1,word2,score" line each as in
+/// the WordSim-353 CSV; tabs are accepted as separators too (SimLex-999, MEN). A first
+/// line whose score isn't a number is taken as the header, and lines starting with '#'
+/// are comments.
+pub fn read_word_pairs(path: impl AsRef<Path>, lowercase: bool) -> io::Result<Vec<(String, String, f64)>> {
+    let mut pairs = vec![];
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split([',', '\t']).map(str::trim).collect::<Vec<_>>();
+        let score = fields.get(2).and_then(|s| s.parse::<f64>().ok());
+        match (fields.len() >= 3, score) {
+            (true, Some(score)) => {
+                let word = |w: &str| if lowercase { w.to_lowercase() } else { w.to_string() };
+                pairs.push((word(fields[0]), word(fields[1]), score));
+            }
+            _ if number == 0 => continue,
+            _ => return Err(invalid(number, "expected word1,word2,score")),
+        }
+    }
+    Ok(pairs)
+}
+
+/// How well cosine similarities agree with human judgements.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SimilarityReport {
+    pub spearman: f64,
+    pub pearson: f64,
+    /// Pairs with both words in the vocabulary, which the correlations are computed on.
+    pub pairs: usize,
+    /// Pairs left out because a word isn't in the vocabulary.
+    pub skipped: usize,
+}
+
+impl fmt::Display for SimilarityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spearman: {:.4}, pearson: {:.4} ({} pairs, {} skipped)", self.spearman, self.pearson, self.pairs, self.skipped)
+    }
+}
+
+pub fn evaluate_word_pairs(vectors: &KeyedVectors, pairs: &[(String, String, f64)]) -> SimilarityReport {
+    let (mut predicted, mut expected) = (vec![], vec![]);
+    for (a, b, score) in pairs {
+        if let Ok(similarity) = vectors.similarity(a, b) {
+            predicted.push(similarity as f64);
+            expected.push(*score);
+        }
+    }
+    SimilarityReport {
+        spearman: spearman(&predicted, &expected),
+        pearson: pearson(&predicted, &expected),
+        pairs: predicted.len(),
+        skipped: pairs.len() - predicted.len(),
+    }
+}
+
+/// Pearson's correlation coefficient, 0 when either side is constant.
+pub fn pearson(x: &[f64], y: &[f64]) -> f64 {
+    assert_eq!(x.len(), y.len(), "samples must have the same length");
+    if x.is_empty() {
+        return 0.0;
+    }
+    let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+    let (mx, my) = (mean(x), mean(y));
+    let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+    for (a, b) in x.iter().zip(y) {
+        sxy += (a - mx) * (b - my);
+        sxx += (a - mx) * (a - mx);
+        syy += (b - my) * (b - my);
+    }
+    if sxx == 0.0 || syy == 0.0 {
+        0.0
+    } else {
+        sxy / (sxx * syy).sqrt()
+    }
+}
+
+/// Spearman's rank correlation: Pearson's on the ranks, with tied values sharing their
+/// average rank.
+pub fn spearman(x: &[f64], y: &[f64]) -> f64 {
+    pearson(&ranks(x), &ranks(y))
+}
+
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        // ranks start..end (0-based) averaged, reported 1-based
+        let rank = (start + end - 1) as f64 / 2.0 + 1.0;
+        for &i in &order[start..end] {
+            ranks[i] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, message))
+}
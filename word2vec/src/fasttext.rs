@@ -0,0 +1,170 @@
+use std::io;
+use std::path::Path;
+use ndarray::{Array1, Array2};
+use feed_forward::embedding::Embedding;
+use crate::format::Format;
+use crate::keyed_vectors::KeyedVectors;
+use crate::train::{train_embeddings, TrainConfig, TrainOptions};
+use crate::vocab::Vocabulary;
+
+/// How words are broken into character n-grams (Bojanowski et al., 2017).
+///
+/// A word is wrapped in "<" and ">" so prefixes and suffixes are distinguishable, and
+/// every substring of `min_n` to `max_n` characters becomes an n-gram: "where" with n = 3
+/// gives "<wh", "whe", "her", "ere" and "re>". N-grams are hashed into `buckets` shared
+/// rows, so their number is bounded however many distinct n-grams the corpus has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subwords {
+    pub min_n: usize,
+    pub max_n: usize,
+    pub buckets: usize,
+}
+
+impl Default for Subwords {
+    fn default() -> Self {
+        Subwords { min_n: 3, max_n: 6, buckets: 2_000_000 }
+    }
+}
+
+impl Subwords {
+    /// The character n-grams of `word`, boundary markers included.
+    pub fn ngrams(&self, word: &str) -> Vec<String> {
+        let chars = format!("<{}>", word).chars().collect::<Vec<_>>();
+        let mut ngrams = vec![];
+        for start in 0..chars.len() {
+            for n in self.min_n.max(1)..=self.max_n {
+                if start + n > chars.len() {
+                    break;
+                }
+                ngrams.push(chars[start..start + n].iter().collect());
+            }
+        }
+        ngrams
+    }
+
+    /// The bucket of every n-gram of `word`, in `ngrams` order.
+    pub fn buckets(&self, word: &str) -> Vec<usize> {
+        if self.buckets == 0 {
+            return vec![];
+        }
+        self.ngrams(word).iter().map(|ngram| hash(ngram) as usize % self.buckets).collect()
+    }
+}
+
+/// 32-bit FNV-1a, with bytes sign-extended the way fastText's C++ hashes its `char`s, so
+/// non-ASCII n-grams land in the same buckets as in fastText.
+pub fn hash(text: &str) -> u32 {
+    let mut h: u32 = 2_166_136_261;
+    for &byte in text.as_bytes() {
+        h ^= byte as i8 as u32;
+        h = h.wrapping_mul(16_777_619);
+    }
+    h
+}
+
+/// Hyperparameters for `FastText::train`. fastText's defaults differ from word2vec's in
+/// the learning rate, 0.05.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FastTextConfig {
+    pub train: TrainConfig,
+    pub subwords: Subwords,
+}
+
+impl Default for FastTextConfig {
+    fn default() -> Self {
+        FastTextConfig { train: TrainConfig { learning_rate: 0.05, ..TrainConfig::default() }, subwords: Subwords::default() }
+    }
+}
+
+/// Subword embeddings: a word is represented by the average of its own vector and the
+/// vectors of its character n-grams, so words never seen in training still get a vector
+/// from the n-grams they share with known words.
+///
+/// The input matrix has one row per vocabulary word followed by `subwords.buckets` n-gram
+/// rows.
+pub struct FastText {
+    vocab: Vocabulary,
+    subwords: Subwords,
+    input: Embedding<f32>,
+    output: Embedding<f32>,
+}
+
+impl FastText {
+    /// A model from trained embeddings: `input` holds the word rows followed by the
+    /// n-gram buckets.
+    pub fn new(vocab: Vocabulary, subwords: Subwords, input: Embedding<f32>, output: Embedding<f32>) -> FastText {
+        assert_eq!(vocab.len() + subwords.buckets, input.vocabulary(), "one input vector per word and bucket");
+        FastText { vocab, subwords, input, output }
+    }
+
+    /// Trains with the word2vec objectives (skip-gram or CBOW, negative sampling or
+    /// hierarchical softmax) on the n-gram averages.
+    pub fn train(corpus: &[Vec<usize>], vocab: Vocabulary, config: &FastTextConfig) -> FastText {
+        Self::train_with(corpus, vocab, config, TrainOptions::default()).expect("training without callbacks can't fail")
+    }
+
+    /// `train` with progress reports, checkpoints or a resumed start.
+    pub fn train_with(corpus: &[Vec<usize>], vocab: Vocabulary, config: &FastTextConfig, options: TrainOptions) -> io::Result<FastText> {
+        let (input, output) = train_embeddings(corpus, &vocab, &config.train, Some(config.subwords), options)?;
+        Ok(FastText { vocab, subwords: config.subwords, input, output })
+    }
+
+    pub fn vocab(&self) -> &Vocabulary {
+        &self.vocab
+    }
+
+    pub fn subwords(&self) -> Subwords {
+        self.subwords
+    }
+
+    pub fn dim(&self) -> usize {
+        self.input.dim()
+    }
+
+    /// The word and n-gram rows, in that order.
+    pub fn input_embeddings(&self) -> &Embedding<f32> {
+        &self.input
+    }
+
+    pub fn output_embeddings(&self) -> &Embedding<f32> {
+        &self.output
+    }
+
+    /// The vector of any word: for a vocabulary word the average of its own row and its
+    /// n-gram rows, for any other word the average of its n-gram rows. A word too short to
+    /// have n-grams, and not in the vocabulary, gets zeros.
+    pub fn vector(&self, word: &str) -> Array1<f32> {
+        let rows = match self.vocab.id(word) {
+            Some(id) => word_rows(&self.subwords, self.vocab.len(), id, word),
+            None => self.subwords.buckets(word).into_iter().map(|b| self.vocab.len() + b).collect(),
+        };
+        let mut vector = Array1::zeros(self.dim());
+        for &row in &rows {
+            vector += &self.input.vector(row);
+        }
+        if !rows.is_empty() {
+            vector /= rows.len() as f32;
+        }
+        vector
+    }
+
+    /// The vectors of the vocabulary words, for export and queries.
+    pub fn keyed_vectors(&self) -> KeyedVectors {
+        let mut vectors = Array2::zeros((self.vocab.len(), self.dim()));
+        for (id, word) in self.vocab.words().iter().enumerate() {
+            vectors.row_mut(id).assign(&self.vector(word));
+        }
+        KeyedVectors::new(self.vocab.words().to_vec(), vectors)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, format: Format) -> io::Result<()> {
+        self.keyed_vectors().save(path, format)
+    }
+}
+
+// the input rows whose average stands for vocabulary word `id`
+pub(crate) fn word_rows(subwords: &Subwords, vocab_size: usize, id: usize, word: &str) -> Vec<usize> {
+    let mut rows = vec![id];
+    rows.extend(subwords.buckets(word).into_iter().map(|b| vocab_size + b));
+    rows
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use memmap2::Mmap;
+use ndarray::ArrayView1;
+
+/// The word vector file formats in common use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// A "<words> <dim>" header, then one "word v1 v2 ..." line per word.
+    #[default]
+    Word2VecText,
+    /// The same header, then per word its bytes, a space and dim little-endian f32s,
+    /// followed by a newline.
+    Word2VecBinary,
+    /// Like `Word2VecText` without the header, as the Stanford GloVe files are written.
+    GloveText,
+}
+
+impl Format {
+    /// Guesses from the extension: ".bin" is binary, anything else word2vec text.
+    pub fn from_path(path: impl AsRef<Path>) -> Format {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("bin") => Format::Word2VecBinary,
+            _ => Format::Word2VecText,
+        }
+    }
+
+    fn has_header(self) -> bool {
+        self != Format::GloveText
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" | "txt" => Ok(Format::Word2VecText),
+            "binary" | "bin" => Ok(Format::Word2VecBinary),
+            "glove" => Ok(Format::GloveText),
+            _ => Err(format!("unknown format {:?}, expected text, binary or glove", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Word2VecText => "text",
+            Format::Word2VecBinary => "binary",
+            Format::GloveText => "glove",
+        })
+    }
+}
+
+/// Writes (word, vector) entries in `format`.
+///
+/// In the binary format a space ends the word, so words containing whitespace are
+/// rejected there; the text readers take the last `dim` fields as the vector and can cope.
+pub fn write_vectors<'a, W, I>(out: &mut W, format: Format, dim: usize, entries: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a str, ArrayView1<'a, f32>)>,
+    I::IntoIter: ExactSizeIterator,
+{
+    let entries = entries.into_iter();
+    if format.has_header() {
+        writeln!(out, "{} {}", entries.len(), dim)?;
+    }
+    for (word, vector) in entries {
+        if word.is_empty() || (format == Format::Word2VecBinary && word.contains(char::is_whitespace)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("can't write the word {:?} as {}", word, format)));
+        }
+        out.write_all(word.as_bytes())?;
+        match format {
+            Format::Word2VecBinary => {
+                out.write_all(b" ")?;
+                for v in vector {
+                    out.write_all(&v.to_le_bytes())?;
+                }
+            }
+            Format::Word2VecText | Format::GloveText => {
+                for v in vector {
+                    write!(out, " {}", v)?;
+                }
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Reads word vector files through a memory map, one entry at a time, so files far
+/// bigger than memory can be scanned or filtered; only what the caller keeps is copied.
+///
+/// Words that aren't valid UTF-8 (the reference tool cuts long words at a byte limit,
+/// which can split a character) are decoded with U+FFFD replacement characters unless
+/// `with_strict_utf8` is set.
+pub struct VectorReader {
+    map: Option<Mmap>,
+    format: Format,
+    position: usize,
+    dim: usize,
+    count: Option<usize>,
+    read: usize,
+    strict_utf8: bool,
+}
+
+impl VectorReader {
+    pub fn open(path: impl AsRef<Path>, format: Format) -> io::Result<VectorReader> {
+        let file = File::open(path)?;
+        // an empty file can't be mapped on every platform
+        let map = if file.metadata()?.len() == 0 {
+            None
+        } else {
+            // Safety: the map is only read, and like every memory-mapped reader we rely on
+            // nobody truncating or rewriting the file while it is open.
+            Some(unsafe { Mmap::map(&file)? })
+        };
+        let mut reader = VectorReader { map, format, position: 0, dim: 0, count: None, read: 0, strict_utf8: false };
+        reader.read_header()?;
+        Ok(reader)
+    }
+
+    /// Fails on words that aren't valid UTF-8 instead of replacing the bad bytes.
+    pub fn with_strict_utf8(mut self, strict: bool) -> Self {
+        self.strict_utf8 = strict;
+        self
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// The number of words announced in the header; GloVe files don't have one.
+    pub fn header_count(&self) -> Option<usize> {
+        self.count
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        if self.format.has_header() {
+            let (start, end) = self.next_line().ok_or_else(|| invalid("missing header"))?;
+            let line = &self.bytes()[start..end];
+            let fields = std::str::from_utf8(line).ok().map(|l| l.split_whitespace().map(str::parse::<usize>).collect::<Vec<_>>());
+            match fields.as_deref() {
+                Some([Ok(count), Ok(dim)]) => {
+                    self.count = Some(*count);
+                    self.dim = *dim;
+                }
+                _ => return Err(invalid("the header must be \"<words> <dim>\"")),
+            }
+        } else {
+            // GloVe: the dimension is the number of fields on the first line after the word
+            let position = self.position;
+            let (start, end) = self.next_line().unwrap_or((0, 0));
+            let line = &self.bytes()[start..end];
+            self.dim = line.split(|b| b.is_ascii_whitespace()).filter(|f| !f.is_empty()).count().saturating_sub(1);
+            self.position = position;
+        }
+        Ok(())
+    }
+
+    // the byte range of the next non-blank line, without surrounding whitespace
+    fn next_line(&mut self) -> Option<(usize, usize)> {
+        let bytes = self.bytes();
+        let mut start = self.position;
+        loop {
+            while start < bytes.len() && bytes[start].is_ascii_whitespace() {
+                start += 1;
+            }
+            if start >= bytes.len() {
+                self.position = start;
+                return None;
+            }
+            let end = bytes[start..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |p| start + p);
+            let line = bytes[start..end].trim_ascii_end();
+            if !line.is_empty() {
+                let range = (start, start + line.len());
+                self.position = end;
+                return Some(range);
+            }
+            start = end;
+        }
+    }
+
+    fn decode(&self, word: &[u8]) -> io::Result<String> {
+        match std::str::from_utf8(word) {
+            Ok(word) => Ok(word.to_string()),
+            Err(_) if self.strict_utf8 => Err(invalid(&format!("entry {}: the word isn't valid UTF-8", self.read + 1))),
+            Err(_) => Ok(String::from_utf8_lossy(word).into_owned()),
+        }
+    }
+
+    fn read_text(&mut self) -> Option<io::Result<(String, Vec<f32>)>> {
+        let dim = self.dim;
+        let entry = self.read + 1;
+        let (start, end) = self.next_line()?;
+        let line = &self.bytes()[start..end];
+        let mut fields = line.rsplitn(dim + 1, |&b| b == b' ' || b == b'\t');
+        let mut vector = vec![0.0; dim];
+        for slot in vector.iter_mut().rev() {
+            let parsed = fields.next()
+                .and_then(|f| std::str::from_utf8(f).ok())
+                .and_then(|f| f.parse::<f32>().ok());
+            match parsed {
+                Some(v) => *slot = v,
+                None => return Some(Err(invalid(&format!("entry {}: expected {} numbers after the word", entry, dim)))),
+            }
+        }
+        let word = match fields.next().map(<[u8]>::trim_ascii) {
+            Some(word) if !word.is_empty() => word,
+            _ => return Some(Err(invalid(&format!("entry {}: missing word", entry)))),
+        };
+        Some(self.decode(word).map(|word| (word, vector)))
+    }
+
+    fn read_binary(&mut self) -> Option<io::Result<(String, Vec<f32>)>> {
+        let bytes = self.bytes();
+        let mut start = self.position;
+        while start < bytes.len() && bytes[start].is_ascii_whitespace() {
+            start += 1;
+        }
+        if start >= bytes.len() {
+            return None;
+        }
+        let entry = self.read + 1;
+        let Some(length) = bytes[start..].iter().position(|&b| b == b' ') else {
+            return Some(Err(invalid(&format!("entry {}: missing the space after the word", entry))));
+        };
+        let values = start + length + 1;
+        let end = values + 4 * self.dim;
+        if end > bytes.len() {
+            return Some(Err(invalid(&format!("entry {}: the file ends inside the vector", entry))));
+        }
+        let vector = bytes[values..end].chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        let word = self.decode(&bytes[start..start + length]);
+        self.position = end;
+        Some(word.map(|word| (word, vector)))
+    }
+}
+
+impl Iterator for VectorReader {
+    type Item = io::Result<(String, Vec<f32>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.count.is_some_and(|count| self.read >= count) {
+            return None;
+        }
+        let entry = match self.format {
+            Format::Word2VecBinary => self.read_binary(),
+            Format::Word2VecText | Format::GloveText => self.read_text(),
+        };
+        match &entry {
+            Some(Ok(_)) => self.read += 1,
+            // stop after an error rather than resynchronizing somewhere in the middle
+            Some(Err(_)) => self.position = self.bytes().len(),
+            None => {
+                if let Some(count) = self.count.filter(|&count| self.read < count) {
+                    let read = self.read;
+                    self.count = Some(read);
+                    return Some(Err(invalid(&format!("the header announces {} words but the file has {}", count, read))));
+                }
+            }
+        }
+        entry
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
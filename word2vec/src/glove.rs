@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use ndarray::{s, Array2};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use feed_forward::seed::rng_from_seed;
+use crate::hogwild::SharedMatrix;
+use crate::keyed_vectors::KeyedVectors;
+use crate::vocab::Vocabulary;
+
+/// Word-word co-occurrence counts, the input to GloVe.
+///
+/// Words `d` positions apart within `window` add 1/d, so near neighbors count more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cooccurrences {
+    size: usize,
+    // (row, column, weight), sorted
+    entries: Vec<(u32, u32, f32)>,
+}
+
+impl Cooccurrences {
+    /// Counts co-occurrences in sentences of word ids below `size`. With `symmetric`
+    /// every pair is counted in both directions; otherwise only left context is counted.
+    pub fn build(corpus: &[Vec<usize>], size: usize, window: usize, symmetric: bool) -> Cooccurrences {
+        let mut counts: HashMap<(u32, u32), f32> = HashMap::new();
+        for sentence in corpus {
+            for (position, &word) in sentence.iter().enumerate() {
+                for distance in 1..=window.min(position) {
+                    let context = sentence[position - distance];
+                    let weight = 1.0 / distance as f32;
+                    *counts.entry((word as u32, context as u32)).or_insert(0.0) += weight;
+                    if symmetric {
+                        *counts.entry((context as u32, word as u32)).or_insert(0.0) += weight;
+                    }
+                }
+            }
+        }
+        let mut entries = counts.into_iter().map(|((i, j), x)| (i, j, x)).collect::<Vec<_>>();
+        entries.sort_by_key(|e| (e.0, e.1));
+        Cooccurrences { size, entries }
+    }
+
+    /// The number of words, which is the matrix's width and height.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of nonzero entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f32 {
+        self.entries.binary_search_by(|e| (e.0 as usize, e.1 as usize).cmp(&(row, column)))
+            .map_or(0.0, |i| self.entries[i].2)
+    }
+
+    pub fn entries(&self) -> &[(u32, u32, f32)] {
+        &self.entries
+    }
+}
+
+/// Hyperparameters for GloVe. The defaults follow the reference implementation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GloveConfig {
+    pub dim: usize,
+    pub window: usize,
+    pub symmetric: bool,
+    /// Co-occurrence counts at or above this get full weight in the loss.
+    pub x_max: f32,
+    /// The exponent of the weighting function below `x_max`.
+    pub alpha: f32,
+    pub epochs: usize,
+    /// AdaGrad's initial learning rate.
+    pub learning_rate: f32,
+    pub threads: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for GloveConfig {
+    fn default() -> Self {
+        GloveConfig {
+            dim: 100,
+            window: 10,
+            symmetric: true,
+            x_max: 100.0,
+            alpha: 0.75,
+            epochs: 25,
+            learning_rate: 0.05,
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: None,
+        }
+    }
+}
+
+/// Trains GloVe (Pennington et al., 2014) on sentences of word ids from `vocab`.
+///
+/// Word vectors w, context vectors w~ and biases b, b~ are fit so that
+/// w_i . w~_j + b_i + b~_j approximates log X_ij for every nonzero co-occurrence, with
+/// each squared error weighted by f(X) = min(1, (X / x_max)^alpha), which stops rare and
+/// very frequent pairs from dominating. Updates use AdaGrad and run Hogwild style on
+/// `config.threads` threads over the shuffled entries.
+///
+/// Returns the sum of word and context vectors, which the paper found works better than
+/// either, and the mean weighted loss of each epoch.
+pub fn train(corpus: &[Vec<usize>], vocab: &Vocabulary, config: &GloveConfig) -> (KeyedVectors, Vec<f64>) {
+    assert!(config.threads > 0, "threads must be positive");
+    let cooccurrences = Cooccurrences::build(corpus, vocab.len(), config.window, config.symmetric);
+    let mut rng = rng_from_seed(config.seed);
+
+    // each row is a vector followed by its bias, like the reference implementation
+    let width = config.dim + 1;
+    let scale = 1.0 / config.dim as f32;
+    let mut init = |_| (rng.gen::<f32>() - 0.5) * scale;
+    let words = SharedMatrix::from_view(Array2::from_shape_fn((vocab.len(), width), &mut init).view());
+    let contexts = SharedMatrix::from_view(Array2::from_shape_fn((vocab.len(), width), &mut init).view());
+    // AdaGrad's squared gradient sums start at 1, which also caps the first steps
+    let ones = Array2::ones((vocab.len(), width));
+    let word_squares = SharedMatrix::from_view(ones.view());
+    let context_squares = SharedMatrix::from_view(ones.view());
+
+    let trainer = GloveTrainer { config, words: &words, contexts: &contexts, word_squares: &word_squares, context_squares: &context_squares };
+    let mut order = (0..cooccurrences.len()).collect::<Vec<_>>();
+    let mut losses = Vec::with_capacity(config.epochs);
+    for _ in 0..config.epochs {
+        order.shuffle(&mut rng);
+        let chunk = order.len().div_ceil(config.threads).max(1);
+        let loss = std::thread::scope(|scope| {
+            let handles = order.chunks(chunk)
+                .map(|part| {
+                    let (trainer, entries) = (&trainer, cooccurrences.entries());
+                    scope.spawn(move || part.iter().map(|&e| trainer.update(entries[e]) as f64).sum::<f64>())
+                })
+                .collect::<Vec<_>>();
+            handles.into_iter().map(|h| h.join().unwrap()).sum::<f64>()
+        });
+        losses.push(loss / cooccurrences.len().max(1) as f64);
+    }
+
+    let mut sum = Array2::zeros((vocab.len(), width));
+    words.write_to(sum.view_mut());
+    let mut context = Array2::zeros((vocab.len(), width));
+    contexts.write_to(context.view_mut());
+    sum += &context;
+    let vectors = sum.slice(s![.., ..config.dim]).to_owned();
+    (KeyedVectors::new(vocab.words().to_vec(), vectors), losses)
+}
+
+struct GloveTrainer<'a> {
+    config: &'a GloveConfig,
+    words: &'a SharedMatrix,
+    contexts: &'a SharedMatrix,
+    word_squares: &'a SharedMatrix,
+    context_squares: &'a SharedMatrix,
+}
+
+impl GloveTrainer<'_> {
+    // one AdaGrad step on a single co-occurrence, returning its weighted loss
+    fn update(&self, (i, j, x): (u32, u32, f32)) -> f32 {
+        let (i, j) = (i as usize, j as usize);
+        let width = self.config.dim + 1;
+        let (mut w, mut c) = (vec![0.0; width], vec![0.0; width]);
+        let (mut gw, mut gc) = (vec![0.0; width], vec![0.0; width]);
+        self.words.read_row(i, &mut w);
+        self.contexts.read_row(j, &mut c);
+        self.word_squares.read_row(i, &mut gw);
+        self.context_squares.read_row(j, &mut gc);
+
+        let dim = self.config.dim;
+        let prediction = w[..dim].iter().zip(&c[..dim]).map(|(a, b)| a * b).sum::<f32>() + w[dim] + c[dim];
+        let difference = prediction - x.ln();
+        let weight = if x < self.config.x_max { (x / self.config.x_max).powf(self.config.alpha) } else { 1.0 };
+        let weighted = weight * difference;
+        if !weighted.is_finite() {
+            return 0.0;
+        }
+
+        // gradients for the vector parts, then the biases, whose gradient is `weighted`
+        let mut word_gradient = c.iter().map(|v| weighted * v).collect::<Vec<_>>();
+        let mut context_gradient = w.iter().map(|v| weighted * v).collect::<Vec<_>>();
+        word_gradient[dim] = weighted;
+        context_gradient[dim] = weighted;
+
+        let step = |gradient: &[f32], squares: &[f32]| {
+            gradient.iter().zip(squares).map(|(g, s)| -self.config.learning_rate * g / s.sqrt()).collect::<Vec<_>>()
+        };
+        self.words.add_to_row(i, 1.0, &step(&word_gradient, &gw));
+        self.contexts.add_to_row(j, 1.0, &step(&context_gradient, &gc));
+        word_gradient.iter_mut().for_each(|g| *g *= *g);
+        context_gradient.iter_mut().for_each(|g| *g *= *g);
+        self.word_squares.add_to_row(i, 1.0, &word_gradient);
+        self.context_squares.add_to_row(j, 1.0, &context_gradient);
+        0.5 * weighted * difference
+    }
+}
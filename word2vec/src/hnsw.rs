@@ -0,0 +1,358 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use ndarray::{ArrayView1, ArrayView2};
+use rand::Rng;
+use feed_forward::seed::rng_from_seed;
+use crate::keyed_vectors::{KeyedVectors, UnknownWord};
+
+// far more layers than any realistic index reaches; bounds what `load` accepts
+const MAX_LEVEL: usize = 63;
+
+/// Parameters of `HnswIndex::build`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HnswConfig {
+    /// Links per node on the upper layers; the bottom layer gets twice as many. More
+    /// links raise recall and memory use. 12 to 48 is the usual range.
+    pub m: usize,
+    /// Candidates considered when linking a new node. Higher builds a better graph,
+    /// more slowly.
+    pub ef_construction: usize,
+    /// The default for `HnswIndex::set_ef_search`.
+    pub ef_search: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        HnswConfig { m: 16, ef_construction: 200, ef_search: 64, seed: None }
+    }
+}
+
+/// An approximate nearest neighbor index over unit vectors, using cosine similarity:
+/// a Hierarchical Navigable Small World graph (Malkov & Yashunin, 2016).
+///
+/// Every vector is a node on layer 0; a geometrically shrinking random subset also lives
+/// on each layer above. A query descends greedily from the single node on the top layer,
+/// then runs a best-first search on layer 0 that keeps `ef_search` candidates. Raising
+/// `ef_search` trades latency for recall without rebuilding the index.
+///
+/// The index stores ids into the matrix it was built from, not words; pair it with the
+/// same `KeyedVectors` to get words back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HnswIndex {
+    dim: usize,
+    // normalized copies of the vectors, row-major
+    vectors: Vec<f32>,
+    // node -> layer -> neighbors
+    links: Vec<Vec<Vec<u32>>>,
+    entry: u32,
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+}
+
+// (distance, node), ordered by distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate(f32, u32);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl HnswIndex {
+    /// Indexes every row of `vectors`.
+    pub fn build(vectors: ArrayView2<f32>, config: &HnswConfig) -> HnswIndex {
+        assert!(config.m >= 2, "m must be at least 2");
+        assert!(vectors.nrows() < u32::MAX as usize, "too many vectors for u32 ids");
+        let mut index = HnswIndex {
+            dim: vectors.ncols(),
+            vectors: Vec::with_capacity(vectors.len()),
+            links: Vec::with_capacity(vectors.nrows()),
+            entry: 0,
+            m: config.m,
+            ef_construction: config.ef_construction.max(1),
+            ef_search: config.ef_search.max(1),
+        };
+        for row in vectors.rows() {
+            let norm = row.dot(&row).sqrt().max(1e-12);
+            index.vectors.extend(row.iter().map(|v| v / norm));
+        }
+
+        let mut rng = rng_from_seed(config.seed);
+        let level_scale = 1.0 / (config.m as f64).ln();
+        for node in 0..vectors.nrows() as u32 {
+            let level = ((-rng.gen::<f64>().max(f64::MIN_POSITIVE).ln() * level_scale) as usize).min(MAX_LEVEL);
+            index.insert(node, level);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn ef_search(&self) -> usize {
+        self.ef_search
+    }
+
+    /// How many candidates a query keeps on the bottom layer; never fewer than k are used.
+    pub fn set_ef_search(&mut self, ef: usize) {
+        self.ef_search = ef.max(1);
+    }
+
+    /// The (id, cosine similarity) of approximately the `k` rows closest to `query`, most
+    /// similar first.
+    pub fn search(&self, query: ArrayView1<f32>, k: usize) -> Vec<(usize, f32)> {
+        assert_eq!(query.len(), self.dim, "query vector has the wrong dimension");
+        if self.is_empty() || k == 0 {
+            return vec![];
+        }
+        let norm = query.dot(&query).sqrt().max(1e-12);
+        let query = query.iter().map(|v| v / norm).collect::<Vec<_>>();
+        let mut entry = Candidate(self.distance_to(&query, self.entry), self.entry);
+        for layer in (1..self.links[self.entry as usize].len()).rev() {
+            entry = self.search_layer(&query, &[entry], 1, layer)[0];
+        }
+        self.search_layer(&query, &[entry], self.ef_search.max(k), 0)
+            .into_iter()
+            .take(k)
+            .map(|Candidate(distance, node)| (node as usize, 1.0 - distance))
+            .collect()
+    }
+
+    /// Approximately `KeyedVectors::most_similar`, for an index built from `vectors`.
+    pub fn most_similar<'a>(&self, vectors: &'a KeyedVectors, word: &str, k: usize) -> Result<Vec<(&'a str, f32)>, UnknownWord> {
+        assert_eq!(vectors.len(), self.len(), "the index was built from other vectors");
+        let id = vectors.id(word).ok_or_else(|| UnknownWord(word.to_string()))?;
+        Ok(self.search(vectors.vectors().row(id), k + 1)
+            .into_iter()
+            .filter(|&(other, _)| other != id)
+            .take(k)
+            .map(|(other, similarity)| (vectors.word(other), similarity))
+            .collect())
+    }
+
+    fn vector(&self, node: u32) -> &[f32] {
+        let start = node as usize * self.dim;
+        &self.vectors[start..start + self.dim]
+    }
+
+    fn distance_to(&self, query: &[f32], node: u32) -> f32 {
+        1.0 - query.iter().zip(self.vector(node)).map(|(a, b)| a * b).sum::<f32>()
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { 2 * self.m } else { self.m }
+    }
+
+    fn insert(&mut self, node: u32, level: usize) {
+        self.links.push(vec![vec![]; level + 1]);
+        if node == 0 {
+            self.entry = node;
+            return;
+        }
+        let query = self.vector(node).to_vec();
+        let top = self.links[self.entry as usize].len() - 1;
+        let mut entries = vec![Candidate(self.distance_to(&query, self.entry), self.entry)];
+        for layer in (level + 1..=top).rev() {
+            entries = vec![self.search_layer(&query, &entries, 1, layer)[0]];
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, &entries, self.ef_construction, layer);
+            let neighbors = self.select_neighbors(&candidates, self.m);
+            let max_links = self.max_links(layer);
+            for &neighbor in &neighbors {
+                let links = &mut self.links[neighbor as usize][layer];
+                links.push(node);
+                if links.len() > max_links {
+                    self.prune(neighbor, layer);
+                }
+            }
+            self.links[node as usize][layer] = neighbors;
+            entries = candidates;
+        }
+        if level > top {
+            self.entry = node;
+        }
+    }
+
+    // cuts an overfull neighbor list back down with the same heuristic used for new nodes
+    fn prune(&mut self, node: u32, layer: usize) {
+        let base = self.vector(node).to_vec();
+        let mut candidates = self.links[node as usize][layer].iter()
+            .map(|&other| Candidate(self.distance_to(&base, other), other))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        self.links[node as usize][layer] = self.select_neighbors(&candidates, self.max_links(layer));
+    }
+
+    // The neighbor selection heuristic from the paper: walking candidates from nearest to
+    // farthest, keep one only if it is closer to the base than to every neighbor kept so
+    // far, which spreads links in different directions. Leftover slots are then filled
+    // with the nearest rejected candidates.
+    fn select_neighbors(&self, candidates: &[Candidate], count: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(count);
+        let mut rejected = vec![];
+        for &Candidate(distance, node) in candidates {
+            if selected.len() == count {
+                break;
+            }
+            let vector = self.vector(node);
+            if selected.iter().all(|&kept| self.distance_to(vector, kept) > distance) {
+                selected.push(node);
+            } else {
+                rejected.push(node);
+            }
+        }
+        let missing = count - selected.len();
+        selected.extend(rejected.into_iter().take(missing));
+        selected
+    }
+
+    // best-first search on one layer, returning up to `ef` nodes nearest first
+    fn search_layer(&self, query: &[f32], entries: &[Candidate], ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited = entries.iter().map(|c| c.1).collect::<HashSet<_>>();
+        let mut frontier = entries.iter().copied().map(Reverse).collect::<BinaryHeap<_>>();
+        let mut nearest = entries.iter().copied().collect::<BinaryHeap<_>>();
+        while nearest.len() > ef {
+            nearest.pop();
+        }
+        while let Some(Reverse(current)) = frontier.pop() {
+            if nearest.len() >= ef && current.0 > nearest.peek().unwrap().0 {
+                break;
+            }
+            for &neighbor in &self.links[current.1 as usize][layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let candidate = Candidate(self.distance_to(query, neighbor), neighbor);
+                if nearest.len() < ef || candidate.0 < nearest.peek().unwrap().0 {
+                    frontier.push(Reverse(candidate));
+                    nearest.push(candidate);
+                    if nearest.len() > ef {
+                        nearest.pop();
+                    }
+                }
+            }
+        }
+        nearest.into_sorted_vec()
+    }
+}
+
+// On disk:
+//
+//   magic "RMLHNSW1", then u64 dim, count, m, ef_construction, ef_search, entry,
+//   count x dim f32 vectors, then per node u64 layers and per layer u64 length and
+//   length x u32 neighbors
+//
+// all little endian.
+
+const MAGIC: &[u8; 8] = b"RMLHNSW1";
+
+impl HnswIndex {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        for value in [self.dim, self.len(), self.m, self.ef_construction, self.ef_search, self.entry as usize] {
+            out.write_all(&(value as u64).to_le_bytes())?;
+        }
+        for v in &self.vectors {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        for layers in &self.links {
+            out.write_all(&(layers.len() as u64).to_le_bytes())?;
+            for neighbors in layers {
+                out.write_all(&(neighbors.len() as u64).to_le_bytes())?;
+                for n in neighbors {
+                    out.write_all(&n.to_le_bytes())?;
+                }
+            }
+        }
+        out.flush()
+    }
+
+    /// Reads an index written by `save`, checking that every link points at a node.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<HnswIndex> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not an HNSW index file"));
+        }
+        let mut header = [0usize; 6];
+        for value in header.iter_mut() {
+            *value = read_u64(&mut input)? as usize;
+        }
+        let [dim, count, m, ef_construction, ef_search, entry] = header;
+        if count > 0 && entry >= count || count >= u32::MAX as usize {
+            return Err(invalid("bad entry point"));
+        }
+
+        let mut bytes = vec![0u8; count.checked_mul(dim).and_then(|n| n.checked_mul(4)).ok_or_else(|| invalid("index too large"))?];
+        input.read_exact(&mut bytes)?;
+        let vectors = bytes.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+
+        let mut links = Vec::with_capacity(count);
+        for _ in 0..count {
+            let layers = read_u64(&mut input)? as usize;
+            if layers == 0 || layers > MAX_LEVEL + 1 {
+                return Err(invalid("bad layer count"));
+            }
+            let mut node = Vec::with_capacity(layers);
+            for _ in 0..layers {
+                let length = read_u64(&mut input)? as usize;
+                let mut bytes = vec![0u8; length.checked_mul(4).ok_or_else(|| invalid("bad link count"))?];
+                input.read_exact(&mut bytes)?;
+                let neighbors = bytes.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect::<Vec<_>>();
+                if neighbors.iter().any(|&n| n as usize >= count) {
+                    return Err(invalid("link to a missing node"));
+                }
+                node.push(neighbors);
+            }
+            links.push(node);
+        }
+        // a node's links on a layer must lead to nodes that exist on that layer
+        for node in &links {
+            for (layer, neighbors) in node.iter().enumerate() {
+                if neighbors.iter().any(|&n| links[n as usize].len() <= layer) {
+                    return Err(invalid("link to a node missing from the layer"));
+                }
+            }
+        }
+        if count > 0 && links.iter().any(|node| node.len() > links[entry].len()) {
+            return Err(invalid("the entry point is not on the top layer"));
+        }
+        Ok(HnswIndex { dim, vectors, links, entry: entry as u32, m, ef_construction, ef_search: ef_search.max(1) })
+    }
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
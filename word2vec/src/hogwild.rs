@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use ndarray::{ArrayView2, ArrayViewMut2};
+
+// A matrix that several threads update at once without locks (Hogwild!, Niu et al. 2011).
+//
+// Entries are f32 bits in relaxed atomics: reads and writes of a single entry are whole,
+// but a read-modify-write can lose a concurrent update. With sparse updates such
+// collisions are rare and SGD shrugs them off, which is the point of Hogwild.
+pub(crate) struct SharedMatrix {
+    data: Vec<AtomicU32>,
+    cols: usize,
+}
+
+impl SharedMatrix {
+    pub(crate) fn from_view(values: ArrayView2<f32>) -> SharedMatrix {
+        SharedMatrix {
+            data: values.iter().map(|v| AtomicU32::new(v.to_bits())).collect(),
+            cols: values.ncols(),
+        }
+    }
+
+    pub(crate) fn read_row(&self, row: usize, out: &mut [f32]) {
+        let entries = &self.data[row * self.cols..(row + 1) * self.cols];
+        for (o, entry) in out.iter_mut().zip(entries) {
+            *o = f32::from_bits(entry.load(Ordering::Relaxed));
+        }
+    }
+
+    /// row += scale * delta
+    pub(crate) fn add_to_row(&self, row: usize, scale: f32, delta: &[f32]) {
+        let entries = &self.data[row * self.cols..(row + 1) * self.cols];
+        for (entry, d) in entries.iter().zip(delta) {
+            let value = f32::from_bits(entry.load(Ordering::Relaxed)) + scale * d;
+            entry.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn write_to(&self, mut target: ArrayViewMut2<f32>) {
+        for (t, entry) in target.iter_mut().zip(&self.data) {
+            *t = f32::from_bits(entry.load(Ordering::Relaxed));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/// A Huffman tree over the vocabulary for hierarchical softmax.
+///
+/// Each word is a leaf; the n - 1 inner nodes each own an output vector, and a word's
+/// probability is the product of binary decisions along its path from the root. Frequent
+/// words get short paths, so an update touches about log2(n) vectors instead of n.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HuffmanTree {
+    codes: Vec<Vec<u8>>,
+    points: Vec<Vec<usize>>,
+}
+
+impl HuffmanTree {
+    /// Builds the tree from counts sorted in descending order, which is how
+    /// `Vocabulary` stores them.
+    pub fn new(counts: &[u64]) -> HuffmanTree {
+        let n = counts.len();
+        if n < 2 {
+            return HuffmanTree { codes: vec![vec![]; n], points: vec![vec![]; n] };
+        }
+        debug_assert!(counts.windows(2).all(|w| w[0] >= w[1]), "counts must be sorted in descending order");
+
+        // leaves are 0..n, inner nodes n..2n - 1 in creation order. Leaves are consumed from
+        // the rarest end and inner nodes are created in increasing weight, so the two
+        // smallest nodes are always at one of the two cursors.
+        let mut weight = counts.to_vec();
+        weight.resize(2 * n - 1, u64::MAX);
+        let mut parent = vec![0; 2 * n - 1];
+        let mut branch = vec![0u8; 2 * n - 1];
+        let mut leaf = n as isize - 1;
+        let mut inner = n;
+        for node in n..2 * n - 1 {
+            let mut pick = || {
+                if leaf >= 0 && weight[leaf as usize] < weight[inner] {
+                    leaf -= 1;
+                    (leaf + 1) as usize
+                } else {
+                    inner += 1;
+                    inner - 1
+                }
+            };
+            let (smaller, larger) = (pick(), pick());
+            weight[node] = weight[smaller] + weight[larger];
+            parent[smaller] = node;
+            parent[larger] = node;
+            branch[larger] = 1;
+        }
+
+        let root = 2 * n - 2;
+        let mut codes = Vec::with_capacity(n);
+        let mut points = Vec::with_capacity(n);
+        for word in 0..n {
+            let (mut code, mut point) = (vec![], vec![]);
+            let mut node = word;
+            while node != root {
+                code.push(branch[node]);
+                point.push(parent[node] - n);
+                node = parent[node];
+            }
+            code.reverse();
+            point.reverse();
+            codes.push(code);
+            points.push(point);
+        }
+        HuffmanTree { codes, points }
+    }
+
+    /// The branch taken at each inner node on the way from the root to `word`.
+    pub fn code(&self, word: usize) -> &[u8] {
+        &self.codes[word]
+    }
+
+    /// The inner nodes (0..n - 1) on the way from the root to `word`, root first.
+    pub fn points(&self, word: usize) -> &[usize] {
+        &self.points[word]
+    }
+
+    pub fn inner_nodes(&self) -> usize {
+        self.codes.len().saturating_sub(1)
+    }
+}
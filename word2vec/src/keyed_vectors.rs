@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, Axis};
+use crate::format::{write_vectors, Format, VectorReader};
+
+/// Word vectors without the training state: a word list and one row per word, like
+/// gensim's KeyedVectors. This is what gets exported, loaded back and queried.
+///
+/// Similarity queries use cosine similarity. Results are (word, similarity) pairs, most
+/// similar first, and never include the query words themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyedVectors {
+    words: Vec<String>,
+    index: HashMap<String, usize>,
+    vectors: Array2<f32>,
+    norms: Array1<f32>,
+}
+
+/// A query mentioned a word the vectors don't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownWord(pub String);
+
+impl fmt::Display for UnknownWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not in the vocabulary", self.0)
+    }
+}
+
+impl Error for UnknownWord {}
+
+// keeps zero vectors from dividing by zero; their similarity to anything is 0
+const MIN_NORM: f32 = 1e-12;
+
+impl KeyedVectors {
+    /// If a word appears twice, lookups find its first row.
+    pub fn new(words: Vec<String>, vectors: Array2<f32>) -> KeyedVectors {
+        assert_eq!(words.len(), vectors.nrows(), "one vector per word");
+        let mut index = HashMap::with_capacity(words.len());
+        for (id, word) in words.iter().enumerate() {
+            index.entry(word.clone()).or_insert(id);
+        }
+        let norms = vectors.map_axis(Axis(1), |row| row.dot(&row).sqrt().max(MIN_NORM));
+        KeyedVectors { words, index, vectors, norms }
+    }
+
+    /// Collects (word, vector) entries such as those of a `VectorReader`. All vectors must
+    /// have the same length.
+    pub fn from_entries<I>(entries: I) -> io::Result<KeyedVectors>
+    where
+        I: IntoIterator<Item = io::Result<(String, Vec<f32>)>>,
+    {
+        let (mut words, mut values, mut dim) = (vec![], vec![], None);
+        for entry in entries {
+            let (word, vector) = entry?;
+            if *dim.get_or_insert(vector.len()) != vector.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("vector for {:?} has {} values, expected {}", word, vector.len(), dim.unwrap()),
+                ));
+            }
+            words.push(word);
+            values.extend(vector);
+        }
+        let vectors = Array2::from_shape_vec((words.len(), dim.unwrap_or(0)), values).unwrap();
+        Ok(KeyedVectors::new(words, vectors))
+    }
+
+    /// Reads a whole file in the given format.
+    pub fn load(path: impl AsRef<Path>, format: Format) -> io::Result<KeyedVectors> {
+        Self::from_entries(VectorReader::open(path, format)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, format: Format) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out, format)?;
+        out.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W, format: Format) -> io::Result<()> {
+        write_vectors(out, format, self.dim(), self.words.iter().map(String::as_str).zip(self.vectors.rows()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn dim(&self) -> usize {
+        self.vectors.ncols()
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn vectors(&self) -> ArrayView2<'_, f32> {
+        self.vectors.view()
+    }
+
+    pub fn id(&self, word: &str) -> Option<usize> {
+        self.index.get(word).copied()
+    }
+
+    pub fn word(&self, id: usize) -> &str {
+        &self.words[id]
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(word)
+    }
+
+    pub fn vector(&self, word: &str) -> Option<ArrayView1<'_, f32>> {
+        self.id(word).map(|id| self.vectors.row(id))
+    }
+
+    fn lookup(&self, word: &str) -> Result<usize, UnknownWord> {
+        self.id(word).ok_or_else(|| UnknownWord(word.to_string()))
+    }
+
+    fn unit(&self, id: usize) -> Array1<f32> {
+        &self.vectors.row(id) / self.norms[id]
+    }
+
+    // cosine similarity of every word (or the `restrict` most frequent) to `query`
+    fn cosines(&self, query: ArrayView1<f32>, restrict: Option<usize>) -> Array1<f32> {
+        let rows = restrict.map_or(self.len(), |r| r.min(self.len()));
+        let norm = query.dot(&query).sqrt().max(MIN_NORM);
+        let vectors = self.vectors.slice(s![..rows, ..]);
+        vectors.dot(&query) / self.norms.slice(s![..rows]) / norm
+    }
+
+    // the k best scores, skipping `exclude`
+    fn top_k(&self, scores: &Array1<f32>, k: usize, exclude: &[usize]) -> Vec<(&str, f32)> {
+        let mut candidates = scores.iter().copied().enumerate()
+            .filter(|(id, _)| !exclude.contains(id))
+            .collect::<Vec<_>>();
+        let by_score = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0));
+        if k < candidates.len() {
+            candidates.select_nth_unstable_by(k, by_score);
+            candidates.truncate(k);
+        }
+        candidates.sort_by(by_score);
+        candidates.into_iter().map(|(id, score)| (self.word(id), score)).collect()
+    }
+
+    pub fn similarity(&self, a: &str, b: &str) -> Result<f32, UnknownWord> {
+        let (a, b) = (self.lookup(a)?, self.lookup(b)?);
+        Ok(self.vectors.row(a).dot(&self.vectors.row(b)) / (self.norms[a] * self.norms[b]))
+    }
+
+    /// The `k` words closest to `word`.
+    pub fn most_similar(&self, word: &str, k: usize) -> Result<Vec<(&str, f32)>, UnknownWord> {
+        let id = self.lookup(word)?;
+        Ok(self.top_k(&self.cosines(self.vectors.row(id), None), k, &[id]))
+    }
+
+    /// The `k` words closest to an arbitrary vector.
+    pub fn similar_by_vector(&self, vector: ArrayView1<f32>, k: usize) -> Vec<(&str, f32)> {
+        assert_eq!(vector.len(), self.dim(), "query vector has the wrong dimension");
+        self.top_k(&self.cosines(vector, None), k, &[])
+    }
+
+    /// Words closest to the sum of the unit vectors of `positive` minus those of
+    /// `negative` (3CosAdd), so `analogy(&["king", "woman"], &["man"], 1)` should give
+    /// "queen". With `restrict`, only the first that many words are candidates, which are
+    /// the most frequent ones in files this crate writes.
+    pub fn analogy(&self, positive: &[&str], negative: &[&str], k: usize, restrict: Option<usize>) -> Result<Vec<(&str, f32)>, UnknownWord> {
+        let (positive, negative) = (self.lookup_all(positive)?, self.lookup_all(negative)?);
+        let mut query = Array1::zeros(self.dim());
+        for &id in &positive {
+            query += &self.unit(id);
+        }
+        for &id in &negative {
+            query -= &self.unit(id);
+        }
+        let exclude = [positive, negative].concat();
+        Ok(self.top_k(&self.cosines(query.view(), restrict), k, &exclude))
+    }
+
+    /// Like `analogy`, but ranks words by 3CosMul (Levy & Goldberg, 2014): the product of
+    /// similarities to `positive` over the product of similarities to `negative`, with
+    /// cosines shifted to [0, 1]. This keeps one large similarity from dominating.
+    pub fn analogy_cos_mul(&self, positive: &[&str], negative: &[&str], k: usize, restrict: Option<usize>) -> Result<Vec<(&str, f32)>, UnknownWord> {
+        const EPSILON: f32 = 1e-3;
+        let (positive, negative) = (self.lookup_all(positive)?, self.lookup_all(negative)?);
+        let shifted = |id: usize| self.cosines(self.vectors.row(id), restrict).mapv(|c| (1.0 + c) / 2.0);
+        let rows = restrict.map_or(self.len(), |r| r.min(self.len()));
+        let mut numerator = Array1::ones(rows);
+        for &id in &positive {
+            numerator *= &shifted(id);
+        }
+        let mut denominator = Array1::from_elem(rows, EPSILON);
+        if !negative.is_empty() {
+            let mut product = Array1::ones(rows);
+            for &id in &negative {
+                product *= &shifted(id);
+            }
+            denominator += &product;
+        }
+        let exclude = [positive, negative].concat();
+        Ok(self.top_k(&(numerator / denominator), k, &exclude))
+    }
+
+    /// The word least like the others: the one with the lowest cosine similarity to the
+    /// mean of all their unit vectors. `None` when no words are given.
+    pub fn doesnt_match<'a>(&self, words: &[&'a str]) -> Result<Option<&'a str>, UnknownWord> {
+        let ids = self.lookup_all(words)?;
+        let mut mean = Array1::<f32>::zeros(self.dim());
+        for &id in &ids {
+            mean += &self.unit(id);
+        }
+        let position = ids.iter()
+            .map(|&id| self.unit(id).dot(&mean))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(position, _)| position);
+        Ok(position.map(|position| words[position]))
+    }
+
+    fn lookup_all(&self, words: &[&str]) -> Result<Vec<usize>, UnknownWord> {
+        words.iter().map(|w| self.lookup(w)).collect()
+    }
+}
@@ -0,0 +1,18 @@
+pub mod checkpoint;
+pub mod corpus;
+pub mod evaluate;
+pub mod fasttext;
+pub mod format;
+pub mod glove;
+pub mod hnsw;
+pub mod huffman;
+pub mod keyed_vectors;
+pub mod model;
+pub mod ngram;
+pub mod phrases;
+pub mod sampling;
+pub mod tokenize;
+pub mod train;
+pub mod vocab;
+
+mod hogwild;
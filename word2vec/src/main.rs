@@ -1,3 +1,548 @@
-fn main() {
-    println!("Hello, world!");
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use clap::{Args, Parser, Subcommand};
+use feed_forward::tensorboard::{self, SummaryWriter};
+use ndarray::{s, Array1};
+use word2vec::checkpoint::Checkpoint;
+use word2vec::corpus::{encode_stream, stream_sentences};
+use word2vec::evaluate::{evaluate_analogies, evaluate_word_pairs, read_analogies, read_word_pairs, AnalogyMethod};
+use word2vec::fasttext::{FastText, FastTextConfig, Subwords};
+use word2vec::format::Format;
+use word2vec::glove::{self, GloveConfig};
+use word2vec::keyed_vectors::KeyedVectors;
+use word2vec::phrases::{PhraseDetector, Phrases};
+use word2vec::tokenize::{Segmentation, Tokenizer};
+use word2vec::train::{train_with, Architecture, Objective, Progress, TrainConfig, TrainOptions};
+use word2vec::vocab::{Vocabulary, VocabularyBuilder};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Learns word vectors from a plain-text corpus with word2vec, GloVe or fastText, and
+/// queries and evaluates them.
+#[derive(Parser, Debug)]
+#[command(name = "word2vec")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Counts the words of a corpus and writes one "<word> <count>" line per word.
+    BuildVocab {
+        #[command(flatten)]
+        corpus: CorpusArgs,
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Trains vectors on a corpus.
+    Train(TrainArgs),
+    /// Converts vectors or a checkpoint to another format.
+    Export {
+        /// Vectors in any format, or a training checkpoint.
+        #[arg(long)]
+        input: PathBuf,
+        /// Defaults to binary for a .bin file and text otherwise.
+        #[arg(long)]
+        input_format: Option<Format>,
+        #[arg(long)]
+        output: PathBuf,
+        /// text, binary or glove. Defaults to binary for a .bin output and text otherwise.
+        #[arg(long)]
+        format: Option<Format>,
+        /// Keep only this many of the first (most frequent) words.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Writes vectors as TensorBoard embedding projector data, labelled with their words.
+    Projector {
+        #[command(flatten)]
+        vectors: VectorArgs,
+        /// The TensorBoard log directory.
+        #[arg(long)]
+        logdir: PathBuf,
+        /// The embedding's name in the projector.
+        #[arg(long, default_value = "word2vec")]
+        name: String,
+        /// Keep only this many of the first (most frequent) words.
+        #[arg(long, default_value_t = 10_000)]
+        limit: usize,
+    },
+    /// Prints the nearest neighbors of words, given as arguments or one per line on stdin.
+    Similar {
+        #[command(flatten)]
+        vectors: VectorArgs,
+        #[arg(short, default_value_t = 10)]
+        k: usize,
+        words: Vec<String>,
+    },
+    /// Answers "a is to b as c is to ?".
+    Analogy {
+        #[command(flatten)]
+        vectors: VectorArgs,
+        #[arg(short, default_value_t = 10)]
+        k: usize,
+        /// Use 3CosMul instead of 3CosAdd.
+        #[arg(long)]
+        cos_mul: bool,
+        a: String,
+        b: String,
+        c: String,
+    },
+    /// Scores vectors on analogy questions and word-similarity judgements.
+    Evaluate {
+        #[command(flatten)]
+        vectors: VectorArgs,
+        /// Questions in the questions-words.txt format.
+        #[arg(long)]
+        analogies: Option<PathBuf>,
+        /// Word pairs with similarity scores, like WordSim-353 or SimLex-999.
+        #[arg(long)]
+        word_pairs: Option<PathBuf>,
+        /// Only the most frequent words answer analogies.
+        #[arg(long, default_value_t = 30_000)]
+        restrict: usize,
+        /// Lowercase the benchmark words.
+        #[arg(long)]
+        lowercase: bool,
+        #[arg(long)]
+        cos_mul: bool,
+    },
+}
+
+/// How a corpus is read and its vocabulary counted.
+#[derive(Args, Debug)]
+struct CorpusArgs {
+    /// Training corpus, one sentence per line. It is streamed, never held in memory as text.
+    #[arg(long)]
+    corpus: PathBuf,
+    /// whitespace or unicode.
+    #[arg(long, default_value_t = Segmentation::Whitespace)]
+    tokenizer: Segmentation,
+    #[arg(long)]
+    lowercase: bool,
+    #[arg(long)]
+    strip_punctuation: bool,
+    /// Join bigrams scoring above this into phrases like new_york.
+    #[arg(long)]
+    phrase_threshold: Option<f64>,
+    /// Most distinct words and bigrams held while detecting phrases; the rarest are
+    /// pruned past it, which bounds memory on large corpora.
+    #[arg(long, default_value_t = 10_000_000)]
+    phrase_table_size: usize,
+    /// Discard words that occur fewer times than this.
+    #[arg(long, default_value_t = 5)]
+    min_count: u64,
+    /// Keep at most this many of the most frequent words.
+    #[arg(long)]
+    max_vocab: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct TrainArgs {
+    #[command(flatten)]
+    corpus: CorpusArgs,
+    /// Use this vocabulary, from build-vocab, instead of counting the corpus.
+    #[arg(long)]
+    vocab: Option<PathBuf>,
+    /// Where to write the vectors.
+    #[arg(long)]
+    output: PathBuf,
+    /// text, binary or glove. Defaults to binary for a .bin output and text otherwise.
+    #[arg(long)]
+    format: Option<Format>,
+    /// word2vec, glove or fasttext. Defaults to word2vec, or with --resume to the
+    /// checkpoint's method.
+    #[arg(long)]
+    method: Option<Method>,
+    /// skip-gram or cbow, for word2vec and fastText.
+    #[arg(long, default_value_t = Architecture::SkipGram)]
+    architecture: Architecture,
+    /// negative-sampling or hierarchical-softmax, for word2vec and fastText.
+    #[arg(long, default_value_t = Objective::NegativeSampling)]
+    objective: Objective,
+    #[arg(long, default_value_t = 100)]
+    dim: usize,
+    #[arg(long, default_value_t = 5)]
+    window: usize,
+    /// Negative samples per word, with negative sampling.
+    #[arg(long, default_value_t = 5)]
+    negative: usize,
+    /// Subsampling threshold for frequent words; 0 disables subsampling.
+    #[arg(long, default_value_t = 1e-3)]
+    sample: f64,
+    #[arg(long, default_value_t = 5)]
+    epochs: usize,
+    /// Defaults to 0.025 for word2vec skip-gram and 0.05 otherwise.
+    #[arg(long)]
+    learning_rate: Option<f32>,
+    /// Shortest character n-gram, for fastText.
+    #[arg(long, default_value_t = 3)]
+    min_n: usize,
+    /// Longest character n-gram, for fastText; below min-n disables n-grams.
+    #[arg(long, default_value_t = 6)]
+    max_n: usize,
+    /// Number of hashed n-gram rows, for fastText.
+    #[arg(long, default_value_t = 2_000_000)]
+    buckets: usize,
+    /// Co-occurrence count with full weight, for GloVe.
+    #[arg(long, default_value_t = 100.0)]
+    x_max: f32,
+    /// Defaults to the number of CPUs.
+    #[arg(long)]
+    threads: Option<usize>,
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Save a checkpoint here after every epoch (word2vec and fastText).
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Continue from the checkpoint, if it exists, with its vocabulary and settings. The
+    /// corpus options must be the ones of the interrupted run.
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+    /// Seconds between progress reports.
+    #[arg(long, default_value_t = 1.0)]
+    progress_interval: f64,
+    /// Log the progress reports to this TensorBoard log directory, and add the most
+    /// frequent words' vectors to its embedding projector at the end.
+    #[arg(long)]
+    tensorboard: Option<PathBuf>,
+    /// How many vectors go to the projector.
+    #[arg(long, default_value_t = 10_000)]
+    projector_limit: usize,
+}
+
+/// Where to read vectors from.
+#[derive(Args, Debug)]
+struct VectorArgs {
+    /// Vectors in any format, or a training checkpoint; a fastText checkpoint also gives
+    /// vectors for unknown words.
+    #[arg(long)]
+    vectors: PathBuf,
+    /// Defaults to binary for a .bin file and text otherwise.
+    #[arg(long)]
+    format: Option<Format>,
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::BuildVocab { corpus, output } => {
+            let phrases = detect_phrases(&corpus)?;
+            let vocab = count_vocabulary(&corpus, &phrases)?;
+            vocab.save(&output)?;
+            println!("wrote {} words covering {} tokens to {}", vocab.len(), vocab.total_count(), output.display());
+        }
+        Command::Train(args) => train(&args)?,
+        Command::Export { input, input_format, output, format, limit } => {
+            let mut vectors = load_vectors(&input, input_format)?.vectors;
+            if let Some(limit) = limit.filter(|&l| l < vectors.len()) {
+                vectors = KeyedVectors::new(vectors.words()[..limit].to_vec(), vectors.vectors().slice(s![..limit, ..]).to_owned());
+            }
+            let format = format.unwrap_or_else(|| Format::from_path(&output));
+            vectors.save(&output, format)?;
+            println!("wrote {} vectors to {} ({})", vectors.len(), output.display(), format);
+        }
+        Command::Projector { vectors, logdir, name, limit } => {
+            let vectors = load_vectors(&vectors.vectors, vectors.format)?.vectors;
+            let written = write_projector(&logdir, &name, &vectors, limit)?;
+            println!("wrote {} vectors to {} as {:?}", written, logdir.display(), name);
+        }
+        Command::Similar { vectors, k, words } => {
+            let loaded = load_vectors(&vectors.vectors, vectors.format)?;
+            let query = |word: &str| {
+                match loaded.vector(word) {
+                    Some(vector) => {
+                        for (neighbor, similarity) in loaded.vectors.similar_by_vector(vector.view(), k + 1).into_iter().filter(|(w, _)| *w != word).take(k) {
+                            println!("{}\t{:.4}", neighbor, similarity);
+                        }
+                    }
+                    None => println!("{} is not in the vocabulary", word),
+                }
+            };
+            if words.is_empty() {
+                for line in io::stdin().lock().lines() {
+                    for word in line?.split_whitespace() {
+                        query(word);
+                    }
+                    io::stdout().flush()?;
+                }
+            } else {
+                for word in &words {
+                    query(word);
+                }
+            }
+        }
+        Command::Analogy { vectors, k, cos_mul, a, b, c } => {
+            let vectors = load_vectors(&vectors.vectors, vectors.format)?.vectors;
+            let (positive, negative) = ([b.as_str(), c.as_str()], [a.as_str()]);
+            let answers = if cos_mul {
+                vectors.analogy_cos_mul(&positive, &negative, k, None)?
+            } else {
+                vectors.analogy(&positive, &negative, k, None)?
+            };
+            for (word, score) in answers {
+                println!("{}\t{:.4}", word, score);
+            }
+        }
+        Command::Evaluate { vectors, analogies, word_pairs, restrict, lowercase, cos_mul } => {
+            if analogies.is_none() && word_pairs.is_none() {
+                return Err("nothing to evaluate: pass --analogies and/or --word-pairs".into());
+            }
+            let vectors = load_vectors(&vectors.vectors, vectors.format)?.vectors;
+            if let Some(path) = analogies {
+                let questions = read_analogies(&path, lowercase)?;
+                let method = if cos_mul { AnalogyMethod::CosMul } else { AnalogyMethod::CosAdd };
+                println!("{}", evaluate_analogies(&vectors, &questions, method, Some(restrict)));
+            }
+            if let Some(path) = word_pairs {
+                let pairs = read_word_pairs(&path, lowercase)?;
+                println!("{}", evaluate_word_pairs(&vectors, &pairs));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn train(args: &TrainArgs) -> Result<()> {
+    if args.method == Some(Method::Glove) && args.checkpoint.is_some() {
+        return Err("checkpoints are only supported for word2vec and fasttext".into());
+    }
+    let resume = match &args.checkpoint {
+        Some(path) if args.resume && path.exists() => Some(Checkpoint::load(path)?),
+        _ => None,
+    };
+    let method = match &resume {
+        Some(checkpoint) => {
+            let trained = if checkpoint.subwords.is_some() { Method::FastText } else { Method::Word2Vec };
+            if let Some(method) = args.method.filter(|&method| method != trained) {
+                let path = args.checkpoint.as_ref().unwrap().display();
+                return Err(format!("{} holds a {} run, which can't be resumed with --method {}", path, trained, method).into());
+            }
+            trained
+        }
+        None => args.method.unwrap_or(Method::Word2Vec),
+    };
+
+    let phrases = detect_phrases(&args.corpus)?;
+    let vocab = match (&resume, &args.vocab) {
+        (Some(checkpoint), _) => checkpoint.vocab.clone(),
+        (None, Some(path)) => {
+            let mut vocab = Vocabulary::load(path)?;
+            if let Some(max_size) = args.corpus.max_vocab {
+                vocab.truncate(max_size);
+            }
+            vocab
+        }
+        (None, None) => count_vocabulary(&args.corpus, &phrases)?,
+    };
+    if vocab.is_empty() {
+        return Err(format!("no word in {} occurs at least {} times", args.corpus.corpus.display(), args.corpus.min_count).into());
+    }
+    let corpus = encode_stream(stream_sentences(&args.corpus.corpus, tokenizer(&args.corpus))?.with_phrases(phrases), &vocab)?;
+    println!("vocabulary: {} words, {} training tokens", vocab.len(), vocab.total_count());
+
+    let defaults = TrainConfig::default();
+    let learning_rate = args.learning_rate.unwrap_or(match (method, args.architecture) {
+        (Method::Word2Vec, Architecture::SkipGram) => 0.025,
+        _ => 0.05,
+    });
+    let threads = args.threads.unwrap_or(defaults.threads);
+    let mut config = TrainConfig {
+        architecture: args.architecture,
+        objective: args.objective,
+        dim: args.dim,
+        window: args.window,
+        negative: args.negative,
+        sample: args.sample,
+        epochs: args.epochs,
+        learning_rate,
+        threads,
+        seed: args.seed,
+        ..defaults
+    };
+    let mut subwords = (method == Method::FastText).then_some(Subwords { min_n: args.min_n, max_n: args.max_n, buckets: args.buckets });
+    let mut events = args.tensorboard.as_ref().map(SummaryWriter::create).transpose()?;
+    let mut event_error = None;
+    let mut options = TrainOptions::default()
+        .with_progress(Duration::from_secs_f64(args.progress_interval), |progress| {
+            report_progress(progress);
+            if let Some(events) = events.as_mut() {
+                if let Err(e) = log_progress(events, progress) {
+                    event_error.get_or_insert(e);
+                }
+            }
+        });
+    if let Some(path) = &args.checkpoint {
+        options = options.with_checkpoints(move |checkpoint| checkpoint.save(path));
+    }
+    if let Some(checkpoint) = resume {
+        println!("resuming {} after epoch {} of {}", args.checkpoint.as_ref().unwrap().display(), checkpoint.epoch, checkpoint.config.epochs);
+        config = TrainConfig { threads, ..checkpoint.config.clone() };
+        subwords = checkpoint.subwords;
+        options = options.resume_from(checkpoint);
+    }
+
+    let format = args.format.unwrap_or_else(|| Format::from_path(&args.output));
+    let start = Instant::now();
+    let vectors = match (method, subwords) {
+        (Method::Glove, _) => {
+            let config = GloveConfig {
+                dim: args.dim,
+                window: args.window,
+                x_max: args.x_max,
+                epochs: args.epochs,
+                learning_rate,
+                threads,
+                seed: args.seed,
+                ..GloveConfig::default()
+            };
+            let (vectors, losses) = glove::train(&corpus, &vocab, &config);
+            // GloVe has no progress reports; release the callback's hold on `events`
+            drop(options);
+            for (epoch, loss) in losses.iter().enumerate() {
+                println!("epoch {}: loss {:.6}", epoch + 1, loss);
+                if let Some(events) = events.as_mut() {
+                    events.add_scalar("train/loss", *loss, epoch as u64 + 1)?;
+                }
+            }
+            vectors
+        }
+        (_, Some(subwords)) => FastText::train_with(&corpus, vocab, &FastTextConfig { train: config, subwords }, options)?.keyed_vectors(),
+        (_, None) => train_with(&corpus, vocab, &config, options)?.keyed_vectors(),
+    };
+    eprintln!();
+    println!("trained in {:.1}s", start.elapsed().as_secs_f64());
+    vectors.save(&args.output, format)?;
+    println!("wrote {} vectors to {} ({})", vectors.len(), args.output.display(), format);
+    if let Some(e) = event_error {
+        return Err(e.into());
+    }
+    if let (Some(logdir), Some(events)) = (&args.tensorboard, events.as_mut()) {
+        events.flush()?;
+        let written = write_projector(logdir, &method.to_string(), &vectors, args.projector_limit)?;
+        println!("logged to {}, {} vectors in the projector", logdir.display(), written);
+    }
+    Ok(())
+}
+
+// scalars by words processed, so resumed and uninterrupted runs line up
+fn log_progress(events: &mut SummaryWriter, progress: &Progress) -> io::Result<()> {
+    events.add_scalar("train/learning_rate", progress.learning_rate as f64, progress.words)?;
+    events.add_scalar("train/words_per_sec", progress.words_per_sec, progress.words)?;
+    events.add_scalar("train/epoch", progress.epoch as f64, progress.words)?;
+    events.flush()
+}
+
+fn write_projector(logdir: &Path, name: &str, vectors: &KeyedVectors, limit: usize) -> io::Result<usize> {
+    let n = limit.min(vectors.len());
+    tensorboard::write_projector(logdir, name, &vectors.words()[..n], vectors.vectors().slice(s![..n, ..]))?;
+    Ok(n)
+}
+
+// one line on stderr, rewritten in place like the reference tool's
+fn report_progress(progress: &Progress) {
+    eprint!(
+        "\repoch {}/{}  progress {:5.1}%  words/sec {:8.0}  lr {:.6}",
+        progress.epoch,
+        progress.epochs,
+        100.0 * progress.fraction(),
+        progress.words_per_sec,
+        progress.learning_rate,
+    );
+}
+
+fn tokenizer(args: &CorpusArgs) -> Tokenizer {
+    Tokenizer::new(args.tokenizer)
+        .with_lowercase(args.lowercase)
+        .with_strip_punctuation(args.strip_punctuation)
+}
+
+fn detect_phrases(args: &CorpusArgs) -> Result<Phrases> {
+    let Some(threshold) = args.phrase_threshold else {
+        return Ok(Phrases::default());
+    };
+    let mut detector = PhraseDetector::new(args.min_count, threshold).with_max_entries(args.phrase_table_size);
+    for sentence in stream_sentences(&args.corpus, tokenizer(args))? {
+        detector.add_sentence(&sentence?);
+    }
+    let phrases = detector.finish();
+    println!("found {} phrases", phrases.len());
+    Ok(phrases)
+}
+
+fn count_vocabulary(args: &CorpusArgs, phrases: &Phrases) -> Result<Vocabulary> {
+    let mut builder = VocabularyBuilder::new(args.min_count);
+    if let Some(max_size) = args.max_vocab {
+        builder = builder.with_max_size(max_size);
+    }
+    for sentence in stream_sentences(&args.corpus, tokenizer(args))?.with_phrases(phrases.clone()) {
+        builder.add_sentence(sentence?.iter().map(String::as_str));
+    }
+    Ok(builder.build())
+}
+
+// Vectors to query, and the fastText model behind them when read from a fastText
+// checkpoint, which also covers unknown words.
+struct Loaded {
+    vectors: KeyedVectors,
+    fasttext: Option<FastText>,
+}
+
+impl Loaded {
+    fn vector(&self, word: &str) -> Option<Array1<f32>> {
+        match (&self.fasttext, self.vectors.vector(word)) {
+            (_, Some(vector)) => Some(vector.to_owned()),
+            (Some(model), None) => Some(model.vector(word)).filter(|v| v.iter().any(|&x| x != 0.0)),
+            (None, None) => None,
+        }
+    }
+}
+
+fn load_vectors(path: &Path, format: Option<Format>) -> Result<Loaded> {
+    if Checkpoint::is_checkpoint(path)? {
+        let checkpoint = Checkpoint::load(path)?;
+        return Ok(match checkpoint.subwords {
+            Some(_) => {
+                let model = checkpoint.into_fasttext().expect("subwords are set");
+                Loaded { vectors: model.keyed_vectors(), fasttext: Some(model) }
+            }
+            None => Loaded { vectors: checkpoint.into_keyed_vectors(), fasttext: None },
+        });
+    }
+    let format = format.unwrap_or_else(|| Format::from_path(path));
+    Ok(Loaded { vectors: KeyedVectors::load(path, format)?, fasttext: None })
+}
+
+/// The embedding model to train.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    Word2Vec,
+    Glove,
+    FastText,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "word2vec" => Ok(Method::Word2Vec),
+            "glove" => Ok(Method::Glove),
+            "fasttext" => Ok(Method::FastText),
+            _ => Err(format!("unknown method {:?}, expected word2vec, glove or fasttext", s)),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Method::Word2Vec => "word2vec",
+            Method::Glove => "glove",
+            Method::FastText => "fasttext",
+        })
+    }
 }
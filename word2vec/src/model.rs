@@ -0,0 +1,57 @@
+use std::io;
+use std::path::Path;
+use ndarray::ArrayView1;
+use feed_forward::embedding::Embedding;
+use crate::format::Format;
+use crate::keyed_vectors::KeyedVectors;
+use crate::vocab::Vocabulary;
+
+/// Trained word vectors together with the vocabulary they index.
+///
+/// `input` holds the word vectors proper (syn0 in the original C code); `output` holds
+/// the context-side vectors the objective scores them against: one per word for negative
+/// sampling (syn1neg), one per Huffman inner node for hierarchical softmax (syn1).
+pub struct Word2Vec {
+    vocab: Vocabulary,
+    input: Embedding<f32>,
+    output: Embedding<f32>,
+}
+
+impl Word2Vec {
+    pub fn new(vocab: Vocabulary, input: Embedding<f32>, output: Embedding<f32>) -> Word2Vec {
+        assert_eq!(vocab.len(), input.vocabulary(), "one input vector per word");
+        Word2Vec { vocab, input, output }
+    }
+
+    pub fn vocab(&self) -> &Vocabulary {
+        &self.vocab
+    }
+
+    pub fn dim(&self) -> usize {
+        self.input.dim()
+    }
+
+    /// The word vectors.
+    pub fn embeddings(&self) -> &Embedding<f32> {
+        &self.input
+    }
+
+    /// The context vectors used by the training objective.
+    pub fn output_embeddings(&self) -> &Embedding<f32> {
+        &self.output
+    }
+
+    pub fn vector(&self, word: &str) -> Option<ArrayView1<'_, f32>> {
+        self.vocab.id(word).map(|id| self.input.vector(id))
+    }
+
+    /// A copy of the word vectors for export and queries.
+    pub fn keyed_vectors(&self) -> KeyedVectors {
+        KeyedVectors::new(self.vocab.words().to_vec(), self.input.vectors().to_owned())
+    }
+
+    /// Writes the word vectors in `format`.
+    pub fn save(&self, path: impl AsRef<Path>, format: Format) -> io::Result<()> {
+        self.keyed_vectors().save(path, format)
+    }
+}
@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use rand::Rng;
+
+pub const SENTENCE_START: &str = "<s>";
+pub const SENTENCE_END: &str = "</s>";
+pub const UNKNOWN: &str = "<unk>";
+
+// ARPA files give impossible events, like predicting <s>, a log probability of -99
+const LOG_ZERO: f64 = -99.0;
+// keeps backoff weights finite when the lower order already covers all the mass
+const MIN_MASS: f64 = 1e-12;
+// counts up to this are discounted by Good-Turing and Katz; larger ones are reliable
+const GOOD_TURING_MAX: u64 = 5;
+
+type Gram = Vec<u32>;
+
+/// How probability mass is moved from seen n-grams to unseen ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Add k to every count (k = 1 is Laplace smoothing).
+    AddK(f64),
+    /// Good-Turing discounted counts, interpolated with the next lower order.
+    GoodTuring,
+    /// Interpolated Kneser-Ney: absolute discounting, with lower orders estimated from
+    /// how many contexts a word follows rather than how often it occurs.
+    KneserNey,
+    /// Katz backoff: Good-Turing discounted counts for seen n-grams, and the freed mass
+    /// handed to the lower order for unseen ones.
+    Katz,
+}
+
+/// N-gram counts for every order up to `order`, collected one sentence at a time.
+///
+/// Each sentence is wrapped in <s> and </s>, so the model learns how sentences start
+/// and end.
+#[derive(Debug, Clone)]
+pub struct NgramCounts {
+    order: usize,
+    words: Vec<String>,
+    index: HashMap<String, u32>,
+    // counts[k - 1] holds the k-grams
+    counts: Vec<HashMap<Gram, u64>>,
+}
+
+impl NgramCounts {
+    pub fn new(order: usize) -> NgramCounts {
+        assert!(order > 0, "the order must be at least 1");
+        let mut counts = NgramCounts { order, words: vec![], index: HashMap::new(), counts: vec![HashMap::new(); order] };
+        for word in [SENTENCE_START, SENTENCE_END, UNKNOWN] {
+            counts.id(word);
+        }
+        counts
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    fn id(&mut self, word: &str) -> u32 {
+        if let Some(&id) = self.index.get(word) {
+            return id;
+        }
+        let id = self.words.len() as u32;
+        self.words.push(word.to_string());
+        self.index.insert(word.to_string(), id);
+        id
+    }
+
+    pub fn add_sentence<'a, T: IntoIterator<Item = &'a str>>(&mut self, tokens: T) {
+        let mut ids = vec![self.index[SENTENCE_START]];
+        for token in tokens {
+            ids.push(self.id(token));
+        }
+        ids.push(self.index[SENTENCE_END]);
+        // every position but the initial <s> is predicted, with up to order - 1 words of
+        // history
+        for end in 1..ids.len() {
+            for length in 1..=self.order.min(end + 1) {
+                *self.counts[length - 1].entry(ids[end + 1 - length..=end].to_vec()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// How many times `gram` (a sequence of words) was seen.
+    pub fn count(&self, gram: &[&str]) -> u64 {
+        if gram.is_empty() || gram.len() > self.order {
+            return 0;
+        }
+        let ids = gram.iter().map(|w| self.index.get(*w).copied()).collect::<Option<Gram>>();
+        ids.and_then(|ids| self.counts[gram.len() - 1].get(&ids).copied()).unwrap_or(0)
+    }
+
+    /// Estimates a language model with the given smoothing.
+    pub fn estimate(&self, smoothing: Smoothing) -> NgramModel {
+        Estimator::new(self, smoothing).run()
+    }
+}
+
+/// (probability, backoff weight), both linear while estimating
+type Table = HashMap<Gram, (f64, f64)>;
+
+struct Estimator<'a> {
+    counts: &'a NgramCounts,
+    smoothing: Smoothing,
+    start: u32,
+    // the words that can be predicted: everything but <s>
+    vocabulary: usize,
+    tables: Vec<Table>,
+}
+
+impl<'a> Estimator<'a> {
+    fn new(counts: &'a NgramCounts, smoothing: Smoothing) -> Estimator<'a> {
+        Estimator {
+            counts,
+            smoothing,
+            start: counts.index[SENTENCE_START],
+            vocabulary: counts.words.len() - 1,
+            tables: vec![],
+        }
+    }
+
+    fn run(mut self) -> NgramModel {
+        for order in 1..=self.counts.order {
+            let table = if order == 1 { self.unigrams() } else { self.higher_order(order) };
+            self.tables.push(table);
+        }
+        let tables = self.tables.into_iter()
+            .map(|table| table.into_iter().map(|(gram, (p, bow))| (gram, Entry { log_prob: log10(p), backoff: log10(bow) })).collect())
+            .collect();
+        NgramModel::from_parts(self.counts.words.clone(), tables)
+    }
+
+    // The counts the estimate for `order` is based on. Kneser-Ney replaces the counts of
+    // lower orders with continuation counts, the number of distinct words seen before the
+    // n-gram, except for n-grams starting with <s>, which nothing can precede.
+    fn adjusted_counts(&self, order: usize) -> HashMap<Gram, u64> {
+        if self.smoothing != Smoothing::KneserNey || order == self.counts.order {
+            return self.counts.counts[order - 1].clone();
+        }
+        let mut adjusted = HashMap::new();
+        for gram in self.counts.counts[order].keys() {
+            *adjusted.entry(gram[1..].to_vec()).or_insert(0) += 1;
+        }
+        for (gram, &count) in &self.counts.counts[order - 1] {
+            if gram[0] == self.start {
+                adjusted.insert(gram.clone(), count);
+            }
+        }
+        adjusted
+    }
+
+    fn unigrams(&self) -> Table {
+        let counts = self.adjusted_counts(1);
+        let total = counts.values().sum::<u64>().max(1) as f64;
+        let uniform = 1.0 / self.vocabulary as f64;
+        let discount = Discount::new(self.smoothing, &counts);
+        let kept = counts.iter().map(|(_, &c)| discount.apply(c)).sum::<f64>() / total;
+        let mut table = Table::new();
+        for id in 0..self.counts.words.len() as u32 {
+            if id == self.start {
+                table.insert(vec![id], (0.0, 1.0));
+                continue;
+            }
+            let count = counts.get(&vec![id]).copied().unwrap_or(0);
+            let p = match self.smoothing {
+                Smoothing::AddK(k) => (count as f64 + k) / (total + k * self.vocabulary as f64),
+                // the discounted mass is spread evenly over the vocabulary
+                _ => discount.apply(count) / total + (1.0 - kept) * uniform,
+            };
+            table.insert(vec![id], (p, 1.0));
+        }
+        table
+    }
+
+    fn higher_order(&mut self, order: usize) -> Table {
+        let counts = self.adjusted_counts(order);
+        let discount = Discount::new(self.smoothing, &counts);
+        let mut histories: HashMap<&[u32], Vec<(&Gram, u64)>> = HashMap::new();
+        for (gram, &count) in &counts {
+            histories.entry(&gram[..order - 1]).or_default().push((gram, count));
+        }
+
+        let mut table = Table::new();
+        let mut backoffs = vec![];
+        for (history, grams) in histories {
+            let total = grams.iter().map(|(_, c)| *c).sum::<u64>() as f64;
+            let lower = grams.iter().map(|(gram, _)| self.probability(&gram[1..])).collect::<Vec<_>>();
+            let lower_mass = lower.iter().sum::<f64>();
+            let discounted = grams.iter().map(|(_, c)| discount.apply(*c) / total).collect::<Vec<_>>();
+            let backoff = match self.smoothing {
+                Smoothing::AddK(k) => {
+                    let seen = grams.iter().map(|(_, c)| (*c as f64 + k) / (total + k * self.vocabulary as f64));
+                    let mut mass = 0.0;
+                    for ((gram, _), p) in grams.iter().zip(seen) {
+                        table.insert((*gram).clone(), (p, 1.0));
+                        mass += p;
+                    }
+                    (1.0 - mass).max(0.0) / (1.0 - lower_mass).max(MIN_MASS)
+                }
+                Smoothing::Katz => {
+                    for ((gram, _), p) in grams.iter().zip(&discounted) {
+                        table.insert((*gram).clone(), (*p, 1.0));
+                    }
+                    (1.0 - discounted.iter().sum::<f64>()).max(0.0) / (1.0 - lower_mass).max(MIN_MASS)
+                }
+                Smoothing::GoodTuring | Smoothing::KneserNey => {
+                    // interpolated: what the discount frees is the weight of the lower order
+                    let weight = (1.0 - discounted.iter().sum::<f64>()).max(0.0);
+                    for (((gram, _), p), low) in grams.iter().zip(&discounted).zip(&lower) {
+                        table.insert((*gram).clone(), (p + weight * low, 1.0));
+                    }
+                    weight
+                }
+            };
+            backoffs.push((history.to_vec(), backoff));
+        }
+        for (history, backoff) in backoffs {
+            if let Some(entry) = self.tables[order - 2].get_mut(&history) {
+                entry.1 = backoff;
+            }
+        }
+        table
+    }
+
+    // the backoff probability of the last word of `gram` given the others, from the
+    // orders estimated so far
+    fn probability(&self, gram: &[u32]) -> f64 {
+        if let Some(&(p, _)) = self.tables[gram.len() - 1].get(gram) {
+            return p;
+        }
+        if gram.len() == 1 {
+            return 0.0;
+        }
+        let backoff = self.tables[gram.len() - 2].get(&gram[..gram.len() - 1]).map_or(1.0, |e| e.1);
+        backoff * self.probability(&gram[1..])
+    }
+}
+
+// maps a raw count to its discounted count for one order
+struct Discount {
+    smoothing: Smoothing,
+    // Good-Turing and Katz: discounted counts for 1..=GOOD_TURING_MAX, indexed by count
+    adjusted: Vec<f64>,
+    // Kneser-Ney's absolute discount
+    absolute: f64,
+}
+
+impl Discount {
+    fn new(smoothing: Smoothing, counts: &HashMap<Gram, u64>) -> Discount {
+        let mut count_of_counts = [0u64; GOOD_TURING_MAX as usize + 2];
+        for &c in counts.values() {
+            if c <= GOOD_TURING_MAX + 1 {
+                count_of_counts[c as usize] += 1;
+            }
+        }
+        let n = |c: u64| count_of_counts[c as usize] as f64;
+        let mut adjusted = (0..=GOOD_TURING_MAX).map(|c| c as f64).collect::<Vec<_>>();
+        let mut absolute = 0.5;
+        match smoothing {
+            Smoothing::GoodTuring | Smoothing::Katz => {
+                // Katz renormalizes so that discounting stops smoothly at GOOD_TURING_MAX
+                let cutoff = if smoothing == Smoothing::Katz && n(1) > 0.0 {
+                    (GOOD_TURING_MAX + 1) as f64 * n(GOOD_TURING_MAX + 1) / n(1)
+                } else {
+                    0.0
+                };
+                for c in 1..=GOOD_TURING_MAX {
+                    if n(c) == 0.0 || n(c + 1) == 0.0 || cutoff >= 1.0 {
+                        continue;
+                    }
+                    let good_turing = (c + 1) as f64 * n(c + 1) / n(c);
+                    let ratio = (good_turing / c as f64 - cutoff) / (1.0 - cutoff);
+                    // the estimate is only trusted when it actually discounts
+                    if ratio > 0.0 && ratio < 1.0 {
+                        adjusted[c as usize] = ratio * c as f64;
+                    }
+                }
+            }
+            Smoothing::KneserNey => {
+                // Ney, Essen & Kneser (1994): D = n1 / (n1 + 2 n2)
+                if n(1) > 0.0 && n(2) > 0.0 {
+                    absolute = n(1) / (n(1) + 2.0 * n(2));
+                }
+            }
+            Smoothing::AddK(_) => {}
+        }
+        Discount { smoothing, adjusted, absolute }
+    }
+
+    fn apply(&self, count: u64) -> f64 {
+        match self.smoothing {
+            Smoothing::KneserNey => (count as f64 - self.absolute).max(0.0),
+            _ if count <= GOOD_TURING_MAX => self.adjusted[count as usize],
+            _ => count as f64,
+        }
+    }
+}
+
+fn log10(p: f64) -> f64 {
+    if p > 0.0 { p.log10().max(LOG_ZERO) } else { LOG_ZERO }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    log_prob: f64,
+    backoff: f64,
+}
+
+/// A backoff n-gram language model, the form an ARPA file stores.
+///
+/// The probability of a word after a history is the listed one if the whole n-gram is
+/// listed; otherwise it is the history's backoff weight times the probability given the
+/// history without its first word. All four smoothing methods are estimated into this
+/// form. Interpolated Kneser-Ney and Good-Turing fit it exactly. Add-k does not quite:
+/// seen n-grams get their add-k probability and each history keeps the add-k mass for
+/// unseen words, but that mass is shared out following the lower order instead of
+/// evenly.
+///
+/// Probabilities are log10, as in ARPA files. Words outside the vocabulary are read as
+/// <unk>.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NgramModel {
+    words: Vec<String>,
+    index: HashMap<String, u32>,
+    tables: Vec<HashMap<Gram, Entry>>,
+}
+
+/// The score of one sentence.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SentenceScore {
+    pub log10_prob: f64,
+    /// The words scored, including the final </s>.
+    pub tokens: usize,
+    /// Words that were neither in the vocabulary nor coverable by <unk>, left unscored.
+    pub oovs: usize,
+}
+
+impl NgramModel {
+    fn from_parts(words: Vec<String>, tables: Vec<HashMap<Gram, Entry>>) -> NgramModel {
+        let index = words.iter().enumerate().map(|(id, w)| (w.clone(), id as u32)).collect();
+        NgramModel { words, index, tables }
+    }
+
+    pub fn order(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// The number of n-grams listed for each order, from unigrams up.
+    pub fn sizes(&self) -> Vec<usize> {
+        self.tables.iter().map(HashMap::len).collect()
+    }
+
+    pub fn vocabulary(&self) -> &[String] {
+        &self.words
+    }
+
+    fn lookup(&self, word: &str) -> Option<u32> {
+        self.index.get(word).or_else(|| self.index.get(UNKNOWN)).copied()
+    }
+
+    /// log10 P(word | context), using the last order - 1 words of `context`. Negative
+    /// infinity for a word the model can't represent.
+    pub fn log10_prob(&self, word: &str, context: &[&str]) -> f64 {
+        let Some(word) = self.lookup(word) else {
+            return f64::NEG_INFINITY;
+        };
+        let mut history = vec![];
+        for token in context.iter().rev().take(self.order() - 1) {
+            match self.lookup(token) {
+                Some(id) => history.push(id),
+                // a word the model can't represent cuts the history short
+                None => break,
+            }
+        }
+        history.reverse();
+        self.log10_prob_ids(&history, word)
+    }
+
+    fn log10_prob_ids(&self, history: &[u32], word: u32) -> f64 {
+        let mut backoff = 0.0;
+        for start in 0..=history.len() {
+            let mut gram = history[start..].to_vec();
+            gram.push(word);
+            if let Some(entry) = self.tables[gram.len() - 1].get(&gram) {
+                return backoff + entry.log_prob;
+            }
+            if gram.len() > 1 {
+                backoff += self.tables[gram.len() - 2].get(&gram[..gram.len() - 1]).map_or(0.0, |e| e.backoff);
+            }
+        }
+        f64::NEG_INFINITY
+    }
+
+    /// Scores a sentence from <s> through its final </s>.
+    pub fn score_sentence(&self, tokens: &[&str]) -> SentenceScore {
+        let mut score = SentenceScore::default();
+        let mut context = vec![SENTENCE_START];
+        for &token in tokens.iter().chain([SENTENCE_END].iter()) {
+            let log_prob = self.log10_prob(token, &context);
+            if log_prob.is_finite() {
+                score.log10_prob += log_prob;
+                score.tokens += 1;
+            } else {
+                score.oovs += 1;
+            }
+            context.push(token);
+        }
+        score
+    }
+
+    /// 10^(-average log10 probability) over every scored word of `sentences`, the end of
+    /// each sentence included.
+    pub fn perplexity<'a, S, T>(&self, sentences: S) -> f64
+    where
+        S: IntoIterator<Item = T>,
+        T: AsRef<[&'a str]>,
+    {
+        let (mut log_prob, mut tokens) = (0.0, 0);
+        for sentence in sentences {
+            let score = self.score_sentence(sentence.as_ref());
+            log_prob += score.log10_prob;
+            tokens += score.tokens;
+        }
+        10f64.powf(-log_prob / tokens.max(1) as f64)
+    }
+
+    /// Generates a sentence word by word until </s> or `max_length` words. <unk> is never
+    /// generated, so a model that knows no other word gives an empty sentence.
+    pub fn sample<R: Rng>(&self, rng: &mut R, max_length: usize) -> Vec<String> {
+        let candidates = (0..self.words.len() as u32)
+            .filter(|&id| self.words[id as usize] != SENTENCE_START && self.words[id as usize] != UNKNOWN)
+            .collect::<Vec<_>>();
+        let Some(&last) = candidates.last() else {
+            return vec![];
+        };
+        let end = self.index.get(SENTENCE_END).copied();
+        let mut history = self.index.get(SENTENCE_START).map(|&id| vec![id]).unwrap_or_default();
+        let mut sentence = vec![];
+        while sentence.len() < max_length {
+            let context = &history[history.len().saturating_sub(self.order() - 1)..];
+            let weights = candidates.iter().map(|&id| 10f64.powf(self.log10_prob_ids(context, id))).collect::<Vec<_>>();
+            let mut target = rng.gen::<f64>() * weights.iter().sum::<f64>();
+            let mut choice = last;
+            for (&id, weight) in candidates.iter().zip(&weights) {
+                target -= weight;
+                if target <= 0.0 {
+                    choice = id;
+                    break;
+                }
+            }
+            if Some(choice) == end {
+                break;
+            }
+            sentence.push(self.words[choice as usize].clone());
+            history.push(choice);
+        }
+        sentence
+    }
+
+    /// Writes the model in the ARPA text format used by SRILM and KenLM.
+    pub fn save_arpa(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_arpa(&mut out)?;
+        out.flush()
+    }
+
+    pub fn write_arpa<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "\n\\data\\")?;
+        for (order, table) in self.tables.iter().enumerate() {
+            writeln!(out, "ngram {}={}", order + 1, table.len())?;
+        }
+        for (order, table) in self.tables.iter().enumerate() {
+            writeln!(out, "\n\\{}-grams:", order + 1)?;
+            let mut entries = table.iter()
+                .map(|(gram, entry)| (gram.iter().map(|&id| self.words[id as usize].as_str()).collect::<Vec<_>>().join(" "), entry))
+                .collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (gram, entry) in entries {
+                write!(out, "{:.7}\t{}", entry.log_prob, gram)?;
+                if order + 1 < self.order() && entry.backoff != 0.0 {
+                    write!(out, "\t{:.7}", entry.backoff)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        writeln!(out, "\n\\end\\")
+    }
+
+    pub fn load_arpa(path: impl AsRef<Path>) -> io::Result<NgramModel> {
+        Self::read_arpa(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads an ARPA file. Every word of the unigram section becomes part of the
+    /// vocabulary; higher order n-grams must only use those words.
+    pub fn read_arpa<R: BufRead>(reader: R) -> io::Result<NgramModel> {
+        let mut declared = vec![];
+        let mut section = None;
+        let mut words: Vec<String> = vec![];
+        let mut index: HashMap<String, u32> = HashMap::new();
+        let mut tables: Vec<HashMap<Gram, Entry>> = vec![];
+        let mut ended = false;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let error = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", number + 1, message));
+            if line.is_empty() || ended {
+                continue;
+            }
+            if line == "\\data\\" {
+                section = Some(0);
+            } else if line == "\\end\\" {
+                ended = true;
+            } else if let Some(order) = line.strip_prefix('\\').and_then(|l| l.strip_suffix("-grams:")) {
+                let order = order.parse::<usize>().map_err(|_| error("bad section header"))?;
+                if order != tables.len() + 1 || order > declared.len() {
+                    return Err(error("n-gram sections must follow the \\data\\ counts in order"));
+                }
+                tables.push(HashMap::new());
+                section = Some(order);
+            } else if section == Some(0) {
+                let count = line.strip_prefix("ngram ")
+                    .and_then(|l| l.split_once('='))
+                    .and_then(|(_, count)| count.trim().parse::<usize>().ok())
+                    .ok_or_else(|| error("expected \"ngram <order>=<count>\""))?;
+                declared.push(count);
+            } else if let Some(order) = section {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                if fields.len() != order + 1 && fields.len() != order + 2 {
+                    return Err(error(&format!("expected a probability, {} words and an optional backoff", order)));
+                }
+                let parse = |s: &str| s.parse::<f64>().map_err(|_| error("bad number"));
+                let log_prob = parse(fields[0])?;
+                let backoff = if fields.len() == order + 2 { parse(fields[order + 1])? } else { 0.0 };
+                let mut gram = Vec::with_capacity(order);
+                for word in &fields[1..=order] {
+                    let id = match index.get(*word) {
+                        Some(&id) => id,
+                        None if order == 1 => {
+                            words.push(word.to_string());
+                            index.insert(word.to_string(), words.len() as u32 - 1);
+                            words.len() as u32 - 1
+                        }
+                        None => return Err(error(&format!("{:?} is not a unigram", word))),
+                    };
+                    gram.push(id);
+                }
+                tables[order - 1].insert(gram, Entry { log_prob, backoff });
+            } else {
+                return Err(error("text before \\data\\"));
+            }
+        }
+        if tables.is_empty() || tables.len() != declared.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "missing n-gram sections"));
+        }
+        for (order, (table, &count)) in tables.iter().zip(&declared).enumerate() {
+            if table.len() != count {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("\\data\\ declares {} {}-grams, the file lists {}", count, order + 1, table.len()),
+                ));
+            }
+        }
+        Ok(NgramModel { words, index, tables })
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::{HashMap, HashSet};
+
+/// Joins the two words of a detected phrase.
+pub const PHRASE_DELIMITER: char = '_';
+
+/// Counts unigrams and bigrams over a stream of sentences to find phrases like
+/// "new york", as the reference word2phrase tool does.
+///
+/// A bigram "a b" scores (count(ab) - min_count) / (count(a) * count(b)) * total, which
+/// is high when the words appear together far more often than chance; bigrams scoring
+/// above `threshold` become phrases. Running detection again on text with phrases
+/// already joined finds longer ones.
+///
+/// Without `with_max_entries` the counts grow with the number of distinct bigrams in the
+/// corpus. With it they are pruned like `VocabularyBuilder`'s word table, and like the
+/// reference tool's: whenever the unigrams and bigrams together pass the limit, those
+/// seen at most once are dropped, then at most twice the next time, and so on.
+#[derive(Debug, Clone)]
+pub struct PhraseDetector {
+    min_count: u64,
+    threshold: f64,
+    unigrams: HashMap<String, u64>,
+    bigrams: HashMap<(String, String), u64>,
+    total: u64,
+    max_entries: Option<usize>,
+    min_reduce: u64,
+}
+
+impl PhraseDetector {
+    /// The reference tool uses a min_count of 5 and a threshold of 100; higher thresholds
+    /// give fewer phrases.
+    pub fn new(min_count: u64, threshold: f64) -> PhraseDetector {
+        PhraseDetector { min_count, threshold, unigrams: HashMap::new(), bigrams: HashMap::new(), total: 0, max_entries: None, min_reduce: 1 }
+    }
+
+    /// Bounds the number of distinct unigrams and bigrams held while counting.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        assert!(max_entries > 0, "the count table needs room for at least one entry");
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// The number of distinct unigrams and bigrams currently counted.
+    pub fn len(&self) -> usize {
+        self.unigrams.len() + self.bigrams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unigrams.is_empty()
+    }
+
+    pub fn add_sentence<S: AsRef<str>>(&mut self, tokens: &[S]) {
+        for (i, token) in tokens.iter().enumerate() {
+            *self.unigrams.entry(token.as_ref().to_string()).or_insert(0) += 1;
+            if let Some(next) = tokens.get(i + 1) {
+                *self.bigrams.entry((token.as_ref().to_string(), next.as_ref().to_string())).or_insert(0) += 1;
+            }
+        }
+        self.total += tokens.len() as u64;
+        // checked per sentence, so the table can overshoot by one sentence's worth
+        if self.max_entries.is_some_and(|max| self.len() > max) {
+            self.reduce();
+        }
+    }
+
+    fn reduce(&mut self) {
+        while self.max_entries.is_some_and(|max| self.len() > max) {
+            let min_reduce = self.min_reduce;
+            self.unigrams.retain(|_, count| *count > min_reduce);
+            self.bigrams.retain(|_, count| *count > min_reduce);
+            self.min_reduce += 1;
+        }
+    }
+
+    pub fn score(&self, first: &str, second: &str) -> f64 {
+        let count = |word: &str| self.unigrams.get(word).copied().unwrap_or(0);
+        let (a, b) = (count(first), count(second));
+        let ab = self.bigrams.get(&(first.to_string(), second.to_string())).copied().unwrap_or(0);
+        if a < self.min_count || b < self.min_count || ab < self.min_count {
+            return 0.0;
+        }
+        (ab - self.min_count) as f64 / (a as f64 * b as f64) * self.total as f64
+    }
+
+    pub fn finish(self) -> Phrases {
+        let mut pairs: HashMap<String, HashSet<String>> = HashMap::new();
+        for (a, b) in self.bigrams.keys() {
+            if self.score(a, b) > self.threshold {
+                pairs.entry(a.clone()).or_default().insert(b.clone());
+            }
+        }
+        Phrases { pairs }
+    }
+}
+
+/// Detected phrases, applied to token streams by joining each phrase into one token.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Phrases {
+    // first word -> the second words it forms a phrase with
+    pairs: HashMap<String, HashSet<String>>,
+}
+
+impl Phrases {
+    pub fn len(&self) -> usize {
+        self.pairs.values().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn contains(&self, first: &str, second: &str) -> bool {
+        self.pairs.get(first).is_some_and(|seconds| seconds.contains(second))
+    }
+
+    /// The phrases as joined tokens, sorted.
+    pub fn joined(&self) -> Vec<String> {
+        let mut joined = self.pairs.iter()
+            .flat_map(|(a, seconds)| seconds.iter().map(move |b| join(a, b)))
+            .collect::<Vec<_>>();
+        joined.sort();
+        joined
+    }
+
+    /// Joins phrases left to right, so in "a b c" with both "a b" and "b c" detected only
+    /// "a_b" is formed.
+    pub fn apply(&self, tokens: Vec<String>) -> Vec<String> {
+        if self.pairs.is_empty() {
+            return tokens;
+        }
+        let mut out = Vec::with_capacity(tokens.len());
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            match tokens.peek() {
+                Some(next) if self.contains(&token, next) => {
+                    let next = tokens.next().unwrap();
+                    out.push(join(&token, &next));
+                }
+                _ => out.push(token),
+            }
+        }
+        out
+    }
+}
+
+fn join(first: &str, second: &str) -> String {
+    format!("{}{}{}", first, PHRASE_DELIMITER, second)
+}
@@ -0,0 +1,64 @@
+use rand::Rng;
+use crate::vocab::Vocabulary;
+
+/// Draws negative samples with probability proportional to count^0.75, which flattens the
+/// unigram distribution so rare words are picked more often than their frequency alone
+/// would suggest.
+///
+/// Like the reference implementation this is a big table in which each word occupies a
+/// share of slots matching its probability, so sampling is a single random index.
+#[derive(Debug, Clone)]
+pub struct UnigramTable {
+    table: Vec<u32>,
+}
+
+impl UnigramTable {
+    pub const POWER: f64 = 0.75;
+
+    pub fn new(vocab: &Vocabulary, size: usize) -> UnigramTable {
+        assert!(!vocab.is_empty(), "can't sample from an empty vocabulary");
+        let weights = vocab.counts().iter().map(|&c| (c as f64).powf(Self::POWER)).collect::<Vec<_>>();
+        let total: f64 = weights.iter().sum();
+
+        let mut table = Vec::with_capacity(size);
+        let mut word = 0;
+        let mut cumulative = weights[0] / total;
+        for slot in 0..size {
+            table.push(word as u32);
+            if (slot + 1) as f64 / size as f64 > cumulative && word + 1 < weights.len() {
+                word += 1;
+                cumulative += weights[word] / total;
+            }
+        }
+        UnigramTable { table }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        self.table[rng.gen_range(0..self.table.len())] as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+/// The probability of keeping each word when subsampling frequent words with threshold
+/// `sample` (Mikolov et al. 2013): (sqrt(f / t) + 1) * t / f, where f is the word's share
+/// of the corpus and t = `sample`. Words rarer than roughly t are always kept. A
+/// threshold of 0 disables subsampling.
+pub fn keep_probabilities(vocab: &Vocabulary, sample: f64) -> Vec<f32> {
+    let total = vocab.total_count() as f64;
+    vocab.counts().iter()
+        .map(|&count| {
+            if sample <= 0.0 {
+                return 1.0;
+            }
+            let ratio = sample * total / count as f64;
+            ((ratio.sqrt() + ratio) as f32).min(1.0)
+        })
+        .collect()
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How text is split into tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Segmentation {
+    /// Split on whitespace only, so punctuation stays attached to words ("dog." is one
+    /// token). This is what the reference word2vec tool expects.
+    #[default]
+    Whitespace,
+    /// Split into words, numbers and punctuation marks following the spirit of the
+    /// Unicode word boundary rules: runs of letters and digits form words, apostrophes and
+    /// hyphens join the letters around them ("don't", "state-of-the-art"), periods and
+    /// commas join digits ("3.14", "1,000"), every other symbol is a token of its own and
+    /// each Han ideograph is a word by itself.
+    Unicode,
+}
+
+impl FromStr for Segmentation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitespace" => Ok(Segmentation::Whitespace),
+            "unicode" => Ok(Segmentation::Unicode),
+            _ => Err(format!("unknown segmentation {:?}, expected whitespace or unicode", s)),
+        }
+    }
+}
+
+impl fmt::Display for Segmentation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Segmentation::Whitespace => "whitespace",
+            Segmentation::Unicode => "unicode",
+        })
+    }
+}
+
+/// Turns lines of raw text into tokens.
+///
+/// The default splits on whitespace and leaves tokens untouched.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tokenizer {
+    segmentation: Segmentation,
+    lowercase: bool,
+    strip_punctuation: bool,
+}
+
+impl Tokenizer {
+    pub fn new(segmentation: Segmentation) -> Tokenizer {
+        Tokenizer { segmentation, ..Tokenizer::default() }
+    }
+
+    /// Lowercases every token, with Unicode case mapping.
+    pub fn with_lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Drops punctuation: with whitespace segmentation it is trimmed from both ends of
+    /// each token, with Unicode segmentation punctuation tokens are left out.
+    pub fn with_strip_punctuation(mut self, strip: bool) -> Self {
+        self.strip_punctuation = strip;
+        self
+    }
+
+    pub fn segmentation(&self) -> Segmentation {
+        self.segmentation
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        match self.segmentation {
+            Segmentation::Whitespace => {
+                for token in text.split_whitespace() {
+                    let token = if self.strip_punctuation { token.trim_matches(|c: char| !is_word_char(c)) } else { token };
+                    if !token.is_empty() {
+                        tokens.push(self.normalize(token));
+                    }
+                }
+            }
+            Segmentation::Unicode => {
+                for (token, is_word) in segment(text) {
+                    if is_word || !self.strip_punctuation {
+                        tokens.push(self.normalize(token));
+                    }
+                }
+            }
+        }
+        tokens
+    }
+
+    fn normalize(&self, token: &str) -> String {
+        if self.lowercase {
+            token.to_lowercase()
+        } else {
+            token.to_string()
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_ideograph(c: char) -> bool {
+    matches!(c as u32, 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F)
+}
+
+// characters that continue a word when they sit between two word characters
+fn joins(c: char, before: char, after: char) -> bool {
+    match c {
+        '\'' | '\u{2019}' | '-' | '\u{2010}' => true,
+        '.' | ',' => before.is_numeric() && after.is_numeric(),
+        _ => false,
+    }
+}
+
+// Splits `text` into (token, is_word) pairs, skipping whitespace.
+fn segment(text: &str) -> Vec<(&str, bool)> {
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if !is_word_char(c) || is_ideograph(c) {
+            let end = chars.get(i + 1).map_or(text.len(), |&(p, _)| p);
+            tokens.push((&text[start..end], is_word_char(c)));
+            i += 1;
+            continue;
+        }
+        let mut j = i + 1;
+        while j < chars.len() {
+            let c = chars[j].1;
+            if is_word_char(c) && !is_ideograph(c) {
+                j += 1;
+            } else if j + 1 < chars.len()
+                && joins(c, chars[j - 1].1, chars[j + 1].1)
+                && is_word_char(chars[j + 1].1)
+                && !is_ideograph(chars[j + 1].1)
+            {
+                j += 2;
+            } else {
+                break;
+            }
+        }
+        let end = chars.get(j).map_or(text.len(), |&(p, _)| p);
+        tokens.push((&text[start..end], true));
+        i = j;
+    }
+    tokens
+}
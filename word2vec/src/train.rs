@@ -0,0 +1,455 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use feed_forward::embedding::Embedding;
+use feed_forward::init::Initializer;
+use feed_forward::seed::rng_from_seed;
+use crate::checkpoint::Checkpoint;
+use crate::fasttext::{word_rows, Subwords};
+use crate::hogwild::SharedMatrix;
+use crate::huffman::HuffmanTree;
+use crate::model::Word2Vec;
+use crate::sampling::{keep_probabilities, UnigramTable};
+use crate::vocab::Vocabulary;
+
+/// Which words predict which.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Architecture {
+    /// Each context word predicts the center word.
+    #[default]
+    SkipGram,
+    /// The average of the context words' vectors predicts the center word.
+    Cbow,
+}
+
+/// How the prediction of the center word is scored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Objective {
+    /// Tell the true center word apart from `negative` words drawn from the noise
+    /// distribution.
+    #[default]
+    NegativeSampling,
+    /// Walk the center word's path in a Huffman tree, one binary decision per inner node.
+    HierarchicalSoftmax,
+}
+
+impl FromStr for Architecture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip-gram" | "skipgram" | "sg" => Ok(Architecture::SkipGram),
+            "cbow" => Ok(Architecture::Cbow),
+            _ => Err(format!("unknown architecture {:?}, expected skip-gram or cbow", s)),
+        }
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Architecture::SkipGram => "skip-gram",
+            Architecture::Cbow => "cbow",
+        })
+    }
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "negative-sampling" | "ns" => Ok(Objective::NegativeSampling),
+            "hierarchical-softmax" | "hs" => Ok(Objective::HierarchicalSoftmax),
+            _ => Err(format!("unknown objective {:?}, expected negative-sampling or hierarchical-softmax", s)),
+        }
+    }
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Objective::NegativeSampling => "negative-sampling",
+            Objective::HierarchicalSoftmax => "hierarchical-softmax",
+        })
+    }
+}
+
+/// Hyperparameters for `train`. The defaults follow the reference word2vec tool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainConfig {
+    pub architecture: Architecture,
+    pub objective: Objective,
+    pub dim: usize,
+    /// The largest distance between a center word and a context word. Each center word
+    /// uses a window drawn uniformly from 1..=window, which weights near words more.
+    pub window: usize,
+    /// Negative samples per positive pair, for `Objective::NegativeSampling`.
+    pub negative: usize,
+    /// Subsampling threshold for frequent words, 0 to disable.
+    pub sample: f64,
+    pub epochs: usize,
+    /// The starting learning rate, decayed linearly towards 0.0001 * learning_rate over
+    /// the whole run. The reference tool uses 0.025 for skip-gram and 0.05 for CBOW.
+    pub learning_rate: f32,
+    pub threads: usize,
+    pub table_size: usize,
+    pub seed: Option<u64>,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        TrainConfig {
+            architecture: Architecture::SkipGram,
+            objective: Objective::NegativeSampling,
+            dim: 100,
+            window: 5,
+            negative: 5,
+            sample: 1e-3,
+            epochs: 5,
+            learning_rate: 0.025,
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            table_size: 10_000_000,
+            seed: None,
+        }
+    }
+}
+
+// how many words a thread processes between updates of the shared progress counter
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+// how often the calling thread checks whether an epoch has finished
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How far a training run has come, passed to the `TrainOptions::with_progress` callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// The epoch in progress, counting from 1.
+    pub epoch: usize,
+    pub epochs: usize,
+    /// Corpus words processed so far, counting epochs done before a resume.
+    pub words: u64,
+    /// Corpus words over all epochs.
+    pub total_words: u64,
+    pub learning_rate: f32,
+    /// Words processed per second since this run started.
+    pub words_per_sec: f64,
+}
+
+impl Progress {
+    /// The share of the run that is done, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        self.words as f64 / self.total_words.max(1) as f64
+    }
+}
+
+type ProgressCallback<'a> = Box<dyn FnMut(&Progress) + 'a>;
+type EpochCallback<'a> = Box<dyn FnMut(&Checkpoint) -> io::Result<()> + 'a>;
+
+/// Progress reporting, checkpoints and resuming for `train_with` and `FastText::train_with`.
+#[derive(Default)]
+pub struct TrainOptions<'a> {
+    progress: Option<(Duration, ProgressCallback<'a>)>,
+    on_epoch: Option<EpochCallback<'a>>,
+    resume: Option<Checkpoint>,
+}
+
+impl<'a> TrainOptions<'a> {
+    /// Calls `report` from the calling thread about every `interval` while training, and
+    /// at the end of every epoch.
+    pub fn with_progress(mut self, interval: Duration, report: impl FnMut(&Progress) + 'a) -> Self {
+        self.progress = Some((interval, Box::new(report)));
+        self
+    }
+
+    /// Calls `save` with a checkpoint after every epoch. An error stops training and is
+    /// returned from `train_with`.
+    pub fn with_checkpoints(mut self, save: impl FnMut(&Checkpoint) -> io::Result<()> + 'a) -> Self {
+        self.on_epoch = Some(Box::new(save));
+        self
+    }
+
+    /// Starts from the vectors of `checkpoint` after its completed epochs instead of from
+    /// random vectors. The run's configuration must have the same dimension and
+    /// objective, and its vocabulary must be the checkpoint's.
+    pub fn resume_from(mut self, checkpoint: Checkpoint) -> Self {
+        self.resume = Some(checkpoint);
+        self
+    }
+}
+
+/// Trains word vectors on sentences of word ids from `vocab`.
+///
+/// The corpus is split between `config.threads` threads, which update the shared vectors
+/// without locking. With one thread and a seed, training is deterministic. With
+/// hierarchical softmax the model's output embeddings are the Huffman tree's inner nodes
+/// rather than one vector per word.
+pub fn train(corpus: &[Vec<usize>], vocab: Vocabulary, config: &TrainConfig) -> Word2Vec {
+    train_with(corpus, vocab, config, TrainOptions::default()).expect("training without callbacks can't fail")
+}
+
+/// `train` with progress reports, checkpoints or a resumed start.
+pub fn train_with(corpus: &[Vec<usize>], vocab: Vocabulary, config: &TrainConfig, options: TrainOptions) -> io::Result<Word2Vec> {
+    let (input, output) = train_embeddings(corpus, &vocab, config, None, options)?;
+    Ok(Word2Vec::new(vocab, input, output))
+}
+
+// The training loop shared with fastText. With `subwords`, each word stands for the
+// average of its own input row and its n-gram rows, which follow the vocabulary's.
+pub(crate) fn train_embeddings(
+    corpus: &[Vec<usize>],
+    vocab: &Vocabulary,
+    config: &TrainConfig,
+    subwords: Option<Subwords>,
+    mut options: TrainOptions,
+) -> io::Result<(Embedding<f32>, Embedding<f32>)> {
+    assert!(config.threads > 0 && config.window > 0, "threads and window must be positive");
+    let mut rng = rng_from_seed(config.seed);
+    // drawn first so the epochs' streams don't depend on how the vectors were initialized
+    let base_seed: u64 = rng.gen();
+    let rows = subwords.map(|subwords| {
+        (0..vocab.len()).map(|id| word_rows(&subwords, vocab.len(), id, vocab.word(id))).collect::<Vec<_>>()
+    });
+    let input_rows = vocab.len() + subwords.map_or(0, |s| s.buckets);
+    let (table, tree, outputs) = match config.objective {
+        Objective::NegativeSampling => (Some(UnigramTable::new(vocab, config.table_size)), None, vocab.len()),
+        Objective::HierarchicalSoftmax => {
+            let tree = HuffmanTree::new(vocab.counts());
+            let inner = tree.inner_nodes().max(1);
+            (None, Some(tree), inner)
+        }
+    };
+
+    let (first_epoch, mut input, mut output) = match options.resume.take() {
+        Some(checkpoint) => {
+            if checkpoint.input.dim() != (input_rows, config.dim)
+                || checkpoint.output.dim() != (outputs, config.dim)
+                || checkpoint.vocab.words() != vocab.words()
+            {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "the checkpoint doesn't match the vocabulary and configuration"));
+            }
+            (checkpoint.epoch, Embedding::from_pretrained(checkpoint.input), Embedding::from_pretrained(checkpoint.output))
+        }
+        None => {
+            let half_width = 0.5 / config.dim as f32;
+            let input = Embedding::new(input_rows, config.dim, Initializer::Uniform { low: -half_width, high: half_width }, &mut rng);
+            (0, input, Embedding::new(outputs, config.dim, Initializer::Zeros, &mut rng))
+        }
+    };
+
+    let corpus_words = corpus.iter().map(|s| s.len() as u64).sum::<u64>();
+    let trainer = Trainer {
+        config,
+        table,
+        tree,
+        subwords: rows.as_deref(),
+        keep: keep_probabilities(vocab, config.sample),
+        input: SharedMatrix::from_view(input.vectors()),
+        output: SharedMatrix::from_view(output.vectors()),
+        processed: AtomicU64::new(first_epoch as u64 * corpus_words),
+        total: (config.epochs as u64 * corpus_words).max(1),
+    };
+
+    let chunk = corpus.len().div_ceil(config.threads).max(1);
+    let started = Instant::now();
+    let start_words = trainer.processed.load(Ordering::Relaxed);
+    for epoch in first_epoch..config.epochs {
+        let progress = || {
+            let words = trainer.processed.load(Ordering::Relaxed);
+            Progress {
+                epoch: epoch + 1,
+                epochs: config.epochs,
+                words,
+                total_words: trainer.total,
+                learning_rate: trainer.learning_rate(),
+                words_per_sec: (words - start_words) as f64 / started.elapsed().as_secs_f64().max(1e-9),
+            }
+        };
+        std::thread::scope(|scope| {
+            let handles = corpus.chunks(chunk).enumerate()
+                .map(|(t, part)| {
+                    let trainer = &trainer;
+                    let seed = base_seed.wrapping_add((epoch * config.threads + t) as u64);
+                    scope.spawn(move || trainer.run(part, &mut StdRng::seed_from_u64(seed)))
+                })
+                .collect::<Vec<_>>();
+            if let Some((interval, report)) = options.progress.as_mut() {
+                let mut last = Instant::now();
+                while !handles.iter().all(|h| h.is_finished()) {
+                    std::thread::sleep(POLL_INTERVAL);
+                    if last.elapsed() >= *interval {
+                        last = Instant::now();
+                        report(&progress());
+                    }
+                }
+            }
+        });
+        if let Some((_, report)) = options.progress.as_mut() {
+            report(&progress());
+        }
+        if let Some(save) = options.on_epoch.as_mut() {
+            trainer.input.write_to(input.vectors_mut());
+            trainer.output.write_to(output.vectors_mut());
+            save(&Checkpoint {
+                config: config.clone(),
+                subwords,
+                epoch: epoch + 1,
+                vocab: vocab.clone(),
+                input: input.vectors().to_owned(),
+                output: output.vectors().to_owned(),
+            })?;
+        }
+    }
+
+    trainer.input.write_to(input.vectors_mut());
+    trainer.output.write_to(output.vectors_mut());
+    Ok((input, output))
+}
+
+struct Trainer<'a> {
+    config: &'a TrainConfig,
+    table: Option<UnigramTable>,
+    tree: Option<HuffmanTree>,
+    subwords: Option<&'a [Vec<usize>]>,
+    keep: Vec<f32>,
+    input: SharedMatrix,
+    output: SharedMatrix,
+    processed: AtomicU64,
+    total: u64,
+}
+
+impl Trainer<'_> {
+    fn learning_rate(&self) -> f32 {
+        let progress = self.processed.load(Ordering::Relaxed) as f32 / (self.total + 1) as f32;
+        self.config.learning_rate * (1.0 - progress).max(0.0001)
+    }
+
+    // one epoch over `sentences`
+    fn run(&self, sentences: &[Vec<usize>], rng: &mut StdRng) {
+        let dim = self.config.dim;
+        let mut buffers = Buffers { hidden: vec![0.0; dim], target: vec![0.0; dim], gradient: vec![0.0; dim], rows: vec![] };
+        let mut unreported = 0;
+        let mut learning_rate = self.learning_rate();
+        for sentence in sentences {
+            unreported += sentence.len() as u64;
+            if unreported >= PROGRESS_INTERVAL {
+                self.processed.fetch_add(unreported, Ordering::Relaxed);
+                unreported = 0;
+                learning_rate = self.learning_rate();
+            }
+
+            let words = sentence.iter().copied()
+                .filter(|&w| self.keep[w] >= 1.0 || self.keep[w] > rng.gen::<f32>())
+                .collect::<Vec<_>>();
+            for (position, &center) in words.iter().enumerate() {
+                let reach = rng.gen_range(1..=self.config.window);
+                let start = position.saturating_sub(reach);
+                let end = (position + reach).min(words.len() - 1);
+                let context = (start..=end).filter(|&p| p != position).map(|p| words[p]);
+                match self.config.architecture {
+                    Architecture::SkipGram => {
+                        for word in context {
+                            buffers.rows.clear();
+                            self.push_rows(word, &mut buffers.rows);
+                            self.step(center, learning_rate, rng, &mut buffers);
+                        }
+                    }
+                    Architecture::Cbow => {
+                        buffers.rows.clear();
+                        for word in context {
+                            self.push_rows(word, &mut buffers.rows);
+                        }
+                        if !buffers.rows.is_empty() {
+                            self.step(center, learning_rate, rng, &mut buffers);
+                        }
+                    }
+                }
+            }
+        }
+        self.processed.fetch_add(unreported, Ordering::Relaxed);
+    }
+
+    fn push_rows(&self, word: usize, rows: &mut Vec<usize>) {
+        match self.subwords {
+            Some(subwords) => rows.extend(&subwords[word]),
+            None => rows.push(word),
+        }
+    }
+
+    // Predicts `center` from the average of the input rows in `buffers.rows`. Like the
+    // reference implementation, every row then gets the full gradient of the average.
+    fn step(&self, center: usize, learning_rate: f32, rng: &mut StdRng, buffers: &mut Buffers) {
+        buffers.hidden.fill(0.0);
+        for &row in &buffers.rows {
+            self.input.read_row(row, &mut buffers.target);
+            for (h, t) in buffers.hidden.iter_mut().zip(&buffers.target) {
+                *h += t;
+            }
+        }
+        let scale = 1.0 / buffers.rows.len() as f32;
+        buffers.hidden.iter_mut().for_each(|h| *h *= scale);
+        self.predict(center, learning_rate, rng, buffers);
+        for &row in &buffers.rows {
+            self.input.add_to_row(row, 1.0, &buffers.gradient);
+        }
+    }
+
+    // Scores `buffers.hidden` against `center` with the configured objective, updates the
+    // output vectors involved and leaves the gradient for the hidden vector (already
+    // scaled by the learning rate) in `buffers.gradient`.
+    fn predict(&self, center: usize, learning_rate: f32, rng: &mut StdRng, buffers: &mut Buffers) {
+        buffers.gradient.fill(0.0);
+        if let Some(tree) = &self.tree {
+            for (&node, &branch) in tree.points(center).iter().zip(tree.code(center)) {
+                // branch 0 is the positive class, as in the reference implementation
+                self.binary_step(node, 1.0 - branch as f32, learning_rate, buffers);
+            }
+        } else if let Some(table) = &self.table {
+            self.binary_step(center, 1.0, learning_rate, buffers);
+            for _ in 0..self.config.negative {
+                let other = table.sample(rng);
+                if other != center {
+                    self.binary_step(other, 0.0, learning_rate, buffers);
+                }
+            }
+        }
+    }
+
+    // one logistic regression step of the hidden vector against output row `row`
+    fn binary_step(&self, row: usize, label: f32, learning_rate: f32, buffers: &mut Buffers) {
+        self.output.read_row(row, &mut buffers.target);
+        let g = (label - sigmoid(dot(&buffers.hidden, &buffers.target))) * learning_rate;
+        for (acc, t) in buffers.gradient.iter_mut().zip(&buffers.target) {
+            *acc += g * t;
+        }
+        self.output.add_to_row(row, g, &buffers.hidden);
+    }
+}
+
+// per-thread scratch space, allocated once
+struct Buffers {
+    hidden: Vec<f32>,
+    target: Vec<f32>,
+    gradient: Vec<f32>,
+    rows: Vec<usize>,
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// clamped like the reference implementation's lookup table, beyond which the
+// gradient is effectively zero anyway
+pub(crate) fn sigmoid(x: f32) -> f32 {
+    if x > 6.0 {
+        1.0
+    } else if x < -6.0 {
+        0.0
+    } else {
+        1.0 / (1.0 + (-x).exp())
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The words of a corpus with their counts, most frequent first.
+///
+/// Ids are positions in that order, so id 0 is the most frequent word; the negative
+/// sampling table and the Huffman tree both rely on it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vocabulary {
+    words: Vec<String>,
+    counts: Vec<u64>,
+    index: HashMap<String, usize>,
+}
+
+impl Vocabulary {
+    /// Counts every token and keeps the words seen at least `min_count` times.
+    pub fn build<'a, S, T>(sentences: S, min_count: u64) -> Vocabulary
+    where
+        S: IntoIterator<Item = T>,
+        T: IntoIterator<Item = &'a str>,
+    {
+        let mut builder = VocabularyBuilder::new(min_count);
+        for sentence in sentences {
+            builder.add_sentence(sentence);
+        }
+        builder.build()
+    }
+
+    /// A vocabulary from precomputed counts, keeping the words with at least `min_count`.
+    pub fn from_counts<I: IntoIterator<Item = (String, u64)>>(counts: I, min_count: u64) -> Vocabulary {
+        let mut entries = counts.into_iter().filter(|(_, count)| *count >= min_count).collect::<Vec<_>>();
+        // ties are broken alphabetically so the ids don't depend on hash order
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let index = entries.iter().enumerate().map(|(id, (word, _))| (word.clone(), id)).collect();
+        let (words, counts) = entries.into_iter().unzip();
+        Vocabulary { words, counts, index }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn id(&self, word: &str) -> Option<usize> {
+        self.index.get(word).copied()
+    }
+
+    pub fn word(&self, id: usize) -> &str {
+        &self.words[id]
+    }
+
+    pub fn count(&self, id: usize) -> u64 {
+        self.counts[id]
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The number of corpus tokens covered by the vocabulary.
+    pub fn total_count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Keeps only the `max_size` most frequent words.
+    pub fn truncate(&mut self, max_size: usize) {
+        for word in self.words.iter().skip(max_size) {
+            self.index.remove(word);
+        }
+        self.words.truncate(max_size);
+        self.counts.truncate(max_size);
+    }
+
+    /// Writes one "<word> <count>" line per word, most frequent first, like the reference
+    /// tool's -save-vocab.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for (word, count) in self.words.iter().zip(&self.counts) {
+            writeln!(out, "{} {}", word, count)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a vocabulary written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Vocabulary> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Vocabulary> {
+        let mut counts = vec![];
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = line.rsplit_once(' ')
+                .and_then(|(word, count)| Some((word.to_string(), count.trim().parse::<u64>().ok()?)));
+            match parsed {
+                Some(entry) if !entry.0.is_empty() => counts.push(entry),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("line {}: expected \"<word> <count>\"", number + 1))),
+            }
+        }
+        Ok(Self::from_counts(counts, 0))
+    }
+
+    /// Maps a sentence to ids, dropping out-of-vocabulary words.
+    pub fn encode<'a, T: IntoIterator<Item = &'a str>>(&self, tokens: T) -> Vec<usize> {
+        tokens.into_iter().filter_map(|token| self.id(token)).collect()
+    }
+}
+
+/// Counts tokens from a stream of sentences and builds a `Vocabulary`, so a corpus can be
+/// counted without holding it in memory.
+///
+/// With `with_max_entries`, the word table is kept under a size limit the way the
+/// reference tool does it: whenever it fills up, words seen at most once are dropped, then
+/// at most twice the next time, and so on. Counts of words that survive are exact from the
+/// point they were last re-added, so only rare words are affected.
+#[derive(Debug, Clone)]
+pub struct VocabularyBuilder {
+    counts: HashMap<String, u64>,
+    min_count: u64,
+    max_size: Option<usize>,
+    max_entries: Option<usize>,
+    min_reduce: u64,
+}
+
+impl VocabularyBuilder {
+    pub fn new(min_count: u64) -> VocabularyBuilder {
+        VocabularyBuilder { counts: HashMap::new(), min_count, max_size: None, max_entries: None, min_reduce: 1 }
+    }
+
+    /// Keeps at most `max_size` words, the most frequent ones.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Bounds the number of distinct words held while counting.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        assert!(max_entries > 0, "the word table needs room for at least one word");
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn add(&mut self, token: &str) {
+        match self.counts.get_mut(token) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(token.to_string(), 1);
+                if self.max_entries.is_some_and(|max| self.counts.len() > max) {
+                    self.reduce();
+                }
+            }
+        }
+    }
+
+    pub fn add_sentence<'a, T: IntoIterator<Item = &'a str>>(&mut self, tokens: T) {
+        for token in tokens {
+            self.add(token);
+        }
+    }
+
+    /// The number of distinct words currently counted.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    fn reduce(&mut self) {
+        while self.max_entries.is_some_and(|max| self.counts.len() > max) {
+            let min_reduce = self.min_reduce;
+            self.counts.retain(|_, count| *count > min_reduce);
+            self.min_reduce += 1;
+        }
+    }
+
+    pub fn build(self) -> Vocabulary {
+        let mut vocab = Vocabulary::from_counts(self.counts, self.min_count);
+        if let Some(max_size) = self.max_size {
+            vocab.truncate(max_size);
+        }
+        vocab
+    }
+}
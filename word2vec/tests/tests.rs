@@ -0,0 +1,950 @@
+mod vocab_tests {
+    use word2vec::vocab::{Vocabulary, VocabularyBuilder};
+
+    #[test]
+    fn test_build_orders_by_frequency() {
+        let sentences = [vec!["the", "cat", "sat"], vec!["the", "dog", "sat"], vec!["the", "end"]];
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().copied()), 2);
+        assert_eq!(vocab.words(), &["the".to_string(), "sat".to_string()]);
+        assert_eq!(vocab.counts(), &[3, 2]);
+        assert_eq!(vocab.id("sat"), Some(1));
+        assert_eq!(vocab.id("cat"), None);
+        assert_eq!(vocab.total_count(), 5);
+        assert_eq!(vocab.encode(["the", "cat", "sat"]), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_max_size_and_truncate() {
+        let mut builder = VocabularyBuilder::new(1).with_max_size(2);
+        builder.add_sentence("a b b c c c d d d d".split(' '));
+        let vocab = builder.build();
+        assert_eq!(vocab.words(), &["d".to_string(), "c".to_string()]);
+
+        let mut vocab = Vocabulary::build(["a b b c c c".split(' ')], 1);
+        vocab.truncate(1);
+        assert_eq!(vocab.len(), 1);
+        assert_eq!(vocab.id("b"), None);
+        assert_eq!(vocab.id("c"), Some(0));
+    }
+
+    #[test]
+    fn test_bounded_counting_drops_rare_words_first() {
+        let mut builder = VocabularyBuilder::new(1).with_max_entries(3);
+        for _ in 0..10 {
+            builder.add_sentence(["the", "of"]);
+        }
+        builder.add_sentence("w1 w2 w3 w4 w5".split(' '));
+        assert!(builder.len() <= 3);
+        let vocab = builder.build();
+        assert_eq!(vocab.count(0), 10);
+        assert_eq!(vocab.count(1), 10);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let vocab = Vocabulary::build(["the cat sat on the mat".split(' ')], 1);
+        let path = std::env::temp_dir().join(format!("word2vec_vocab_{}.txt", std::process::id()));
+        vocab.save(&path).unwrap();
+        let loaded = Vocabulary::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, vocab);
+
+        let bad = Vocabulary::read_from("the 3\ncat three\n".as_bytes());
+        assert_eq!(bad.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+mod tokenize_tests {
+    use word2vec::tokenize::{Segmentation, Tokenizer};
+
+    #[test]
+    fn test_whitespace() {
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize("  The dog,  barked. "), vec!["The", "dog,", "barked."]);
+        let tokenizer = tokenizer.with_lowercase(true).with_strip_punctuation(true);
+        assert_eq!(tokenizer.tokenize("\"The dog, barked!\" -- Ünïcode"), vec!["the", "dog", "barked", "ünïcode"]);
+    }
+
+    #[test]
+    fn test_unicode_segmentation() {
+        let tokenizer = Tokenizer::new(Segmentation::Unicode);
+        assert_eq!(
+            tokenizer.tokenize("Don't pay $1,000.50 for state-of-the-art (café) tools!"),
+            vec!["Don't", "pay", "$", "1,000.50", "for", "state-of-the-art", "(", "café", ")", "tools", "!"]
+        );
+        assert_eq!(tokenizer.tokenize("end. Start"), vec!["end", ".", "Start"]);
+        assert_eq!(tokenizer.tokenize("我爱NLP"), vec!["我", "爱", "NLP"]);
+
+        let tokenizer = tokenizer.with_lowercase(true).with_strip_punctuation(true);
+        assert_eq!(tokenizer.tokenize("Hello, WORLD -- 'quoted'"), vec!["hello", "world", "quoted"]);
+    }
+}
+
+mod phrases_tests {
+    use word2vec::phrases::PhraseDetector;
+
+    fn tokens(text: &str) -> Vec<String> {
+        text.split(' ').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_detects_collocations() {
+        let mut detector = PhraseDetector::new(2, 1.0);
+        let fillers = ["the", "a", "big", "city", "visit", "love", "in", "of"];
+        for i in 0..40 {
+            let sentence = format!("{} new york {} {}", fillers[i % 8], fillers[(i + 3) % 8], fillers[(i + 5) % 8]);
+            detector.add_sentence(&tokens(&sentence));
+        }
+        assert!(detector.score("new", "york") > detector.score("the", "new"));
+        let phrases = detector.finish();
+        assert!(phrases.contains("new", "york"));
+        assert!(!phrases.contains("york", "new"));
+        assert!(phrases.joined().contains(&"new_york".to_string()));
+        assert_eq!(phrases.apply(tokens("i love new york city")), tokens("i love new_york city"));
+    }
+
+    #[test]
+    fn test_rare_bigrams_are_not_phrases() {
+        let mut detector = PhraseDetector::new(5, 0.0);
+        detector.add_sentence(&tokens("rare pair rare pair"));
+        assert_eq!(detector.score("rare", "pair"), 0.0);
+        assert!(detector.finish().is_empty());
+    }
+
+    #[test]
+    fn test_max_entries_prunes_rare_counts() {
+        let mut detector = PhraseDetector::new(2, 1.0).with_max_entries(20);
+        for i in 0..200 {
+            detector.add_sentence(&tokens(&format!("new york word{} other{}", i, i)));
+            assert!(detector.len() <= 20 + 7, "{}", detector.len());
+        }
+        // the rare words are gone, the phrase that repeats is still found
+        assert_eq!(detector.score("word7", "other7"), 0.0);
+        assert!(detector.finish().contains("new", "york"));
+    }
+}
+
+mod corpus_tests {
+    use word2vec::corpus::*;
+    use word2vec::phrases::PhraseDetector;
+    use word2vec::tokenize::{Segmentation, Tokenizer};
+    use word2vec::vocab::Vocabulary;
+
+    #[test]
+    fn test_stream_tokenizes_and_splits_long_lines() {
+        let long = vec!["w"; MAX_SENTENCE_LENGTH + 5].join(" ");
+        let text = format!("Hello, world!\n\n{}\n", long);
+        let tokenizer = Tokenizer::new(Segmentation::Unicode).with_lowercase(true).with_strip_punctuation(true);
+        let sentences = SentenceStream::new(text.as_bytes(), tokenizer).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(sentences.len(), 3);
+        assert_eq!(sentences[0], vec!["hello", "world"]);
+        assert_eq!(sentences[1].len(), MAX_SENTENCE_LENGTH);
+        assert_eq!(sentences[2].len(), 5);
+    }
+
+    #[test]
+    fn test_stream_with_phrases_and_encode() {
+        let text = "new york is big\nnew york is old\nnew shoes\n";
+        let mut detector = PhraseDetector::new(1, 0.1);
+        for sentence in SentenceStream::new(text.as_bytes(), Tokenizer::default()) {
+            detector.add_sentence(&sentence.unwrap());
+        }
+        let phrases = detector.finish();
+        let stream = || SentenceStream::new(text.as_bytes(), Tokenizer::default()).with_phrases(phrases.clone());
+        let vocab = Vocabulary::build(stream().map(|s| s.unwrap()).collect::<Vec<_>>().iter().map(|s| s.iter().map(String::as_str)), 2);
+        assert_eq!(vocab.words(), &["is".to_string(), "new_york".to_string()]);
+        assert_eq!(encode_stream(stream(), &vocab).unwrap(), vec![vec![1, 0], vec![1, 0]]);
+    }
+}
+
+mod sampling_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use word2vec::sampling::*;
+    use word2vec::vocab::Vocabulary;
+
+    fn vocab() -> Vocabulary {
+        Vocabulary::from_counts(vec![("a".to_string(), 1000), ("b".to_string(), 100), ("c".to_string(), 10)], 1)
+    }
+
+    #[test]
+    fn test_unigram_table_follows_three_quarter_power() {
+        let table = UnigramTable::new(&vocab(), 100_000);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut hits = [0usize; 3];
+        for _ in 0..200_000 {
+            hits[table.sample(&mut rng)] += 1;
+        }
+        let weights = [1000f64.powf(0.75), 100f64.powf(0.75), 10f64.powf(0.75)];
+        let total: f64 = weights.iter().sum();
+        for (h, w) in hits.iter().zip(weights) {
+            assert!((*h as f64 / 200_000.0 - w / total).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_subsampling_keeps_rare_words() {
+        let keep = keep_probabilities(&vocab(), 0.01);
+        // f(a) = 1000 / 1110, so keep = sqrt(t / f) + t / f
+        let ratio = 0.01f64 * 1110.0 / 1000.0;
+        assert!((keep[0] as f64 - (ratio.sqrt() + ratio)).abs() < 1e-6);
+        assert!(keep[0] < keep[1]);
+        assert_eq!(keep[2], 1.0);
+        assert!(keep_probabilities(&vocab(), 0.0).iter().all(|&p| p == 1.0));
+    }
+}
+
+mod train_tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use word2vec::model::Word2Vec;
+    use word2vec::train::{train, Architecture, Objective, TrainConfig};
+    use word2vec::vocab::Vocabulary;
+
+    // sentences drawn from one of two disjoint topics
+    pub fn topic_corpus() -> Vec<Vec<String>> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..400).map(|i| {
+            let topic = if i % 2 == 0 { "a" } else { "b" };
+            (0..12).map(|_| format!("{}{}", topic, rng.gen_range(0..6))).collect()
+        }).collect()
+    }
+
+    pub fn cosine(a: ndarray::ArrayView1<f32>, b: ndarray::ArrayView1<f32>) -> f32 {
+        a.dot(&b) / (a.dot(&a).sqrt() * b.dot(&b).sqrt())
+    }
+
+    // mean cosine similarity within a topic and across topics
+    fn topic_similarity(model: &Word2Vec) -> (f32, f32) {
+        let (mut same, mut different) = (0.0, 0.0);
+        for i in 0..6 {
+            for j in 0..6 {
+                let a = model.vector(&format!("a{}", i)).unwrap();
+                if i != j {
+                    same += cosine(a, model.vector(&format!("a{}", j)).unwrap());
+                }
+                different += cosine(a, model.vector(&format!("b{}", j)).unwrap());
+            }
+        }
+        (same / 30.0, different / 36.0)
+    }
+
+    fn train_topics(config: TrainConfig) -> Word2Vec {
+        let sentences = topic_corpus();
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().map(String::as_str)), 1);
+        let corpus = word2vec::corpus::encode_sentences(&sentences, &vocab);
+        train(&corpus, vocab, &config)
+    }
+
+    #[test]
+    fn test_skip_gram_separates_topics() {
+        let sentences = topic_corpus();
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().map(String::as_str)), 1);
+        let corpus = word2vec::corpus::encode_sentences(&sentences, &vocab);
+        let config = TrainConfig { dim: 16, epochs: 5, sample: 0.0, threads: 1, table_size: 100_000, seed: Some(3), ..TrainConfig::default() };
+        let model = train(&corpus, vocab, &config);
+        assert_eq!(model.vocab().len(), 12);
+
+        let (same, different) = topic_similarity(&model);
+        assert!(same > different + 0.5, "{} vs {}", same, different);
+
+        // one thread with a seed is reproducible
+        let again = train(&corpus, model.vocab().clone(), &config);
+        assert_eq!(again.embeddings().vectors(), model.embeddings().vectors());
+    }
+
+    #[test]
+    fn test_hogwild_threads() {
+        let sentences = topic_corpus();
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().map(String::as_str)), 1);
+        let corpus = word2vec::corpus::encode_sentences(&sentences, &vocab);
+        let config = TrainConfig { dim: 16, epochs: 5, sample: 0.0, threads: 4, table_size: 100_000, seed: Some(3), ..TrainConfig::default() };
+        let model = train(&corpus, vocab, &config);
+        let near = cosine(model.vector("a0").unwrap(), model.vector("a1").unwrap());
+        let far = cosine(model.vector("a0").unwrap(), model.vector("b1").unwrap());
+        assert!(near > far);
+    }
+
+    #[test]
+    fn test_cbow_separates_topics() {
+        let model = train_topics(TrainConfig {
+            architecture: Architecture::Cbow, dim: 16, epochs: 5, sample: 0.0, learning_rate: 0.05,
+            threads: 1, table_size: 100_000, seed: Some(3), ..TrainConfig::default()
+        });
+        let (same, different) = topic_similarity(&model);
+        assert!(same > different + 0.5, "{} vs {}", same, different);
+    }
+
+    #[test]
+    fn test_hierarchical_softmax_separates_topics() {
+        for architecture in [Architecture::SkipGram, Architecture::Cbow] {
+            let model = train_topics(TrainConfig {
+                architecture, objective: Objective::HierarchicalSoftmax, dim: 16, epochs: 5, sample: 0.0,
+                learning_rate: 0.05, threads: 1, seed: Some(3), ..TrainConfig::default()
+            });
+            // one output vector per inner node of the Huffman tree
+            assert_eq!(model.output_embeddings().vocabulary(), 11);
+            let (same, different) = topic_similarity(&model);
+            assert!(same > different + 0.5, "{}: {} vs {}", architecture, same, different);
+        }
+    }
+
+    #[test]
+    fn test_parse_modes() {
+        assert_eq!("cbow".parse::<Architecture>(), Ok(Architecture::Cbow));
+        assert_eq!("skip-gram".parse::<Architecture>(), Ok(Architecture::SkipGram));
+        assert_eq!("hs".parse::<Objective>(), Ok(Objective::HierarchicalSoftmax));
+        assert_eq!(Objective::NegativeSampling.to_string().parse::<Objective>(), Ok(Objective::NegativeSampling));
+        assert!("glove".parse::<Architecture>().is_err());
+    }
+}
+
+mod huffman_tests {
+    use word2vec::huffman::HuffmanTree;
+
+    #[test]
+    fn test_codes_are_prefix_free_and_complete() {
+        let counts = [50, 20, 20, 9, 5, 3, 2, 1];
+        let tree = HuffmanTree::new(&counts);
+        assert_eq!(tree.inner_nodes(), 7);
+        for a in 0..counts.len() {
+            assert_eq!(tree.code(a).len(), tree.points(a).len());
+            // every path starts at the root, the last inner node created
+            assert_eq!(tree.points(a)[0], 6);
+            for b in 0..counts.len() {
+                if a != b {
+                    assert!(!tree.code(b).starts_with(tree.code(a)), "{} is a prefix of {}", a, b);
+                }
+            }
+        }
+        // a full binary tree satisfies Kraft's inequality with equality
+        let kraft: f64 = (0..counts.len()).map(|w| 0.5f64.powi(tree.code(w).len() as i32)).sum();
+        assert!((kraft - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_frequent_words_get_shorter_codes() {
+        let counts = [100, 40, 30, 10, 5, 5, 1, 1];
+        let tree = HuffmanTree::new(&counts);
+        for w in 1..counts.len() {
+            assert!(tree.code(w - 1).len() <= tree.code(w).len());
+        }
+        // optimal weighted path length for these counts
+        let cost: u64 = counts.iter().enumerate().map(|(w, c)| c * tree.code(w).len() as u64).sum();
+        assert_eq!(cost, 379);
+    }
+
+    #[test]
+    fn test_tiny_vocabularies() {
+        assert_eq!(HuffmanTree::new(&[3]).inner_nodes(), 0);
+        let tree = HuffmanTree::new(&[3, 1]);
+        assert_eq!((tree.code(0), tree.code(1)), (&[1u8][..], &[0u8][..]));
+    }
+}
+
+mod format_tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+    use ndarray::array;
+    use word2vec::format::{Format, VectorReader};
+    use word2vec::keyed_vectors::KeyedVectors;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("word2vec_{}_{}", std::process::id(), name))
+    }
+
+    fn write_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = temp_path(name);
+        std::fs::File::create(&path).unwrap().write_all(bytes).unwrap();
+        path
+    }
+
+    fn vectors() -> KeyedVectors {
+        let words = ["the", "köln", "new_york"].iter().map(|w| w.to_string()).collect();
+        KeyedVectors::new(words, array![[0.1, -2.5, 3.0], [1e-7, 0.0, -0.333_333_34], [4.25, 5.5, -6.0]])
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        for format in [Format::Word2VecText, Format::Word2VecBinary, Format::GloveText] {
+            let path = temp_path(&format!("round_trip.{}", format));
+            vectors().save(&path, format).unwrap();
+            let loaded = KeyedVectors::load(&path, format).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded, vectors(), "{}", format);
+            assert_eq!(loaded.vector("köln").unwrap(), vectors().vector("köln").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("bin".parse::<Format>(), Ok(Format::Word2VecBinary));
+        assert_eq!("glove".parse::<Format>(), Ok(Format::GloveText));
+        assert_eq!(Format::from_path("vectors.bin"), Format::Word2VecBinary);
+        assert_eq!(Format::from_path("vectors.txt"), Format::Word2VecText);
+        assert!("csv".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_text_files_from_other_tools() {
+        // trailing spaces as the reference tool writes them, CRLF line endings, and a GloVe
+        // token containing a space
+        let path = write_file("other.txt", b"2 2\r\nfoo 1 2 \r\nbar 3 4 \r\n");
+        let loaded = KeyedVectors::load(&path, Format::Word2VecText).unwrap();
+        assert_eq!(loaded.vector("bar").unwrap(), array![3.0f32, 4.0]);
+        std::fs::remove_file(&path).unwrap();
+
+        let path = write_file("glove.txt", b"the 0.5 -1\n. . 2 3\n");
+        let reader = VectorReader::open(&path, Format::GloveText).unwrap();
+        assert_eq!((reader.dim(), reader.header_count()), (2, None));
+        let loaded = KeyedVectors::from_entries(reader).unwrap();
+        assert_eq!(loaded.words(), &["the".to_string(), ". .".to_string()]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_non_utf8_words() {
+        let mut bytes = b"2 1\n".to_vec();
+        for word in [&b"ok"[..], &b"bad\xff\xfe"[..]] {
+            bytes.extend(word);
+            bytes.push(b' ');
+            bytes.extend(1.5f32.to_le_bytes());
+            bytes.push(b'\n');
+        }
+        let path = write_file("non_utf8.bin", &bytes);
+        let loaded = KeyedVectors::load(&path, Format::Word2VecBinary).unwrap();
+        assert_eq!(loaded.word(1), "bad\u{fffd}\u{fffd}");
+
+        let strict = VectorReader::open(&path, Format::Word2VecBinary).unwrap().with_strict_utf8(true);
+        let error = KeyedVectors::from_entries(strict).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_streaming_and_malformed_files() {
+        let path = temp_path("stream.bin");
+        vectors().save(&path, Format::Word2VecBinary).unwrap();
+        // only the first entries are copied out of the map
+        let first = KeyedVectors::from_entries(VectorReader::open(&path, Format::Word2VecBinary).unwrap().take(2)).unwrap();
+        assert_eq!(first.len(), 2);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 3);
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(KeyedVectors::load(&path, Format::Word2VecBinary).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        let path = write_file("short.txt", b"3 2\na 1 2\nb 3 4\n");
+        assert!(KeyedVectors::load(&path, Format::Word2VecText).is_err());
+        std::fs::write(&path, b"2 2\na 1 2\nb 3\n").unwrap();
+        assert!(KeyedVectors::load(&path, Format::Word2VecText).is_err());
+        std::fs::write(&path, b"not a header\n").unwrap();
+        assert!(VectorReader::open(&path, Format::Word2VecText).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+mod query_tests {
+    use ndarray::array;
+    use word2vec::keyed_vectors::{KeyedVectors, UnknownWord};
+
+    // axes: royalty, male, female, fruit
+    pub fn toy_vectors() -> KeyedVectors {
+        let words = ["king", "queen", "man", "woman", "apple", "banana", "prince"].iter().map(|w| w.to_string()).collect();
+        KeyedVectors::new(words, array![
+            [1.0, 1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 0.1],
+            [0.0, 0.0, 1.0, 0.1],
+            [0.0, 0.0, 0.0, 1.0],
+            [0.2, 0.0, 0.0, 1.0],
+            [0.9, 1.0, 0.0, 0.1],
+        ])
+    }
+
+    #[test]
+    fn test_most_similar() {
+        let vectors = toy_vectors();
+        let similar = vectors.most_similar("king", 2).unwrap();
+        assert_eq!(similar[0].0, "prince");
+        assert!(similar[0].1 > similar[1].1);
+        assert!(similar.iter().all(|(w, _)| *w != "king"));
+        assert_eq!(vectors.most_similar("king", 100).unwrap().len(), 6);
+        assert!((vectors.similarity("apple", "apple").unwrap() - 1.0).abs() < 1e-6);
+        assert_eq!(vectors.most_similar("emperor", 3).unwrap_err(), UnknownWord("emperor".to_string()));
+        assert_eq!(vectors.similar_by_vector(array![0.0, 0.0, 0.0, 2.0].view(), 1)[0].0, "apple");
+    }
+
+    #[test]
+    fn test_analogies() {
+        let vectors = toy_vectors();
+        let add = vectors.analogy(&["king", "woman"], &["man"], 1, None).unwrap();
+        assert_eq!(add[0].0, "queen");
+        let mul = vectors.analogy_cos_mul(&["king", "woman"], &["man"], 1, None).unwrap();
+        assert_eq!(mul[0].0, "queen");
+        // with only king and queen as candidates and king a query word, queen is all that is left
+        assert_eq!(vectors.analogy(&["king", "woman"], &["man"], 5, Some(2)).unwrap(), vec![("queen", add[0].1)]);
+        assert!(vectors.analogy(&["king"], &["duke"], 1, None).is_err());
+    }
+
+    #[test]
+    fn test_doesnt_match() {
+        let vectors = toy_vectors();
+        assert_eq!(vectors.doesnt_match(&["king", "queen", "man", "apple"]).unwrap(), Some("apple"));
+        assert_eq!(vectors.doesnt_match(&["apple", "banana", "prince"]).unwrap(), Some("prince"));
+        assert_eq!(vectors.doesnt_match(&[]).unwrap(), None);
+    }
+}
+
+mod evaluate_tests {
+    use word2vec::evaluate::*;
+    use super::query_tests::toy_vectors;
+
+    #[test]
+    fn test_spearman_and_pearson() {
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0];
+        // ties share their average rank, as in scipy.stats.spearmanr
+        assert!((spearman(&x, &[5.0, 6.0, 7.0, 8.0, 7.0]) - 0.820_782_681_668_123_3).abs() < 1e-12);
+        assert!((spearman(&x, &[1.0, 4.0, 9.0, 16.0, 25.0]) - 1.0).abs() < 1e-12);
+        assert!((pearson(&x, &[10.0, 8.0, 6.0, 4.0, 2.0]) + 1.0).abs() < 1e-12);
+        assert_eq!(pearson(&x, &[1.0; 5]), 0.0);
+    }
+
+    #[test]
+    fn test_analogy_benchmark() {
+        let path = std::env::temp_dir().join(format!("word2vec_analogies_{}.txt", std::process::id()));
+        std::fs::write(&path, ": royalty\nMan King Woman Queen\nman woman king queen\n: other\nman king duke duchess\n").unwrap();
+        let questions = read_analogies(&path, true).unwrap();
+        std::fs::write(&path, "a b c\n").unwrap();
+        assert!(read_analogies(&path, true).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(questions.len(), 3);
+        assert_eq!(questions[0].section, "royalty");
+
+        for method in [AnalogyMethod::CosAdd, AnalogyMethod::CosMul] {
+            let report = evaluate_analogies(&toy_vectors(), &questions, method, None);
+            assert_eq!((report.correct, report.answered, report.skipped), (2, 2, 1));
+            assert_eq!(report.sections, vec![("royalty".to_string(), 2, 2)]);
+            assert_eq!(report.accuracy(), 1.0);
+            assert!(report.to_string().ends_with("total: 100.00% (2/2), 1 skipped"));
+        }
+        // questions mentioning words outside the restricted vocabulary are skipped
+        let report = evaluate_analogies(&toy_vectors(), &questions, AnalogyMethod::CosAdd, Some(3));
+        assert_eq!(report.skipped, 3);
+    }
+
+    #[test]
+    fn test_word_similarity_benchmark() {
+        let path = std::env::temp_dir().join(format!("word2vec_wordsim_{}.csv", std::process::id()));
+        std::fs::write(&path, "Word 1,Word 2,Human (mean)\nking,prince,9.1\nking,apple,1.2\napple,banana,8.0\nqueen,woman,7.5\nKing,Emperor,9.0\n").unwrap();
+        let pairs = read_word_pairs(&path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pairs[0], ("king".to_string(), "prince".to_string(), 9.1));
+        let report = evaluate_word_pairs(&toy_vectors(), &pairs);
+        assert_eq!((report.pairs, report.skipped), (4, 1));
+        assert!(report.spearman > 0.7, "{}", report);
+    }
+}
+
+mod hnsw_tests {
+    use ndarray::Array2;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use word2vec::hnsw::{HnswConfig, HnswIndex};
+    use word2vec::keyed_vectors::KeyedVectors;
+
+    fn random_vectors(count: usize, dim: usize) -> KeyedVectors {
+        let mut rng = StdRng::seed_from_u64(11);
+        let vectors = Array2::from_shape_fn((count, dim), |_| rng.gen_range(-1.0..1.0f32));
+        KeyedVectors::new((0..count).map(|i| format!("w{}", i)).collect(), vectors)
+    }
+
+    // the share of the exact top k found by the index, averaged over queries
+    fn recall(index: &HnswIndex, vectors: &KeyedVectors, k: usize) -> f64 {
+        let queries = (0..vectors.len()).step_by(20).collect::<Vec<_>>();
+        let mut found = 0;
+        for &id in &queries {
+            let word = vectors.word(id);
+            let exact = vectors.most_similar(word, k).unwrap();
+            let approximate = index.most_similar(vectors, word, k).unwrap();
+            found += approximate.iter().filter(|(w, _)| exact.iter().any(|(e, _)| e == w)).count();
+        }
+        found as f64 / (queries.len() * k) as f64
+    }
+
+    #[test]
+    fn test_recall_follows_ef_search() {
+        let vectors = random_vectors(2000, 16);
+        let config = HnswConfig { m: 8, ef_construction: 100, ef_search: 10, seed: Some(5) };
+        let mut index = HnswIndex::build(vectors.vectors(), &config);
+        assert_eq!(index.len(), 2000);
+        let low = recall(&index, &vectors, 10);
+        index.set_ef_search(200);
+        let high = recall(&index, &vectors, 10);
+        assert!(high >= 0.95, "recall {}", high);
+        assert!(high >= low, "{} < {}", high, low);
+
+        // similarities are exact cosines, sorted
+        let results = index.most_similar(&vectors, "w3", 5).unwrap();
+        for pair in results.windows(2) {
+            assert!(pair[0].1 >= pair[1].1);
+        }
+        let (word, similarity) = results[0];
+        assert!((similarity - vectors.similarity("w3", word).unwrap()).abs() < 1e-5);
+        assert!(index.most_similar(&vectors, "nope", 5).is_err());
+    }
+
+    #[test]
+    fn test_small_indexes_are_exact() {
+        let empty = HnswIndex::build(Array2::<f32>::zeros((0, 4)).view(), &HnswConfig::default());
+        assert!(empty.search(ndarray::array![1.0, 0.0, 0.0, 0.0].view(), 3).is_empty());
+
+        let vectors = random_vectors(30, 4);
+        let index = HnswIndex::build(vectors.vectors(), &HnswConfig { seed: Some(1), ..HnswConfig::default() });
+        for word in ["w0", "w7", "w29"] {
+            let exact = vectors.most_similar(word, 5).unwrap().into_iter().map(|(w, _)| w).collect::<Vec<_>>();
+            let approximate = index.most_similar(&vectors, word, 5).unwrap().into_iter().map(|(w, _)| w).collect::<Vec<_>>();
+            assert_eq!(approximate, exact);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let vectors = random_vectors(300, 8);
+        let index = HnswIndex::build(vectors.vectors(), &HnswConfig { seed: Some(2), ..HnswConfig::default() });
+        let path = std::env::temp_dir().join(format!("word2vec_hnsw_{}.idx", std::process::id()));
+        index.save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.most_similar(&vectors, "w1", 10).unwrap(), index.most_similar(&vectors, "w1", 10).unwrap());
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() / 2);
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(HnswIndex::load(&path).is_err());
+        std::fs::write(&path, b"RMLCKPT1").unwrap();
+        assert!(HnswIndex::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+mod ngram_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use word2vec::ngram::*;
+
+    const SMOOTHINGS: [Smoothing; 4] = [Smoothing::AddK(0.5), Smoothing::GoodTuring, Smoothing::KneserNey, Smoothing::Katz];
+
+    fn corpus() -> Vec<Vec<&'static str>> {
+        let mut sentences = vec![];
+        for _ in 0..10 {
+            sentences.push(vec!["i", "moved", "to", "san", "francisco"]);
+        }
+        for context in ["reading", "new", "my", "the"] {
+            sentences.push(vec!["i", "need", context, "glasses"]);
+        }
+        for sentence in ["the cat sat on the mat", "the dog sat on the log", "a cat saw a dog", "the cat saw the dog sat"] {
+            for _ in 0..3 {
+                sentences.push(sentence.split(' ').collect());
+            }
+        }
+        sentences
+    }
+
+    fn counts(order: usize) -> NgramCounts {
+        let mut counts = NgramCounts::new(order);
+        for sentence in corpus() {
+            counts.add_sentence(sentence);
+        }
+        counts
+    }
+
+    #[test]
+    fn test_counts() {
+        let counts = counts(3);
+        assert_eq!(counts.count(&["san", "francisco"]), 10);
+        assert_eq!(counts.count(&[SENTENCE_START, "i"]), 14);
+        assert_eq!(counts.count(&["francisco", SENTENCE_END]), 10);
+        assert_eq!(counts.count(&[SENTENCE_START]), 0);
+        assert_eq!(counts.count(&["a", "b", "c", "d"]), 0);
+    }
+
+    #[test]
+    fn test_distributions_sum_to_one() {
+        for smoothing in SMOOTHINGS {
+            let model = counts(3).estimate(smoothing);
+            let words = model.vocabulary().iter().filter(|w| *w != SENTENCE_START).cloned().collect::<Vec<_>>();
+            for context in [vec![], vec![SENTENCE_START], vec!["the", "cat"], vec!["san", "francisco"], vec!["glasses", "the"], vec!["unseen"]] {
+                let total: f64 = words.iter().map(|w| 10f64.powf(model.log10_prob(w, &context))).sum();
+                assert!((total - 1.0).abs() < 1e-6, "{:?} after {:?}: {}", smoothing, context, total);
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_k_and_continuation_counts() {
+        let counts = counts(2);
+        let add_one = counts.estimate(Smoothing::AddK(1.0));
+        // unigrams: (count + 1) / (tokens + |V|), where the vocabulary has every word seen,
+        // </s> and <unk>, but not <s>
+        let tokens = corpus().iter().map(|s| s.len() + 1).sum::<usize>() as f64;
+        let vocabulary = (add_one.vocabulary().len() - 1) as f64;
+        assert!((add_one.log10_prob("francisco", &[]) - (11.0 / (tokens + vocabulary)).log10()).abs() < 1e-6);
+        assert!((add_one.log10_prob("zebra", &[]) - (1.0 / (tokens + vocabulary)).log10()).abs() < 1e-6);
+
+        // francisco is frequent but only ever follows san, so Kneser-Ney considers it a
+        // worse guess after a new context than glasses, which follows four different words
+        let kneser_ney = counts.estimate(Smoothing::KneserNey);
+        assert!(add_one.log10_prob("francisco", &[]) > add_one.log10_prob("glasses", &[]));
+        assert!(kneser_ney.log10_prob("francisco", &[]) < kneser_ney.log10_prob("glasses", &[]));
+        assert!(kneser_ney.log10_prob("francisco", &["san"]) > -0.1);
+    }
+
+    #[test]
+    fn test_perplexity() {
+        for smoothing in SMOOTHINGS {
+            let model = counts(3).estimate(smoothing);
+            let seen = model.perplexity([["the", "cat", "sat", "on", "the", "mat"]]);
+            let scrambled = model.perplexity([["mat", "the", "on", "sat", "cat", "the"]]);
+            assert!(seen < scrambled, "{:?}: {} vs {}", smoothing, seen, scrambled);
+            assert!(model.perplexity([["zebra", "sat"]]).is_finite());
+        }
+        let model = counts(3).estimate(Smoothing::KneserNey);
+        let score = model.score_sentence(&["the", "cat", "sat"]);
+        assert_eq!((score.tokens, score.oovs), (4, 0));
+        let expected = model.log10_prob("the", &[SENTENCE_START])
+            + model.log10_prob("cat", &[SENTENCE_START, "the"])
+            + model.log10_prob("sat", &["the", "cat"])
+            + model.log10_prob(SENTENCE_END, &["cat", "sat"]);
+        assert!((score.log10_prob - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sampling() {
+        let model = counts(3).estimate(Smoothing::Katz);
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut sentences = vec![];
+        for _ in 0..50 {
+            let sentence = model.sample(&mut rng, 20);
+            assert!(sentence.len() <= 20);
+            assert!(sentence.iter().all(|w| model.vocabulary().contains(w) && w != UNKNOWN && w != SENTENCE_START));
+            sentences.push(sentence.join(" "));
+        }
+        assert!(sentences.iter().any(|s| s == "i moved to san francisco"));
+    }
+
+    #[test]
+    fn test_arpa_round_trip() {
+        for smoothing in SMOOTHINGS {
+            let model = counts(3).estimate(smoothing);
+            let mut arpa = vec![];
+            model.write_arpa(&mut arpa).unwrap();
+            let text = String::from_utf8(arpa.clone()).unwrap();
+            assert!(text.contains("\\data\\\nngram 1=") && text.contains("\\3-grams:") && text.trim_end().ends_with("\\end\\"));
+            assert!(text.contains("-99.0000000\t<s>"));
+
+            let loaded = NgramModel::read_arpa(arpa.as_slice()).unwrap();
+            assert_eq!(loaded.order(), 3);
+            assert_eq!(loaded.sizes(), model.sizes());
+            for (word, context) in [("cat", vec!["the"]), ("mat", vec!["sat", "on", "the"]), ("francisco", vec!["dog"]), ("zebra", vec![])] {
+                assert!((loaded.log10_prob(word, &context) - model.log10_prob(word, &context)).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_arpa() {
+        let valid = "\\data\\\nngram 1=2\n\n\\1-grams:\n-0.3\t</s>\n-0.3\ta\n\n\\end\\\n";
+        assert_eq!(NgramModel::read_arpa(valid.as_bytes()).unwrap().order(), 1);
+        for bad in [
+            "\\data\\\nngram 1=3\n\\1-grams:\n-0.3\t</s>\n-0.3\ta\n\\end\\\n",
+            "\\data\\\nngram 1=2\n\\1-grams:\n-0.3\t</s>\nhigh\ta\n\\end\\\n",
+            "\\data\\\nngram 1=1\nngram 2=1\n\\1-grams:\n-0.3\ta\n\\2-grams:\n-0.1\ta b\n\\end\\\n",
+            "-0.3\ta\n",
+        ] {
+            assert_eq!(NgramModel::read_arpa(bad.as_bytes()).unwrap_err().kind(), std::io::ErrorKind::InvalidData, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_sample_without_words_is_empty() {
+        let arpa = "\\data\\\nngram 1=2\n\n\\1-grams:\n-99\t<s>\n-0.3\t<unk>\n\n\\end\\\n";
+        let model = NgramModel::read_arpa(arpa.as_bytes()).unwrap();
+        assert!(model.sample(&mut StdRng::seed_from_u64(1), 5).is_empty());
+    }
+}
+
+mod glove_tests {
+    use word2vec::corpus::encode_sentences;
+    use word2vec::glove::{train, Cooccurrences, GloveConfig};
+    use word2vec::vocab::Vocabulary;
+    use super::train_tests::{cosine, topic_corpus};
+
+    #[test]
+    fn test_cooccurrences_weight_by_distance() {
+        let corpus = vec![vec![0, 1, 2]];
+        let symmetric = Cooccurrences::build(&corpus, 3, 2, true);
+        assert_eq!(symmetric.len(), 6);
+        assert_eq!(symmetric.get(1, 0), 1.0);
+        assert_eq!(symmetric.get(0, 1), 1.0);
+        assert_eq!(symmetric.get(2, 0), 0.5);
+        assert_eq!(symmetric.get(0, 0), 0.0);
+
+        let left = Cooccurrences::build(&corpus, 3, 1, false);
+        assert_eq!(left.entries(), &[(1, 0, 1.0), (2, 1, 1.0)]);
+    }
+
+    #[test]
+    fn test_separates_topics() {
+        let sentences = topic_corpus();
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().map(String::as_str)), 1);
+        let corpus = encode_sentences(&sentences, &vocab);
+        let config = GloveConfig { dim: 16, window: 5, epochs: 50, threads: 1, seed: Some(3), ..GloveConfig::default() };
+        let (vectors, losses) = train(&corpus, &vocab, &config);
+        assert_eq!((vectors.len(), vectors.dim()), (12, 16));
+        assert!(losses.last().unwrap() < &(losses[0] / 2.0), "{:?}", losses);
+
+        let near = cosine(vectors.vector("a0").unwrap(), vectors.vector("a1").unwrap());
+        let far = cosine(vectors.vector("a0").unwrap(), vectors.vector("b1").unwrap());
+        assert!(near > far + 0.3, "{} vs {}", near, far);
+
+        let again = train(&corpus, &vocab, &config).0;
+        assert_eq!(again.vectors(), vectors.vectors());
+    }
+}
+
+mod fasttext_tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use word2vec::corpus::encode_sentences;
+    use word2vec::fasttext::{hash, FastText, FastTextConfig, Subwords};
+    use word2vec::train::TrainConfig;
+    use word2vec::vocab::Vocabulary;
+    use super::train_tests::cosine;
+
+    #[test]
+    fn test_ngrams_and_hashing() {
+        let subwords = Subwords { min_n: 3, max_n: 3, buckets: 100 };
+        assert_eq!(subwords.ngrams("where"), vec!["<wh", "whe", "her", "ere", "re>"]);
+        assert_eq!(Subwords { min_n: 3, max_n: 4, buckets: 100 }.ngrams("ab"), vec!["<ab", "<ab>", "ab>"]);
+        assert!(subwords.buckets("where").iter().all(|&b| b < 100));
+        assert!(Subwords { buckets: 0, ..subwords }.buckets("where").is_empty());
+
+        assert_eq!(hash(""), 2_166_136_261);
+        assert_eq!(hash("a"), 0xe40c292c);
+        // bytes above 0x7f are sign-extended like C++ chars
+        assert_ne!(hash("é"), 0);
+    }
+
+    #[test]
+    fn test_out_of_vocabulary_vectors() {
+        // two topics whose words share a stem, so unseen words of a topic share its n-grams
+        let mut rng = StdRng::seed_from_u64(7);
+        let sentences = (0..400).map(|i| {
+            let stem = if i % 2 == 0 { "apple" } else { "zebra" };
+            (0..12).map(|_| format!("{}{}", stem, rng.gen_range(0..6))).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().map(String::as_str)), 1);
+        let corpus = encode_sentences(&sentences, &vocab);
+        let config = FastTextConfig {
+            train: TrainConfig { dim: 16, epochs: 5, sample: 0.0, threads: 1, table_size: 100_000, seed: Some(3), learning_rate: 0.05, ..TrainConfig::default() },
+            subwords: Subwords { buckets: 10_000, ..Subwords::default() },
+        };
+        let model = FastText::train(&corpus, vocab, &config);
+        assert_eq!(model.input_embeddings().vocabulary(), 12 + 10_000);
+
+        let unseen = model.vector("apple9");
+        let near = cosine(unseen.view(), model.vector("apple1").view());
+        let far = cosine(unseen.view(), model.vector("zebra1").view());
+        assert!(near > far + 0.3, "{} vs {}", near, far);
+        // too short for any n-gram
+        assert!(model.vector("").iter().all(|&v| v == 0.0));
+
+        let vectors = model.keyed_vectors();
+        assert_eq!(vectors.len(), 12);
+        assert_eq!(vectors.vector("apple1").unwrap(), model.vector("apple1"));
+    }
+}
+
+mod checkpoint_tests {
+    use std::cell::RefCell;
+    use std::time::Duration;
+    use word2vec::checkpoint::Checkpoint;
+    use word2vec::corpus::encode_sentences;
+    use word2vec::fasttext::{FastText, FastTextConfig, Subwords};
+    use word2vec::train::{train, train_with, Objective, TrainConfig, TrainOptions};
+    use word2vec::vocab::Vocabulary;
+    use super::train_tests::topic_corpus;
+
+    fn setup() -> (Vec<Vec<usize>>, Vocabulary) {
+        let sentences = topic_corpus();
+        let vocab = Vocabulary::build(sentences.iter().map(|s| s.iter().map(String::as_str)), 1);
+        (encode_sentences(&sentences, &vocab), vocab)
+    }
+
+    #[test]
+    fn test_resume_matches_uninterrupted_run() {
+        let (corpus, vocab) = setup();
+        let config = TrainConfig { dim: 8, epochs: 3, threads: 1, table_size: 10_000, seed: Some(5), objective: Objective::HierarchicalSoftmax, ..TrainConfig::default() };
+        let full = train(&corpus, vocab.clone(), &config);
+
+        let checkpoints = RefCell::new(vec![]);
+        let reports = RefCell::new(vec![]);
+        let options = TrainOptions::default()
+            .with_progress(Duration::from_secs(60), |p| reports.borrow_mut().push(*p))
+            .with_checkpoints(|c| {
+                let mut bytes = vec![];
+                c.write_to(&mut bytes)?;
+                checkpoints.borrow_mut().push(bytes);
+                Ok(())
+            });
+        let model = train_with(&corpus, vocab.clone(), &config, options).unwrap();
+        assert_eq!(model.embeddings().vectors(), full.embeddings().vectors());
+        // reported at the end of every epoch
+        let reports = reports.into_inner();
+        assert_eq!(reports.iter().map(|p| p.epoch).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(reports[2].words, reports[2].total_words);
+        assert!((reports[0].fraction() - 1.0 / 3.0).abs() < 1e-9);
+
+        let checkpoints = checkpoints.into_inner();
+        assert_eq!(checkpoints.len(), 3);
+        let checkpoint = Checkpoint::read_from(checkpoints[0].as_slice()).unwrap();
+        assert_eq!((checkpoint.epoch, &checkpoint.config, checkpoint.subwords), (1, &config, None));
+        assert_eq!(checkpoint.vocab, vocab);
+        let resumed = train_with(&corpus, vocab.clone(), &config, TrainOptions::default().resume_from(checkpoint)).unwrap();
+        assert_eq!(resumed.embeddings().vectors(), full.embeddings().vectors());
+        assert_eq!(resumed.output_embeddings().vectors(), full.output_embeddings().vectors());
+
+        // a checkpoint only resumes the configuration it came from
+        let checkpoint = Checkpoint::read_from(checkpoints[0].as_slice()).unwrap();
+        let other = TrainConfig { dim: 4, ..config.clone() };
+        assert!(train_with(&corpus, vocab, &other, TrainOptions::default().resume_from(checkpoint)).is_err());
+    }
+
+    #[test]
+    fn test_fasttext_checkpoint_files() {
+        let (corpus, vocab) = setup();
+        let config = FastTextConfig {
+            train: TrainConfig { dim: 8, epochs: 2, threads: 1, table_size: 10_000, seed: Some(5), ..TrainConfig::default() },
+            subwords: Subwords { buckets: 100, ..Subwords::default() },
+        };
+        let path = std::env::temp_dir().join(format!("word2vec_checkpoint_{}", std::process::id()));
+        let options = TrainOptions::default().with_checkpoints(|c| c.save(&path));
+        let model = FastText::train_with(&corpus, vocab, &config, options).unwrap();
+
+        assert!(Checkpoint::is_checkpoint(&path).unwrap());
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert_eq!((checkpoint.epoch, checkpoint.subwords), (2, Some(config.subwords)));
+        let restored = checkpoint.into_fasttext().unwrap();
+        assert_eq!(restored.vector("a1x"), model.vector("a1x"));
+        assert_eq!(restored.keyed_vectors().vectors(), model.keyed_vectors().vectors());
+
+        std::fs::write(&path, b"RMLW2VC1 truncated").unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        std::fs::write(&path, b"1 2\na 0.5 0.5\n").unwrap();
+        assert!(!Checkpoint::is_checkpoint(&path).unwrap());
+        assert_eq!(Checkpoint::load(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+}